@@ -0,0 +1,321 @@
+// ATA commands tunnelled through SCSI (SAT ATA PASS-THROUGH 12/16)
+//
+// SAS HBAs and USB bridges hide the ATA drive behind a SCSI device, but most of them
+// translate these CDBs, which lets IDENTIFY DEVICE and SMART reach the drive anyway.
+// everything here only builds and decodes bytes, the commands go through a ScsiTransport
+
+use crate::structs::*;
+use crate::transport::{DataDirection, DriveHandle, ScsiResponse, ScsiTransport};
+use std::time::Duration;
+
+pub const ATA_PASS_THROUGH_12: u8 = 0xA1; // operation codes
+pub const ATA_PASS_THROUGH_16: u8 = 0x85;
+
+pub const ATA_IDENTIFY_DEVICE: u8 = 0xEC; // ata commands
+pub const ATA_SMART: u8 = 0xB0;
+
+pub const SMART_READ_DATA: u8 = 0xD0; // SMART feature register values
+pub const SMART_READ_THRESHOLDS: u8 = 0xD1;
+pub const SMART_RETURN_STATUS: u8 = 0xDA;
+pub const SMART_LBA_MID: u8 = 0x4F;   // SMART commands carry this signature in lba mid/high
+pub const SMART_LBA_HIGH: u8 = 0xC2;
+
+pub const SCSI_STATUS_GOOD: u8 = 0x00;
+pub const SCSI_STATUS_CHECK_CONDITION: u8 = 0x02;
+
+pub const SENSE_KEY_NO_SENSE: u8 = 0x00;
+pub const SENSE_KEY_RECOVERED_ERROR: u8 = 0x01;
+pub const SENSE_KEY_NOT_READY: u8 = 0x02;
+pub const SENSE_KEY_ILLEGAL_REQUEST: u8 = 0x05;
+pub const SENSE_KEY_ABORTED_COMMAND: u8 = 0x0B;
+
+const ASC_INVALID_OPCODE: u8 = 0x20;
+const ASC_INVALID_FIELD_IN_CDB: u8 = 0x24;
+const ASC_ATA_INFORMATION_AVAILABLE: (u8, u8) = (0x00, 0x1D); // fixed format sense carrying ata registers
+const ATA_STATUS_RETURN_DESCRIPTOR: u8 = 0x09;
+
+const ATA_STATUS_ERR: u8 = 0x01; // status register bits
+const ATA_ERROR_ABRT: u8 = 0x04; // error register bits
+
+pub const SENSE_BUFFER_SIZE: usize = 32; // room for descriptor sense with an ata status return descriptor
+
+// size of the pass-through CDB, older USB bridges only understand the 12 byte one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CdbSize {
+    Twelve,
+    Sixteen,
+}
+
+// how the ata command moves data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AtaProtocol {
+    NonData,   // no data, registers are read back through the sense data
+    PioDataIn, // one or more 512 byte blocks from the drive
+}
+
+impl AtaProtocol {
+    fn code(self) -> u8 {
+        match self {
+            AtaProtocol::NonData => 3,
+            AtaProtocol::PioDataIn => 4,
+        }
+    }
+}
+
+// the ata registers a command is issued with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct AtaTaskfile {
+    pub features: u16,
+    pub count: u16,
+    pub lba: u64, // 28 bit commands only use the low 24 bits plus the device register
+    pub device: u8,
+    pub command: u8,
+}
+
+impl AtaTaskfile {
+    // IDENTIFY DEVICE, returns one 512 byte block
+    pub fn identify_device() -> Self {
+        AtaTaskfile { count: 1, command: ATA_IDENTIFY_DEVICE, ..AtaTaskfile::default() }
+    }
+
+    // a SMART sub command selected by `feature`, e.g. SMART_READ_DATA
+    pub fn smart(feature: u8) -> Self {
+        AtaTaskfile {
+            features: feature as u16,
+            count: 1,
+            lba: ((SMART_LBA_HIGH as u64) << 16) | ((SMART_LBA_MID as u64) << 8),
+            command: ATA_SMART,
+            ..AtaTaskfile::default()
+        }
+    }
+}
+
+// builds an ATA PASS-THROUGH CDB for `taskfile`
+//
+// with `check_condition` set the device returns the ata registers in the sense data even on success
+pub fn build_cdb(size: CdbSize, taskfile: &AtaTaskfile, protocol: AtaProtocol, check_condition: bool) -> Vec<u8> {
+    let protocol_byte = protocol.code() << 1;
+    let mut flags = if check_condition { 0x20 } else { 0x00 }; // CK_COND
+    if protocol == AtaProtocol::PioDataIn {
+        flags |= 0x08 | 0x04 | 0x02; // T_DIR from device, BYT_BLOK blocks, T_LENGTH in the count field
+    }
+    let lba = taskfile.lba.to_be_bytes(); // lba[7] is bits 7:0
+
+    match size {
+        CdbSize::Twelve => vec![
+            ATA_PASS_THROUGH_12,
+            protocol_byte,
+            flags,
+            taskfile.features as u8,
+            taskfile.count as u8,
+            lba[7],           // lba 7:0
+            lba[6],           // lba 15:8
+            lba[5],           // lba 23:16
+            taskfile.device,
+            taskfile.command,
+            0,                // reserved
+            0,                // control
+        ],
+        CdbSize::Sixteen => vec![
+            ATA_PASS_THROUGH_16,
+            protocol_byte,    // EXTEND stays clear, only 28 bit commands are issued
+            flags,
+            (taskfile.features >> 8) as u8,
+            taskfile.features as u8,
+            (taskfile.count >> 8) as u8,
+            taskfile.count as u8,
+            lba[4],           // lba 31:24
+            lba[7],           // lba 7:0
+            lba[3],           // lba 39:32
+            lba[6],           // lba 15:8
+            lba[2],           // lba 47:40
+            lba[5],           // lba 23:16
+            taskfile.device,
+            taskfile.command,
+            0,                // control
+        ],
+    }
+}
+
+// ata registers returned in the sense data
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct AtaStatusReturn {
+    pub error: u8,
+    pub count: u16,
+    pub lba: u64,
+    pub device: u8,
+    pub status: u8,
+}
+
+impl AtaStatusReturn {
+    // the drive rejected the command (ERR set in the status register)
+    pub fn is_error(&self) -> bool {
+        self.status & ATA_STATUS_ERR != 0
+    }
+
+    // the drive aborted the command, usually because it does not support it
+    pub fn is_aborted(&self) -> bool {
+        self.is_error() && self.error & ATA_ERROR_ABRT != 0
+    }
+}
+
+// the parts of SCSI sense data the pass-through commands care about
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SenseData {
+    pub sense_key: u8,
+    pub asc: u8,
+    pub ascq: u8,
+    pub ata_status: Option<AtaStatusReturn>,
+}
+
+// decodes fixed (0x70/0x71) or descriptor (0x72/0x73) format sense data
+pub fn decode_sense(sense: &[u8]) -> Option<SenseData> {
+    let response_code = sense.first()? & 0x7F;
+    match response_code {
+        0x70 | 0x71 => {
+            if sense.len() < 14 {
+                return None;
+            }
+            let mut decoded = SenseData { sense_key: sense[2] & 0x0F, asc: sense[12], ascq: sense[13], ata_status: None };
+            // SAT puts the ata registers in the information and command specific fields
+            if (decoded.asc, decoded.ascq) == ASC_ATA_INFORMATION_AVAILABLE {
+                decoded.ata_status = Some(AtaStatusReturn {
+                    error: sense[3],
+                    status: sense[4],
+                    device: sense[5],
+                    count: sense[6] as u16,
+                    lba: u64::from_le_bytes([sense[9], sense[10], sense[11], 0, 0, 0, 0, 0]),
+                });
+            }
+            Some(decoded)
+        }
+        0x72 | 0x73 => {
+            if sense.len() < 8 {
+                return None;
+            }
+            let mut decoded = SenseData { sense_key: sense[1] & 0x0F, asc: sense[2], ascq: sense[3], ata_status: None };
+            let end = (8 + sense[7] as usize).min(sense.len());
+            let mut offset = 8;
+            while offset + 2 <= end {
+                let descriptor_len = 2 + sense[offset + 1] as usize;
+                let descriptor = &sense[offset..(offset + descriptor_len).min(end)];
+                if descriptor[0] == ATA_STATUS_RETURN_DESCRIPTOR && descriptor.len() >= 14 {
+                    decoded.ata_status = Some(AtaStatusReturn {
+                        error: descriptor[3],
+                        count: u16::from_be_bytes([descriptor[4], descriptor[5]]),
+                        lba: u64::from_be_bytes([0, 0, descriptor[10], descriptor[8], descriptor[6], descriptor[11], descriptor[9], descriptor[7]]),
+                        device: descriptor[12],
+                        status: descriptor[13],
+                    });
+                }
+                offset += descriptor_len;
+            }
+            Some(decoded)
+        }
+        _ => None,
+    }
+}
+
+// turns the outcome of a pass-through command into the ata registers (if returned) or a win32 error code
+pub fn check_response(response: &ScsiResponse, sense: &[u8]) -> Result<Option<AtaStatusReturn>, u32> {
+    let sense = decode_sense(&sense[..response.sense_len.min(sense.len())]);
+    match response.status {
+        SCSI_STATUS_GOOD => match sense.and_then(|sense| sense.ata_status) {
+            Some(ata_status) if ata_status.is_error() => Err(ata_error_code(&ata_status)),
+            ata_status => Ok(ata_status),
+        },
+        SCSI_STATUS_CHECK_CONDITION => {
+            let sense = sense.ok_or(ERROR_GEN_FAILURE)?;
+            if let Some(ata_status) = sense.ata_status {
+                if ata_status.is_error() {
+                    return Err(ata_error_code(&ata_status));
+                }
+                if matches!(sense.sense_key, SENSE_KEY_NO_SENSE | SENSE_KEY_RECOVERED_ERROR) {
+                    return Ok(Some(ata_status)); // CK_COND was set, the registers are the answer
+                }
+            }
+            match (sense.sense_key, sense.asc) {
+                (SENSE_KEY_ILLEGAL_REQUEST, ASC_INVALID_OPCODE | ASC_INVALID_FIELD_IN_CDB) => Err(ERROR_NOT_SUPPORTED),
+                (SENSE_KEY_NOT_READY, _) => Err(ERROR_NOT_READY),
+                _ => Err(ERROR_GEN_FAILURE),
+            }
+        }
+        _ => Err(ERROR_GEN_FAILURE), // BUSY, RESERVATION CONFLICT and friends
+    }
+}
+
+fn ata_error_code(ata_status: &AtaStatusReturn) -> u32 {
+    if ata_status.is_aborted() {
+        ERROR_NOT_SUPPORTED
+    } else {
+        ERROR_GEN_FAILURE
+    }
+}
+
+// true when the device does not know the pass-through operation code itself
+fn is_invalid_opcode(response: &ScsiResponse, sense: &[u8]) -> bool {
+    response.status == SCSI_STATUS_CHECK_CONDITION
+        && matches!(
+            decode_sense(&sense[..response.sense_len.min(sense.len())]),
+            Some(SenseData { sense_key: SENSE_KEY_ILLEGAL_REQUEST, asc: ASC_INVALID_OPCODE, .. })
+        )
+}
+
+// issues `taskfile` with the 16 byte CDB, falling back to the 12 byte one on devices that reject it
+//
+// returns the ata registers if the device reported them, `data` holds the data in phase
+pub fn ata_pass_through(
+    transport: &dyn ScsiTransport,
+    handle: DriveHandle,
+    taskfile: &AtaTaskfile,
+    protocol: AtaProtocol,
+    check_condition: bool,
+    data: &mut [u8],
+    timeout: Duration,
+) -> Result<Option<AtaStatusReturn>, u32> {
+    let direction = if protocol == AtaProtocol::PioDataIn { DataDirection::FromDevice } else { DataDirection::None };
+    let mut sense = [0u8; SENSE_BUFFER_SIZE];
+
+    let cdb = build_cdb(CdbSize::Sixteen, taskfile, protocol, check_condition);
+    let mut response = transport.execute(handle, &cdb, direction, data, &mut sense, timeout)?;
+    if is_invalid_opcode(&response, &sense) {
+        sense = [0u8; SENSE_BUFFER_SIZE];
+        let cdb = build_cdb(CdbSize::Twelve, taskfile, protocol, check_condition);
+        response = transport.execute(handle, &cdb, direction, data, &mut sense, timeout)?;
+    }
+
+    let ata_status = check_response(&response, &sense)?;
+    if direction == DataDirection::FromDevice && response.transferred < data.len() {
+        return Err(ERROR_INVALID_DATA); // short read, the block would be partly stale
+    }
+    Ok(ata_status)
+}
+
+// reads the 512 byte IDENTIFY DEVICE block
+pub fn identify_device(transport: &dyn ScsiTransport, handle: DriveHandle, timeout: Duration) -> Result<[u8; IDENTIFY_BUFFER_SIZE], u32> {
+    let mut identify_data = [0u8; IDENTIFY_BUFFER_SIZE];
+    ata_pass_through(transport, handle, &AtaTaskfile::identify_device(), AtaProtocol::PioDataIn, false, &mut identify_data, timeout)?;
+    Ok(identify_data)
+}
+
+// reads the 512 byte SMART READ DATA block (attribute table)
+pub fn smart_read_data(transport: &dyn ScsiTransport, handle: DriveHandle, timeout: Duration) -> Result<[u8; IDENTIFY_BUFFER_SIZE], u32> {
+    let mut smart_data = [0u8; IDENTIFY_BUFFER_SIZE];
+    ata_pass_through(transport, handle, &AtaTaskfile::smart(SMART_READ_DATA), AtaProtocol::PioDataIn, false, &mut smart_data, timeout)?;
+    Ok(smart_data)
+}
+
+// reads the 512 byte SMART READ ATTRIBUTE THRESHOLDS block
+pub fn smart_read_thresholds(transport: &dyn ScsiTransport, handle: DriveHandle, timeout: Duration) -> Result<[u8; IDENTIFY_BUFFER_SIZE], u32> {
+    let mut thresholds = [0u8; IDENTIFY_BUFFER_SIZE];
+    ata_pass_through(transport, handle, &AtaTaskfile::smart(SMART_READ_THRESHOLDS), AtaProtocol::PioDataIn, false, &mut thresholds, timeout)?;
+    Ok(thresholds)
+}
+
+// sends SMART RETURN STATUS and returns the (lba mid, lba high) registers the verdict is encoded in
+pub fn smart_return_status(transport: &dyn ScsiTransport, handle: DriveHandle, timeout: Duration) -> Result<(u8, u8), u32> {
+    // CK_COND asks the translation layer to return the registers, a non-data command has no other answer
+    let ata_status = ata_pass_through(transport, handle, &AtaTaskfile::smart(SMART_RETURN_STATUS), AtaProtocol::NonData, true, &mut [], timeout)?;
+    let ata_status = ata_status.ok_or(ERROR_INVALID_DATA)?;
+    Ok(((ata_status.lba >> 8) as u8, (ata_status.lba >> 16) as u8))
+}
@@ -0,0 +1,124 @@
+// encoding and decoding of the fixed width strings in ATA IDENTIFY data
+//
+// ATA strings hold two characters per 16 bit word, high byte first, and are padded with
+// spaces. read as little endian bytes every pair comes out swapped, and some storage drivers
+// hand the same field back as hex digits. everything here works on owned strings and
+// slices of any length, odd ones included, and never panics
+
+use std::fmt;
+
+// a decoded ATA string: cut at the first nul, padding trimmed, internal spaces kept
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AtaString(String);
+
+impl AtaString {
+    // wraps text that is already in reading order
+    pub fn new(text: &str) -> Self {
+        AtaString::from_bytes(text.as_bytes())
+    }
+
+    // decodes bytes in reading order, e.g. a field the driver already swapped back
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let end = bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len());
+        AtaString(String::from_utf8_lossy(&bytes[..end]).trim().to_string())
+    }
+
+    // decodes bytes as they sit in an IDENTIFY buffer, every pair swapped (a trailing odd byte is kept as is)
+    pub fn from_swapped_bytes(bytes: &[u8]) -> Self {
+        AtaString::from_bytes(&swap_pairs(bytes))
+    }
+
+    // decodes IDENTIFY words, e.g. words 10-19 for the serial number
+    pub fn from_words(words: &[u16]) -> Self {
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+        AtaString::from_bytes(&bytes)
+    }
+
+    // decodes "57442d..." style hex, None unless it is whole bytes of printable text
+    pub fn from_hex(text: &str) -> Option<Self> {
+        let digits = text.trim().as_bytes();
+        if digits.len() < 2 || !digits.len().is_multiple_of(2) {
+            return None;
+        }
+        let bytes: Vec<u8> = digits
+            .chunks_exact(2)
+            .map(|pair| Some(hex_value(pair[0])? << 4 | hex_value(pair[1])?))
+            .collect::<Option<_>>()?;
+        // nul padding is fine, a nul between the characters means this was never text
+        let end = bytes.iter().rposition(|&byte| byte != 0).map_or(0, |last| last + 1);
+        if !bytes[..end].iter().all(|&byte| byte.is_ascii_graphic() || byte == b' ') {
+            return None;
+        }
+        let decoded = AtaString::from_bytes(&bytes[..end]);
+        if decoded.is_empty() {
+            None
+        } else {
+            Some(decoded)
+        }
+    }
+
+    // the text space padded or cut to `len` bytes, in reading order
+    pub fn to_padded_bytes(&self, len: usize) -> Vec<u8> {
+        let mut bytes: Vec<u8> = self.0.bytes().take(len).collect();
+        bytes.resize(len, b' ');
+        bytes
+    }
+
+    // the text space padded or cut to `len` bytes, every pair swapped as in an IDENTIFY buffer
+    pub fn to_swapped_bytes(&self, len: usize) -> Vec<u8> {
+        swap_pairs(&self.to_padded_bytes(len))
+    }
+
+    // the text space padded or cut to `count` IDENTIFY words
+    pub fn to_words(&self, count: usize) -> Vec<u16> {
+        self.to_padded_bytes(count * 2).chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect()
+    }
+
+    // the text as lowercase hex digits, two per byte
+    pub fn to_hex(&self) -> String {
+        self.0.bytes().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    // the text with every pair of characters swapped, as when ATA words are read with the wrong endianness
+    pub fn swapped(&self) -> Self {
+        let len = self.0.len() + self.0.len() % 2; // pad an odd length with one space, like the drive does
+        AtaString::from_swapped_bytes(&self.to_padded_bytes(len))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl fmt::Display for AtaString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<AtaString> for String {
+    fn from(text: AtaString) -> String {
+        text.0
+    }
+}
+
+// swaps every pair of bytes, leaving a trailing odd byte where it is
+fn swap_pairs(bytes: &[u8]) -> Vec<u8> {
+    let mut swapped = bytes.to_vec();
+    for pair in swapped.chunks_exact_mut(2) {
+        pair.swap(0, 1);
+    }
+    swapped
+}
+
+fn hex_value(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
+}
@@ -0,0 +1,487 @@
+// command line front end of the crate
+//
+//   hddserial list                 drives, serials and the methods that found them
+//   hddserial identify <drive>     decoded IDENTIFY (or NVMe Identify Controller) fields
+//   hddserial smart <drive>        SMART attributes (or the NVMe SMART / Health log)
+//   hddserial dump <drive>         raw IDENTIFY data, or a capture of every request with --capture
+//   hddserial decode <file>        the report of a saved IDENTIFY, descriptor or vpd dump, no drive access
+
+mod output;
+
+use output::{render_fields, render_rows, Format, Record};
+use simplehddserial::capture::{Capture, CapturedEvent, Recorder, ReplayNvmeTransport, ReplayScsiTransport, ReplayTransport};
+use simplehddserial::decode::Structure;
+use simplehddserial::identify::{FormFactor, IdentifyDevice, RotationRate};
+use simplehddserial::nvme::{NvmeIdentifyController, NvmeSmartHealthLog};
+use simplehddserial::smart::{AttributeKind, SmartAttributes, ThresholdStatus};
+use simplehddserial::transport::{DriveTransport, NvmeTransport, ScsiTransport};
+use simplehddserial::{BusType, DriveIdentity, DriveInfo, HardDriveSerial, ScanOptions, SerialError};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
+
+const USAGE: &str = "\
+usage: hddserial [options] <command>
+
+commands:
+  list                 drives, serial numbers and the methods that found them
+  identify <drive>     decoded IDENTIFY data (Identify Controller on NVMe drives)
+  smart <drive>        SMART attributes (the SMART / Health log on NVMe drives)
+  dump <drive>         the raw 512 byte IDENTIFY block of an ATA drive, as hdparm --Istdout
+                       style hex words or with --binary as bytes; with --capture every
+                       ioctl, SCSI and NVMe request and response as a capture file
+  decode <file>        decode a saved dump (- for stdin) without touching any drive: binary,
+                       hdparm --Istdout, smartctl -r ioctl or plain hex ATA IDENTIFY, NVMe
+                       Identify, STORAGE_DEVICE_DESCRIPTOR or vpd page 0x80 / 0x83 data
+
+<drive> is an index (0 for \\\\.\\PhysicalDrive0 or the first block device) or a device path
+
+options:
+  --format <table|json|csv>   output format of list, identify, smart and decode (default table)
+  --output <file>             write to a file instead of stdout
+  --timeout <ms>              give up on a single request after this long
+  --replay <capture>          answer from a capture file instead of the drives
+  --sysfs-root <dir>          read drive attributes from a sysfs tree other than /sys
+  --binary                    dump: raw bytes instead of hex
+  --capture                   dump: write a capture of every request instead of the IDENTIFY block
+  -h, --help                  print this help
+
+exit status:
+  0  every drive answered
+  1  every drive or the requested query failed
+  2  bad command line
+  3  no drives found
+  4  some drives answered, others failed
+";
+
+const EXIT_OK: i32 = 0;
+const EXIT_ALL_FAILED: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_NO_DRIVES: i32 = 3;
+const EXIT_PARTIAL: i32 = 4;
+
+enum Command {
+    List,
+    Identify(String),
+    Smart(String),
+    Dump(String),
+    Decode(String),
+}
+
+struct Args {
+    command: Command,
+    format: Format,
+    output: Option<PathBuf>,
+    timeout: Option<Duration>,
+    replay: Option<PathBuf>,
+    sysfs_root: Option<PathBuf>,
+    binary: bool,
+    capture: bool,
+}
+
+// a failed command: what to print and which exit status to leave with
+struct Failure {
+    status: i32,
+    message: String,
+}
+
+impl Failure {
+    fn new(status: i32, message: impl Into<String>) -> Self {
+        Failure { status, message: message.into() }
+    }
+}
+
+fn main() {
+    let raw_args: Vec<String> = std::env::args().skip(1).collect();
+    if raw_args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        print!("{}", USAGE);
+        return;
+    }
+    let status = match parse_args(raw_args).and_then(|args| run(&args)) {
+        Ok(status) => status,
+        Err(failure) => {
+            eprintln!("hddserial: {}", failure.message);
+            if failure.status == EXIT_USAGE {
+                eprintln!("run hddserial --help for usage");
+            }
+            failure.status
+        }
+    };
+    process::exit(status);
+}
+
+fn parse_args(raw_args: Vec<String>) -> Result<Args, Failure> {
+    let usage = |message: String| Failure::new(EXIT_USAGE, message);
+    let mut raw_args = raw_args.into_iter();
+    let mut positional = Vec::new();
+    let (mut format, mut output, mut timeout, mut replay, mut sysfs_root, mut binary, mut capture) = (Format::Table, None, None, None, None, false, false);
+
+    while let Some(arg) = raw_args.next() {
+        let mut value = || raw_args.next().ok_or_else(|| usage(format!("{} needs a value", arg)));
+        match arg.as_str() {
+            "--format" => {
+                let name = value()?;
+                format = Format::parse(&name).ok_or_else(|| usage(format!("unknown format {:?}, expected table, json or csv", name)))?;
+            }
+            "--output" => output = Some(PathBuf::from(value()?)),
+            "--timeout" => {
+                let millis = value()?;
+                timeout = Some(Duration::from_millis(millis.parse().map_err(|_| usage(format!("bad timeout {:?}, expected milliseconds", millis)))?));
+            }
+            "--replay" => replay = Some(PathBuf::from(value()?)),
+            "--sysfs-root" => sysfs_root = Some(PathBuf::from(value()?)),
+            "--binary" => binary = true,
+            "--capture" => capture = true,
+            option if option.starts_with("--") => return Err(usage(format!("unknown option {}", option))),
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let command = match (positional.next().as_deref(), positional.next()) {
+        (Some("list"), None) => Command::List,
+        (Some("identify"), Some(drive)) => Command::Identify(drive),
+        (Some("smart"), Some(drive)) => Command::Smart(drive),
+        (Some("dump"), Some(drive)) => Command::Dump(drive),
+        (Some("decode"), Some(file)) => Command::Decode(file),
+        (Some(command @ ("identify" | "smart" | "dump")), None) => return Err(usage(format!("{} needs a drive", command))),
+        (Some("decode"), None) => return Err(usage("decode needs a file".to_string())),
+        (Some("list"), Some(extra)) => return Err(usage(format!("unexpected argument {}", extra))),
+        (Some(command), _) => return Err(usage(format!("unknown command {}", command))),
+        (None, _) => return Err(usage("no command given".to_string())),
+    };
+    if let Some(extra) = positional.next() {
+        return Err(usage(format!("unexpected argument {}", extra)));
+    }
+    if (binary || capture) && !matches!(command, Command::Dump(_)) {
+        return Err(usage("--binary and --capture only apply to dump".to_string()));
+    }
+    Ok(Args { command, format, output, timeout, replay, sysfs_root, binary, capture })
+}
+
+fn run(args: &Args) -> Result<i32, Failure> {
+    match &args.command {
+        Command::List => list(args),
+        Command::Identify(drive) => identify(args, drive),
+        Command::Smart(drive) => smart(args, drive),
+        Command::Dump(drive) => dump(args, drive),
+        Command::Decode(file) => decode(args, file),
+    }
+}
+
+// the transports the methods go through, None where the library default applies
+struct Transports {
+    ioctl: Option<Arc<dyn DriveTransport>>, // None where drives are read through sysfs instead
+    scsi: Option<Arc<dyn ScsiTransport>>,
+    nvme: Option<Arc<dyn NvmeTransport>>,
+}
+
+impl Transports {
+    // the same transports, recording every request into `recorder`
+    fn recorded(self, recorder: &Recorder) -> Transports {
+        Transports {
+            ioctl: self.ioctl.map(|transport| Arc::new(recorder.drive_transport(transport)) as Arc<dyn DriveTransport>),
+            scsi: self.scsi.map(|transport| Arc::new(recorder.scsi_transport(transport)) as Arc<dyn ScsiTransport>),
+            nvme: self.nvme.map(|transport| Arc::new(recorder.nvme_transport(transport)) as Arc<dyn NvmeTransport>),
+        }
+    }
+}
+
+fn transports(args: &Args) -> Result<Transports, Failure> {
+    if let Some(path) = &args.replay {
+        let capture = Capture::load(path).map_err(|error| Failure::new(EXIT_USAGE, format!("{}: {}", path.display(), error)))?;
+        // a capture only answers for the channels it was recorded through
+        let scsi = capture.events.iter().any(|event| matches!(event, CapturedEvent::ScsiOpen { .. }));
+        let nvme = capture.events.iter().any(|event| matches!(event, CapturedEvent::NvmeOpen { .. }));
+        return Ok(Transports {
+            scsi: scsi.then(|| Arc::new(ReplayScsiTransport::new(capture.clone())) as Arc<dyn ScsiTransport>),
+            nvme: nvme.then(|| Arc::new(ReplayNvmeTransport::new(capture.clone())) as Arc<dyn NvmeTransport>),
+            ioctl: Some(Arc::new(ReplayTransport::new(capture))),
+        });
+    }
+    if args.sysfs_root.is_some() {
+        return Ok(Transports { ioctl: None, scsi: None, nvme: None });
+    }
+    #[cfg(windows)]
+    return Ok(Transports { ioctl: Some(Arc::new(simplehddserial::Win32Transport)), scsi: None, nvme: None });
+    #[cfg(target_os = "linux")]
+    return Ok(Transports { ioctl: None, scsi: Some(Arc::new(simplehddserial::SgIoTransport)), nvme: Some(Arc::new(simplehddserial::NvmeIoctlTransport)) });
+    #[cfg(not(any(windows, target_os = "linux")))]
+    Ok(Transports { ioctl: None, scsi: None, nvme: None })
+}
+
+fn hd_serial(args: &Args, transports: Transports, options: ScanOptions) -> Result<HardDriveSerial, Failure> {
+    let options = match args.timeout {
+        Some(timeout) => options.with_operation_timeout(timeout),
+        None => options,
+    };
+    let mut hd_serial = match (transports.ioctl, &args.sysfs_root) {
+        (Some(transport), _) => HardDriveSerial::with_transport(transport),
+        (None, Some(root)) => HardDriveSerial::with_sysfs_root(root),
+        #[cfg(target_os = "linux")]
+        (None, None) => HardDriveSerial::new(),
+        #[cfg(not(target_os = "linux"))]
+        (None, None) => return Err(Failure::new(EXIT_USAGE, "no drive access on this platform, use --replay")),
+    };
+    if let Some(scsi) = transports.scsi {
+        hd_serial = hd_serial.with_scsi_transport(scsi);
+    }
+    if let Some(nvme) = transports.nvme {
+        hd_serial = hd_serial.with_nvme_transport(nvme);
+    }
+    Ok(hd_serial.with_options(options))
+}
+
+// scans the one drive a command is about
+fn find_drive(args: &Args, transports: Transports, drive: &str) -> Result<(HardDriveSerial, DriveInfo), Failure> {
+    let options = match drive.parse::<usize>() {
+        Ok(index) => ScanOptions::new().with_drive_indices([index]),
+        Err(_) => ScanOptions::new().with_drive_paths([drive]),
+    };
+    let mut hd_serial = hd_serial(args, transports, options)?;
+    match hd_serial.get_serial_numbers() {
+        Ok(mut drives) if !drives.is_empty() => Ok((hd_serial, drives.remove(0))),
+        Ok(_) => Err(Failure::new(EXIT_NO_DRIVES, format!("drive {} not found", drive))),
+        Err(errors) if errors.is_empty() => Err(Failure::new(EXIT_NO_DRIVES, format!("drive {} not found", drive))),
+        Err(errors) => Err(Failure::new(EXIT_ALL_FAILED, error_lines(&errors))),
+    }
+}
+
+fn error_lines(errors: &[SerialError]) -> String {
+    errors.iter().map(ToString::to_string).collect::<Vec<_>>().join("\nhddserial: ")
+}
+
+fn query_failed(error: SerialError) -> Failure {
+    Failure::new(EXIT_ALL_FAILED, error.to_string())
+}
+
+// writes to --output or stdout
+fn emit(args: &Args, bytes: &[u8]) -> Result<(), Failure> {
+    let result = match &args.output {
+        Some(path) => fs::write(path, bytes),
+        None => io::stdout().write_all(bytes),
+    };
+    result.map_err(|error| Failure::new(EXIT_ALL_FAILED, format!("failed to write output: {}", error)))
+}
+
+fn list(args: &Args) -> Result<i32, Failure> {
+    let mut hd_serial = hd_serial(args, transports(args)?, ScanOptions::new())?;
+    let result = hd_serial.get_serial_numbers();
+    for error in hd_serial.errors() {
+        eprintln!("hddserial: {}", error);
+    }
+    match result {
+        Ok(drives) => {
+            let records: Vec<Record> = drives.iter().map(drive_record).collect();
+            emit(args, render_rows(args.format, &records).as_bytes())?;
+            Ok(if hd_serial.errors().is_empty() { EXIT_OK } else { EXIT_PARTIAL })
+        }
+        Err(errors) if errors.is_empty() => Err(Failure::new(EXIT_NO_DRIVES, "no drives found")),
+        Err(_) => Err(Failure::new(EXIT_ALL_FAILED, "no drive reported a serial number")),
+    }
+}
+
+fn identify(args: &Args, drive: &str) -> Result<i32, Failure> {
+    let (hd_serial, drive) = find_drive(args, transports(args)?, drive)?;
+    let record = match drive.bus_type {
+        BusType::Nvme => nvme_controller_record(&hd_serial.nvme_identify_controller(&drive).map_err(query_failed)?),
+        _ => identify_record(&hd_serial.identify(&drive).map_err(query_failed)?),
+    };
+    emit(args, render_fields(args.format, &record).as_bytes())?;
+    Ok(EXIT_OK)
+}
+
+fn smart(args: &Args, drive: &str) -> Result<i32, Failure> {
+    let (hd_serial, drive) = find_drive(args, transports(args)?, drive)?;
+    let text = match drive.bus_type {
+        BusType::Nvme => render_fields(args.format, &nvme_health_record(&hd_serial.nvme_smart_health(&drive).map_err(query_failed)?)),
+        _ => render_rows(args.format, &attribute_records(&hd_serial.smart_attributes(&drive).map_err(query_failed)?)),
+    };
+    emit(args, text.as_bytes())?;
+    Ok(EXIT_OK)
+}
+
+fn dump(args: &Args, drive: &str) -> Result<i32, Failure> {
+    if args.capture {
+        let transports = transports(args)?;
+        if transports.ioctl.is_none() && transports.scsi.is_none() && transports.nvme.is_none() {
+            return Err(Failure::new(EXIT_USAGE, "--capture records the ioctl, SCSI and NVMe requests, which are not used here (try --replay)"));
+        }
+        let recorder = Recorder::new();
+        let (hd_serial, drive) = find_drive(args, transports.recorded(&recorder), drive)?;
+        if let Err(error) = hd_serial.identify_data(&drive) {
+            eprintln!("hddserial: {}", error); // the scan itself is still worth capturing
+        }
+        emit(args, recorder.capture().to_string().as_bytes())?;
+        return Ok(EXIT_OK);
+    }
+
+    let (hd_serial, drive) = find_drive(args, transports(args)?, drive)?;
+    let block = hd_serial.identify_data(&drive).map_err(query_failed)?;
+    if args.binary {
+        emit(args, &block)?;
+    } else {
+        // eight words per line like hdparm --Istdout
+        let words: Vec<String> = block.chunks_exact(2).map(|pair| format!("{:04x}", u16::from_le_bytes([pair[0], pair[1]]))).collect();
+        let lines: String = words.chunks(8).map(|line| format!("{}\n", line.join(" "))).collect();
+        emit(args, lines.as_bytes())?;
+    }
+    Ok(EXIT_OK)
+}
+
+// prints what a live identify would for IDENTIFY and Identify Controller data, and the identity
+// a live list would report for the other structures
+fn decode(args: &Args, file: &str) -> Result<i32, Failure> {
+    let input = if file == "-" {
+        let mut input = Vec::new();
+        io::stdin().read_to_end(&mut input).map(|_| input)
+    } else {
+        fs::read(file)
+    };
+    let input = input.map_err(|error| Failure::new(EXIT_ALL_FAILED, format!("{}: {}", file, error)))?;
+    let dump = simplehddserial::decode::decode(&input).map_err(|error| Failure::new(EXIT_ALL_FAILED, format!("{}: {}", file, error)))?;
+    eprintln!("hddserial: {}: {} ({})", file, dump.structure, dump.format);
+
+    let record = match &dump.structure {
+        Structure::AtaIdentify(identify) => identify_record(identify),
+        Structure::NvmeIdentifyController(controller) => nvme_controller_record(controller),
+        structure => identity_record(&structure.identity()),
+    };
+    emit(args, render_fields(args.format, &record).as_bytes())?;
+    Ok(EXIT_OK)
+}
+
+fn drive_record(drive: &DriveInfo) -> Record {
+    vec![
+        ("index", drive.index.into()),
+        ("path", drive.device_path.as_str().into()),
+        ("serial", drive.serial.as_str().into()),
+        ("model", drive.model.clone().into()),
+        ("firmware", drive.firmware.clone().into()),
+        ("bus", format!("{:?}", drive.bus_type).into()),
+        ("method", drive.method.to_string().into()),
+        ("agreement", format!("{:?}", drive.consensus.agreement).to_lowercase().into()),
+        ("methods", drive.consensus.agreeing.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ").into()),
+    ]
+}
+
+fn identity_record(identity: &DriveIdentity) -> Record {
+    let non_empty = |text: &str| if text.is_empty() { None } else { Some(text.to_string()) };
+    vec![
+        ("serial", non_empty(&identity.serial).into()),
+        ("model", identity.model.clone().into()),
+        ("firmware", identity.firmware.clone().into()),
+        ("bus", format!("{:?}", identity.bus_type).into()),
+        ("identifiers", non_empty(&identity.identifiers.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ")).into()),
+    ]
+}
+
+fn identify_record(identify: &IdentifyDevice) -> Record {
+    let sata = identify.sata.as_ref();
+    let speed = |speed: Option<simplehddserial::identify::SataSpeed>| speed.map(|speed| format!("{} Gb/s", speed.gbps()));
+    vec![
+        ("serial", identify.serial.as_str().into()),
+        ("model", identify.model.as_str().into()),
+        ("firmware", identify.firmware.as_str().into()),
+        ("sectors", identify.sectors().into()),
+        ("capacity_bytes", identify.capacity_bytes().into()),
+        ("logical_sector_size", identify.logical_sector_size.into()),
+        ("physical_sector_size", identify.physical_sector_size.into()),
+        ("wwn", identify.wwn.map(|wwn| format!("{:016x}", wwn)).into()),
+        ("rotation", rotation_text(identify.rotation_rate).into()),
+        ("form_factor", form_factor_text(identify.form_factor).into()),
+        ("ata_version", identify.ata_major_version.map(|version| version.to_string()).into()),
+        ("sata_max_speed", speed(sata.and_then(|sata| sata.max_speed)).into()),
+        ("sata_current_speed", speed(sata.and_then(|sata| sata.current_speed)).into()),
+        ("ncq", sata.map(|sata| sata.ncq_supported).into()),
+        ("trim", identify.trim_supported.into()),
+    ]
+}
+
+fn rotation_text(rotation_rate: RotationRate) -> Option<String> {
+    match rotation_rate {
+        RotationRate::NotReported | RotationRate::Reserved(_) => None,
+        RotationRate::NonRotating => Some("non-rotating".to_string()),
+        RotationRate::Rpm(rpm) => Some(format!("{} rpm", rpm)),
+    }
+}
+
+fn form_factor_text(form_factor: FormFactor) -> Option<String> {
+    let name = match form_factor {
+        FormFactor::NotReported | FormFactor::Reserved(_) => return None,
+        FormFactor::Inch5_25 => "5.25 inch",
+        FormFactor::Inch3_5 => "3.5 inch",
+        FormFactor::Inch2_5 => "2.5 inch",
+        FormFactor::Inch1_8 => "1.8 inch",
+        FormFactor::SmallerThan1_8 => "smaller than 1.8 inch",
+        FormFactor::MSata => "mSATA",
+        FormFactor::M2 => "M.2",
+        FormFactor::MicroSsd => "MicroSSD",
+        FormFactor::CFast => "CFast",
+    };
+    Some(name.to_string())
+}
+
+fn nvme_controller_record(controller: &NvmeIdentifyController) -> Record {
+    let (major, minor, tertiary) = controller.version_triple();
+    vec![
+        ("serial", controller.serial.as_str().into()),
+        ("model", controller.model.as_str().into()),
+        ("firmware", controller.firmware.as_str().into()),
+        ("vendor_id", format!("{:04x}", controller.vendor_id).into()),
+        ("ieee_oui", controller.ieee_oui_string().into()),
+        ("controller_id", controller.controller_id.into()),
+        ("version", format!("{}.{}.{}", major, minor, tertiary).into()),
+        ("namespaces", controller.namespace_count.into()),
+        ("total_capacity", controller.total_capacity.into()),
+        ("subsystem_nqn", controller.subsystem_nqn.as_str().into()),
+    ]
+}
+
+fn attribute_records(attributes: &SmartAttributes) -> Vec<Record> {
+    attributes
+        .attributes
+        .iter()
+        .map(|attribute| {
+            let kind = match attribute.kind() {
+                AttributeKind::PreFail => "pre-fail",
+                AttributeKind::OldAge => "old-age",
+            };
+            let status = match attribute.status() {
+                ThresholdStatus::Ok => "ok",
+                ThresholdStatus::FailingNow => "failing now",
+                ThresholdStatus::FailedInPast => "failed in past",
+            };
+            vec![
+                ("id", attribute.id.into()),
+                ("flags", format!("{:04x}", attribute.flags).into()),
+                ("kind", kind.into()),
+                ("current", attribute.current.into()),
+                ("worst", attribute.worst.into()),
+                ("threshold", attribute.threshold.into()),
+                ("raw", attribute.raw.into()),
+                ("status", status.into()),
+            ]
+        })
+        .collect()
+}
+
+fn nvme_health_record(log: &NvmeSmartHealthLog) -> Record {
+    vec![
+        ("critical_warning", format!("{:02x}", log.critical_warning).into()),
+        ("temperature_kelvin", log.composite_temperature.into()),
+        ("available_spare", log.available_spare.into()),
+        ("available_spare_threshold", log.available_spare_threshold.into()),
+        ("percentage_used", log.percentage_used.into()),
+        ("data_units_read", log.data_units_read.into()),
+        ("data_units_written", log.data_units_written.into()),
+        ("power_cycles", log.power_cycles.into()),
+        ("power_on_hours", log.power_on_hours.into()),
+        ("unsafe_shutdowns", log.unsafe_shutdowns.into()),
+        ("media_errors", log.media_errors.into()),
+        ("error_log_entries", log.error_log_entries.into()),
+    ]
+}
@@ -0,0 +1,183 @@
+// rendering of command results as an aligned table, JSON or CSV
+//
+// a command builds records (ordered name/value pairs) and picks whether they are rows of one
+// table (drives, attributes) or the fields of a single item (one drive's IDENTIFY data)
+
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Table,
+    Json,
+    Csv,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name {
+            "table" => Some(Format::Table),
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+}
+
+// one value of a record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Text(String),
+    Number(u128), // NVMe counters are 128 bit
+    Bool(bool),
+    Missing,      // not reported, null in JSON and empty in CSV
+}
+
+impl From<&str> for Value {
+    fn from(text: &str) -> Self {
+        Value::Text(text.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(text: String) -> Self {
+        Value::Text(text)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(flag: bool) -> Self {
+        Value::Bool(flag)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Missing, Into::into)
+    }
+}
+
+macro_rules! number_value {
+    ($($number:ty),*) => {
+        $(impl From<$number> for Value {
+            fn from(number: $number) -> Self {
+                Value::Number(number as u128)
+            }
+        })*
+    };
+}
+
+number_value!(u8, u16, u32, u64, u128, usize);
+
+impl Value {
+    fn text(&self) -> String {
+        match self {
+            Value::Text(text) => text.clone(),
+            Value::Number(number) => number.to_string(),
+            Value::Bool(flag) => if *flag { "yes" } else { "no" }.to_string(),
+            Value::Missing => String::new(),
+        }
+    }
+
+    fn json(&self) -> String {
+        match self {
+            Value::Text(text) => json_string(text),
+            Value::Number(number) => number.to_string(),
+            Value::Bool(flag) => flag.to_string(),
+            Value::Missing => "null".to_string(),
+        }
+    }
+}
+
+// ordered (name, value) pairs
+pub type Record = Vec<(&'static str, Value)>;
+
+// records that share their fields, one row each
+pub fn render_rows(format: Format, records: &[Record]) -> String {
+    let names: Vec<&str> = records.first().map(|record| record.iter().map(|(name, _)| *name).collect()).unwrap_or_default();
+    match format {
+        Format::Table => {
+            let cells: Vec<Vec<String>> = records.iter().map(|record| record.iter().map(|(_, value)| table_cell(value)).collect()).collect();
+            let widths: Vec<usize> = names
+                .iter()
+                .enumerate()
+                .map(|(column, name)| cells.iter().map(|row| row[column].chars().count()).chain([name.len()]).max().unwrap_or(0))
+                .collect();
+            let mut text = String::new();
+            for row in std::iter::once(names.iter().map(|name| name.to_uppercase()).collect()).chain(cells) {
+                let line: Vec<String> = row.iter().zip(&widths).map(|(cell, &width)| format!("{:<width$}", cell, width = width)).collect();
+                writeln!(text, "{}", line.join("  ").trim_end()).unwrap();
+            }
+            text
+        }
+        Format::Json => {
+            let objects: Vec<String> = records.iter().map(|record| format!("  {}", json_object(record))).collect();
+            if objects.is_empty() {
+                "[]\n".to_string()
+            } else {
+                format!("[\n{}\n]\n", objects.join(",\n"))
+            }
+        }
+        Format::Csv => {
+            let mut text = csv_line(names.iter().map(|name| name.to_string()));
+            for record in records {
+                text += &csv_line(record.iter().map(|(_, value)| value.text()));
+            }
+            text
+        }
+    }
+}
+
+// the fields of a single item, in a table one per line
+pub fn render_fields(format: Format, record: &Record) -> String {
+    match format {
+        Format::Table => {
+            let width = record.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+            record.iter().map(|(name, value)| format!("{:<width$}  {}\n", name, table_cell(value), width = width)).collect()
+        }
+        Format::Json => format!("{}\n", json_object(record)),
+        Format::Csv => render_rows(Format::Csv, std::slice::from_ref(record)),
+    }
+}
+
+fn table_cell(value: &Value) -> String {
+    match value {
+        Value::Missing => "-".to_string(),
+        value => value.text(),
+    }
+}
+
+fn json_object(record: &Record) -> String {
+    let fields: Vec<String> = record.iter().map(|(name, value)| format!("{}: {}", json_string(name), value.json())).collect();
+    format!("{{{}}}", fields.join(", "))
+}
+
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted += "\\\"",
+            '\\' => quoted += "\\\\",
+            '\n' => quoted += "\\n",
+            '\r' => quoted += "\\r",
+            '\t' => quoted += "\\t",
+            c if (c as u32) < 0x20 => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// one CSV line, fields with separators, quotes or line breaks are quoted (RFC 4180)
+fn csv_line(fields: impl Iterator<Item = String>) -> String {
+    let fields: Vec<String> = fields
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field
+            }
+        })
+        .collect();
+    format!("{}\n", fields.join(","))
+}
@@ -0,0 +1,748 @@
+// recording and replaying the raw conversation between the retrieval methods and a drive
+//
+// a RecordingTransport sits between HardDriveSerial and the real transport and keeps every
+// open and ioctl with its bytes, result and timing, RecordingScsiTransport and
+// RecordingNvmeTransport do the same for SCSI commands and NVMe admin commands. the Capture
+// they produce is saved as a versioned text file, and the replay transports answer the same
+// requests from that file on any platform, so a drive that misbehaves in the field becomes a
+// file a test can load
+//
+// file format, one event per line, tab separated key=value fields, hex encoded bytes:
+//
+//   simplehddserial capture 2
+//   open       path=\\.\PhysicalDrive0  rights=admin  result=ok  micros=35
+//   ioctl      path=\\.\PhysicalDrive0  code=0x00074080  in=  out_len=24  out=01000001  result=ok:24  micros=12
+//   scsi_open  path=/dev/sda  result=ok  micros=20
+//   scsi       path=/dev/sda  cdb=12010080ff0000  dir=from_device  data_len=1020  data=0080...  status=0x00  sense=  result=ok:24  micros=180
+//   nvme_open  path=/dev/nvme0  result=ok  micros=15
+//   nvme       path=/dev/nvme0  opcode=0x06  nsid=0  cdw10=0x00000001  ..  cdw15=0x00000000  data_len=4096  data=4d14...  result=ok:0  micros=90
+//
+// data and output bytes are stored without their trailing zeros, lines starting with # are
+// comments. version 1 files (open and ioctl events only) are still read
+
+use crate::structs::{ERROR_FILE_NOT_FOUND, ERROR_NOT_SUPPORTED};
+use crate::transport::{AccessRights, DataDirection, DriveHandle, DriveTransport, NvmeAdminCommand, NvmeTransport, ScsiResponse, ScsiTransport};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+pub const CAPTURE_VERSION: u32 = 2; // the version written, every version up to it is read
+const CAPTURE_HEADER: &str = "simplehddserial capture";
+
+// one request a retrieval method made, with the answer it got
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CapturedEvent {
+    Open {
+        path: String,
+        rights: AccessRights,
+        result: Result<(), u32>, // win32 error code if the open failed
+        elapsed: Duration,
+    },
+    Ioctl {
+        path: String,
+        control_code: u32,
+        input: Vec<u8>,
+        output: Vec<u8>,           // output buffer after the call, trailing zeros dropped
+        output_len: usize,         // size of the output buffer the method passed
+        result: Result<usize, u32>, // bytes returned or win32 error code
+        elapsed: Duration,
+    },
+    ScsiOpen {
+        path: String,
+        result: Result<(), u32>,
+        elapsed: Duration,
+    },
+    Scsi {
+        path: String,
+        cdb: Vec<u8>,
+        direction: DataDirection,
+        data: Vec<u8>,                     // data sent to the device, or the data buffer after the call, trailing zeros dropped
+        data_len: usize,                   // size of the data buffer the method passed
+        sense: Vec<u8>,                    // the sense bytes the device returned
+        result: Result<ScsiResponse, u32>, // status and transfer count, or win32 error code
+        elapsed: Duration,
+    },
+    NvmeOpen {
+        path: String,
+        result: Result<(), u32>,
+        elapsed: Duration,
+    },
+    Nvme {
+        path: String,
+        command: NvmeAdminCommand,
+        data: Vec<u8>,            // data buffer after the call, trailing zeros dropped
+        data_len: usize,          // size of the data buffer the method passed
+        result: Result<u32, u32>, // dword 0 of the completion, or the win32 error code a failed completion maps to
+        elapsed: Duration,
+    },
+}
+
+// why a capture file could not be loaded
+#[derive(Debug)]
+pub enum CaptureError {
+    Io(io::Error),
+    UnsupportedVersion(String),                  // the header line, e.g. from a newer release
+    Malformed { line: usize, reason: String },   // 1 based line number
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::Io(error) => write!(f, "failed to read capture: {}", error),
+            CaptureError::UnsupportedVersion(header) => write!(f, "unsupported capture header {:?}, expected \"{} {}\" or older", header, CAPTURE_HEADER, CAPTURE_VERSION),
+            CaptureError::Malformed { line, reason } => write!(f, "malformed capture line {}: {}", line, reason),
+        }
+    }
+}
+
+impl Error for CaptureError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CaptureError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CaptureError {
+    fn from(error: io::Error) -> Self {
+        CaptureError::Io(error)
+    }
+}
+
+// everything recorded during one or more scans, in the order it happened
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capture {
+    pub events: Vec<CapturedEvent>,
+}
+
+impl Capture {
+    // decodes the text format described at the top of this file
+    pub fn parse(text: &str) -> Result<Capture, CaptureError> {
+        let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'));
+        let header = lines.next().map(|(_, line)| line.trim()).unwrap_or_default();
+        let version = header.strip_prefix(CAPTURE_HEADER).and_then(|version| version.trim().parse::<u32>().ok());
+        if !version.is_some_and(|version| (1..=CAPTURE_VERSION).contains(&version)) {
+            return Err(CaptureError::UnsupportedVersion(header.to_string()));
+        }
+        let events = lines
+            .map(|(number, line)| parse_event(line).map_err(|reason| CaptureError::Malformed { line: number + 1, reason }))
+            .collect::<Result<_, _>>()?;
+        Ok(Capture { events })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Capture, CaptureError> {
+        Capture::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl fmt::Display for Capture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} {}", CAPTURE_HEADER, CAPTURE_VERSION)?;
+        for event in &self.events {
+            match event {
+                CapturedEvent::Open { path, rights, result, elapsed } => {
+                    writeln!(f, "open\tpath={}\trights={}\tresult={}\tmicros={}", path, rights_name(*rights), open_result(result), elapsed.as_micros())?;
+                }
+                CapturedEvent::Ioctl { path, control_code, input, output, output_len, result, elapsed } => {
+                    let result = match result {
+                        Ok(returned) => format!("ok:{}", returned),
+                        Err(code) => format!("err:{}", code),
+                    };
+                    writeln!(
+                        f,
+                        "ioctl\tpath={}\tcode={:#010x}\tin={}\tout_len={}\tout={}\tresult={}\tmicros={}",
+                        path, control_code, to_hex(input), output_len, to_hex(output), result, elapsed.as_micros()
+                    )?;
+                }
+                CapturedEvent::ScsiOpen { path, result, elapsed } => {
+                    writeln!(f, "scsi_open\tpath={}\tresult={}\tmicros={}", path, open_result(result), elapsed.as_micros())?;
+                }
+                CapturedEvent::Scsi { path, cdb, direction, data, data_len, sense, result, elapsed } => {
+                    let (status, result) = match result {
+                        Ok(response) => (response.status, format!("ok:{}", response.transferred)),
+                        Err(code) => (0, format!("err:{}", code)),
+                    };
+                    writeln!(
+                        f,
+                        "scsi\tpath={}\tcdb={}\tdir={}\tdata_len={}\tdata={}\tstatus={:#04x}\tsense={}\tresult={}\tmicros={}",
+                        path, to_hex(cdb), direction_name(*direction), data_len, to_hex(data), status, to_hex(sense), result, elapsed.as_micros()
+                    )?;
+                }
+                CapturedEvent::NvmeOpen { path, result, elapsed } => {
+                    writeln!(f, "nvme_open\tpath={}\tresult={}\tmicros={}", path, open_result(result), elapsed.as_micros())?;
+                }
+                CapturedEvent::Nvme { path, command, data, data_len, result, elapsed } => {
+                    let result = match result {
+                        Ok(dword0) => format!("ok:{}", dword0),
+                        Err(code) => format!("err:{}", code),
+                    };
+                    writeln!(
+                        f,
+                        "nvme\tpath={}\topcode={:#04x}\tnsid={}\tcdw10={:#010x}\tcdw11={:#010x}\tcdw12={:#010x}\tcdw13={:#010x}\tcdw14={:#010x}\tcdw15={:#010x}\tdata_len={}\tdata={}\tresult={}\tmicros={}",
+                        path, command.opcode, command.nsid, command.cdw10, command.cdw11, command.cdw12, command.cdw13, command.cdw14, command.cdw15,
+                        data_len, to_hex(data), result, elapsed.as_micros()
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn open_result(result: &Result<(), u32>) -> String {
+    match result {
+        Ok(()) => "ok".to_string(),
+        Err(code) => format!("err:{}", code),
+    }
+}
+
+fn direction_name(direction: DataDirection) -> &'static str {
+    match direction {
+        DataDirection::None => "none",
+        DataDirection::FromDevice => "from_device",
+        DataDirection::ToDevice => "to_device",
+    }
+}
+
+fn rights_name(rights: AccessRights) -> &'static str {
+    match rights {
+        AccessRights::Admin => "admin",
+        AccessRights::Smart => "smart",
+        AccessRights::Zero => "zero",
+    }
+}
+
+fn parse_event(line: &str) -> Result<CapturedEvent, String> {
+    let mut fields = line.split('\t');
+    let kind = fields.next().unwrap_or_default();
+    let fields: HashMap<&str, &str> = fields.filter_map(|field| field.split_once('=')).collect();
+    let field = |name: &str| fields.get(name).copied().ok_or_else(|| format!("missing {}", name));
+    let elapsed = Duration::from_micros(field("micros")?.parse().map_err(|_| "bad micros".to_string())?);
+    let number = |name: &str| field(name)?.parse::<usize>().map_err(|_| format!("bad {}", name));
+    let hex_number = |name: &str| {
+        let text = field(name)?;
+        u32::from_str_radix(text.trim_start_matches("0x"), 16).map_err(|_| format!("bad {} {:?}", name, text))
+    };
+    let open_result = || -> Result<Result<(), u32>, String> {
+        match field("result")? {
+            "ok" => Ok(Ok(())),
+            other => Ok(Err(parse_error_code(other)?)),
+        }
+    };
+
+    match kind {
+        "open" => Ok(CapturedEvent::Open {
+            path: field("path")?.to_string(),
+            rights: match field("rights")? {
+                "admin" => AccessRights::Admin,
+                "smart" => AccessRights::Smart,
+                "zero" => AccessRights::Zero,
+                other => return Err(format!("unknown rights {:?}", other)),
+            },
+            result: open_result()?,
+            elapsed,
+        }),
+        "ioctl" => {
+            let code = field("code")?;
+            Ok(CapturedEvent::Ioctl {
+                path: field("path")?.to_string(),
+                control_code: u32::from_str_radix(code.trim_start_matches("0x"), 16).map_err(|_| format!("bad control code {:?}", code))?,
+                input: from_hex(field("in")?)?,
+                output: from_hex(field("out")?)?,
+                output_len: field("out_len")?.parse().map_err(|_| "bad out_len".to_string())?,
+                result: match field("result")?.strip_prefix("ok:") {
+                    Some(returned) => Ok(returned.parse().map_err(|_| "bad byte count".to_string())?),
+                    None => Err(parse_error_code(field("result")?)?),
+                },
+                elapsed,
+            })
+        }
+        "scsi_open" => Ok(CapturedEvent::ScsiOpen { path: field("path")?.to_string(), result: open_result()?, elapsed }),
+        "scsi" => {
+            let sense = from_hex(field("sense")?)?;
+            Ok(CapturedEvent::Scsi {
+                path: field("path")?.to_string(),
+                cdb: from_hex(field("cdb")?)?,
+                direction: match field("dir")? {
+                    "none" => DataDirection::None,
+                    "from_device" => DataDirection::FromDevice,
+                    "to_device" => DataDirection::ToDevice,
+                    other => return Err(format!("unknown direction {:?}", other)),
+                },
+                data: from_hex(field("data")?)?,
+                data_len: number("data_len")?,
+                result: match field("result")?.strip_prefix("ok:") {
+                    Some(transferred) => Ok(ScsiResponse {
+                        status: u8::try_from(hex_number("status")?).map_err(|_| "bad status".to_string())?,
+                        sense_len: sense.len(),
+                        transferred: transferred.parse().map_err(|_| "bad byte count".to_string())?,
+                    }),
+                    None => Err(parse_error_code(field("result")?)?),
+                },
+                sense,
+                elapsed,
+            })
+        }
+        "nvme_open" => Ok(CapturedEvent::NvmeOpen { path: field("path")?.to_string(), result: open_result()?, elapsed }),
+        "nvme" => Ok(CapturedEvent::Nvme {
+            path: field("path")?.to_string(),
+            command: NvmeAdminCommand {
+                opcode: u8::try_from(hex_number("opcode")?).map_err(|_| "bad opcode".to_string())?,
+                nsid: field("nsid")?.parse().map_err(|_| "bad nsid".to_string())?,
+                cdw10: hex_number("cdw10")?,
+                cdw11: hex_number("cdw11")?,
+                cdw12: hex_number("cdw12")?,
+                cdw13: hex_number("cdw13")?,
+                cdw14: hex_number("cdw14")?,
+                cdw15: hex_number("cdw15")?,
+            },
+            data: from_hex(field("data")?)?,
+            data_len: number("data_len")?,
+            result: match field("result")?.strip_prefix("ok:") {
+                Some(dword0) => Ok(dword0.parse().map_err(|_| "bad completion dword".to_string())?),
+                None => Err(parse_error_code(field("result")?)?),
+            },
+            elapsed,
+        }),
+        other => Err(format!("unknown event {:?}", other)),
+    }
+}
+
+fn parse_error_code(result: &str) -> Result<u32, String> {
+    result.strip_prefix("err:").and_then(|code| code.parse().ok()).ok_or_else(|| format!("bad result {:?}", result))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Result<Vec<u8>, String> {
+    if !text.len().is_multiple_of(2) {
+        return Err("odd number of hex digits".to_string());
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| text.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()).ok_or_else(|| format!("bad hex at {}", i)))
+        .collect()
+}
+
+// the event log recording transports share, so the SCSI and NVMe commands of a scan land in
+// one capture with its ioctls, in the order they were made
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    events: Arc<Mutex<Vec<CapturedEvent>>>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Recorder::default()
+    }
+
+    // records the opens and ioctls sent through `inner`
+    pub fn drive_transport(&self, inner: Arc<dyn DriveTransport>) -> RecordingTransport {
+        RecordingTransport { inner, paths: Mutex::new(HashMap::new()), recorder: self.clone() }
+    }
+
+    // records the opens and commands sent through `inner`
+    pub fn scsi_transport(&self, inner: Arc<dyn ScsiTransport>) -> RecordingScsiTransport {
+        RecordingScsiTransport { inner, paths: Mutex::new(HashMap::new()), recorder: self.clone() }
+    }
+
+    // records the opens and admin commands sent through `inner`
+    pub fn nvme_transport(&self, inner: Arc<dyn NvmeTransport>) -> RecordingNvmeTransport {
+        RecordingNvmeTransport { inner, paths: Mutex::new(HashMap::new()), recorder: self.clone() }
+    }
+
+    // everything recorded so far
+    pub fn capture(&self) -> Capture {
+        Capture { events: self.events.lock().unwrap().clone() }
+    }
+
+    fn push(&self, event: CapturedEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+// DriveTransport that passes everything to `inner` and records it
+pub struct RecordingTransport {
+    inner: Arc<dyn DriveTransport>,
+    paths: Mutex<HashMap<DriveHandle, String>>,
+    recorder: Recorder,
+}
+
+impl RecordingTransport {
+    // records into a capture of its own
+    pub fn new(inner: Arc<dyn DriveTransport>) -> Self {
+        Recorder::new().drive_transport(inner)
+    }
+
+    // everything recorded so far, by this transport and the others sharing its recorder
+    pub fn capture(&self) -> Capture {
+        self.recorder.capture()
+    }
+}
+
+impl DriveTransport for RecordingTransport {
+    fn open(&self, path: &str, rights: AccessRights) -> Result<DriveHandle, u32> {
+        let start_time = Instant::now();
+        let result = self.inner.open(path, rights);
+        if let Ok(handle) = result {
+            self.paths.lock().unwrap().insert(handle, path.to_string());
+        }
+        self.recorder.push(CapturedEvent::Open {
+            path: path.to_string(),
+            rights,
+            result: result.map(|_| ()),
+            elapsed: start_time.elapsed(),
+        });
+        result
+    }
+
+    fn ioctl(&self, handle: DriveHandle, control_code: u32, input: &[u8], output: &mut [u8], timeout: Duration) -> Result<usize, u32> {
+        let start_time = Instant::now();
+        let result = self.inner.ioctl(handle, control_code, input, output, timeout);
+        self.recorder.push(CapturedEvent::Ioctl {
+            path: self.paths.lock().unwrap().get(&handle).cloned().unwrap_or_default(),
+            control_code,
+            input: input.to_vec(),
+            output: without_trailing_zeros(output).to_vec(),
+            output_len: output.len(),
+            result,
+            elapsed: start_time.elapsed(),
+        });
+        result
+    }
+
+    fn close(&self, handle: DriveHandle) {
+        self.paths.lock().unwrap().remove(&handle);
+        self.inner.close(handle);
+    }
+}
+
+// ScsiTransport that passes everything to `inner` and records it
+pub struct RecordingScsiTransport {
+    inner: Arc<dyn ScsiTransport>,
+    paths: Mutex<HashMap<DriveHandle, String>>,
+    recorder: Recorder,
+}
+
+impl RecordingScsiTransport {
+    // records into a capture of its own
+    pub fn new(inner: Arc<dyn ScsiTransport>) -> Self {
+        Recorder::new().scsi_transport(inner)
+    }
+
+    // everything recorded so far, by this transport and the others sharing its recorder
+    pub fn capture(&self) -> Capture {
+        self.recorder.capture()
+    }
+}
+
+impl ScsiTransport for RecordingScsiTransport {
+    fn open(&self, path: &str) -> Result<DriveHandle, u32> {
+        let start_time = Instant::now();
+        let result = self.inner.open(path);
+        if let Ok(handle) = result {
+            self.paths.lock().unwrap().insert(handle, path.to_string());
+        }
+        self.recorder.push(CapturedEvent::ScsiOpen { path: path.to_string(), result: result.map(|_| ()), elapsed: start_time.elapsed() });
+        result
+    }
+
+    fn execute(&self, handle: DriveHandle, cdb: &[u8], direction: DataDirection, data: &mut [u8], sense: &mut [u8], timeout: Duration) -> Result<ScsiResponse, u32> {
+        let start_time = Instant::now();
+        let sent = without_trailing_zeros(data).to_vec(); // what a command to the device carries, the device may scribble on the buffer
+        let result = self.inner.execute(handle, cdb, direction, data, sense, timeout);
+        let sense_len = result.map_or(0, |response| response.sense_len.min(sense.len()));
+        self.recorder.push(CapturedEvent::Scsi {
+            path: self.paths.lock().unwrap().get(&handle).cloned().unwrap_or_default(),
+            cdb: cdb.to_vec(),
+            direction,
+            data: match direction {
+                DataDirection::ToDevice => sent,
+                _ => without_trailing_zeros(data).to_vec(),
+            },
+            data_len: data.len(),
+            sense: sense[..sense_len].to_vec(),
+            result: result.map(|response| ScsiResponse { sense_len, ..response }),
+            elapsed: start_time.elapsed(),
+        });
+        result
+    }
+
+    fn close(&self, handle: DriveHandle) {
+        self.paths.lock().unwrap().remove(&handle);
+        self.inner.close(handle);
+    }
+}
+
+// NvmeTransport that passes everything to `inner` and records it
+pub struct RecordingNvmeTransport {
+    inner: Arc<dyn NvmeTransport>,
+    paths: Mutex<HashMap<DriveHandle, String>>,
+    recorder: Recorder,
+}
+
+impl RecordingNvmeTransport {
+    // records into a capture of its own
+    pub fn new(inner: Arc<dyn NvmeTransport>) -> Self {
+        Recorder::new().nvme_transport(inner)
+    }
+
+    // everything recorded so far, by this transport and the others sharing its recorder
+    pub fn capture(&self) -> Capture {
+        self.recorder.capture()
+    }
+}
+
+impl NvmeTransport for RecordingNvmeTransport {
+    fn open(&self, path: &str) -> Result<DriveHandle, u32> {
+        let start_time = Instant::now();
+        let result = self.inner.open(path);
+        if let Ok(handle) = result {
+            self.paths.lock().unwrap().insert(handle, path.to_string());
+        }
+        self.recorder.push(CapturedEvent::NvmeOpen { path: path.to_string(), result: result.map(|_| ()), elapsed: start_time.elapsed() });
+        result
+    }
+
+    fn admin_command(&self, handle: DriveHandle, command: &NvmeAdminCommand, data: &mut [u8], timeout: Duration) -> Result<u32, u32> {
+        let start_time = Instant::now();
+        let result = self.inner.admin_command(handle, command, data, timeout);
+        self.recorder.push(CapturedEvent::Nvme {
+            path: self.paths.lock().unwrap().get(&handle).cloned().unwrap_or_default(),
+            command: *command,
+            data: without_trailing_zeros(data).to_vec(),
+            data_len: data.len(),
+            result,
+            elapsed: start_time.elapsed(),
+        });
+        result
+    }
+
+    fn close(&self, handle: DriveHandle) {
+        self.paths.lock().unwrap().remove(&handle);
+        self.inner.close(handle);
+    }
+}
+
+fn without_trailing_zeros(bytes: &[u8]) -> &[u8] {
+    &bytes[..bytes.iter().rposition(|&byte| byte != 0).map_or(0, |last| last + 1)]
+}
+
+// the recorded events one replay transport answers from, and the handles it gave out
+//
+// opens are matched by path (and rights), requests by path and their bytes, each recorded
+// event answering once in recorded order. a drive that is not in the capture does not exist
+// (ERROR_FILE_NOT_FOUND), a request that is not in it is not supported
+struct Replay {
+    events: Mutex<Vec<Option<CapturedEvent>>>, // None once replayed
+    paths: Mutex<HashMap<DriveHandle, String>>,
+    next_handle: AtomicUsize,
+}
+
+impl Replay {
+    // keeps the events of one transport, the others replay the rest
+    fn new(capture: Capture, answers: impl Fn(&CapturedEvent) -> bool) -> Self {
+        Replay {
+            events: Mutex::new(capture.events.into_iter().filter(answers).map(Some).collect()),
+            paths: Mutex::new(HashMap::new()),
+            next_handle: AtomicUsize::new(1),
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.events.lock().unwrap().iter().flatten().count()
+    }
+
+    // takes the first unplayed event `matches` accepts
+    fn take(&self, matches: impl Fn(&CapturedEvent) -> bool) -> Option<CapturedEvent> {
+        let mut events = self.events.lock().unwrap();
+        events.iter_mut().find(|event| event.as_ref().is_some_and(&matches))?.take()
+    }
+
+    // a handle for `path` if the recorded open succeeded
+    fn open(&self, path: &str, result: Option<Result<(), u32>>) -> Result<DriveHandle, u32> {
+        match result {
+            Some(Ok(())) => {
+                let handle = DriveHandle(self.next_handle.fetch_add(1, Ordering::SeqCst));
+                self.paths.lock().unwrap().insert(handle, path.to_string());
+                Ok(handle)
+            }
+            Some(Err(code)) => Err(code),
+            None => Err(ERROR_FILE_NOT_FOUND),
+        }
+    }
+
+    fn path(&self, handle: DriveHandle) -> String {
+        self.paths.lock().unwrap().get(&handle).cloned().unwrap_or_default()
+    }
+
+    fn close(&self, handle: DriveHandle) {
+        self.paths.lock().unwrap().remove(&handle);
+    }
+}
+
+// fills `buffer` with what the device left in it, zeros past the recorded bytes
+fn fill(buffer: &mut [u8], recorded: &[u8]) {
+    buffer.fill(0);
+    let len = recorded.len().min(buffer.len());
+    buffer[..len].copy_from_slice(&recorded[..len]);
+}
+
+// DriveTransport that answers the recorded opens and ioctls of a capture instead of a drive
+//
+// opens are matched by path and rights, ioctls by path, control code and input bytes
+pub struct ReplayTransport {
+    replay: Replay,
+}
+
+impl ReplayTransport {
+    pub fn new(capture: Capture) -> Self {
+        ReplayTransport { replay: Replay::new(capture, |event| matches!(event, CapturedEvent::Open { .. } | CapturedEvent::Ioctl { .. })) }
+    }
+
+    // number of recorded opens and ioctls that were not asked for (yet)
+    pub fn remaining(&self) -> usize {
+        self.replay.remaining()
+    }
+}
+
+impl DriveTransport for ReplayTransport {
+    fn open(&self, path: &str, rights: AccessRights) -> Result<DriveHandle, u32> {
+        let recorded = self.replay.take(|event| matches!(event, CapturedEvent::Open { path: p, rights: r, .. } if p == path && *r == rights));
+        let result = match recorded {
+            Some(CapturedEvent::Open { result, .. }) => Some(result),
+            _ => None,
+        };
+        self.replay.open(path, result)
+    }
+
+    fn ioctl(&self, handle: DriveHandle, control_code: u32, input: &[u8], output: &mut [u8], _timeout: Duration) -> Result<usize, u32> {
+        let path = self.replay.path(handle);
+        let recorded = self.replay.take(|event| {
+            matches!(event, CapturedEvent::Ioctl { path: p, control_code: c, input: i, .. } if *p == path && *c == control_code && i == input)
+        });
+        let Some(CapturedEvent::Ioctl { output: recorded_output, result, .. }) = recorded else {
+            debug!(path, control_code, "request not in the capture");
+            return Err(ERROR_NOT_SUPPORTED);
+        };
+        fill(output, &recorded_output);
+        result.map(|returned| returned.min(output.len()))
+    }
+
+    fn close(&self, handle: DriveHandle) {
+        self.replay.close(handle);
+    }
+}
+
+// ScsiTransport that answers the recorded opens and commands of a capture instead of a device
+//
+// commands are matched by path, CDB and direction, and by the bytes sent for commands to the device
+pub struct ReplayScsiTransport {
+    replay: Replay,
+}
+
+impl ReplayScsiTransport {
+    pub fn new(capture: Capture) -> Self {
+        ReplayScsiTransport { replay: Replay::new(capture, |event| matches!(event, CapturedEvent::ScsiOpen { .. } | CapturedEvent::Scsi { .. })) }
+    }
+
+    // number of recorded opens and commands that were not asked for (yet)
+    pub fn remaining(&self) -> usize {
+        self.replay.remaining()
+    }
+}
+
+impl ScsiTransport for ReplayScsiTransport {
+    fn open(&self, path: &str) -> Result<DriveHandle, u32> {
+        let recorded = self.replay.take(|event| matches!(event, CapturedEvent::ScsiOpen { path: p, .. } if p == path));
+        let result = match recorded {
+            Some(CapturedEvent::ScsiOpen { result, .. }) => Some(result),
+            _ => None,
+        };
+        self.replay.open(path, result)
+    }
+
+    fn execute(&self, handle: DriveHandle, cdb: &[u8], direction: DataDirection, data: &mut [u8], sense: &mut [u8], _timeout: Duration) -> Result<ScsiResponse, u32> {
+        let path = self.replay.path(handle);
+        let sent = without_trailing_zeros(data);
+        let recorded = self.replay.take(|event| {
+            matches!(event, CapturedEvent::Scsi { path: p, cdb: c, direction: d, data: recorded, .. }
+                if *p == path && c == cdb && *d == direction && (direction != DataDirection::ToDevice || recorded == sent))
+        });
+        let Some(CapturedEvent::Scsi { data: recorded_data, sense: recorded_sense, result, .. }) = recorded else {
+            debug!(path, operation_code = cdb.first().copied().unwrap_or_default(), "command not in the capture");
+            return Err(ERROR_NOT_SUPPORTED);
+        };
+        if direction == DataDirection::FromDevice {
+            fill(data, &recorded_data);
+        }
+        fill(sense, &recorded_sense);
+        result.map(|response| ScsiResponse {
+            status: response.status,
+            sense_len: response.sense_len.min(sense.len()),
+            transferred: response.transferred.min(data.len()),
+        })
+    }
+
+    fn close(&self, handle: DriveHandle) {
+        self.replay.close(handle);
+    }
+}
+
+// NvmeTransport that answers the recorded opens and admin commands of a capture instead of a controller
+//
+// admin commands are matched by path and every field of the command
+pub struct ReplayNvmeTransport {
+    replay: Replay,
+}
+
+impl ReplayNvmeTransport {
+    pub fn new(capture: Capture) -> Self {
+        ReplayNvmeTransport { replay: Replay::new(capture, |event| matches!(event, CapturedEvent::NvmeOpen { .. } | CapturedEvent::Nvme { .. })) }
+    }
+
+    // number of recorded opens and admin commands that were not asked for (yet)
+    pub fn remaining(&self) -> usize {
+        self.replay.remaining()
+    }
+}
+
+impl NvmeTransport for ReplayNvmeTransport {
+    fn open(&self, path: &str) -> Result<DriveHandle, u32> {
+        let recorded = self.replay.take(|event| matches!(event, CapturedEvent::NvmeOpen { path: p, .. } if p == path));
+        let result = match recorded {
+            Some(CapturedEvent::NvmeOpen { result, .. }) => Some(result),
+            _ => None,
+        };
+        self.replay.open(path, result)
+    }
+
+    fn admin_command(&self, handle: DriveHandle, command: &NvmeAdminCommand, data: &mut [u8], _timeout: Duration) -> Result<u32, u32> {
+        let path = self.replay.path(handle);
+        let recorded = self.replay.take(|event| matches!(event, CapturedEvent::Nvme { path: p, command: c, .. } if *p == path && c == command));
+        let Some(CapturedEvent::Nvme { data: recorded_data, result, .. }) = recorded else {
+            debug!(path, opcode = command.opcode, "admin command not in the capture");
+            return Err(ERROR_NOT_SUPPORTED);
+        };
+        fill(data, &recorded_data);
+        result
+    }
+
+    fn close(&self, handle: DriveHandle) {
+        self.replay.close(handle);
+    }
+}
@@ -0,0 +1,123 @@
+// reconciles the serial numbers different methods report for the same drive
+//
+// methods disagree in predictable ways: some drivers hand back the serial hex-encoded,
+// some byte-swapped, most padded with spaces. candidates that are the same serial under
+// one of those encodings are grouped, and the largest group wins the vote
+
+use crate::ata_string::AtaString;
+use crate::drive_info::Method;
+
+// how strongly the methods agree on the chosen serial
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Agreement {
+    Single,     // only one method produced a serial
+    Unanimous,  // every method produced the same serial
+    Majority,   // more than half agree, the rest conflict
+    Plurality,  // no majority, the largest group (or the earliest method on a tie) won
+}
+
+// outcome of the vote for one drive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Consensus {
+    pub serial: String,                     // canonical serial number
+    pub agreement: Agreement,
+    pub agreeing: Vec<Method>,              // methods whose candidate matches `serial`, in run order
+    pub conflicts: Vec<(Method, String)>,   // methods that reported something else, with what they reported
+}
+
+impl Consensus {
+    pub fn has_conflict(&self) -> bool {
+        !self.conflicts.is_empty()
+    }
+}
+
+// votes on the candidates (in run order) and picks one canonical serial
+pub fn reconcile(candidates: &[(Method, String)]) -> Option<Consensus> {
+    if candidates.is_empty() {
+        return None;
+    }
+    let forms: Vec<Vec<String>> = candidates.iter().map(|(_, serial)| serial_forms(serial)).collect();
+
+    // group candidates that share at least one form (union-find over a handful of entries)
+    let mut group: Vec<usize> = (0..candidates.len()).collect();
+    for i in 0..candidates.len() {
+        for j in 0..i {
+            if forms[i].iter().any(|form| forms[j].contains(form)) {
+                let (from, to) = (group[i], group[j]);
+                group.iter_mut().filter(|g| **g == from).for_each(|g| *g = to);
+            }
+        }
+    }
+
+    // the biggest group wins, ties go to the group holding the earliest method
+    let group_size = |g: usize| group.iter().filter(|&&other| other == g).count();
+    let winner = (0..candidates.len())
+        .map(|i| group[i])
+        .max_by(|&a, &b| group_size(a).cmp(&group_size(b)).then(b.cmp(&a)))?;
+    let members: Vec<usize> = (0..candidates.len()).filter(|&i| group[i] == winner).collect();
+
+    // within the group pick the form most members can be decoded to, then the form
+    // most methods reported as-is (byte-swapping works both ways), then the earliest method's
+    let mut best: Option<(usize, usize, String)> = None;
+    for &i in &members {
+        for form in &forms[i] {
+            let support = members.iter().filter(|&&m| forms[m].contains(form)).count();
+            let reported = members.iter().filter(|&&m| &forms[m][0] == form).count();
+            let better = match &best {
+                None => true,
+                Some((best_support, best_reported, _)) => (support, reported) > (*best_support, *best_reported),
+            };
+            if better {
+                best = Some((support, reported, form.clone()));
+            }
+        }
+    }
+    let (_, _, serial) = best?;
+
+    let agreeing: Vec<Method> = members.iter().map(|&i| candidates[i].0).collect();
+    let conflicts: Vec<(Method, String)> = (0..candidates.len())
+        .filter(|i| group[*i] != winner)
+        .map(|i| candidates[i].clone())
+        .collect();
+    let agreement = if candidates.len() == 1 {
+        Agreement::Single
+    } else if conflicts.is_empty() {
+        Agreement::Unanimous
+    } else if agreeing.len() * 2 > candidates.len() {
+        Agreement::Majority
+    } else {
+        Agreement::Plurality
+    };
+
+    Some(Consensus { serial, agreement, agreeing, conflicts })
+}
+
+// every spelling a raw candidate could stand for: as reported, hex-decoded, and byte-swapped
+// (the first entry is always the trimmed candidate itself)
+fn serial_forms(raw: &str) -> Vec<String> {
+    let mut forms = vec![trim_serial(raw)];
+    if let Some(decoded) = AtaString::from_hex(&forms[0]) {
+        forms.push(decoded.into_string());
+    }
+    for form in forms.clone() {
+        forms.push(AtaString::new(&form).swapped().into_string());
+        // the drive swaps the padded 20 byte field, so an odd amount of padding shifts the pairs
+        let mut shifted = format!(" {}", form).into_bytes();
+        if !shifted.len().is_multiple_of(2) {
+            shifted.push(b' ');
+        }
+        forms.push(AtaString::from_swapped_bytes(&shifted).into_string());
+    }
+
+    let mut unique: Vec<String> = Vec::new();
+    for form in forms {
+        if !form.is_empty() && !unique.contains(&form) {
+            unique.push(form);
+        }
+    }
+    unique
+}
+
+fn trim_serial(raw: &str) -> String {
+    raw.trim_matches(|c: char| c.is_whitespace() || c == '\0').to_string()
+}
@@ -0,0 +1,294 @@
+// offline decoding of raw identify dumps, without any drive access
+//
+// a dump is recognized in two steps. first the file layout:
+//
+//   binary        the raw bytes, anything that is not printable text
+//   hdparm        `hdparm --Istdout`, 16 bit words as 4 hex digits, 8 per line
+//   smartctl      `smartctl -r ioctl,2`, lines like `000-015: 40 00 ff 3f ... |@..?|`, only
+//                 the first DATA START / DATA END block is read when the markers are present
+//   plain hex     bytes as pairs of hex digits, e.g. `xxd -p` output
+//
+// then the structure the bytes hold, by its own fields rather than the file name: a
+// STORAGE_DEVICE_DESCRIPTOR by its version and size, a vpd page 0x80 or 0x83 by its page
+// code and length, 512 bytes as ATA IDENTIFY DEVICE data and 4096 bytes as an NVMe Identify
+// Controller or Identify Namespace structure. the decoded structure yields the same identity
+// the live retrieval methods build from it
+//
+// text layouts may carry comment lines starting with #
+
+use crate::drive_info::DriveIdentity;
+use crate::identify::{IdentifyDevice, IdentifyIntegrityError};
+use crate::ioctl_operations::{identity_from_descriptor, identity_from_identify, identity_from_nvme_controller};
+use crate::nvme::{NvmeIdentifyController, NvmeIdentifyNamespace, NVME_IDENTIFY_DATA_SIZE};
+use crate::scsi::{logical_unit_identifiers, parse_device_identification, parse_unit_serial_number, DesignationDescriptor, VPD_DEVICE_IDENTIFICATION, VPD_UNIT_SERIAL_NUMBER};
+use crate::storage_descriptor::{DescriptorError, StorageDeviceDescriptor, STORAGE_DEVICE_DESCRIPTOR_HEADER_SIZE};
+use crate::structs::{IDENTIFY_BUFFER_SIZE, STORAGE_DEVICE_DESCRIPTOR};
+use byteorder::{ByteOrder, LittleEndian};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// how the bytes of a dump were written down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    Binary,
+    Hdparm,   // hdparm --Istdout words
+    Smartctl, // smartctl -r ioctl hex lines
+    PlainHex,
+}
+
+impl fmt::Display for InputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            InputFormat::Binary => "binary",
+            InputFormat::Hdparm => "hdparm --Istdout words",
+            InputFormat::Smartctl => "smartctl -r ioctl hex",
+            InputFormat::PlainHex => "plain hex",
+        };
+        f.write_str(name)
+    }
+}
+
+// the structure a dump holds, decoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Structure {
+    AtaIdentify(IdentifyDevice),
+    NvmeIdentifyController(NvmeIdentifyController),
+    NvmeIdentifyNamespace(NvmeIdentifyNamespace),
+    StorageDeviceDescriptor(StorageDeviceDescriptor),
+    UnitSerialNumber(String),                         // vpd page 0x80, empty if the page is blank
+    DeviceIdentification(Vec<DesignationDescriptor>), // vpd page 0x83
+}
+
+impl Structure {
+    // the serial, model, firmware, bus and identifiers the live method reading this structure reports
+    //
+    // the serial is empty for structures that do not carry one (a device identification page,
+    // a namespace without identifiers) or carry an unusable one
+    pub fn identity(&self) -> DriveIdentity {
+        match self {
+            Structure::AtaIdentify(identify) => identity_from_identify(identify.clone()),
+            Structure::NvmeIdentifyController(controller) => identity_from_nvme_controller(controller.clone(), Vec::new()),
+            Structure::NvmeIdentifyNamespace(namespace) => identity_from_nvme_controller(NvmeIdentifyController::default(), namespace.identifiers()),
+            Structure::StorageDeviceDescriptor(descriptor) => identity_from_descriptor(descriptor.clone()),
+            Structure::UnitSerialNumber(serial) => DriveIdentity { serial: serial.clone(), ..DriveIdentity::default() },
+            Structure::DeviceIdentification(descriptors) => DriveIdentity { identifiers: logical_unit_identifiers(descriptors), ..DriveIdentity::default() },
+        }
+    }
+}
+
+impl fmt::Display for Structure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Structure::AtaIdentify(_) => "ATA IDENTIFY DEVICE data",
+            Structure::NvmeIdentifyController(_) => "NVMe Identify Controller data",
+            Structure::NvmeIdentifyNamespace(_) => "NVMe Identify Namespace data",
+            Structure::StorageDeviceDescriptor(_) => "STORAGE_DEVICE_DESCRIPTOR",
+            Structure::UnitSerialNumber(_) => "unit serial number vpd page (0x80)",
+            Structure::DeviceIdentification(_) => "device identification vpd page (0x83)",
+        };
+        f.write_str(name)
+    }
+}
+
+// a recognized and decoded dump
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedDump {
+    pub format: InputFormat,
+    pub bytes: Vec<u8>, // the structure as raw bytes, whatever the input format
+    pub structure: Structure,
+}
+
+// why a dump could not be decoded
+#[derive(Debug)]
+pub enum DecodeError {
+    Io(io::Error),
+    UnrecognizedInput,                          // text in none of the known layouts, or no bytes at all
+    Malformed { line: usize, reason: String },  // a line that does not fit the recognized layout, 1 based
+    UnrecognizedStructure { len: usize },       // bytes that match no structure the crate decodes
+    Identify(IdentifyIntegrityError),           // IDENTIFY data a live query would refuse too
+    Descriptor(DescriptorError),
+    InvalidVpdPage,                             // a designation descriptor runs past the page
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Io(error) => write!(f, "failed to read dump: {}", error),
+            DecodeError::UnrecognizedInput => write!(f, "not binary, hdparm --Istdout, smartctl -r ioctl or plain hex data"),
+            DecodeError::Malformed { line, reason } => write!(f, "malformed dump line {}: {}", line, reason),
+            DecodeError::UnrecognizedStructure { len } => write!(f, "{} bytes of data that match no known structure", len),
+            DecodeError::Identify(error) => error.fmt(f),
+            DecodeError::Descriptor(error) => error.fmt(f),
+            DecodeError::InvalidVpdPage => write!(f, "device identification page with a descriptor past its end"),
+        }
+    }
+}
+
+impl Error for DecodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DecodeError::Io(error) => Some(error),
+            DecodeError::Identify(error) => Some(error),
+            DecodeError::Descriptor(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DecodeError {
+    fn from(error: io::Error) -> Self {
+        DecodeError::Io(error)
+    }
+}
+
+// recognizes and decodes the contents of a dump file
+pub fn decode(input: &[u8]) -> Result<DecodedDump, DecodeError> {
+    let (format, bytes) = read_dump(input)?;
+    let structure = decode_structure(&bytes)?;
+    Ok(DecodedDump { format, bytes, structure })
+}
+
+pub fn decode_file(path: impl AsRef<Path>) -> Result<DecodedDump, DecodeError> {
+    decode(&fs::read(path)?)
+}
+
+// the raw bytes of a dump in any of the known layouts
+pub fn read_dump(input: &[u8]) -> Result<(InputFormat, Vec<u8>), DecodeError> {
+    let text = match std::str::from_utf8(input) {
+        Ok(text) if text.chars().all(|c| !c.is_control() || c.is_ascii_whitespace()) => text,
+        _ => return Ok((InputFormat::Binary, input.to_vec())),
+    };
+    let lines: Vec<(usize, &str)> = text
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+
+    let (format, bytes) = if lines.iter().any(|(_, line)| smartctl_offset(line).is_some()) {
+        (InputFormat::Smartctl, read_smartctl(&lines)?)
+    } else if !lines.is_empty() && lines.iter().flat_map(|(_, line)| line.split_whitespace()).all(is_hex_word) {
+        let words = lines.iter().flat_map(|(_, line)| line.split_whitespace());
+        (InputFormat::Hdparm, words.flat_map(|word| u16::from_str_radix(word, 16).unwrap().to_le_bytes()).collect())
+    } else {
+        (InputFormat::PlainHex, read_plain_hex(&lines)?)
+    };
+    if bytes.is_empty() {
+        return Err(DecodeError::UnrecognizedInput);
+    }
+    Ok((format, bytes))
+}
+
+fn is_hex_word(token: &str) -> bool {
+    token.len() == 4 && token.bytes().all(|digit| digit.is_ascii_hexdigit())
+}
+
+// the first offset of a smartctl data line, e.g. 16 for `016-031: ...`
+fn smartctl_offset(line: &str) -> Option<usize> {
+    let (range, _) = line.split_once(':')?;
+    let (first, last) = range.split_once('-')?;
+    if last.is_empty() || !last.bytes().all(|digit| digit.is_ascii_digit()) {
+        return None;
+    }
+    first.parse().ok()
+}
+
+fn read_smartctl(lines: &[(usize, &str)]) -> Result<Vec<u8>, DecodeError> {
+    let markers = lines.iter().any(|(_, line)| line.contains("DATA START"));
+    let mut inside = !markers;
+    let mut bytes = Vec::new();
+    for &(number, line) in lines {
+        if markers && line.contains("DATA START") {
+            inside = true;
+        } else if markers && line.contains("DATA END") {
+            break;
+        } else if let (true, Some(offset)) = (inside, smartctl_offset(line)) {
+            if offset != bytes.len() {
+                return Err(DecodeError::Malformed { line: number, reason: format!("offset {} where {} was expected", offset, bytes.len()) });
+            }
+            let (_, data) = line.split_once(':').unwrap();
+            let data = data.split('|').next().unwrap_or_default(); // drop the ascii column
+            for pair in data.split_whitespace() {
+                if pair.len() != 2 {
+                    return Err(DecodeError::Malformed { line: number, reason: format!("{:?} is not a hex byte", pair) });
+                }
+                bytes.push(u8::from_str_radix(pair, 16).map_err(|_| DecodeError::Malformed { line: number, reason: format!("{:?} is not a hex byte", pair) })?);
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+fn read_plain_hex(lines: &[(usize, &str)]) -> Result<Vec<u8>, DecodeError> {
+    let mut bytes = Vec::new();
+    for &(number, line) in lines {
+        let digits: Vec<u8> = line.bytes().filter(|byte| !byte.is_ascii_whitespace()).collect();
+        if !digits.iter().all(u8::is_ascii_hexdigit) {
+            return Err(DecodeError::UnrecognizedInput);
+        }
+        if !digits.len().is_multiple_of(2) {
+            return Err(DecodeError::Malformed { line: number, reason: "odd number of hex digits".to_string() });
+        }
+        bytes.extend(digits.chunks(2).map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap()));
+    }
+    Ok(bytes)
+}
+
+// recognizes the structure in `bytes` and decodes it
+//
+// IDENTIFY data goes through the same integrity checks as a live query, a blank or corrupted
+// block is an error rather than a plausible wrong serial
+pub fn decode_structure(bytes: &[u8]) -> Result<Structure, DecodeError> {
+    if is_storage_device_descriptor(bytes) {
+        return StorageDeviceDescriptor::parse(bytes).map(Structure::StorageDeviceDescriptor).map_err(DecodeError::Descriptor);
+    }
+    match vpd_page_code(bytes) {
+        Some(VPD_UNIT_SERIAL_NUMBER) => return Ok(Structure::UnitSerialNumber(parse_unit_serial_number(bytes).unwrap_or_default())),
+        Some(VPD_DEVICE_IDENTIFICATION) => return parse_device_identification(bytes).map(Structure::DeviceIdentification).map_err(|_| DecodeError::InvalidVpdPage),
+        _ => {}
+    }
+    match bytes.len() {
+        IDENTIFY_BUFFER_SIZE => {
+            let block: &[u8; IDENTIFY_BUFFER_SIZE] = bytes.try_into().unwrap();
+            IdentifyDevice::parse_checked(block).map(Structure::AtaIdentify).map_err(DecodeError::Identify)
+        }
+        NVME_IDENTIFY_DATA_SIZE => {
+            let data: &[u8; NVME_IDENTIFY_DATA_SIZE] = bytes.try_into().unwrap();
+            if is_nvme_identify_controller(data) {
+                Ok(Structure::NvmeIdentifyController(NvmeIdentifyController::parse(data)))
+            } else {
+                Ok(Structure::NvmeIdentifyNamespace(NvmeIdentifyNamespace::parse(data)))
+            }
+        }
+        len => Err(DecodeError::UnrecognizedStructure { len }),
+    }
+}
+
+// the version field of a descriptor is the size of the structure the driver was built with
+fn is_storage_device_descriptor(bytes: &[u8]) -> bool {
+    bytes.len() >= STORAGE_DEVICE_DESCRIPTOR_HEADER_SIZE
+        && (STORAGE_DEVICE_DESCRIPTOR_HEADER_SIZE..=std::mem::size_of::<STORAGE_DEVICE_DESCRIPTOR>()).contains(&(LittleEndian::read_u32(&bytes[0..4]) as usize))
+        && LittleEndian::read_u32(&bytes[4..8]) as usize <= bytes.len()
+}
+
+// the page code of a vpd page whose length field accounts for every non zero byte
+fn vpd_page_code(bytes: &[u8]) -> Option<u8> {
+    if bytes.len() < 4 || !matches!(bytes[1], VPD_UNIT_SERIAL_NUMBER | VPD_DEVICE_IDENTIFICATION) {
+        return None;
+    }
+    let end = 4 + u16::from_be_bytes([bytes[2], bytes[3]]) as usize;
+    if end > bytes.len() || bytes[end..].iter().any(|&byte| byte != 0) {
+        return None;
+    }
+    Some(bytes[1])
+}
+
+// the serial and model number fields of a controller are printable ascii, the same bytes of
+// a namespace are the low end of its size and capacity
+fn is_nvme_identify_controller(data: &[u8; NVME_IDENTIFY_DATA_SIZE]) -> bool {
+    let text = &data[4..64];
+    text.iter().all(|&byte| (0x20..0x7F).contains(&byte)) && text.iter().any(|&byte| byte != b' ')
+}
@@ -0,0 +1,274 @@
+// structured per-drive results returned by HardDriveSerial
+
+use crate::consensus::{reconcile, Consensus};
+use crate::error::SerialError;
+use std::fmt;
+use std::time::Duration;
+
+// the ways a serial number can be retrieved
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Method {
+    AdminRights, // DFP_RECEIVE_DRIVE_DATA IDENTIFY with a read/write handle
+    Smart,       // SMART_RCV_DRIVE_DATA IDENTIFY
+    ZeroRights,  // IOCTL_STORAGE_QUERY_PROPERTY device descriptor
+    Sysfs,       // linux /sys/block attributes
+    AtaPassThrough, // IDENTIFY DEVICE through a SCSI ATA PASS-THROUGH CDB, e.g. behind a SAS HBA or USB bridge
+    NvmeIdentify,   // NVMe Identify Controller admin command
+    ScsiVpd,        // SCSI INQUIRY with the unit serial number and device identification vpd pages
+}
+
+impl Method {
+    // short tag used in timing labels, e.g. hd0Trd#A
+    pub fn tag(self) -> &'static str {
+        match self {
+            Method::AdminRights => "A",
+            Method::Smart => "SM",
+            Method::ZeroRights => "Z",
+            Method::Sysfs => "SY",
+            Method::AtaPassThrough => "PT",
+            Method::NvmeIdentify => "NV",
+            Method::ScsiVpd => "VPD",
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Method::AdminRights => "admin rights",
+            Method::Smart => "SMART",
+            Method::ZeroRights => "zero rights",
+            Method::Sysfs => "sysfs",
+            Method::AtaPassThrough => "ATA pass-through",
+            Method::NvmeIdentify => "NVMe identify",
+            Method::ScsiVpd => "SCSI VPD",
+        };
+        f.write_str(name)
+    }
+}
+
+// bus a drive is attached through, values follow the windows STORAGE_BUS_TYPE enum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BusType {
+    #[default]
+    Unknown,
+    Scsi,
+    Atapi,
+    Ata,
+    Ieee1394,
+    Ssa,
+    Fibre,
+    Usb,
+    Raid,
+    IScsi,
+    Sas,
+    Sata,
+    Sd,
+    Mmc,
+    Virtual,
+    FileBackedVirtual,
+    Spaces,
+    Nvme,
+    Scm,
+    Ufs,
+}
+
+impl BusType {
+    // converts a raw STORAGE_BUS_TYPE value
+    pub fn from_storage_bus_type(value: u32) -> BusType {
+        match value {
+            0x01 => BusType::Scsi,
+            0x02 => BusType::Atapi,
+            0x03 => BusType::Ata,
+            0x04 => BusType::Ieee1394,
+            0x05 => BusType::Ssa,
+            0x06 => BusType::Fibre,
+            0x07 => BusType::Usb,
+            0x08 => BusType::Raid,
+            0x09 => BusType::IScsi,
+            0x0A => BusType::Sas,
+            0x0B => BusType::Sata,
+            0x0C => BusType::Sd,
+            0x0D => BusType::Mmc,
+            0x0E => BusType::Virtual,
+            0x0F => BusType::FileBackedVirtual,
+            0x10 => BusType::Spaces,
+            0x11 => BusType::Nvme,
+            0x12 => BusType::Scm,
+            0x13 => BusType::Ufs,
+            _ => BusType::Unknown,
+        }
+    }
+}
+
+// a globally unique identifier a drive reports besides its serial number
+//
+// displayed the way linux spells them in /sys/block/*/wwid, e.g. eui.0025385b71b09e83
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DriveIdentifier {
+    Eui64([u8; 8]),      // IEEE extended unique identifier of an NVMe namespace or SCSI logical unit
+    Nguid([u8; 16]),     // NVMe namespace globally unique identifier (or a 16 byte SCSI EUI-64 designator)
+    Uuid([u8; 16]),      // NVMe namespace UUID
+    Naa(Vec<u8>),        // SCSI NAA designator, e.g. the WWN of a SAS or SATA disk
+    T10VendorId(String), // SCSI T10 vendor id designator, vendor padded to 8 characters
+    ScsiName(String),    // SCSI name string, e.g. an iSCSI qualified name
+}
+
+impl DriveIdentifier {
+    // the identifier bytes as lowercase hex without any prefix, None for the text identifiers
+    pub fn hex(&self) -> Option<String> {
+        let bytes: &[u8] = match self {
+            DriveIdentifier::Eui64(bytes) => bytes,
+            DriveIdentifier::Nguid(bytes) | DriveIdentifier::Uuid(bytes) => bytes,
+            DriveIdentifier::Naa(bytes) => bytes,
+            DriveIdentifier::T10VendorId(_) | DriveIdentifier::ScsiName(_) => return None,
+        };
+        Some(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+    }
+
+    // parses the linux wwid spelling produced by Display, None for the ones that name no
+    // worldwide identifier (e.g. the nvme.<vendor>-<serial>-<model> fallback of the kernel)
+    pub fn from_wwid(wwid: &str) -> Option<DriveIdentifier> {
+        let (prefix, value) = wwid.split_once('.')?;
+        let bytes = || -> Option<Vec<u8>> {
+            let digits: Vec<u8> = value.bytes().filter(|&digit| digit != b'-').collect();
+            if digits.is_empty() || !digits.len().is_multiple_of(2) {
+                return None;
+            }
+            digits.chunks(2).map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()).collect()
+        };
+        match prefix {
+            "eui" => {
+                let bytes = bytes()?;
+                match bytes.len() {
+                    8 => Some(DriveIdentifier::Eui64(bytes.try_into().ok()?)),
+                    16 => Some(DriveIdentifier::Nguid(bytes.try_into().ok()?)),
+                    _ => None,
+                }
+            }
+            "uuid" => Some(DriveIdentifier::Uuid(bytes()?.try_into().ok()?)),
+            "naa" if !value.contains('-') => Some(DriveIdentifier::Naa(bytes()?)),
+            "t10" if !value.trim().is_empty() => Some(DriveIdentifier::T10VendorId(value.to_string())),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DriveIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = self.hex().unwrap_or_default();
+        match self {
+            DriveIdentifier::Eui64(_) | DriveIdentifier::Nguid(_) => write!(f, "eui.{}", hex),
+            DriveIdentifier::Uuid(_) => write!(f, "uuid.{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32]),
+            DriveIdentifier::Naa(_) => write!(f, "naa.{}", hex),
+            DriveIdentifier::T10VendorId(text) => write!(f, "t10.{}", text),
+            DriveIdentifier::ScsiName(name) => f.write_str(name),
+        }
+    }
+}
+
+// what one method produced for one drive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodOutcome {
+    pub method: Method,
+    pub result: Result<String, SerialError>, // the serial number, or why the method failed
+    pub duration: Duration,                  // how long the method took
+}
+
+// everything learned about one physical drive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DriveInfo {
+    pub index: usize,                  // drive index, e.g. N in \\.\PhysicalDriveN
+    pub device_path: String,           // path the drive was opened through
+    pub serial: String,                // canonical serial number the methods voted for
+    pub model: Option<String>,
+    pub firmware: Option<String>,
+    pub bus_type: BusType,
+    pub identifiers: Vec<DriveIdentifier>, // EUI-64, NGUID and the like reported by any method, without duplicates
+    pub method: Method,                // first method that agreed with the canonical serial
+    pub consensus: Consensus,          // how strongly the methods agreed, and who disagreed
+    pub outcomes: Vec<MethodOutcome>,  // every method that was tried, in order, with its timing
+}
+
+// identity details a successful method reports alongside the serial, or that a decoded dump carries
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DriveIdentity {
+    pub serial: String,
+    pub model: Option<String>,
+    pub firmware: Option<String>,
+    pub bus_type: BusType,
+    pub identifiers: Vec<DriveIdentifier>,
+}
+
+// collects the outcomes of every method run against a single drive
+#[derive(Debug)]
+pub(crate) struct DriveProbe {
+    index: usize,
+    device_path: String,
+    outcomes: Vec<MethodOutcome>,
+    identities: Vec<(Method, DriveIdentity)>, // from every successful method, in order
+}
+
+impl DriveProbe {
+    pub fn new(index: usize, device_path: String) -> Self {
+        DriveProbe { index, device_path, outcomes: Vec::new(), identities: Vec::new() }
+    }
+
+    pub fn device_path(&self) -> &str {
+        &self.device_path
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    // whether any method produced a serial number yet
+    pub fn has_serial(&self) -> bool {
+        !self.identities.is_empty()
+    }
+
+    pub fn record_success(&mut self, method: Method, identity: DriveIdentity, duration: Duration) {
+        self.outcomes.push(MethodOutcome { method, result: Ok(identity.serial.clone()), duration });
+        self.identities.push((method, identity));
+    }
+
+    pub fn record_failure(&mut self, error: SerialError, duration: Duration) {
+        self.outcomes.push(MethodOutcome { method: error.method(), result: Err(error), duration });
+    }
+
+    // a DriveInfo if at least one method produced a serial number
+    //
+    // the serial is voted on across methods, the other details come from the agreeing
+    // methods first and fall back to any method that reported them
+    pub fn into_drive_info(self) -> Option<DriveInfo> {
+        let candidates: Vec<(Method, String)> = self.identities.iter().map(|(method, identity)| (*method, identity.serial.clone())).collect();
+        let consensus = reconcile(&candidates)?;
+        let method = consensus.agreeing[0];
+
+        let mut ranked: Vec<&DriveIdentity> = Vec::new();
+        ranked.extend(self.identities.iter().filter(|(m, _)| consensus.agreeing.contains(m)).map(|(_, identity)| identity));
+        ranked.extend(self.identities.iter().filter(|(m, _)| !consensus.agreeing.contains(m)).map(|(_, identity)| identity));
+        let model = ranked.iter().find_map(|identity| identity.model.clone());
+        let firmware = ranked.iter().find_map(|identity| identity.firmware.clone());
+        let bus_type = ranked.iter().map(|identity| identity.bus_type).find(|bus_type| *bus_type != BusType::Unknown).unwrap_or_default();
+        let mut identifiers: Vec<DriveIdentifier> = Vec::new();
+        for identifier in ranked.iter().flat_map(|identity| identity.identifiers.iter()) {
+            if !identifiers.contains(identifier) {
+                identifiers.push(identifier.clone());
+            }
+        }
+
+        Some(DriveInfo {
+            index: self.index,
+            device_path: self.device_path,
+            serial: consensus.serial.clone(),
+            model,
+            firmware,
+            bus_type,
+            identifiers,
+            method,
+            consensus,
+            outcomes: self.outcomes,
+        })
+    }
+}
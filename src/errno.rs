@@ -0,0 +1,16 @@
+// errno handling shared by the linux transports
+
+use crate::structs::*;
+use std::io;
+
+// maps errno of the last failed call to the win32 code every transport reports
+pub fn last_error_code() -> u32 {
+    match io::Error::last_os_error().raw_os_error() {
+        Some(libc::EACCES) | Some(libc::EPERM) => ERROR_ACCESS_DENIED,
+        Some(libc::ENOENT) | Some(libc::ENODEV) | Some(libc::ENXIO) => ERROR_FILE_NOT_FOUND,
+        Some(libc::ENOTTY) | Some(libc::EINVAL) | Some(libc::EOPNOTSUPP) => ERROR_NOT_SUPPORTED,
+        Some(libc::ETIMEDOUT) => ERROR_TIMEOUT,
+        Some(libc::EBUSY) => ERROR_NOT_READY,
+        _ => ERROR_GEN_FAILURE,
+    }
+}
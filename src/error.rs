@@ -0,0 +1,102 @@
+// typed errors reported by HardDriveSerial
+
+use crate::drive_info::Method;
+use crate::structs::*;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+// win32 codes only needed to classify errors
+const ERROR_INVALID_FUNCTION: u32 = 1;
+const ERROR_PATH_NOT_FOUND: u32 = 3;
+const ERROR_DEV_NOT_EXIST: u32 = 55;
+const ERROR_SEM_TIMEOUT: u32 = 121;
+const WAIT_TIMEOUT: u32 = 258;
+
+// why a method failed to produce a serial number for a drive
+//
+// `drive` is None for failures that happen before any drive is known, e.g. listing sysfs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SerialError {
+    AccessDenied { drive: Option<usize>, method: Method },                      // needs elevation
+    NotSupported { drive: Option<usize>, method: Method },                      // the method does not work on this drive or driver
+    DeviceNotPresent { drive: Option<usize>, method: Method },                  // nothing at this drive index or path
+    Timeout { drive: Option<usize>, method: Method },                           // the drive did not answer in time
+    InvalidResponse { drive: Option<usize>, method: Method, reason: String },   // the drive answered with unusable data
+    ChecksumMismatch { drive: Option<usize>, method: Method },                  // IDENTIFY data failed its word 255 checksum
+    Os { drive: Option<usize>, method: Method, code: u32 },                     // any other operating system error
+}
+
+impl SerialError {
+    // classifies a win32 error code reported by a transport
+    pub fn from_os_code(drive: Option<usize>, method: Method, code: u32) -> SerialError {
+        match code {
+            ERROR_ACCESS_DENIED => SerialError::AccessDenied { drive, method },
+            ERROR_NOT_SUPPORTED | ERROR_INVALID_FUNCTION => SerialError::NotSupported { drive, method },
+            ERROR_FILE_NOT_FOUND | ERROR_PATH_NOT_FOUND | ERROR_DEV_NOT_EXIST => SerialError::DeviceNotPresent { drive, method },
+            ERROR_TIMEOUT | WAIT_TIMEOUT | ERROR_SEM_TIMEOUT => SerialError::Timeout { drive, method },
+            ERROR_CRC => SerialError::ChecksumMismatch { drive, method },
+            ERROR_INVALID_DATA => SerialError::InvalidResponse { drive, method, reason: "malformed response".to_string() },
+            _ => SerialError::Os { drive, method, code },
+        }
+    }
+
+    // classifies an i/o error, e.g. from reading sysfs
+    pub fn from_io(drive: Option<usize>, method: Method, error: &io::Error) -> SerialError {
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => SerialError::AccessDenied { drive, method },
+            io::ErrorKind::NotFound => SerialError::DeviceNotPresent { drive, method },
+            io::ErrorKind::TimedOut => SerialError::Timeout { drive, method },
+            _ => match error.raw_os_error() {
+                Some(code) => SerialError::Os { drive, method, code: code as u32 },
+                None => SerialError::InvalidResponse { drive, method, reason: error.to_string() },
+            },
+        }
+    }
+
+    // drive index the error belongs to
+    pub fn drive(&self) -> Option<usize> {
+        match self {
+            SerialError::AccessDenied { drive, .. }
+            | SerialError::NotSupported { drive, .. }
+            | SerialError::DeviceNotPresent { drive, .. }
+            | SerialError::Timeout { drive, .. }
+            | SerialError::InvalidResponse { drive, .. }
+            | SerialError::ChecksumMismatch { drive, .. }
+            | SerialError::Os { drive, .. } => *drive,
+        }
+    }
+
+    // method that failed
+    pub fn method(&self) -> Method {
+        match self {
+            SerialError::AccessDenied { method, .. }
+            | SerialError::NotSupported { method, .. }
+            | SerialError::DeviceNotPresent { method, .. }
+            | SerialError::Timeout { method, .. }
+            | SerialError::InvalidResponse { method, .. }
+            | SerialError::ChecksumMismatch { method, .. }
+            | SerialError::Os { method, .. } => *method,
+        }
+    }
+}
+
+impl fmt::Display for SerialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(drive) = self.drive() {
+            write!(f, "Drive {}: ", drive)?;
+        }
+        let method = self.method();
+        match self {
+            SerialError::AccessDenied { .. } => write!(f, "access denied using {}", method),
+            SerialError::NotSupported { .. } => write!(f, "{} method not supported", method),
+            SerialError::DeviceNotPresent { .. } => write!(f, "no device present for {}", method),
+            SerialError::Timeout { .. } => write!(f, "timed out using {}", method),
+            SerialError::InvalidResponse { reason, .. } => write!(f, "invalid response using {}: {}", method, reason),
+            SerialError::ChecksumMismatch { .. } => write!(f, "IDENTIFY checksum mismatch using {}", method),
+            SerialError::Os { code, .. } => write!(f, "failed to get serial number using {}, error code: {}", method, code),
+        }
+    }
+}
+
+impl Error for SerialError {}
@@ -0,0 +1,337 @@
+// typed decoding of the 512 byte ATA IDENTIFY DEVICE block
+//
+// word numbers below follow the ATA/ATAPI command set (ACS) specification
+
+use crate::ata_string::AtaString;
+use std::error::Error;
+use std::fmt;
+
+pub const IDENTIFY_CHECKSUM_SIGNATURE: u8 = 0xA5; // low byte of word 255 when the checksum is present
+const MAX_LOGICAL_SECTOR_SIZE: u32 = 1 << 20; // larger sizes in words 117-118 are garbage, not a real drive
+
+// reasons a raw IDENTIFY block is rejected before decoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdentifyIntegrityError {
+    AllZero,                                        // every byte is 0x00, nothing was transferred
+    AllOnes,                                        // every byte is 0xFF, typically a floating bus
+    ChecksumMismatch { stored: u8, expected: u8 },  // word 255 checksum byte does not match the data
+}
+
+impl fmt::Display for IdentifyIntegrityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdentifyIntegrityError::AllZero => write!(f, "IDENTIFY data is all zero"),
+            IdentifyIntegrityError::AllOnes => write!(f, "IDENTIFY data is all 0xFF"),
+            IdentifyIntegrityError::ChecksumMismatch { stored, expected } => {
+                write!(f, "IDENTIFY checksum mismatch: stored {:#04x}, expected {:#04x}", stored, expected)
+            }
+        }
+    }
+}
+
+impl Error for IdentifyIntegrityError {}
+
+// rejects blocks that cannot be real IDENTIFY data
+//
+// when the low byte of word 255 carries the 0xA5 signature, the high byte is a checksum
+// chosen so that all 512 bytes sum to zero; blocks without the signature predate it and pass
+pub fn verify_identify_block(block: &[u8]) -> Result<(), IdentifyIntegrityError> {
+    if block.iter().all(|&byte| byte == 0x00) {
+        return Err(IdentifyIntegrityError::AllZero);
+    }
+    if block.iter().all(|&byte| byte == 0xFF) {
+        return Err(IdentifyIntegrityError::AllOnes);
+    }
+    if block.len() >= 512 && block[510] == IDENTIFY_CHECKSUM_SIGNATURE {
+        let expected = identify_checksum(block);
+        if block[511] != expected {
+            return Err(IdentifyIntegrityError::ChecksumMismatch { stored: block[511], expected });
+        }
+    }
+    Ok(())
+}
+
+// checksum byte word 255 should carry for the first 511 bytes of `block`
+pub fn identify_checksum(block: &[u8]) -> u8 {
+    let sum = block[..511].iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+    sum.wrapping_neg()
+}
+
+// nominal media rotation rate, word 217
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationRate {
+    NotReported,
+    NonRotating, // solid state device
+    Rpm(u16),
+    Reserved(u16),
+}
+
+// nominal form factor, word 168 bits 3:0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormFactor {
+    NotReported,
+    Inch5_25,
+    Inch3_5,
+    Inch2_5,
+    Inch1_8,
+    SmallerThan1_8,
+    MSata,
+    M2,
+    MicroSsd,
+    CFast,
+    Reserved(u8),
+}
+
+// sata signaling speed generations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SataSpeed {
+    Gen1, // 1.5 Gb/s
+    Gen2, // 3.0 Gb/s
+    Gen3, // 6.0 Gb/s
+}
+
+impl SataSpeed {
+    pub fn gbps(self) -> f32 {
+        match self {
+            SataSpeed::Gen1 => 1.5,
+            SataSpeed::Gen2 => 3.0,
+            SataSpeed::Gen3 => 6.0,
+        }
+    }
+}
+
+// serial ata capabilities and features, words 76-79
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SataCapabilities {
+    pub max_speed: Option<SataSpeed>,     // fastest supported generation, word 76 bits 3:1
+    pub current_speed: Option<SataSpeed>, // negotiated generation, word 77 bits 3:1
+    pub ncq_supported: bool,              // native command queuing, word 76 bit 8
+    pub features_supported: u16,          // raw word 78
+    pub features_enabled: u16,            // raw word 79
+}
+
+// highest ata standard the device claims to conform to, word 80
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AtaMajorVersion {
+    Ata1,
+    Ata2,
+    Ata3,
+    AtaAtapi4,
+    AtaAtapi5,
+    AtaAtapi6,
+    AtaAtapi7,
+    Ata8Acs,
+    Acs2,
+    Acs3,
+    Acs4,
+    Acs5,
+}
+
+impl fmt::Display for AtaMajorVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AtaMajorVersion::Ata1 => "ATA-1",
+            AtaMajorVersion::Ata2 => "ATA-2",
+            AtaMajorVersion::Ata3 => "ATA-3",
+            AtaMajorVersion::AtaAtapi4 => "ATA/ATAPI-4",
+            AtaMajorVersion::AtaAtapi5 => "ATA/ATAPI-5",
+            AtaMajorVersion::AtaAtapi6 => "ATA/ATAPI-6",
+            AtaMajorVersion::AtaAtapi7 => "ATA/ATAPI-7",
+            AtaMajorVersion::Ata8Acs => "ATA8-ACS",
+            AtaMajorVersion::Acs2 => "ACS-2",
+            AtaMajorVersion::Acs3 => "ACS-3",
+            AtaMajorVersion::Acs4 => "ACS-4",
+            AtaMajorVersion::Acs5 => "ACS-5",
+        };
+        f.write_str(name)
+    }
+}
+
+// decoded IDENTIFY DEVICE data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentifyDevice {
+    pub serial: String,                              // words 10-19
+    pub firmware: String,                            // words 23-26
+    pub model: String,                               // words 27-46
+    pub lba28_sectors: u32,                          // words 60-61
+    pub lba48_sectors: Option<u64>,                  // words 100-103, when the 48-bit feature set is supported
+    pub logical_sector_size: u32,                    // bytes, words 106 and 117-118
+    pub physical_sector_size: u32,                   // bytes, word 106
+    pub wwn: Option<u64>,                            // world wide name, words 108-111
+    pub rotation_rate: RotationRate,                 // word 217
+    pub form_factor: FormFactor,                     // word 168
+    pub sata: Option<SataCapabilities>,              // words 76-79, None on parallel ata devices
+    pub trim_supported: bool,                        // DATA SET MANAGEMENT / TRIM, word 169 bit 0
+    pub ata_major_version: Option<AtaMajorVersion>,  // word 80
+}
+
+impl IdentifyDevice {
+    // decodes a raw IDENTIFY DEVICE block, never fails since every field has a "not reported" value
+    pub fn parse(block: &[u8; 512]) -> IdentifyDevice {
+        let words = identify_words(block);
+
+        IdentifyDevice {
+            serial: AtaString::from_words(&words[10..=19]).into_string(),
+            firmware: AtaString::from_words(&words[23..=26]).into_string(),
+            model: AtaString::from_words(&words[27..=46]).into_string(),
+            lba28_sectors: u32::from(words[60]) | (u32::from(words[61]) << 16),
+            lba48_sectors: parse_lba48_sectors(&words),
+            logical_sector_size: parse_logical_sector_size(&words),
+            physical_sector_size: parse_physical_sector_size(&words),
+            wwn: parse_wwn(&words),
+            rotation_rate: parse_rotation_rate(words[217]),
+            form_factor: parse_form_factor(words[168]),
+            sata: parse_sata_capabilities(&words),
+            trim_supported: words[169] & 0x0001 != 0,
+            ata_major_version: parse_ata_major_version(words[80]),
+        }
+    }
+
+    // like parse, but first rejects blank and corrupted blocks
+    pub fn parse_checked(block: &[u8; 512]) -> Result<IdentifyDevice, IdentifyIntegrityError> {
+        verify_identify_block(block)?;
+        Ok(IdentifyDevice::parse(block))
+    }
+
+    // number of user addressable logical sectors, preferring the 48-bit count
+    pub fn sectors(&self) -> u64 {
+        self.lba48_sectors.unwrap_or(u64::from(self.lba28_sectors))
+    }
+
+    // user addressable capacity in bytes, saturating for blocks that report nonsense
+    pub fn capacity_bytes(&self) -> u64 {
+        self.sectors().saturating_mul(u64::from(self.logical_sector_size))
+    }
+}
+
+// splits the block into its 256 little endian words
+pub fn identify_words(block: &[u8; 512]) -> [u16; 256] {
+    let mut words = [0u16; 256];
+    for (word, bytes) in words.iter_mut().zip(block.chunks_exact(2)) {
+        *word = u16::from_le_bytes([bytes[0], bytes[1]]);
+    }
+    words
+}
+
+// a word is only meaningful when bit 14 is set and bit 15 is clear
+fn word_valid(word: u16) -> bool {
+    word & 0xC000 == 0x4000
+}
+
+fn parse_lba48_sectors(words: &[u16; 256]) -> Option<u64> {
+    if !word_valid(words[83]) || words[83] & 0x0400 == 0 {
+        return None;
+    }
+    Some(words[100..=103].iter().rev().fold(0u64, |sectors, &word| (sectors << 16) | u64::from(word)))
+}
+
+fn parse_logical_sector_size(words: &[u16; 256]) -> u32 {
+    let word = words[106];
+    if word_valid(word) && word & 0x1000 != 0 {
+        // words 117-118 hold the logical sector size in words
+        let size_in_words = u32::from(words[117]) | (u32::from(words[118]) << 16);
+        let size = size_in_words.checked_mul(2).filter(|size| (1..=MAX_LOGICAL_SECTOR_SIZE).contains(size));
+        if let Some(size) = size {
+            return size;
+        }
+    }
+    512
+}
+
+fn parse_physical_sector_size(words: &[u16; 256]) -> u32 {
+    let word = words[106];
+    let logical = parse_logical_sector_size(words);
+    if word_valid(word) && word & 0x2000 != 0 {
+        // bits 3:0 are log2 of the number of logical sectors per physical sector
+        return logical.checked_mul(1 << (word & 0x000F)).unwrap_or(logical);
+    }
+    logical
+}
+
+fn parse_wwn(words: &[u16; 256]) -> Option<u64> {
+    let supported = (word_valid(words[84]) && words[84] & 0x0100 != 0) || (word_valid(words[87]) && words[87] & 0x0100 != 0);
+    let wwn = words[108..=111].iter().fold(0u64, |wwn, &word| (wwn << 16) | u64::from(word));
+    if supported && wwn != 0 {
+        Some(wwn)
+    } else {
+        None
+    }
+}
+
+fn parse_rotation_rate(word: u16) -> RotationRate {
+    match word {
+        0x0000 => RotationRate::NotReported,
+        0x0001 => RotationRate::NonRotating,
+        0x0401..=0xFFFE => RotationRate::Rpm(word),
+        _ => RotationRate::Reserved(word),
+    }
+}
+
+fn parse_form_factor(word: u16) -> FormFactor {
+    match word & 0x000F {
+        0 => FormFactor::NotReported,
+        1 => FormFactor::Inch5_25,
+        2 => FormFactor::Inch3_5,
+        3 => FormFactor::Inch2_5,
+        4 => FormFactor::Inch1_8,
+        5 => FormFactor::SmallerThan1_8,
+        6 => FormFactor::MSata,
+        7 => FormFactor::M2,
+        8 => FormFactor::MicroSsd,
+        9 => FormFactor::CFast,
+        other => FormFactor::Reserved(other as u8),
+    }
+}
+
+fn parse_sata_capabilities(words: &[u16; 256]) -> Option<SataCapabilities> {
+    let capabilities = words[76];
+    if capabilities == 0x0000 || capabilities == 0xFFFF {
+        return None; // not a serial ata device
+    }
+
+    let max_speed = if capabilities & 0x0008 != 0 {
+        Some(SataSpeed::Gen3)
+    } else if capabilities & 0x0004 != 0 {
+        Some(SataSpeed::Gen2)
+    } else if capabilities & 0x0002 != 0 {
+        Some(SataSpeed::Gen1)
+    } else {
+        None
+    };
+    let current_speed = match (words[77] >> 1) & 0x0007 {
+        1 => Some(SataSpeed::Gen1),
+        2 => Some(SataSpeed::Gen2),
+        3 => Some(SataSpeed::Gen3),
+        _ => None,
+    };
+
+    Some(SataCapabilities {
+        max_speed,
+        current_speed,
+        ncq_supported: capabilities & 0x0100 != 0,
+        features_supported: words[78],
+        features_enabled: words[79],
+    })
+}
+
+fn parse_ata_major_version(word: u16) -> Option<AtaMajorVersion> {
+    if word == 0x0000 || word == 0xFFFF {
+        return None;
+    }
+    const VERSIONS: [AtaMajorVersion; 12] = [
+        AtaMajorVersion::Ata1,
+        AtaMajorVersion::Ata2,
+        AtaMajorVersion::Ata3,
+        AtaMajorVersion::AtaAtapi4,
+        AtaMajorVersion::AtaAtapi5,
+        AtaMajorVersion::AtaAtapi6,
+        AtaMajorVersion::AtaAtapi7,
+        AtaMajorVersion::Ata8Acs,
+        AtaMajorVersion::Acs2,
+        AtaMajorVersion::Acs3,
+        AtaMajorVersion::Acs4,
+        AtaMajorVersion::Acs5,
+    ];
+    // bit n (1..=12) flags conformance to version n, report the highest one set
+    (1..=VERSIONS.len()).rev().find(|&bit| word & (1 << bit) != 0).map(|bit| VERSIONS[bit - 1])
+}
@@ -1,313 +1,367 @@
 use crate::structs::*;
 use crate::utils::*;
-use std::mem::zeroed;
-use std::ptr::null_mut;
-use std::mem;
-use winapi::{
-    shared::{
-        minwindef::{DWORD, BYTE},
-        ntdef::ULONG,
-    },
-    um::{
-        winioctl::{
-            METHOD_BUFFERED, CTL_CODE, IOCTL_DISK_BASE, FILE_READ_ACCESS, FILE_WRITE_ACCESS,
-            IOCTL_STORAGE_QUERY_PROPERTY, StorageDeviceProperty, PropertyStandardQuery,
-            STORAGE_PROPERTY_QUERY, IOCTL_DISK_GET_DRIVE_GEOMETRY_EX, IOCTL_DISK_GET_DRIVE_GEOMETRY,
-        },
-        winnt::{
-            GENERIC_READ, GENERIC_WRITE, FILE_SHARE_READ, FILE_SHARE_WRITE,
-            HANDLE, FILE_SHARE_DELETE,
-        },
-        fileapi::{CreateFileA, OPEN_EXISTING},
-        ioapiset::DeviceIoControl,
-        errhandlingapi::GetLastError,
-        synchapi::{WaitForSingleObject, CreateEventA},
-        minwinbase::OVERLAPPED,
-        winbase::{INFINITE, FILE_FLAG_OVERLAPPED, WAIT_OBJECT_0},
-    },
-};
-
-use winapi::shared::winerror::ERROR_IO_PENDING;
-
-use lazy_static::lazy_static;
-
-lazy_static! {
-    static ref SMART_GET_VERSION: DWORD = CTL_CODE(IOCTL_DISK_BASE, 0x0020, METHOD_BUFFERED, FILE_READ_ACCESS);                        // control code for getting SMART version
-    static ref SMART_RCV_DRIVE_DATA: DWORD = CTL_CODE(IOCTL_DISK_BASE, 0x0022, METHOD_BUFFERED, FILE_READ_ACCESS | FILE_WRITE_ACCESS); // control code for receiving SMART data
+use crate::identify::{verify_identify_block, IdentifyDevice, IdentifyIntegrityError};
+use crate::transport::{DriveHandle, DriveTransport, NvmeTransport, ScsiTransport};
+use crate::ata_passthrough::{identify_device, smart_read_data, smart_read_thresholds, smart_return_status};
+use crate::smart::{decode_return_status, Health, HealthVerdict, SmartAttributes};
+use crate::nvme::{identify_controller, namespace_identifiers, smart_health_log, NvmeIdentifyController};
+use crate::scsi::{device_identification, logical_unit_identifiers, standard_inquiry, unit_serial_number};
+use crate::storage_descriptor::StorageDeviceDescriptor;
+use crate::ata_string::AtaString;
+use crate::drive_info::{BusType, DriveIdentifier, DriveIdentity};
+use tracing::debug;
+use std::mem::{self, zeroed};
+use std::time::Duration;
+
+// a driver that reports fewer bytes than a whole SENDCMDOUTPARAMS left part of b_buffer unwritten
+fn check_sendcmdout_length(bytes_returned: usize) -> Result<(), DWORD> {
+    if bytes_returned < mem::size_of::<SENDCMDOUTPARAMS>() {
+        debug!(bytes_returned, "short SENDCMDOUTPARAMS, refusing the partial buffer");
+        return Err(ERROR_INVALID_DATA);
+    }
+    Ok(())
 }
 
+// builds the identity reported by the IDENTIFY based methods
+fn identity_from_identify_data(identify_data: &[BYTE; IDENTIFY_BUFFER_SIZE]) -> Result<DriveIdentity, DWORD> {
+    let serial = extract_serial_from_identify_data(identify_data).map_err(|error| identify_error_code(&error))?; // extract the serial from the identify data
+    let identify = IdentifyDevice::parse(identify_data); // decode model, firmware and transport details
+    Ok(DriveIdentity { serial, ..identity_from_identify(identify) })
+}
 
-pub fn create_file_with_admin_rights(drive_name: &str) -> HANDLE {
-    unsafe {
-        CreateFileA(
-            drive_name.as_ptr() as *const i8,      // drive name to open
-            GENERIC_READ | GENERIC_WRITE,     // desired access (read and write)
-            FILE_SHARE_READ | FILE_SHARE_WRITE,   // share mode (read and write)
-            null_mut(),                  // security attributes (none)
-            OPEN_EXISTING,              // open only if it exists
-            FILE_FLAG_OVERLAPPED,        // overlapped operation flag
-            null_mut(),                         // template file (none)
-        )
+// the identity details of decoded IDENTIFY data, the serial as decoded without any checks
+pub(crate) fn identity_from_identify(identify: IdentifyDevice) -> DriveIdentity {
+    DriveIdentity {
+        serial: identify.serial,
+        model: non_empty(identify.model),
+        firmware: non_empty(identify.firmware),
+        bus_type: if identify.sata.is_some() { BusType::Sata } else { BusType::Ata },
+        ..DriveIdentity::default()
     }
 }
 
-pub fn create_file_with_smart_rights(drive_name: &str) -> HANDLE {
-    unsafe {
-        CreateFileA(
-            drive_name.as_ptr() as *const i8,                        // drive name
-            GENERIC_READ | GENERIC_WRITE,                       // read and write access
-            FILE_SHARE_DELETE | FILE_SHARE_READ | FILE_SHARE_WRITE, // sharing permissions
-            null_mut(),                                    // no security attributes
-            OPEN_EXISTING,                                // open only existing
-            FILE_FLAG_OVERLAPPED,                          // overlapped i/o
-            null_mut(),                                           // no template file
-        )
+// the identity in a STORAGE_DEVICE_DESCRIPTOR, with an empty serial if the descriptor has no usable one
+pub(crate) fn identity_from_descriptor(descriptor: StorageDeviceDescriptor) -> DriveIdentity {
+    let serial_number = descriptor.serial.unwrap_or_default();
+    let serial_number = AtaString::from_hex(&serial_number).map_or(serial_number, AtaString::into_string); // some drivers report the serial hex encoded
+    let valid = serial_number.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == ' '); // check if serial number contains only valid characters
+    let model = match (descriptor.vendor, descriptor.product) {
+        (Some(vendor), Some(product)) => Some(format!("{} {}", vendor, product)),
+        (vendor, product) => product.or(vendor),
+    };
+    DriveIdentity {
+        serial: if valid { serial_number } else { String::new() },
+        model,
+        firmware: descriptor.revision,
+        bus_type: descriptor.bus_type,
+        ..DriveIdentity::default()
     }
 }
 
-pub fn create_file_with_zero_rights(drive_name: &str) -> HANDLE {
-    unsafe {
-        CreateFileA(
-            drive_name.as_ptr() as *const i8,    // name of the drive
-            0,                              // no access rights
-            FILE_SHARE_READ | FILE_SHARE_WRITE, // share read and write
-            null_mut(),                // default security
-            OPEN_EXISTING,            // open only if it exists
-            FILE_FLAG_OVERLAPPED,      // use overlapped i/o
-            null_mut(),                       // no template file
-        )
+// the identity of an NVMe controller, a blank serial falls back to the first namespace identifier
+pub(crate) fn identity_from_nvme_controller(controller: NvmeIdentifyController, identifiers: Vec<DriveIdentifier>) -> DriveIdentity {
+    let serial = match (controller.serial.is_empty(), identifiers.first().and_then(DriveIdentifier::hex)) {
+        (true, Some(hex)) => hex,
+        _ => controller.serial,
+    };
+    DriveIdentity {
+        serial,
+        model: non_empty(controller.model),
+        firmware: non_empty(controller.firmware),
+        bus_type: BusType::Nvme,
+        identifiers,
     }
 }
 
-pub fn get_drive_serial_with_admin_rights(h_physical_drive_ioctl: HANDLE, b_drive_num: BYTE) -> Option<String> {
-    let mut version_params: GETVERSIONOUTPARAMS = unsafe { zeroed() };                   // initialize the struct with zeros
-    let mut dw_bytes_returned: DWORD = 0;                                                // initialize the number of bytes returned
-    let mut overlapped: OVERLAPPED = unsafe { zeroed() };                                // initialize the overlapped struct with zeros
-    overlapped.hEvent = unsafe { 
-        CreateEventA(
-            null_mut(), 1, 0, null_mut()    // create an event for overlapped operation
-        ) 
-    };
+fn non_empty(text: String) -> Option<String> {
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
 
-    let result = unsafe {
-        DeviceIoControl(
-            h_physical_drive_ioctl,                                           // handle to the drive
-            DFP_GET_VERSION,                                          // control code for getting the version
-            null_mut(),                                                    // no input buffer
-            0,                                                          // size of input buffer
-            &mut version_params as *mut _ as *mut winapi::ctypes::c_void, // output buffer
-            mem::size_of::<GETVERSIONOUTPARAMS>() as DWORD,            // size of output buffer
-            &mut dw_bytes_returned,                                   // number of bytes returned
-            &mut overlapped,                                             // overlapped structure
-        )
-    };
+pub fn get_drive_serial_with_admin_rights(transport: &dyn DriveTransport, h_physical_drive_ioctl: DriveHandle, timeout: Duration, b_drive_num: BYTE) -> Result<DriveIdentity, DWORD> {
+    let mut version_params: GETVERSIONOUTPARAMS = unsafe { zeroed() }; // initialize the struct with zeros
 
-    if result == 0 {
-        let error = unsafe { GetLastError() };
-        if error == ERROR_IO_PENDING {
-            if unsafe { WaitForSingleObject(overlapped.hEvent, INFINITE) } != WAIT_OBJECT_0 { // wait for the overlapped operation to complete
-                return None;
-            }
-        } else {
-            return None;
-        }
-    }
+    transport.ioctl(
+        h_physical_drive_ioctl,                                // handle to the drive
+        DFP_GET_VERSION,                                       // control code for getting the version
+        &[],                                                   // no input buffer
+        unsafe { struct_as_bytes_mut(&mut version_params) },   // output buffer
+        timeout,                                               // give up on the request after this long
+    )?;
 
-    if version_params.b_ide_device_map <= 0 {
-        return None;
+    if version_params.b_ide_device_map == 0 {
+        return Err(ERROR_NOT_SUPPORTED); // no IDE devices behind this driver
     }
 
     let mut scip: SENDCMDINPARAMS = unsafe { zeroed() }; // input parameters for sending a command
     let mut by_id_out_cmd: SENDCMDOUTPARAMS = unsafe { zeroed() }; // output parameters for sending a command
 
-    if do_identify(
-        h_physical_drive_ioctl,    // handle to the drive
-        &mut scip,          // input parameters
-        &mut by_id_out_cmd, // output parameters
-        IDE_ATA_IDENTIFY, // command to identify the drive
-        b_drive_num,                // drive number
-        &mut dw_bytes_returned, // number of bytes returned
-    ) {
-        return match extract_serial_from_identify_data(&by_id_out_cmd.b_buffer) { // extract the serial from the identify data
-            Ok(serial) => Some(serial),
-            Err(_) => None,
-        };
-    }
+    let bytes_returned = do_identify(
+        transport,              // transport to the drive
+        h_physical_drive_ioctl, // handle to the drive
+        &mut scip,              // input parameters
+        &mut by_id_out_cmd,     // output parameters
+        IDE_ATA_IDENTIFY,       // command to identify the drive
+        b_drive_num,            // drive number
+        timeout,                // give up on the request after this long
+    )?;
+    check_sendcmdout_length(bytes_returned)?;
+
+    identity_from_identify_data(&by_id_out_cmd.b_buffer)
+}
 
-    None
+pub fn get_drive_serial_with_smart(transport: &dyn DriveTransport, h_physical_drive_ioctl: DriveHandle, timeout: Duration, b_drive_num: BYTE) -> Result<DriveIdentity, DWORD> {
+    let identify_data = read_identify_block_with_smart(transport, h_physical_drive_ioctl, timeout, b_drive_num)?;
+    identity_from_identify_data(&identify_data)
 }
 
-pub fn get_drive_serial_with_smart(h_physical_drive_ioctl: HANDLE, _b_drive_num: BYTE) -> Option<String> {
+// reads the raw IDENTIFY DEVICE block through SMART_RCV_DRIVE_DATA
+pub fn read_identify_block_with_smart(transport: &dyn DriveTransport, h_physical_drive_ioctl: DriveHandle, timeout: Duration, _b_drive_num: BYTE) -> Result<[BYTE; IDENTIFY_BUFFER_SIZE], DWORD> {
     let mut get_version_params: GETVERSIONINPARAMS = unsafe { zeroed() }; // initialize with zeros
-    let mut dw_bytes_returned: DWORD = 0; // initialize bytes returned
-    let mut overlapped: OVERLAPPED = unsafe { zeroed() }; // initialize overlapped struct
-    overlapped.hEvent = unsafe { CreateEventA(null_mut(), 1, 0, null_mut()) }; // create an event
-
-    let result = unsafe {
-        DeviceIoControl(
-            h_physical_drive_ioctl, // handle to drive
-            *SMART_GET_VERSION, // control code for SMART get version
-            null_mut(), // no input data
-            0, // input data size
-            &mut get_version_params as *mut _ as *mut winapi::ctypes::c_void, // output buffer
-            mem::size_of::<GETVERSIONINPARAMS>() as DWORD, // output buffer size
-            &mut dw_bytes_returned, // bytes returned
-            &mut overlapped, // overlapped struct
-        )
-    };
 
-    if result == 0 {
-        let error = unsafe { GetLastError() }; // get last error if operation failed
-        if error == ERROR_IO_PENDING {
-            if unsafe { WaitForSingleObject(overlapped.hEvent, INFINITE) } != WAIT_OBJECT_0 { // wait for overlapped operation to complete
-                return None;
-            }
-        } else {
-            return None; // return none if operation failed
-        }
-    }
+    transport.ioctl(
+        h_physical_drive_ioctl,                                    // handle to drive
+        SMART_GET_VERSION,                                         // control code for SMART get version
+        &[],                                                       // no input data
+        unsafe { struct_as_bytes_mut(&mut get_version_params) },   // output buffer
+        timeout,                                                   // give up on the request after this long
+    )?;
 
-    let command_size: ULONG =
-        (mem::size_of::<SENDCMDINPARAMS>() + IDENTIFY_BUFFER_SIZE) as ULONG; // calculate command size
     let mut command: SENDCMDINPARAMS = unsafe { zeroed() }; // initialize command struct with zeros
     command.ir_drive_regs.b_command_reg = IDE_ATA_IDENTIFY; // set the command to identify
+    let mut response: SENDCMDOUTPARAMS = unsafe { zeroed() }; // the driver answers with a SENDCMDOUTPARAMS
+
+    let bytes_returned = transport.ioctl(
+        h_physical_drive_ioctl,                          // handle to the drive
+        SMART_RCV_DRIVE_DATA,                            // control code for receiving SMART data
+        unsafe { struct_as_bytes(&command) },            // input buffer (command)
+        unsafe { struct_as_bytes_mut(&mut response) },   // output buffer (response)
+        timeout,                                         // give up on the request after this long
+    )?;
+    check_sendcmdout_length(bytes_returned)?;
+
+    check_identify_block(&response.b_buffer)?; // check the word 255 signature and checksum before trusting anything in the buffer
+    Ok(response.b_buffer)
+}
 
-    let result = unsafe {
-        DeviceIoControl(
-            h_physical_drive_ioctl, // handle to the drive
-            *SMART_RCV_DRIVE_DATA, // control code for receiving SMART data
-            &mut command as *mut _ as *mut winapi::ctypes::c_void, // input buffer (command)
-            mem::size_of::<SENDCMDINPARAMS>() as DWORD, // size of input buffer
-            &mut command as *mut _ as *mut winapi::ctypes::c_void, // output buffer (command)
-            command_size as DWORD, // size of output buffer
-            &mut dw_bytes_returned, // bytes returned
-            &mut overlapped, // overlapped struct
-        )
-    };
+// reads the raw 512 byte IDENTIFY block through an ATA PASS-THROUGH CDB, with the same checks
+pub fn read_identify_block_with_ata_pass_through(transport: &dyn ScsiTransport, h_device: DriveHandle, timeout: Duration) -> Result<[BYTE; IDENTIFY_BUFFER_SIZE], DWORD> {
+    let identify_data = identify_device(transport, h_device, timeout)?;
+    check_identify_block(&identify_data)?;
+    Ok(identify_data)
+}
 
-    if result == 0 {
-        let error = unsafe { GetLastError() }; // check for error
-        if error == ERROR_IO_PENDING { // check if pending
-            if unsafe { WaitForSingleObject(overlapped.hEvent, INFINITE) } != WAIT_OBJECT_0 { // wait if pending
-                return None; // return None if wait fails
-            }
-        } else {
-            return None; // return None if other errors occur
+// refuses blank, floating bus and corrupted IDENTIFY blocks, a checksum mismatch is ERROR_CRC
+fn check_identify_block(identify_data: &[BYTE; IDENTIFY_BUFFER_SIZE]) -> Result<(), DWORD> {
+    match verify_identify_block(identify_data) {
+        Ok(()) => Ok(()),
+        Err(IdentifyIntegrityError::ChecksumMismatch { .. }) => Err(ERROR_CRC),
+        Err(error) => {
+            debug!(%error, "refusing the IDENTIFY block");
+            Err(ERROR_INVALID_DATA)
         }
-    } 
+    }
+}
 
-    return match extract_serial_from_identify_data(&command.c_buffer) { // try extracting serial from identify data
-        Ok(serial) => Some(serial),
-        Err(_) => None,
-    };
+// reads a 512 byte SMART block (READ_ATTRIBUTES or READ_THRESHOLDS) through SMART_RCV_DRIVE_DATA
+pub fn read_smart_block(transport: &dyn DriveTransport, h_physical_drive_ioctl: DriveHandle, b_feature: BYTE, timeout: Duration, b_drive_num: BYTE) -> Result<[BYTE; IDENTIFY_BUFFER_SIZE], DWORD> {
+    let mut command: SENDCMDINPARAMS = unsafe { zeroed() }; // initialize command struct with zeros
+    command.c_buffer_size = IDENTIFY_BUFFER_SIZE as ULONG;
+    command.ir_drive_regs.b_features_reg = b_feature; // which SMART block to read
+    command.ir_drive_regs.b_sector_count_reg = 1;
+    command.ir_drive_regs.b_sector_number_reg = 1;
+    command.ir_drive_regs.b_cyl_low_reg = SMART_CYL_LOW;
+    command.ir_drive_regs.b_cyl_high_reg = SMART_CYL_HI;
+    command.ir_drive_regs.b_drive_head_reg = 0xA0 | ((b_drive_num & 1) << 4);
+    command.ir_drive_regs.b_command_reg = SMART_CMD;
+    command.b_drive_number = b_drive_num;
+    let mut response: SENDCMDOUTPARAMS = unsafe { zeroed() }; // the driver answers with a SENDCMDOUTPARAMS
+
+    let bytes_returned = transport.ioctl(
+        h_physical_drive_ioctl,                          // handle to the drive
+        SMART_RCV_DRIVE_DATA,                            // control code for receiving SMART data
+        unsafe { struct_as_bytes(&command) },            // input buffer (command)
+        unsafe { struct_as_bytes_mut(&mut response) },   // output buffer (response)
+        timeout,                                         // give up on the request after this long
+    )?;
+    check_sendcmdout_length(bytes_returned)?;
+
+    Ok(response.b_buffer)
 }
 
-pub fn get_drive_serial_with_zero_rights(h_physical_drive_ioctl: HANDLE) -> Option<String> {
-    let mut dw_bytes_returned: DWORD = 0; // initialize bytes returned to 0
-    let mut overlapped: OVERLAPPED = unsafe { zeroed() }; // zero the overlapped structure
-    overlapped.hEvent = unsafe { CreateEventA(null_mut(), 1, 0, null_mut()) }; // create event for overlapped operation
+// reads the SMART attribute table and, if the drive supports them, the thresholds
+pub fn get_smart_attributes_with_smart(transport: &dyn DriveTransport, h_physical_drive_ioctl: DriveHandle, timeout: Duration, b_drive_num: BYTE) -> Result<SmartAttributes, DWORD> {
+    let smart_data = read_smart_block(transport, h_physical_drive_ioctl, READ_ATTRIBUTES, timeout, b_drive_num)?;
+    let thresholds = optional_thresholds(read_smart_block(transport, h_physical_drive_ioctl, READ_THRESHOLDS, timeout, b_drive_num))?;
+    Ok(SmartAttributes::parse(&smart_data, thresholds.as_ref()))
+}
 
-    // First try to get serial using STORAGE_PROPERTY_QUERY
-    let mut query: STORAGE_PROPERTY_QUERY = unsafe { zeroed() }; // initialize the query structure
-    query.PropertyId = StorageDeviceProperty; // we want the device property
-    query.QueryType = PropertyStandardQuery; // we'll do a standard query
+// pass-through counterpart of get_smart_attributes_with_smart
+pub fn get_smart_attributes_with_ata_pass_through(transport: &dyn ScsiTransport, h_device: DriveHandle, timeout: Duration) -> Result<SmartAttributes, DWORD> {
+    let smart_data = smart_read_data(transport, h_device, timeout)?;
+    let thresholds = optional_thresholds(smart_read_thresholds(transport, h_device, timeout))?;
+    Ok(SmartAttributes::parse(&smart_data, thresholds.as_ref()))
+}
 
-    let mut buffer: [BYTE; 10000] = [0; 10000]; // buffer to receive the query results
+// READ ATTRIBUTE THRESHOLDS is obsolete since ATA-8, a drive that aborts it just has none
+fn optional_thresholds(result: Result<[BYTE; IDENTIFY_BUFFER_SIZE], DWORD>) -> Result<Option<[BYTE; IDENTIFY_BUFFER_SIZE]>, DWORD> {
+    match result {
+        Ok(thresholds) => Ok(Some(thresholds)),
+        Err(ERROR_NOT_SUPPORTED) => Ok(None),
+        Err(error_code) => Err(error_code),
+    }
+}
 
-    let result = unsafe {
-        DeviceIoControl(
-            h_physical_drive_ioctl, // handle to the drive
-            IOCTL_STORAGE_QUERY_PROPERTY, // control code to query properties
-            &mut query as *mut _ as *mut winapi::ctypes::c_void, // input buffer (our query)
-            mem::size_of::<STORAGE_PROPERTY_QUERY>() as DWORD, // size of input buffer
-            &mut buffer as *mut _ as *mut winapi::ctypes::c_void, // output buffer
-            mem::size_of_val(&buffer) as DWORD, // size of output buffer
-            &mut dw_bytes_returned, // number of bytes returned
-            &mut overlapped, // overlapped struct
-        )
-    };
+// sends SMART RETURN STATUS through SMART_SEND_DRIVE_COMMAND and decodes the verdict
+pub fn get_health_with_smart(transport: &dyn DriveTransport, h_physical_drive_ioctl: DriveHandle, timeout: Duration, b_drive_num: BYTE) -> Result<Health, DWORD> {
+    let mut command: SENDCMDINPARAMS = unsafe { zeroed() }; // initialize command struct with zeros
+    command.ir_drive_regs.b_features_reg = RETURN_SMART_STATUS;
+    command.ir_drive_regs.b_sector_count_reg = 1;
+    command.ir_drive_regs.b_sector_number_reg = 1;
+    command.ir_drive_regs.b_cyl_low_reg = SMART_CYL_LOW;
+    command.ir_drive_regs.b_cyl_high_reg = SMART_CYL_HI;
+    command.ir_drive_regs.b_drive_head_reg = 0xA0 | ((b_drive_num & 1) << 4);
+    command.ir_drive_regs.b_command_reg = SMART_CMD;
+    command.b_drive_number = b_drive_num;
+    let mut response: SENDCMDOUTPARAMS = unsafe { zeroed() }; // the driver copies the returned IDEREGS into b_buffer
+
+    transport.ioctl(
+        h_physical_drive_ioctl,                          // handle to the drive
+        SMART_SEND_DRIVE_COMMAND,                        // control code for non-data SMART commands
+        unsafe { struct_as_bytes(&command) },            // input buffer (command)
+        unsafe { struct_as_bytes_mut(&mut response) },   // output buffer (response)
+        timeout,                                         // give up on the request after this long
+    )?;
+
+    // b_buffer holds an IDEREGS, cylinder low and high are its 4th and 5th bytes
+    let verdict = decode_return_status(response.b_buffer[3], response.b_buffer[4]).ok_or(ERROR_INVALID_DATA)?;
+    Ok(Health { verdict, critical_warning: None })
+}
 
-    if result != 0  || unsafe{ GetLastError() } == ERROR_IO_PENDING {
-        if result == 0 {
-            if unsafe { WaitForSingleObject(overlapped.hEvent, INFINITE) } != WAIT_OBJECT_0 { // wait for operation to complete
-                return None;
-            }
-        }
+// pass-through counterpart of get_health_with_smart
+pub fn get_health_with_ata_pass_through(transport: &dyn ScsiTransport, h_device: DriveHandle, timeout: Duration) -> Result<Health, DWORD> {
+    let (lba_mid, lba_high) = smart_return_status(transport, h_device, timeout)?;
+    let verdict = decode_return_status(lba_mid, lba_high).ok_or(ERROR_INVALID_DATA)?;
+    Ok(Health { verdict, critical_warning: None })
+}
 
-        let descrip: *const STORAGE_DEVICE_DESCRIPTOR = buffer.as_ptr() as *const STORAGE_DEVICE_DESCRIPTOR; // cast buffer to descriptor pointer
-        let mut serial_buffer: [BYTE; 1000] = [0; 1000]; // create buffer for serial number
+// maps the critical warning byte of the SMART / Health log to the same verdict, any bit set fails
+pub fn get_health_with_nvme(transport: &dyn NvmeTransport, h_controller: DriveHandle, timeout: Duration) -> Result<Health, DWORD> {
+    let critical_warning = smart_health_log(transport, h_controller, timeout)?.critical_warning;
+    let verdict = if critical_warning == 0 { HealthVerdict::Passed } else { HealthVerdict::Failing };
+    Ok(Health { verdict, critical_warning: Some(critical_warning) })
+}
 
-        flip_and_code_bytes(
-            unsafe { (*descrip).serial_number_offset } as usize, // get offset of serial number
-            0, // no flipping needed
-            &buffer, // buffer with the query results
-            &mut serial_buffer, // buffer for serial number
-        );
+pub fn get_drive_serial_with_zero_rights(transport: &dyn DriveTransport, h_physical_drive_ioctl: DriveHandle, timeout: Duration) -> Result<DriveIdentity, DWORD> {
+    // First try to get serial using STORAGE_PROPERTY_QUERY
+    let mut query: STORAGE_PROPERTY_QUERY = unsafe { zeroed() }; // initialize the query structure
+    query.PropertyId = STORAGE_DEVICE_PROPERTY; // we want the device property
+    query.QueryType = PROPERTY_STANDARD_QUERY; // we'll do a standard query
 
-        let serial_number = String::from_utf8_lossy(&serial_buffer) // create a string from the serial buffer
-            .trim_end_matches(char::from(0))
-            .to_string();
+    let mut buffer: [BYTE; 10000] = [0; 10000]; // buffer to receive the query results
 
-        if serial_number.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') { // check if serial number contains only valid characters
-            return Some(serial_number); // return the serial number if valid
-        }
-    }
+    let result = transport.ioctl(
+        h_physical_drive_ioctl,               // handle to the drive
+        IOCTL_STORAGE_QUERY_PROPERTY,         // control code to query properties
+        unsafe { struct_as_bytes(&query) },   // input buffer (our query)
+        &mut buffer,                          // output buffer
+        timeout,                              // give up on the request after this long
+    );
 
-    // If STORAGE_PROPERTY_QUERY fails, try using IOCTL_DISK_GET_DRIVE_GEOMETRY_EX
-    let mut geometry_ex: DISK_GEOMETRY_EX = unsafe { zeroed() }; // initialize the geometry structure
-    let result = unsafe {
-        DeviceIoControl(
-            h_physical_drive_ioctl, // handle to the drive
-            IOCTL_DISK_GET_DRIVE_GEOMETRY_EX, // control code to get geometry
-            null_mut(), // no input data
-            0, // input data size
-            &mut geometry_ex as *mut _ as *mut winapi::ctypes::c_void, // output buffer
-            mem::size_of::<DISK_GEOMETRY_EX>() as DWORD, // size of output buffer
-            &mut dw_bytes_returned, // number of bytes returned
-            &mut overlapped, // overlapped structure
-        )
-    };
+    if result == Err(ERROR_TIMEOUT) {
+        return Err(ERROR_TIMEOUT); // a hung drive will not answer the geometry queries either
+    }
 
-    if result == 0 {
-        let error = unsafe { GetLastError() }; // get last error
-        if error == ERROR_IO_PENDING {
-            if unsafe { WaitForSingleObject(overlapped.hEvent, INFINITE) } != WAIT_OBJECT_0 { // wait for operation to finish
-                return None;
+    if let Ok(returned) = result {
+        match StorageDeviceDescriptor::parse(&buffer[..returned.min(buffer.len())]) { // validate size and offsets before reading any string
+            Ok(descriptor) => {
+                let identity = identity_from_descriptor(descriptor);
+                if !identity.serial.is_empty() {
+                    return Ok(identity); // return the serial number if valid
+                }
             }
-        } else {
-            return None; // return None if other errors occured
+            Err(error) => debug!(%error, "ignoring malformed storage device descriptor"),
         }
     }
 
+    // If STORAGE_PROPERTY_QUERY fails, try using IOCTL_DISK_GET_DRIVE_GEOMETRY_EX
+    let mut geometry_ex: DISK_GEOMETRY_EX = unsafe { zeroed() }; // initialize the geometry structure
+    transport.ioctl(
+        h_physical_drive_ioctl,                             // handle to the drive
+        IOCTL_DISK_GET_DRIVE_GEOMETRY_EX,                   // control code to get geometry
+        &[],                                                // no input data
+        unsafe { struct_as_bytes_mut(&mut geometry_ex) },   // output buffer
+        timeout,                                            // give up on the request after this long
+    )?;
+
     // If IOCTL_DISK_GET_DRIVE_GEOMETRY_EX fails, try using IOCTL_DISK_GET_DRIVE_GEOMETRY
     let mut geometry: DISK_GEOMETRY = unsafe { zeroed() }; // initialize the geometry struct
-    let result = unsafe {
-        DeviceIoControl(
-            h_physical_drive_ioctl, // handle to drive
-            IOCTL_DISK_GET_DRIVE_GEOMETRY, // ioctl to get drive geometry
-            null_mut(), // no input data
-            0, // no input data size
-            &mut geometry as *mut _ as *mut winapi::ctypes::c_void, // output buffer
-            mem::size_of::<DISK_GEOMETRY>() as DWORD, // output buffer size
-            &mut dw_bytes_returned, // bytes returned
-            &mut overlapped, // overlapped struct
-        )
+    transport.ioctl(
+        h_physical_drive_ioctl,                          // handle to drive
+        IOCTL_DISK_GET_DRIVE_GEOMETRY,                   // ioctl to get drive geometry
+        &[],                                             // no input data
+        unsafe { struct_as_bytes_mut(&mut geometry) },   // output buffer
+        timeout,                                         // give up on the request after this long
+    )?;
+
+    Err(ERROR_NOT_SUPPORTED) // geometry never carries a serial number
+}
+
+pub fn get_drive_serial_with_ata_pass_through(transport: &dyn ScsiTransport, h_device: DriveHandle, timeout: Duration) -> Result<DriveIdentity, DWORD> {
+    let identify_data = identify_device(transport, h_device, timeout)?; // IDENTIFY DEVICE through an ATA PASS-THROUGH CDB
+    identity_from_identify_data(&identify_data)
+}
+
+// reads the identity of a SCSI logical unit: vendor, product and revision from the standard
+// INQUIRY data, the serial from vpd page 0x80 and the worldwide identifiers from page 0x83
+//
+// the device identification page is optional, a device without a unit serial number page is
+// reported as ERROR_NOT_SUPPORTED
+pub fn get_drive_serial_with_scsi_vpd(transport: &dyn ScsiTransport, h_device: DriveHandle, timeout: Duration) -> Result<DriveIdentity, DWORD> {
+    let inquiry = standard_inquiry(transport, h_device, timeout)?; // standard INQUIRY data
+    let serial = unit_serial_number(transport, h_device, timeout)?.ok_or(ERROR_NOT_SUPPORTED)?; // vpd page 0x80
+    let identifiers = match device_identification(transport, h_device, timeout) {
+        Ok(descriptors) => logical_unit_identifiers(&descriptors),
+        Err(ERROR_TIMEOUT) => return Err(ERROR_TIMEOUT),
+        Err(error_code) => {
+            debug!(error_code, "device identification page not available");
+            Vec::new()
+        }
     };
+    let model = format!("{} {}", inquiry.vendor, inquiry.product).trim().to_string();
+
+    Ok(DriveIdentity {
+        serial,
+        model: non_empty(model),
+        firmware: non_empty(inquiry.revision),
+        bus_type: BusType::Unknown, // the transport protocol is not part of the INQUIRY data
+        identifiers,
+    })
+}
 
-    if result == 0 {
-        let error = unsafe { GetLastError() }; // get the last error
-        if error == ERROR_IO_PENDING {
-            if unsafe { WaitForSingleObject(overlapped.hEvent, INFINITE) } != WAIT_OBJECT_0 { // wait for the operation to complete
-                return None;
+// reads the controller identity, plus the identifiers of namespace `nsid` if known
+//
+// namespace identifiers are optional: a controller that rejects the namespace commands still
+// reports its serial. a controller with a blank serial falls back to the first identifier
+pub fn get_drive_serial_with_nvme_identify(transport: &dyn NvmeTransport, h_controller: DriveHandle, nsid: Option<u32>, timeout: Duration) -> Result<DriveIdentity, DWORD> {
+    let controller = identify_controller(transport, h_controller, timeout)?; // Identify Controller data structure
+
+    // a single namespace controller can only mean namespace 1
+    let nsid = nsid.or(if controller.namespace_count == 1 { Some(1) } else { None });
+    let identifiers = match nsid {
+        Some(nsid) => match namespace_identifiers(transport, h_controller, nsid, timeout) {
+            Ok(identifiers) => identifiers,
+            Err(ERROR_TIMEOUT) => return Err(ERROR_TIMEOUT),
+            Err(error_code) => {
+                debug!(nsid, error_code, "namespace identifiers not available");
+                Vec::new()
             }
-        } else {
-            return None; // return None if another error happened
-        }
-    }
+        },
+        None => Vec::new(),
+    };
 
-    None
+    Ok(identity_from_nvme_controller(controller, identifiers))
 }
-
-pub fn get_last_error() -> DWORD {
-    unsafe { GetLastError() } // just return the last error code
-}
\ No newline at end of file
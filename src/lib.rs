@@ -2,177 +2,776 @@
 mod structs;
 mod utils;
 mod ioctl_operations;
+pub mod ata_string;
+pub mod ata_passthrough;
+pub mod nvme;
+pub mod scsi;
+pub mod smart;
+pub mod transport;
+pub mod sysfs;
+pub mod identify;
+pub mod storage_descriptor;
+pub mod capture;
+pub mod scrub;
+pub mod decode;
+mod drive_info;
+mod consensus;
+mod scan_options;
+mod error;
+#[cfg(windows)]
+mod win32_transport;
+#[cfg(target_os = "linux")]
+mod errno;
+#[cfg(target_os = "linux")]
+mod sg_io_transport;
+#[cfg(target_os = "linux")]
+mod nvme_ioctl_transport;
 
 use structs::*;
 use ioctl_operations::*;
-use winapi::{
-    shared::minwindef::BYTE,
-    um::handleapi::CloseHandle,
-};
+use transport::{AccessRights, DriveTransport, NvmeTransport, ScsiTransport};
+use sysfs::SysfsBackend;
+use smart::{Health, SmartAttributes};
+use nvme::{NvmeIdentifyController, NvmeSmartHealthLog};
+pub use identify::IdentifyDevice;
+pub use drive_info::{BusType, DriveIdentifier, DriveIdentity, DriveInfo, Method, MethodOutcome};
+pub use consensus::{reconcile, Agreement, Consensus};
+use drive_info::DriveProbe;
+pub use error::SerialError;
+pub use scan_options::{DriveSelection, ScanOptions, DEFAULT_MAX_CONCURRENCY, DEFAULT_OPERATION_TIMEOUT, DEFAULT_SCAN_TIMEOUT, MIN_OPERATION_TIMEOUT};
+#[cfg(windows)]
+pub use win32_transport::Win32Transport;
+#[cfg(target_os = "linux")]
+pub use sg_io_transport::SgIoTransport;
+#[cfg(target_os = "linux")]
+pub use nvme_ioctl_transport::NvmeIoctlTransport;
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tracing::{debug, debug_span, info_span, warn};
+
+// where drive information comes from
+#[derive(Clone)]
+enum Backend {
+    Ioctl(Arc<dyn DriveTransport>), // ioctl based methods (admin, smart, zero rights) through a transport
+    Sysfs(SysfsBackend),            // linux sysfs attributes
+}
 
-#[derive(Debug)]
 pub struct HardDriveSerial {
-    serial_numbers: Vec<String>,
-    errors: Vec<String>,
+    backend: Backend,
+    scsi: Option<Arc<dyn ScsiTransport>>, // used by the ATA pass-through method, if attached
+    nvme: Option<Arc<dyn NvmeTransport>>, // used by the NVMe identify method, if attached
+    options: ScanOptions,
+    drives: Vec<DriveInfo>,
+    errors: Vec<SerialError>,
     timings: Vec<(usize, String, Duration)>, // (drive_index, timing_type, duration)
 }
 
+impl fmt::Debug for HardDriveSerial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HardDriveSerial")
+            .field("options", &self.options)
+            .field("drives", &self.drives)
+            .field("errors", &self.errors)
+            .field("timings", &self.timings)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(any(windows, target_os = "linux"))]
+impl Default for HardDriveSerial {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl HardDriveSerial {
+    // uses the win32 DeviceIoControl backend
+    #[cfg(windows)]
     pub fn new() -> Self {
+        Self::with_transport(Arc::new(Win32Transport))
+    }
+
+    // reads drive attributes from sysfs mounted at /sys, IDENTIFY data through SG_IO and
+    // NVMe Identify Controller data through the NVMe admin ioctl
+    #[cfg(target_os = "linux")]
+    pub fn new() -> Self {
+        Self::with_sysfs_root(sysfs::DEFAULT_SYSFS_ROOT)
+            .with_scsi_transport(Arc::new(SgIoTransport))
+            .with_nvme_transport(Arc::new(NvmeIoctlTransport))
+    }
+
+    // runs every retrieval method through the given transport, e.g. an in-memory fake
+    pub fn with_transport(transport: Arc<dyn DriveTransport>) -> Self {
+        Self::with_backend(Backend::Ioctl(transport))
+    }
+
+    // reads drive attributes from a sysfs tree rooted at `root`, e.g. a fake directory in tests
+    pub fn with_sysfs_root(root: impl Into<PathBuf>) -> Self {
+        Self::with_backend(Backend::Sysfs(SysfsBackend::new(root)))
+    }
+
+    // sends ATA PASS-THROUGH commands through `transport` for the ATA pass-through method,
+    // which is skipped when no SCSI transport is attached
+    pub fn with_scsi_transport(mut self, transport: Arc<dyn ScsiTransport>) -> Self {
+        self.scsi = Some(transport);
+        self
+    }
+
+    // sends NVMe admin commands through `transport` for the NVMe identify method,
+    // which is skipped when no NVMe transport is attached
+    pub fn with_nvme_transport(mut self, transport: Arc<dyn NvmeTransport>) -> Self {
+        self.nvme = Some(transport);
+        self
+    }
+
+    fn with_backend(backend: Backend) -> Self {
         HardDriveSerial {
-            serial_numbers: Vec::new(),
+            backend,
+            scsi: None,
+            nvme: None,
+            options: ScanOptions::default(),
+            drives: Vec::new(),
             errors: Vec::new(),
             timings: Vec::new(),
         }
     }
 
-    // attempts to retrieve hard drive serial numbers using different methods
-    // returns a Result containing a vector of serial numbers if successful, or a vector of error messages if not
-    pub fn get_serial_numbers(&mut self) -> Result<Vec<String>, Vec<String>> {
-        // create a vector of threads, one for each possible drive index
-        let threads: Vec<_> = (0..MAX_IDE_DRIVES)
-            .map(|i_drive| {
-                let mut hd_serial = HardDriveSerial::new();
-                // spawn a thread to read drive information for a specific drive index
-                thread::spawn(move || {
-                    hd_serial.read_physical_drive_in_nt_with_admin_rights(i_drive as BYTE);
-                    hd_serial.read_physical_drive_in_nt_using_smart(i_drive as BYTE);
-                    hd_serial.read_physical_drive_in_nt_with_zero_rights(i_drive as BYTE);
-                    hd_serial
-                })
-            })
-            .collect();
+    // replaces the default scan options, e.g. to shorten the timeouts
+    pub fn with_options(mut self, options: ScanOptions) -> Self {
+        self.options = options;
+        self
+    }
 
-        // collect results from each thread, aggregating serial numbers, errors and timings
-        for thread in threads {
-            let mut hd_serial = thread.join().unwrap();
-            self.serial_numbers.append(&mut hd_serial.serial_numbers);
-            self.errors.append(&mut hd_serial.errors);
-            self.timings.append(&mut hd_serial.timings);
-        }
+    pub fn options(&self) -> &ScanOptions {
+        &self.options
+    }
 
-        // print execution timings for each drive and method
-        for (drive_index, timing_type, duration) in &self.timings {
-            println!("Drive {:#?}, Method {}: [{:#?}]", drive_index, timing_type, duration);
+    // attempts to retrieve hard drive serial numbers using different methods
+    // returns a Result containing one DriveInfo per drive that produced a serial number, or the collected errors if none did
+    pub fn get_serial_numbers(&mut self) -> Result<Vec<DriveInfo>, Vec<SerialError>> {
+        match self.backend.clone() {
+            Backend::Ioctl(transport) => self.read_drives_with_transport(transport),
+            Backend::Sysfs(sysfs) => self.read_drives_from_sysfs(&sysfs),
         }
 
-        // return serial numbers if any were found, otherwise return the collected errors
-        if self.serial_numbers.is_empty() {
+        // return the drives if any serial was found, otherwise return the collected errors
+        if self.drives.is_empty() {
             Err(self.errors.clone())
         } else {
-            Ok(self.serial_numbers.clone())
+            Ok(self.drives.clone())
         }
     }
 
-    // attempts to retrieve the hard drive serial number using admin rights
-    fn read_physical_drive_in_nt_with_admin_rights(&mut self, b_drive_num: BYTE) {
-        let start_time = std::time::Instant::now(); // record the start time for performance measurement
-        let drive_name = format!("\\\\.\\PhysicalDrive{}", b_drive_num);
-        // attempt to open the drive with admin rights
-        let h_physical_drive_ioctl = create_file_with_admin_rights(&drive_name);
+    // a scanner with the same backend, transports and options but no results, for a worker thread
+    fn fresh(&self) -> HardDriveSerial {
+        HardDriveSerial {
+            scsi: self.scsi.clone(),
+            nvme: self.nvme.clone(),
+            options: self.options.clone(),
+            ..HardDriveSerial::with_backend(self.backend.clone())
+        }
+    }
 
-        // check if the handle is valid
-        if h_physical_drive_ioctl == INVALID_HANDLE_VALUE {
-            return; // return early if the handle is invalid
+    // errors collected by the last scan, including drives that failed while others succeeded
+    pub fn errors(&self) -> &[SerialError] {
+        &self.errors
+    }
+
+    // how long each method took in the last scan, as (drive_index, timing_type, duration)
+    // where timing_type reads e.g. hd0Trd#A
+    pub fn timings(&self) -> &[(usize, String, Duration)] {
+        &self.timings
+    }
+
+    // reads the SMART attribute table and thresholds of a drive found by the last scan
+    //
+    // goes through SMART_RCV_DRIVE_DATA on the ioctl backend and through ATA PASS-THROUGH if a
+    // SCSI transport is attached (the only way on the sysfs backend). a drive that returns no
+    // thresholds still gets its attributes, with `threshold` left empty
+    pub fn smart_attributes(&self, drive: &DriveInfo) -> Result<SmartAttributes, SerialError> {
+        let _drive_span = info_span!("smart", index = drive.index, path = %drive.device_path).entered();
+        self.run_smart_command(drive, get_smart_attributes_with_smart, get_smart_attributes_with_ata_pass_through)
+    }
+
+    // reads the raw IDENTIFY DEVICE block of an ATA drive found by the last scan, through the
+    // same channels as smart_attributes
+    pub fn identify_data(&self, drive: &DriveInfo) -> Result<[u8; IDENTIFY_BUFFER_SIZE], SerialError> {
+        let _drive_span = info_span!("identify", index = drive.index, path = %drive.device_path).entered();
+        self.run_smart_command(drive, read_identify_block_with_smart, read_identify_block_with_ata_pass_through)
+    }
+
+    // decoded IDENTIFY DEVICE data of an ATA drive found by the last scan
+    pub fn identify(&self, drive: &DriveInfo) -> Result<IdentifyDevice, SerialError> {
+        self.identify_data(drive).map(|block| IdentifyDevice::parse(&block))
+    }
+
+    // reads the Identify Controller data of an NVMe drive found by the last scan
+    pub fn nvme_identify_controller(&self, drive: &DriveInfo) -> Result<NvmeIdentifyController, SerialError> {
+        let _drive_span = info_span!("identify", index = drive.index, path = %drive.device_path).entered();
+        self.run_nvme_command(drive, nvme::identify_controller)
+    }
+
+    // quick pass/fail verdict of a drive found by the last scan
+    //
+    // ATA drives answer SMART RETURN STATUS, through the same channels as smart_attributes.
+    // NVMe drives are judged by the critical warning byte of their SMART / Health log
+    pub fn health(&self, drive: &DriveInfo) -> Result<Health, SerialError> {
+        let _drive_span = info_span!("health", index = drive.index, path = %drive.device_path).entered();
+        match (&self.nvme, drive.bus_type) {
+            (Some(_), BusType::Nvme) => self.run_nvme_command(drive, get_health_with_nvme),
+            _ => self.run_smart_command(drive, get_health_with_smart, get_health_with_ata_pass_through),
         }
+    }
 
-        // attempt to retrieve the serial number
-        if let Some(serial) = get_drive_serial_with_admin_rights(h_physical_drive_ioctl, b_drive_num) {
-            // validate the serial number
-            if self.is_valid_serial_number(&serial) {
-                self.serial_numbers.push(serial);
-            } else {
-                // log an error if the serial number is invalid
-                let error_message = format!("Drive {}: Invalid serial number received with admin rights", b_drive_num);
-                self.errors.push(error_message);
+    // reads the SMART / Health Information log of an NVMe drive found by the last scan
+    pub fn nvme_smart_health(&self, drive: &DriveInfo) -> Result<NvmeSmartHealthLog, SerialError> {
+        let _drive_span = info_span!("smart", index = drive.index, path = %drive.device_path).entered();
+        self.run_nvme_command(drive, nvme::smart_health_log)
+    }
+
+    // runs an admin command on the controller of an NVMe drive, if an NVMe transport is attached
+    fn run_nvme_command<T>(&self, drive: &DriveInfo, command: impl Fn(&dyn NvmeTransport, transport::DriveHandle, Duration) -> Result<T, DWORD>) -> Result<T, SerialError> {
+        let index = Some(drive.index);
+        let Some(nvme) = self.nvme.as_ref() else {
+            return Err(SerialError::NotSupported { drive: index, method: Method::NvmeIdentify });
+        };
+        let result = nvme.open(&nvme::controller_path(&drive.device_path)).and_then(|h_controller| {
+            let result = command(nvme.as_ref(), h_controller, self.options.operation_timeout());
+            nvme.close(h_controller); // close the handle to the controller
+            result
+        });
+        result.map_err(|error_code| SerialError::from_os_code(index, Method::NvmeIdentify, error_code))
+    }
+
+    // runs a SMART command through SMART_RCV_DRIVE_DATA / SMART_SEND_DRIVE_COMMAND on the ioctl
+    // backend, falling back to ATA pass-through when they are not available for the drive
+    fn run_smart_command<T>(
+        &self,
+        drive: &DriveInfo,
+        with_smart: impl Fn(&dyn DriveTransport, transport::DriveHandle, Duration, BYTE) -> Result<T, DWORD>,
+        with_ata_pass_through: impl Fn(&dyn ScsiTransport, transport::DriveHandle, Duration) -> Result<T, DWORD>,
+    ) -> Result<T, SerialError> {
+        let timeout = self.options.operation_timeout();
+        let index = Some(drive.index);
+        if let Backend::Ioctl(transport) = &self.backend {
+            let result = transport.open(&drive.device_path, AccessRights::Smart).and_then(|h_physical_drive_ioctl| {
+                let result = with_smart(transport.as_ref(), h_physical_drive_ioctl, timeout, drive.index as BYTE);
+                transport.close(h_physical_drive_ioctl); // close the handle to the drive
+                result
+            });
+            match result {
+                // the driver does not support them, or the ioctl transport does not know the device
+                // at all (e.g. a replayed capture of a linux device)
+                Err(ERROR_NOT_SUPPORTED | ERROR_FILE_NOT_FOUND) if self.scsi.is_some() => debug!("SMART ioctls not available, trying ATA pass-through"),
+                result => return result.map_err(|error_code| SerialError::from_os_code(index, Method::Smart, error_code)),
             }
-        } else {
-            // handle errors and log them
-            let error_code = ioctl_operations::get_last_error();
-            if error_code == 50 { // ERROR_NOT_SUPPORTED
-                println!("Drive {}: Admin rights method not supported. Skipping.", b_drive_num);
-            } else {
-                let error_message = format!("Drive {}: Failed to get serial number with admin rights, error code: {}", b_drive_num, error_code);
-                self.errors.push(error_message);
+        }
+
+        let Some(scsi) = self.scsi.as_ref() else {
+            return Err(SerialError::NotSupported { drive: index, method: Method::AtaPassThrough });
+        };
+        let result = scsi.open(&drive.device_path).and_then(|h_device| {
+            let result = with_ata_pass_through(scsi.as_ref(), h_device, timeout);
+            scsi.close(h_device); // close the handle to the device
+            result
+        });
+        result.map_err(|error_code| SerialError::from_os_code(index, Method::AtaPassThrough, error_code))
+    }
+
+    // probes the selected drives with the ioctl methods
+    fn read_drives_with_transport(&mut self, transport: Arc<dyn DriveTransport>) {
+        let deadline = Instant::now() + self.options.scan_timeout();
+        let targets = self.ioctl_targets();
+        let first_method = self.options.methods().first().copied().unwrap_or(Method::AdminRights);
+        self.probe_drives(targets, first_method, deadline, move |hd_serial, i_drive, device_path, current_method| {
+            hd_serial.read_drive_with_transport(transport.as_ref(), i_drive, device_path, current_method, deadline);
+        });
+    }
+
+    // runs `probe` on every target, a (drive index, device) pair, on a pool of up to max_concurrency threads
+    //
+    // the scan waits for the threads until the scan deadline only, a thread stuck in a transport
+    // that ignores its operation timeout is left behind and its drive reported as timed out
+    fn probe_drives(
+        &mut self,
+        targets: Vec<(usize, String)>,
+        first_method: Method,
+        deadline: Instant,
+        probe: impl Fn(&mut HardDriveSerial, usize, String, &Mutex<Method>) + Send + Sync + 'static,
+    ) {
+        let probe = Arc::new(probe);
+        // which method each drive is running, so a drive given up on can be reported against it
+        let current_methods: Arc<Vec<Mutex<Method>>> = Arc::new(targets.iter().map(|_| Mutex::new(first_method)).collect());
+        let queue = Arc::new(Mutex::new(targets.clone().into_iter().enumerate().collect::<VecDeque<_>>()));
+        let (sender, receiver) = mpsc::channel();
+
+        for _ in 0..self.options.max_concurrency().min(targets.len()) {
+            let probe = Arc::clone(&probe);
+            let current_methods = Arc::clone(&current_methods);
+            let queue = Arc::clone(&queue);
+            let sender = sender.clone();
+            let template = self.fresh();
+            // spawn a worker that probes drives from the queue until it is empty or the deadline passed
+            thread::spawn(move || loop {
+                let next = queue.lock().unwrap().pop_front();
+                let Some((slot, (i_drive, device))) = next else { break };
+                if Instant::now() >= deadline {
+                    break;
+                }
+                let mut hd_serial = template.fresh();
+                probe(&mut hd_serial, i_drive, device, &current_methods[slot]);
+                if sender.send((slot, hd_serial)).is_err() {
+                    break; // the scan stopped waiting
+                }
+            });
+        }
+        drop(sender);
+
+        // collect results until every drive reported back or the scan deadline passed
+        let mut finished: Vec<Option<HardDriveSerial>> = targets.iter().map(|_| None).collect();
+        while finished.iter().any(Option::is_none) {
+            match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok((slot, hd_serial)) => finished[slot] = Some(hd_serial),
+                Err(_) => break, // deadline passed
+            }
+        }
+
+        // aggregate drives, errors and timings in the order the drives were selected
+        for (slot, hd_serial) in finished.into_iter().enumerate() {
+            match hd_serial {
+                Some(mut hd_serial) => {
+                    self.drives.append(&mut hd_serial.drives);
+                    self.errors.append(&mut hd_serial.errors);
+                    self.timings.append(&mut hd_serial.timings);
+                }
+                None => {
+                    let method = *current_methods[slot].lock().unwrap();
+                    warn!(drive = targets[slot].0, %method, "drive did not finish before the scan deadline, giving up on it");
+                    self.errors.push(SerialError::Timeout { drive: Some(targets[slot].0), method });
+                }
+            }
+        }
+    }
+
+    // runs the selected ioctl methods against one drive, in order
+    fn read_drive_with_transport(&mut self, transport: &dyn DriveTransport, i_drive: usize, device_path: String, current_method: &Mutex<Method>, deadline: Instant) {
+        let _drive_span = info_span!("drive", index = i_drive, path = %device_path).entered();
+        let mut probe = DriveProbe::new(i_drive, device_path);
+        for method in self.options.methods().to_vec() {
+            if self.options.stop_at_first_serial() && probe.has_serial() {
+                debug!("serial found, skipping the remaining methods");
+                break;
+            }
+            if method == Method::Sysfs
+                || (matches!(method, Method::AtaPassThrough | Method::ScsiVpd) && self.scsi.is_none())
+                || (method == Method::NvmeIdentify && self.nvme.is_none())
+            {
+                continue; // not available through this backend
+            }
+            let _method_span = debug_span!("method", %method).entered();
+            *current_method.lock().unwrap() = method;
+            let Some(timeout) = self.request_timeout(deadline) else {
+                self.record_scan_timeout(&mut probe, method);
+                break;
+            };
+            match method {
+                Method::AdminRights => self.read_physical_drive_in_nt_with_admin_rights(transport, &mut probe, timeout, i_drive as BYTE),
+                Method::Smart => self.read_physical_drive_in_nt_using_smart(transport, &mut probe, timeout, i_drive as BYTE),
+                Method::ZeroRights => self.read_physical_drive_in_nt_with_zero_rights(transport, &mut probe, timeout, i_drive as BYTE),
+                Method::AtaPassThrough => self.read_drive_with_ata_pass_through(&mut probe, timeout),
+                Method::ScsiVpd => self.read_drive_with_scsi_vpd(&mut probe, timeout),
+                Method::NvmeIdentify => self.read_drive_with_nvme_identify(&mut probe, timeout),
+                Method::Sysfs => {} // skipped above
             }
         }
+        self.drives.extend(probe.into_drive_info());
+    }
+
+    // the timeout of the next request: the operation timeout, cut short by the scan deadline
+    //
+    // None once the deadline passed. never zero, the linux transports read a zero timeout as
+    // the kernel default of about a minute
+    fn request_timeout(&self, deadline: Instant) -> Option<Duration> {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return None;
+        }
+        Some(self.options.operation_timeout().min(remaining).max(MIN_OPERATION_TIMEOUT))
+    }
+
+    // reports that the device could not be opened for `method`, a missing device is not an error
+    fn record_open_failure(&mut self, probe: &mut DriveProbe, method: Method, error_code: u32, start_time: Instant) {
+        let error = SerialError::from_os_code(Some(probe.index()), method, error_code);
+        if !matches!(error, SerialError::DeviceNotPresent { .. }) {
+            debug!(%error, %method, "could not open the device");
+            self.errors.push(error.clone());
+        }
+        probe.record_failure(error, start_time.elapsed());
+    }
+
+    // reports `method` as timed out because the scan deadline passed before it started, the
+    // caller skips the methods after it
+    fn record_scan_timeout(&mut self, probe: &mut DriveProbe, method: Method) {
+        warn!(drive = probe.index(), %method, "scan deadline passed, skipping the remaining methods");
+        let error = SerialError::Timeout { drive: Some(probe.index()), method };
+        self.errors.push(error.clone());
+        probe.record_failure(error, Duration::ZERO);
+    }
+
+    // (drive index, device path) of every drive the ioctl methods should probe
+    fn ioctl_targets(&self) -> Vec<(usize, String)> {
+        let physical_drive = |i_drive: usize| format!("\\\\.\\PhysicalDrive{}", i_drive);
+        match self.options.drives() {
+            DriveSelection::All => (0..MAX_IDE_DRIVES).map(|i_drive| (i_drive, physical_drive(i_drive))).collect(),
+            DriveSelection::Indices(indices) => indices.iter().map(|&i_drive| (i_drive, physical_drive(i_drive))).collect(),
+            DriveSelection::Paths(paths) => paths
+                .iter()
+                .enumerate()
+                .map(|(position, path)| {
+                    // \\.\PhysicalDriveN keeps index N, any other path is numbered by its position
+                    let i_drive = path.strip_prefix("\\\\.\\PhysicalDrive").and_then(|n| n.parse().ok()).unwrap_or(position);
+                    (i_drive, path.clone())
+                })
+                .collect(),
+        }
+    }
+
+    // runs the selected sysfs and ATA pass-through methods on every selected block device listed in sysfs
+    fn read_drives_from_sysfs(&mut self, sysfs: &SysfsBackend) {
+        let deadline = Instant::now() + self.options.scan_timeout();
+        let drive_names = match sysfs.list_drives() {
+            Ok(drive_names) => drive_names,
+            Err(error) => {
+                self.errors.push(SerialError::from_io(None, Method::Sysfs, &error));
+                return;
+            }
+        };
+
+        // drives keep their position in the listing as index, paths that are not listed are numbered by their position
+        let targets: Vec<(usize, String)> = match self.options.drives() {
+            DriveSelection::All => drive_names.iter().cloned().enumerate().collect(),
+            DriveSelection::Indices(indices) => indices
+                .iter()
+                .filter_map(|&i_drive| match drive_names.get(i_drive) {
+                    Some(drive_name) => Some((i_drive, drive_name.clone())),
+                    None => {
+                        self.errors.push(SerialError::DeviceNotPresent { drive: Some(i_drive), method: Method::Sysfs }); // past the end of the listing
+                        None
+                    }
+                })
+                .collect(),
+            DriveSelection::Paths(paths) => paths
+                .iter()
+                .enumerate()
+                .map(|(position, path)| {
+                    let drive_name = path.trim_start_matches("/dev/").to_string();
+                    (drive_names.iter().position(|name| *name == drive_name).unwrap_or(position), drive_name)
+                })
+                .collect(),
+        };
+
+        let first_method = self.options.methods().first().copied().unwrap_or(Method::Sysfs);
+        let sysfs = sysfs.clone();
+        self.probe_drives(targets, first_method, deadline, move |hd_serial, i_drive, drive_name, current_method| {
+            hd_serial.read_drive_with_sysfs(&sysfs, i_drive, drive_name, current_method, deadline);
+        });
+    }
+
+    // runs the selected sysfs and ATA pass-through methods against one block device, in order
+    fn read_drive_with_sysfs(&mut self, sysfs: &SysfsBackend, i_drive: usize, drive_name: String, current_method: &Mutex<Method>, deadline: Instant) {
+        let device_path = format!("/dev/{}", drive_name);
+        let _drive_span = info_span!("drive", index = i_drive, path = %device_path).entered();
+        let mut probe = DriveProbe::new(i_drive, device_path);
+        for method in self.options.methods().to_vec() {
+            if self.options.stop_at_first_serial() && probe.has_serial() {
+                debug!("serial found, skipping the remaining methods");
+                break;
+            }
+            let available = match method {
+                Method::Sysfs => true,
+                Method::AtaPassThrough | Method::ScsiVpd => self.scsi.is_some(),
+                Method::NvmeIdentify => self.nvme.is_some() && drive_name.starts_with("nvme"),
+                _ => false,
+            };
+            if !available {
+                continue; // not available through this backend, or not for this device
+            }
+            let _method_span = debug_span!("method", %method).entered();
+            *current_method.lock().unwrap() = method;
+            let Some(timeout) = self.request_timeout(deadline) else {
+                self.record_scan_timeout(&mut probe, method);
+                break;
+            };
+            match method {
+                Method::Sysfs => self.read_drive_from_sysfs(sysfs, &mut probe, &drive_name),
+                Method::AtaPassThrough => self.read_drive_with_ata_pass_through(&mut probe, timeout),
+                Method::ScsiVpd => self.read_drive_with_scsi_vpd(&mut probe, timeout),
+                Method::NvmeIdentify => self.read_drive_with_nvme_identify(&mut probe, timeout),
+                _ => {} // skipped above
+            }
+        }
+        self.drives.extend(probe.into_drive_info());
+    }
+
+    // reads the serial number sysfs exposes for one block device
+    fn read_drive_from_sysfs(&mut self, sysfs: &SysfsBackend, probe: &mut DriveProbe, drive_name: &str) {
+        let start_time = Instant::now(); // record the start time for performance measurement
+        let drive = Some(probe.index());
+        let outcome = match sysfs.read_drive(drive_name) {
+            Ok(sysfs_drive) => match sysfs_drive.best_serial() {
+                Some(serial) => {
+                    let identity = DriveIdentity {
+                        serial: serial.to_string(),
+                        model: sysfs_drive.model.clone(),
+                        firmware: sysfs_drive.firmware.clone(),
+                        bus_type: sysfs_drive.bus_type,
+                        identifiers: sysfs_drive.identifiers.clone(),
+                    };
+                    self.validate_identity(identity, drive, Method::Sysfs)
+                }
+                None => Err(SerialError::NotSupported { drive, method: Method::Sysfs }), // no serial exposed for this device
+            },
+            Err(error) => Err(SerialError::from_io(drive, Method::Sysfs, &error)),
+        };
+        if let Err(error) = &outcome {
+            self.errors.push(error.clone());
+        }
+        self.record_outcome(probe, Method::Sysfs, start_time, outcome);
+    }
+
+    // attempts to retrieve the hard drive serial number with IDENTIFY DEVICE through ATA PASS-THROUGH
+    fn read_drive_with_ata_pass_through(&mut self, probe: &mut DriveProbe, timeout: Duration) {
+        let Some(scsi) = self.scsi.clone() else { return };
+        let start_time = Instant::now(); // record the start time for performance measurement
+        let drive = Some(probe.index());
+        // attempt to open the device
+        let h_device = match scsi.open(probe.device_path()) {
+            Ok(handle) => handle,
+            Err(error_code) => {
+                self.record_open_failure(probe, Method::AtaPassThrough, error_code, start_time);
+                return; // return early if the device could not be opened
+            }
+        };
+
+        // attempt to retrieve the serial number
+        let outcome = match get_drive_serial_with_ata_pass_through(scsi.as_ref(), h_device, timeout) {
+            Ok(identity) => self.validate_identity(identity, drive, Method::AtaPassThrough),
+            Err(error_code) => {
+                let error = SerialError::from_os_code(drive, Method::AtaPassThrough, error_code);
+                if let SerialError::NotSupported { .. } = error {
+                    debug!("the device does not translate ATA PASS-THROUGH, skipping");
+                } else {
+                    self.errors.push(error.clone());
+                }
+                Err(error)
+            }
+        };
+
+        // close the handle to the device
+        scsi.close(h_device);
+
+        self.record_outcome(probe, Method::AtaPassThrough, start_time, outcome);
+    }
+
+    // attempts to retrieve the serial number from the SCSI INQUIRY data and vpd pages
+    fn read_drive_with_scsi_vpd(&mut self, probe: &mut DriveProbe, timeout: Duration) {
+        let Some(scsi) = self.scsi.clone() else { return };
+        let start_time = Instant::now(); // record the start time for performance measurement
+        let drive = Some(probe.index());
+        // attempt to open the device
+        let h_device = match scsi.open(probe.device_path()) {
+            Ok(handle) => handle,
+            Err(error_code) => {
+                self.record_open_failure(probe, Method::ScsiVpd, error_code, start_time);
+                return; // return early if the device could not be opened
+            }
+        };
+
+        // attempt to retrieve the serial number
+        let outcome = match get_drive_serial_with_scsi_vpd(scsi.as_ref(), h_device, timeout) {
+            Ok(identity) => self.validate_identity(identity, drive, Method::ScsiVpd),
+            Err(error_code) => {
+                let error = SerialError::from_os_code(drive, Method::ScsiVpd, error_code);
+                if let SerialError::NotSupported { .. } = error {
+                    debug!("the device has no unit serial number page, skipping");
+                } else {
+                    self.errors.push(error.clone());
+                }
+                Err(error)
+            }
+        };
+
+        // close the handle to the device
+        scsi.close(h_device);
+
+        self.record_outcome(probe, Method::ScsiVpd, start_time, outcome);
+    }
+
+    // attempts to retrieve the serial number with the NVMe Identify Controller admin command
+    fn read_drive_with_nvme_identify(&mut self, probe: &mut DriveProbe, timeout: Duration) {
+        let Some(nvme) = self.nvme.clone() else { return };
+        let start_time = Instant::now(); // record the start time for performance measurement
+        let drive = Some(probe.index());
+        // admin commands go to the controller, not the namespace block device
+        let h_controller = match nvme.open(&nvme::controller_path(probe.device_path())) {
+            Ok(handle) => handle,
+            Err(error_code) => {
+                self.record_open_failure(probe, Method::NvmeIdentify, error_code, start_time);
+                return; // return early if the controller could not be opened
+            }
+        };
+
+        // attempt to retrieve the serial number
+        let nsid = nvme::namespace_id(probe.device_path());
+        let outcome = match get_drive_serial_with_nvme_identify(nvme.as_ref(), h_controller, nsid, timeout) {
+            Ok(identity) => self.validate_identity(identity, drive, Method::NvmeIdentify),
+            Err(error_code) => {
+                let error = SerialError::from_os_code(drive, Method::NvmeIdentify, error_code);
+                if let SerialError::NotSupported { .. } = error {
+                    debug!("the device is not an NVMe controller, skipping");
+                } else {
+                    self.errors.push(error.clone());
+                }
+                Err(error)
+            }
+        };
+
+        // close the handle to the controller
+        nvme.close(h_controller);
+
+        self.record_outcome(probe, Method::NvmeIdentify, start_time, outcome);
+    }
+
+    // attempts to retrieve the hard drive serial number using admin rights
+    fn read_physical_drive_in_nt_with_admin_rights(&mut self, transport: &dyn DriveTransport, probe: &mut DriveProbe, timeout: Duration, b_drive_num: BYTE) {
+        let start_time = Instant::now(); // record the start time for performance measurement
+        let drive = Some(b_drive_num as usize);
+        // attempt to open the drive with admin rights
+        let h_physical_drive_ioctl = match transport.open(probe.device_path(), AccessRights::Admin) {
+            Ok(handle) => handle,
+            Err(error_code) => {
+                self.record_open_failure(probe, Method::AdminRights, error_code, start_time);
+                return; // return early if the drive could not be opened
+            }
+        };
+
+        // attempt to retrieve the serial number
+        let outcome = match get_drive_serial_with_admin_rights(transport, h_physical_drive_ioctl, timeout, b_drive_num) {
+            Ok(identity) => self.validate_identity(identity, drive, Method::AdminRights),
+            Err(error_code) => {
+                // handle errors and log them
+                let error = SerialError::from_os_code(drive, Method::AdminRights, error_code);
+                if let SerialError::NotSupported { .. } = error {
+                    debug!("admin rights method not supported, skipping");
+                } else {
+                    self.errors.push(error.clone());
+                }
+                Err(error)
+            }
+        };
 
         // close the handle to the drive
-        unsafe { CloseHandle(h_physical_drive_ioctl); }
+        transport.close(h_physical_drive_ioctl);
 
-        // record the end time and store the timing information
-        let end_time = std::time::Instant::now();
-        self.timings.push((b_drive_num as usize, format!("hd{}Trd#A", b_drive_num), end_time.duration_since(start_time)));
+        self.record_outcome(probe, Method::AdminRights, start_time, outcome);
     }
 
     // attempts to retrieve the hard drive serial number using SMART
-    fn read_physical_drive_in_nt_using_smart(&mut self, b_drive_num: BYTE) {
-        let start_time = std::time::Instant::now(); // record the start time for performance measurement
-        let drive_name = format!("\\\\.\\PhysicalDrive{}", b_drive_num);
+    fn read_physical_drive_in_nt_using_smart(&mut self, transport: &dyn DriveTransport, probe: &mut DriveProbe, timeout: Duration, b_drive_num: BYTE) {
+        let start_time = Instant::now(); // record the start time for performance measurement
+        let drive = Some(b_drive_num as usize);
         // attempt to open the drive with SMART rights
-        let h_physical_drive_ioctl = create_file_with_smart_rights(&drive_name);
-
-        // check if the handle is valid
-        if h_physical_drive_ioctl == INVALID_HANDLE_VALUE {
-            return; // return early if the handle is invalid
-        }
+        let h_physical_drive_ioctl = match transport.open(probe.device_path(), AccessRights::Smart) {
+            Ok(handle) => handle,
+            Err(error_code) => {
+                self.record_open_failure(probe, Method::Smart, error_code, start_time);
+                return; // return early if the drive could not be opened
+            }
+        };
 
         // attempt to retrieve the serial number using SMART
-        if let Some(serial) = get_drive_serial_with_smart(h_physical_drive_ioctl, b_drive_num) {
-            // validate the serial number
-            if self.is_valid_serial_number(&serial) {
-                self.serial_numbers.push(serial);
-            } else {
-                // log an error if the serial number is invalid
-                let error_message = format!("Drive {}: Invalid serial number received using SMART", b_drive_num);
-                self.errors.push(error_message);
+        let outcome = match get_drive_serial_with_smart(transport, h_physical_drive_ioctl, timeout, b_drive_num) {
+            Ok(identity) => self.validate_identity(identity, drive, Method::Smart),
+            Err(error_code) => {
+                let error = SerialError::from_os_code(drive, Method::Smart, error_code);
+                if let SerialError::ChecksumMismatch { .. } | SerialError::Timeout { .. } = error {
+                    // the drive answered with corrupted IDENTIFY data, or hung
+                    self.errors.push(error.clone());
+                }
+                Err(error)
             }
-        } 
+        };
 
         // close the handle to the drive
-        unsafe { CloseHandle(h_physical_drive_ioctl); }
+        transport.close(h_physical_drive_ioctl);
 
-        // record the end time and store the timing information
-        let end_time = std::time::Instant::now();
-        self.timings.push((b_drive_num as usize, format!("hd{}Trd#SM", b_drive_num), end_time.duration_since(start_time)));
+        self.record_outcome(probe, Method::Smart, start_time, outcome);
     }
 
     // attempts to retrieve the hard drive serial number with zero rights
-    fn read_physical_drive_in_nt_with_zero_rights(&mut self, b_drive_num: BYTE) {
-        let start_time = std::time::Instant::now(); // record the start time for performance measurement
-        let drive_name = format!("\\\\.\\PhysicalDrive{}", b_drive_num);
+    fn read_physical_drive_in_nt_with_zero_rights(&mut self, transport: &dyn DriveTransport, probe: &mut DriveProbe, timeout: Duration, b_drive_num: BYTE) {
+        let start_time = Instant::now(); // record the start time for performance measurement
+        let drive = Some(b_drive_num as usize);
         // attempt to open the drive with zero rights
-        let h_physical_drive_ioctl = create_file_with_zero_rights(&drive_name);
-
-        // check if the handle is valid
-        if h_physical_drive_ioctl == INVALID_HANDLE_VALUE {
-            return; // return early if the handle is invalid
-        }
+        let h_physical_drive_ioctl = match transport.open(probe.device_path(), AccessRights::Zero) {
+            Ok(handle) => handle,
+            Err(error_code) => {
+                self.record_open_failure(probe, Method::ZeroRights, error_code, start_time);
+                return; // return early if the drive could not be opened
+            }
+        };
 
         // attempt to retrieve the serial number with zero rights
-        if let Some(serial) = get_drive_serial_with_zero_rights(h_physical_drive_ioctl) {
-            // validate the serial number
-            if self.is_valid_serial_number(&serial) {
-                self.serial_numbers.push(serial);
-            } else {
-                // log an error if the serial number is invalid
-                let error_message = format!("Drive {}: Invalid serial number received with zero rights", b_drive_num);
-                self.errors.push(error_message);
+        let outcome = match get_drive_serial_with_zero_rights(transport, h_physical_drive_ioctl, timeout) {
+            Ok(identity) => self.validate_identity(identity, drive, Method::ZeroRights),
+            Err(error_code) => {
+                let error = SerialError::from_os_code(drive, Method::ZeroRights, error_code);
+                if let SerialError::Timeout { .. } = error {
+                    self.errors.push(error.clone()); // the drive hung
+                }
+                Err(error)
             }
-        }
+        };
 
         // close the handle to the drive
-        unsafe { CloseHandle(h_physical_drive_ioctl); }
+        transport.close(h_physical_drive_ioctl);
 
-        // record the end time and store the timing information
-        let end_time = std::time::Instant::now();
-        self.timings.push((b_drive_num as usize, format!("hd{}Trd#Z", b_drive_num), end_time.duration_since(start_time)));
+        self.record_outcome(probe, Method::ZeroRights, start_time, outcome);
+    }
+
+    // checks the serial a method produced, logging an error if it is malformed
+    fn validate_identity(&mut self, identity: DriveIdentity, drive: Option<usize>, method: Method) -> Result<DriveIdentity, SerialError> {
+        if self.is_valid_serial_number(&identity.serial) {
+            Ok(identity)
+        } else {
+            // log an error if the serial number is invalid
+            let error = SerialError::InvalidResponse { drive, method, reason: format!("invalid serial number {:?}", identity.serial) };
+            self.errors.push(error.clone());
+            Err(error)
+        }
+    }
+
+    // stores the timing information and the outcome of one method
+    fn record_outcome(&mut self, probe: &mut DriveProbe, method: Method, start_time: Instant, outcome: Result<DriveIdentity, SerialError>) {
+        let duration = start_time.elapsed();
+        let drive_index = probe.index();
+        self.timings.push((drive_index, format!("hd{}Trd#{}", drive_index, method.tag()), duration));
+        match &outcome {
+            Ok(identity) => debug!(serial = %identity.serial, ?duration, "method produced a serial"),
+            Err(error) => debug!(%error, ?duration, "method failed"),
+        }
+        match outcome {
+            Ok(identity) => probe.record_success(method, identity, duration),
+            Err(error) => probe.record_failure(error, duration),
+        }
     }
 
     // validates the format of the serial number
     fn is_valid_serial_number(&self, serial: &str) -> bool {
-        !serial.is_empty() && serial.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+        !serial.is_empty() && serial.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == ' ') // decoded serials keep their internal spaces
     }
-}
\ No newline at end of file
+}
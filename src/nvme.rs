@@ -0,0 +1,326 @@
+// NVMe admin commands and the structures they return
+//
+// the commands go through an NvmeTransport, everything else here only works on bytes
+// so it can be checked against canned buffers
+
+use crate::drive_info::DriveIdentifier;
+use crate::transport::{DriveHandle, NvmeAdminCommand, NvmeTransport, ERROR_INVALID_DATA, ERROR_NOT_SUPPORTED};
+use byteorder::{ByteOrder, LittleEndian};
+use std::time::Duration;
+
+pub const NVME_ADMIN_GET_LOG_PAGE: u8 = 0x02; // admin opcodes
+pub const NVME_ADMIN_IDENTIFY: u8 = 0x06;
+
+pub const NVME_NSID_ALL: u32 = 0xFFFF_FFFF; // addresses the controller as a whole in log page requests
+
+pub const NVME_IDENTIFY_DATA_SIZE: usize = 4096; // every identify structure is one 4 KiB page
+
+pub const CNS_NAMESPACE: u8 = 0x00; // identify controller or namespace structure (CNS) values
+pub const CNS_CONTROLLER: u8 = 0x01;
+pub const CNS_NAMESPACE_DESCRIPTORS: u8 = 0x03; // namespace identification descriptor list, NVMe 1.3 and later
+
+pub const LOG_SMART_HEALTH: u8 = 0x02; // log page identifiers
+pub const LOG_SMART_HEALTH_SIZE: usize = 512;
+
+pub const CRITICAL_WARNING_SPARE: u8 = 0x01;           // available spare below its threshold
+pub const CRITICAL_WARNING_TEMPERATURE: u8 = 0x02;     // temperature outside its thresholds
+pub const CRITICAL_WARNING_RELIABILITY: u8 = 0x04;     // media or internal errors degraded reliability
+pub const CRITICAL_WARNING_READ_ONLY: u8 = 0x08;       // media placed in read only mode
+pub const CRITICAL_WARNING_VOLATILE_BACKUP: u8 = 0x10; // volatile memory backup device failed
+pub const CRITICAL_WARNING_PMR_READ_ONLY: u8 = 0x20;   // persistent memory region became read only
+
+pub const NIDT_EUI64: u8 = 0x01; // namespace identifier types in the descriptor list
+pub const NIDT_NGUID: u8 = 0x02;
+pub const NIDT_UUID: u8 = 0x03;
+pub const NIDT_CSI: u8 = 0x04;
+
+// builds an Identify admin command for the given CNS value
+pub fn identify_command(cns: u8, nsid: u32, controller_id: u16) -> NvmeAdminCommand {
+    NvmeAdminCommand {
+        opcode: NVME_ADMIN_IDENTIFY,
+        nsid,
+        cdw10: cns as u32 | (controller_id as u32) << 16, // CNS in bits 7:0, CNTID in bits 31:16
+        ..NvmeAdminCommand::default()
+    }
+}
+
+// builds a Get Log Page admin command reading `length` bytes (a multiple of 4) of log `lid`
+pub fn get_log_page_command(lid: u8, nsid: u32, length: usize) -> NvmeAdminCommand {
+    let dwords = (length / 4).saturating_sub(1) as u32; // number of dwords, zero based
+    NvmeAdminCommand {
+        opcode: NVME_ADMIN_GET_LOG_PAGE,
+        nsid,
+        cdw10: lid as u32 | (dwords & 0xFFFF) << 16, // LID in bits 7:0, NUMDL in bits 31:16
+        cdw11: dwords >> 16,                        // NUMDU
+        ..NvmeAdminCommand::default()
+    }
+}
+
+// the fields of the Identify Controller data structure (CNS 01h) the crate cares about
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NvmeIdentifyController {
+    pub vendor_id: u16,            // PCI vendor ID, bytes 0-1
+    pub subsystem_vendor_id: u16,  // PCI subsystem vendor ID, bytes 2-3
+    pub serial: String,            // bytes 4-23
+    pub model: String,             // bytes 24-63
+    pub firmware: String,          // bytes 64-71
+    pub ieee_oui: u32,             // organizationally unique identifier, bytes 73-75 (stored least significant byte first)
+    pub controller_id: u16,        // bytes 78-79
+    pub version: u32,              // NVMe version, e.g. 0x00010300 for 1.3, bytes 80-83
+    pub namespace_count: u32,      // bytes 516-519
+    pub total_capacity: u128,      // total NVM capacity in bytes, bytes 280-295 (0 if not reported)
+    pub unallocated_capacity: u128, // bytes 296-311
+    pub subsystem_nqn: String,     // NVM subsystem NVMe qualified name, bytes 768-1023
+}
+
+impl NvmeIdentifyController {
+    // decodes a 4096 byte Identify Controller data structure
+    pub fn parse(data: &[u8; NVME_IDENTIFY_DATA_SIZE]) -> Self {
+        NvmeIdentifyController {
+            vendor_id: LittleEndian::read_u16(&data[0..2]),
+            subsystem_vendor_id: LittleEndian::read_u16(&data[2..4]),
+            serial: nvme_string(&data[4..24]),
+            model: nvme_string(&data[24..64]),
+            firmware: nvme_string(&data[64..72]),
+            ieee_oui: LittleEndian::read_u24(&data[73..76]),
+            controller_id: LittleEndian::read_u16(&data[78..80]),
+            version: LittleEndian::read_u32(&data[80..84]),
+            namespace_count: LittleEndian::read_u32(&data[516..520]),
+            total_capacity: LittleEndian::read_u128(&data[280..296]),
+            unallocated_capacity: LittleEndian::read_u128(&data[296..312]),
+            subsystem_nqn: nvme_string(&data[768..1024]),
+        }
+    }
+
+    // the OUI in the usual dash separated notation, e.g. 00-25-38
+    pub fn ieee_oui_string(&self) -> String {
+        format!("{:02X}-{:02X}-{:02X}", self.ieee_oui >> 16 & 0xFF, self.ieee_oui >> 8 & 0xFF, self.ieee_oui & 0xFF)
+    }
+
+    // version as (major, minor, tertiary), e.g. (1, 3, 0)
+    pub fn version_triple(&self) -> (u16, u8, u8) {
+        ((self.version >> 16) as u16, (self.version >> 8) as u8, self.version as u8)
+    }
+}
+
+// the fields of the Identify Namespace data structure (CNS 00h) the crate cares about
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NvmeIdentifyNamespace {
+    pub size: u64,        // namespace size in logical blocks, bytes 0-7
+    pub capacity: u64,    // bytes 8-15
+    pub utilization: u64, // bytes 16-23
+    pub nguid: [u8; 16],  // bytes 104-119, all zero if not reported
+    pub eui64: [u8; 8],   // bytes 120-127, all zero if not reported
+}
+
+impl NvmeIdentifyNamespace {
+    // decodes a 4096 byte Identify Namespace data structure
+    pub fn parse(data: &[u8; NVME_IDENTIFY_DATA_SIZE]) -> Self {
+        let mut namespace = NvmeIdentifyNamespace {
+            size: LittleEndian::read_u64(&data[0..8]),
+            capacity: LittleEndian::read_u64(&data[8..16]),
+            utilization: LittleEndian::read_u64(&data[16..24]),
+            ..NvmeIdentifyNamespace::default()
+        };
+        namespace.nguid.copy_from_slice(&data[104..120]);
+        namespace.eui64.copy_from_slice(&data[120..128]);
+        namespace
+    }
+
+    // the EUI-64 and NGUID the namespace reports, skipping the all zero ones
+    pub fn identifiers(&self) -> Vec<DriveIdentifier> {
+        let mut identifiers = Vec::new();
+        if self.eui64 != [0; 8] {
+            identifiers.push(DriveIdentifier::Eui64(self.eui64));
+        }
+        if self.nguid != [0; 16] {
+            identifiers.push(DriveIdentifier::Nguid(self.nguid));
+        }
+        identifiers
+    }
+}
+
+// one entry of the Namespace Identification Descriptor list (CNS 03h)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NamespaceDescriptor {
+    Identifier(DriveIdentifier),          // EUI-64, NGUID or UUID
+    CommandSet(u8),                       // command set identifier (CSI)
+    Unknown { kind: u8, value: Vec<u8> }, // a type this crate does not know yet
+}
+
+// decodes a Namespace Identification Descriptor list
+//
+// every descriptor is a 4 byte header (type, length, 2 reserved) followed by `length` bytes,
+// the list ends at the first descriptor of type 0. a descriptor running past the buffer, or a
+// known type with the wrong length, is reported as ERROR_INVALID_DATA
+pub fn parse_namespace_descriptors(data: &[u8]) -> Result<Vec<NamespaceDescriptor>, u32> {
+    let mut descriptors = Vec::new();
+    let mut offset = 0;
+    while offset + 4 <= data.len() && data[offset] != 0 {
+        let (kind, len) = (data[offset], data[offset + 1] as usize);
+        let value = data.get(offset + 4..offset + 4 + len).ok_or(ERROR_INVALID_DATA)?;
+        let descriptor = match (kind, len) {
+            (NIDT_EUI64, 8) => NamespaceDescriptor::Identifier(DriveIdentifier::Eui64(value.try_into().unwrap())),
+            (NIDT_NGUID, 16) => NamespaceDescriptor::Identifier(DriveIdentifier::Nguid(value.try_into().unwrap())),
+            (NIDT_UUID, 16) => NamespaceDescriptor::Identifier(DriveIdentifier::Uuid(value.try_into().unwrap())),
+            (NIDT_CSI, 1) => NamespaceDescriptor::CommandSet(value[0]),
+            (NIDT_EUI64..=NIDT_CSI, _) => return Err(ERROR_INVALID_DATA),
+            _ => NamespaceDescriptor::Unknown { kind, value: value.to_vec() },
+        };
+        descriptors.push(descriptor);
+        offset += 4 + len;
+    }
+    Ok(descriptors)
+}
+
+// the SMART / Health Information log page (02h)
+//
+// counters are 128 bit in the spec and kept that way, data units are thousands of 512 byte units
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NvmeSmartHealthLog {
+    pub critical_warning: u8,            // CRITICAL_WARNING_* bits, byte 0
+    pub composite_temperature: u16,      // kelvin, bytes 1-2
+    pub available_spare: u8,             // percent, byte 3
+    pub available_spare_threshold: u8,   // percent, byte 4
+    pub percentage_used: u8,             // estimate of the endurance used, may exceed 100, byte 5
+    pub data_units_read: u128,           // bytes 32-47
+    pub data_units_written: u128,        // bytes 48-63
+    pub host_read_commands: u128,        // bytes 64-79
+    pub host_write_commands: u128,       // bytes 80-95
+    pub controller_busy_time: u128,      // minutes, bytes 96-111
+    pub power_cycles: u128,              // bytes 112-127
+    pub power_on_hours: u128,            // bytes 128-143
+    pub unsafe_shutdowns: u128,          // bytes 144-159
+    pub media_errors: u128,              // unrecovered data integrity errors, bytes 160-175
+    pub error_log_entries: u128,         // bytes 176-191
+    pub warning_temperature_time: u32,   // minutes above the warning threshold, bytes 192-195
+    pub critical_temperature_time: u32,  // minutes above the critical threshold, bytes 196-199
+    pub temperature_sensors: Vec<u16>,   // kelvin, the sensors that report one, bytes 200-215
+}
+
+impl NvmeSmartHealthLog {
+    // decodes a 512 byte SMART / Health Information log page
+    pub fn parse(data: &[u8; LOG_SMART_HEALTH_SIZE]) -> Self {
+        let counter = |offset: usize| LittleEndian::read_u128(&data[offset..offset + 16]);
+        NvmeSmartHealthLog {
+            critical_warning: data[0],
+            composite_temperature: LittleEndian::read_u16(&data[1..3]),
+            available_spare: data[3],
+            available_spare_threshold: data[4],
+            percentage_used: data[5],
+            data_units_read: counter(32),
+            data_units_written: counter(48),
+            host_read_commands: counter(64),
+            host_write_commands: counter(80),
+            controller_busy_time: counter(96),
+            power_cycles: counter(112),
+            power_on_hours: counter(128),
+            unsafe_shutdowns: counter(144),
+            media_errors: counter(160),
+            error_log_entries: counter(176),
+            warning_temperature_time: LittleEndian::read_u32(&data[192..196]),
+            critical_temperature_time: LittleEndian::read_u32(&data[196..200]),
+            temperature_sensors: data[200..216].chunks_exact(2).map(LittleEndian::read_u16).filter(|kelvin| *kelvin != 0).collect(),
+        }
+    }
+
+    // composite temperature in degrees celsius
+    pub fn temperature_celsius(&self) -> i32 {
+        self.composite_temperature as i32 - 273
+    }
+
+    pub fn bytes_read(&self) -> u128 {
+        self.data_units_read.saturating_mul(512_000)
+    }
+
+    pub fn bytes_written(&self) -> u128 {
+        self.data_units_written.saturating_mul(512_000)
+    }
+}
+
+// ascii fields in NVMe structures are space padded and, unlike ATA, not byte swapped
+fn nvme_string(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).trim_matches(|c: char| c == ' ' || c == '\0').to_string()
+}
+
+// reads the Identify Controller data structure
+pub fn identify_controller(transport: &dyn NvmeTransport, handle: DriveHandle, timeout: Duration) -> Result<NvmeIdentifyController, u32> {
+    let mut data = [0u8; NVME_IDENTIFY_DATA_SIZE];
+    transport.admin_command(handle, &identify_command(CNS_CONTROLLER, 0, 0), &mut data, timeout)?;
+    Ok(NvmeIdentifyController::parse(&data))
+}
+
+// reads the controller wide SMART / Health Information log page
+pub fn smart_health_log(transport: &dyn NvmeTransport, handle: DriveHandle, timeout: Duration) -> Result<NvmeSmartHealthLog, u32> {
+    let mut data = [0u8; LOG_SMART_HEALTH_SIZE];
+    transport.admin_command(handle, &get_log_page_command(LOG_SMART_HEALTH, NVME_NSID_ALL, LOG_SMART_HEALTH_SIZE), &mut data, timeout)?;
+    Ok(NvmeSmartHealthLog::parse(&data))
+}
+
+// reads the Identify Namespace data structure of namespace `nsid`
+pub fn identify_namespace(transport: &dyn NvmeTransport, handle: DriveHandle, nsid: u32, timeout: Duration) -> Result<NvmeIdentifyNamespace, u32> {
+    let mut data = [0u8; NVME_IDENTIFY_DATA_SIZE];
+    transport.admin_command(handle, &identify_command(CNS_NAMESPACE, nsid, 0), &mut data, timeout)?;
+    Ok(NvmeIdentifyNamespace::parse(&data))
+}
+
+// reads the Namespace Identification Descriptor list of namespace `nsid`
+pub fn namespace_descriptors(transport: &dyn NvmeTransport, handle: DriveHandle, nsid: u32, timeout: Duration) -> Result<Vec<NamespaceDescriptor>, u32> {
+    let mut data = [0u8; NVME_IDENTIFY_DATA_SIZE];
+    transport.admin_command(handle, &identify_command(CNS_NAMESPACE_DESCRIPTORS, nsid, 0), &mut data, timeout)?;
+    parse_namespace_descriptors(&data)
+}
+
+// every identifier of namespace `nsid`, from the descriptor list first and the Identify Namespace
+// structure second (controllers before NVMe 1.3 reject the descriptor list)
+pub fn namespace_identifiers(transport: &dyn NvmeTransport, handle: DriveHandle, nsid: u32, timeout: Duration) -> Result<Vec<DriveIdentifier>, u32> {
+    let mut identifiers: Vec<DriveIdentifier> = match namespace_descriptors(transport, handle, nsid, timeout) {
+        Ok(descriptors) => descriptors
+            .into_iter()
+            .filter_map(|descriptor| match descriptor {
+                NamespaceDescriptor::Identifier(identifier) => Some(identifier),
+                _ => None,
+            })
+            .collect(),
+        Err(ERROR_NOT_SUPPORTED) | Err(ERROR_INVALID_DATA) => Vec::new(),
+        Err(error_code) => return Err(error_code),
+    };
+    match identify_namespace(transport, handle, nsid, timeout) {
+        Ok(namespace) => {
+            for identifier in namespace.identifiers() {
+                if !identifiers.contains(&identifier) {
+                    identifiers.push(identifier);
+                }
+            }
+        }
+        Err(error_code) if identifiers.is_empty() => return Err(error_code),
+        Err(_) => {} // the descriptor list already named the namespace
+    }
+    Ok(identifiers)
+}
+
+// splits e.g. /dev/nvme0n1 into the length of /dev/nvme0 and namespace id 1
+fn split_namespace_path(device_path: &str) -> Option<(usize, u32)> {
+    let name_start = device_path.rfind("nvme")? + 4;
+    let name = &device_path[name_start..];
+    let controller_len = name.bytes().take_while(u8::is_ascii_digit).count();
+    let namespace = name[controller_len..].strip_prefix('n')?;
+    if controller_len == 0 || namespace.is_empty() || !namespace.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    Some((name_start + controller_len, namespace.parse().ok()?))
+}
+
+// the controller character device for a namespace block device, e.g. /dev/nvme0n1 -> /dev/nvme0
+// (paths that are not namespaces are returned unchanged)
+pub fn controller_path(device_path: &str) -> String {
+    match split_namespace_path(device_path) {
+        Some((controller_len, _)) => device_path[..controller_len].to_string(),
+        None => device_path.to_string(),
+    }
+}
+
+// the namespace id of a namespace block device, e.g. /dev/nvme0n1 -> 1
+pub fn namespace_id(device_path: &str) -> Option<u32> {
+    split_namespace_path(device_path).map(|(_, nsid)| nsid)
+}
@@ -0,0 +1,84 @@
+use crate::errno::last_error_code;
+use crate::structs::*;
+use crate::transport::{DriveHandle, NvmeAdminCommand, NvmeTransport};
+use std::ffi::CString;
+use std::os::raw::{c_int, c_uint};
+use std::time::Duration;
+
+const NVME_IOCTL_ADMIN_CMD: libc::c_ulong = 0xC048_4E41; // _IOWR('N', 0x41, struct nvme_admin_cmd), from <linux/nvme_ioctl.h>
+
+const NVME_SC_INVALID_OPCODE: c_int = 0x01; // generic command status codes
+const NVME_SC_INVALID_FIELD: c_int = 0x02;
+const NVME_SC_INVALID_NS: c_int = 0x0B;
+
+// mirrors struct nvme_passthru_cmd from <linux/nvme_ioctl.h>
+#[repr(C)]
+#[derive(Default)]
+struct NvmePassthruCmd {
+    opcode: u8,
+    flags: u8,
+    rsvd1: u16,
+    nsid: u32,
+    cdw2: u32,
+    cdw3: u32,
+    metadata: u64,
+    addr: u64,
+    metadata_len: u32,
+    data_len: u32,
+    cdw10: u32,
+    cdw11: u32,
+    cdw12: u32,
+    cdw13: u32,
+    cdw14: u32,
+    cdw15: u32,
+    timeout_ms: u32,
+    result: u32,
+}
+
+const _: () = assert!(std::mem::size_of::<NvmePassthruCmd>() == 72);
+
+// NvmeTransport backed by NVME_IOCTL_ADMIN_CMD on /dev/nvmeN (needs CAP_SYS_ADMIN)
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NvmeIoctlTransport;
+
+impl NvmeTransport for NvmeIoctlTransport {
+    fn open(&self, path: &str) -> Result<DriveHandle, u32> {
+        let device_name = CString::new(path).map_err(|_| ERROR_INVALID_NAME)?; // open needs a nul terminated name
+        let fd = unsafe { libc::open(device_name.as_ptr(), libc::O_RDONLY) };
+        if fd < 0 {
+            return Err(last_error_code());
+        }
+        Ok(DriveHandle(fd as usize))
+    }
+
+    fn admin_command(&self, handle: DriveHandle, command: &NvmeAdminCommand, data: &mut [u8], timeout: Duration) -> Result<u32, u32> {
+        let mut passthru = NvmePassthruCmd {
+            opcode: command.opcode,
+            nsid: command.nsid,
+            addr: data.as_mut_ptr() as u64,      // data buffer
+            data_len: data.len() as u32,         // size of the data buffer
+            cdw10: command.cdw10,
+            cdw11: command.cdw11,
+            cdw12: command.cdw12,
+            cdw13: command.cdw13,
+            cdw14: command.cdw14,
+            cdw15: command.cdw15,
+            timeout_ms: timeout.as_millis().clamp(1, c_uint::MAX as u128) as u32, // the kernel aborts the command after this long, 0 would mean its default
+            ..NvmePassthruCmd::default()
+        };
+
+        // negative means the ioctl failed, positive is the nvme status field of the completion
+        match unsafe { libc::ioctl(handle.0 as c_int, NVME_IOCTL_ADMIN_CMD, &mut passthru) } {
+            0 => Ok(passthru.result),
+            status if status < 0 => Err(last_error_code()),
+            status => match status & 0x7FF {
+                NVME_SC_INVALID_OPCODE | NVME_SC_INVALID_FIELD | NVME_SC_INVALID_NS => Err(ERROR_NOT_SUPPORTED),
+                _ => Err(ERROR_GEN_FAILURE),
+            },
+        }
+    }
+
+    fn close(&self, handle: DriveHandle) {
+        unsafe { libc::close(handle.0 as c_int); }
+    }
+}
@@ -0,0 +1,118 @@
+// settings that control how HardDriveSerial scans drives
+
+use crate::drive_info::Method;
+use std::time::Duration;
+
+pub const DEFAULT_OPERATION_TIMEOUT: Duration = Duration::from_secs(10); // longest a single ioctl may stay pending
+pub const DEFAULT_SCAN_TIMEOUT: Duration = Duration::from_secs(60);      // longest a whole scan may take
+pub const DEFAULT_MAX_CONCURRENCY: usize = 16;                           // one thread per possible ioctl drive index
+pub const MIN_OPERATION_TIMEOUT: Duration = Duration::from_millis(1);    // shortest timeout handed to a transport, zero means no timeout to the kernel
+
+// which drives a scan probes
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum DriveSelection {
+    #[default]
+    All,                 // every drive index (ioctl) or every block device (sysfs)
+    Indices(Vec<usize>), // only these drive indices
+    Paths(Vec<String>),  // only these device paths, e.g. \\.\PhysicalDrive1 or /dev/sda
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanOptions {
+    operation_timeout: Duration,
+    scan_timeout: Duration,
+    methods: Vec<Method>,
+    drives: DriveSelection,
+    max_concurrency: usize,
+    stop_at_first_serial: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            operation_timeout: DEFAULT_OPERATION_TIMEOUT,
+            scan_timeout: DEFAULT_SCAN_TIMEOUT,
+            methods: vec![Method::AdminRights, Method::Smart, Method::ZeroRights, Method::Sysfs, Method::AtaPassThrough, Method::ScsiVpd, Method::NvmeIdentify],
+            drives: DriveSelection::All,
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            stop_at_first_serial: false,
+        }
+    }
+}
+
+impl ScanOptions {
+    pub fn new() -> Self {
+        ScanOptions::default()
+    }
+
+    // an ioctl still pending after this long is cancelled and its method reports a timeout
+    pub fn with_operation_timeout(mut self, timeout: Duration) -> Self {
+        self.operation_timeout = timeout;
+        self
+    }
+
+    // deadline for the whole scan, drives still being probed when it passes are reported as timed out
+    pub fn with_scan_timeout(mut self, timeout: Duration) -> Self {
+        self.scan_timeout = timeout;
+        self
+    }
+
+    // methods to run on every drive, in this order (each backend skips the methods it does not implement)
+    pub fn with_methods(mut self, methods: impl IntoIterator<Item = Method>) -> Self {
+        self.methods.clear();
+        for method in methods {
+            if !self.methods.contains(&method) {
+                self.methods.push(method);
+            }
+        }
+        self
+    }
+
+    // probes only these drive indices
+    pub fn with_drive_indices(mut self, indices: impl IntoIterator<Item = usize>) -> Self {
+        self.drives = DriveSelection::Indices(indices.into_iter().collect());
+        self
+    }
+
+    // probes only these device paths
+    pub fn with_drive_paths<S: Into<String>>(mut self, paths: impl IntoIterator<Item = S>) -> Self {
+        self.drives = DriveSelection::Paths(paths.into_iter().map(Into::into).collect());
+        self
+    }
+
+    // number of drives probed at the same time (at least one)
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    // skips the remaining methods on a drive once one of them produced a valid serial
+    pub fn with_stop_at_first_serial(mut self, stop: bool) -> Self {
+        self.stop_at_first_serial = stop;
+        self
+    }
+
+    pub fn operation_timeout(&self) -> Duration {
+        self.operation_timeout
+    }
+
+    pub fn scan_timeout(&self) -> Duration {
+        self.scan_timeout
+    }
+
+    pub fn methods(&self) -> &[Method] {
+        &self.methods
+    }
+
+    pub fn drives(&self) -> &DriveSelection {
+        &self.drives
+    }
+
+    pub fn max_concurrency(&self) -> usize {
+        self.max_concurrency
+    }
+
+    pub fn stop_at_first_serial(&self) -> bool {
+        self.stop_at_first_serial
+    }
+}
@@ -0,0 +1,369 @@
+// replacing the serials and unique identifiers in captured data before it is shared
+//
+// every identifying field is rewritten in place with a pseudonym of the same length and
+// layout: padding, character classes (digit, upper and lower case letter) and punctuation stay
+// where they were, hex encoded fields stay hex in the same case, and the IDENTIFY checksum is
+// adjusted so a block that verified before still verifies. the same input and key always give
+// the same pseudonym, so the scrubbed data still decodes along the same paths and the methods
+// still agree with each other
+//
+// serial characters are replaced by a substitution drawn per serial (seeded by the key and the
+// serial's sorted characters). a substitution commutes with the pair swapping and padding
+// shifts drivers apply, so a byte swapped or hex encoded copy of a serial scrubs to the byte
+// swapped or hex encoded copy of its pseudonym
+
+use crate::ata_passthrough::{ATA_IDENTIFY_DEVICE, ATA_PASS_THROUGH_12, ATA_PASS_THROUGH_16, SCSI_STATUS_GOOD};
+use crate::ata_string::AtaString;
+use crate::capture::{Capture, CapturedEvent};
+use crate::identify::IDENTIFY_CHECKSUM_SIGNATURE;
+use crate::nvme::{
+    parse_namespace_descriptors, CNS_CONTROLLER, CNS_NAMESPACE, CNS_NAMESPACE_DESCRIPTORS, NIDT_EUI64, NIDT_NGUID, NIDT_UUID, NVME_ADMIN_IDENTIFY,
+    NVME_IDENTIFY_DATA_SIZE,
+};
+use crate::scsi::{
+    parse_device_identification, INQUIRY, DESIGNATOR_EUI64, DESIGNATOR_NAA, DESIGNATOR_SCSI_NAME, DESIGNATOR_T10_VENDOR_ID, VPD_DEVICE_IDENTIFICATION,
+    VPD_UNIT_SERIAL_NUMBER,
+};
+use crate::storage_descriptor::{DescriptorError, StorageDeviceDescriptor};
+use crate::structs::{
+    DFP_RECEIVE_DRIVE_DATA, IDEREGS, IDE_ATA_IDENTIFY, IDENTIFY_BUFFER_SIZE, IOCTL_STORAGE_QUERY_PROPERTY, SENDCMDINPARAMS,
+    SENDCMDOUTPARAMS, ERROR_INVALID_DATA,
+};
+use crate::transport::{DataDirection, NvmeAdminCommand, ScsiResponse};
+use byteorder::{ByteOrder, LittleEndian};
+use std::mem::offset_of;
+
+const IDENTIFY_COMMAND_OFFSET: usize = offset_of!(SENDCMDINPARAMS, ir_drive_regs) + offset_of!(IDEREGS, b_command_reg); // command register in SENDCMDINPARAMS
+const IDENTIFY_DATA_OFFSET: usize = offset_of!(SENDCMDOUTPARAMS, b_buffer); // IDENTIFY block in SENDCMDOUTPARAMS
+const DESCRIPTOR_SERIAL_OFFSET: usize = 24; // SerialNumberOffset in STORAGE_DEVICE_DESCRIPTOR
+
+// rewrites identifying fields with deterministic pseudonyms
+#[derive(Debug, Clone)]
+pub struct Scrubber {
+    key: Vec<u8>,
+}
+
+impl Scrubber {
+    // scrubs with `key`, which has to stay secret: anyone who knows it can scrub a guessed serial
+    // and compare the pseudonyms. a fresh random key per capture also hides that two captures
+    // came from the same drive, one key kept across captures keeps their pseudonyms comparable
+    pub fn new(key: &[u8]) -> Self {
+        Scrubber { key: key.to_vec() }
+    }
+
+    // the pseudonym of a serial, e.g. "WD-WCC4E1234567" becomes "WD-" plus eight letters and digits in the same places
+    pub fn pseudonym(&self, serial: &str) -> String {
+        let mut bytes = serial.as_bytes().to_vec();
+        self.substitute(&mut bytes);
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    // rewrites the serial number (words 10-19) and world wide name (words 108-111) of an IDENTIFY block
+    pub fn scrub_identify(&self, block: &mut [u8; IDENTIFY_BUFFER_SIZE]) {
+        let sum_before = byte_sum(&block[..511]);
+
+        let mut serial = swap_pairs(&block[20..40]);
+        self.substitute(&mut serial);
+        block[20..40].copy_from_slice(&swap_pairs(&serial));
+
+        // NAA and IEEE OUI (the top 28 bits) name the vendor and are kept, the 36 bit unique id is replaced
+        let wwn = (108..112).fold(0u64, |wwn, word| wwn << 16 | LittleEndian::read_u16(&block[word * 2..word * 2 + 2]) as u64);
+        if wwn != 0 {
+            let unique_id = Stream::new(&self.key, "wwn", &wwn.to_be_bytes()).next_u64() & 0xF_FFFF_FFFF;
+            let wwn = wwn & !0xF_FFFF_FFFF | unique_id;
+            for (i, word) in (108..112).enumerate() {
+                LittleEndian::write_u16(&mut block[word * 2..word * 2 + 2], (wwn >> (48 - 16 * i)) as u16);
+            }
+        }
+
+        // move the checksum by as much as the data moved, a valid checksum stays valid and a bad one stays exactly as bad
+        if block[510] == IDENTIFY_CHECKSUM_SIGNATURE {
+            let delta = byte_sum(&block[..511]).wrapping_sub(sum_before);
+            block[511] = block[511].wrapping_sub(delta);
+        }
+    }
+
+    // rewrites the serial number string of a STORAGE_DEVICE_DESCRIPTOR, hex encoded serials stay hex encoded
+    //
+    // descriptors that do not parse are refused, not guessed at
+    pub fn scrub_storage_descriptor(&self, buffer: &mut [u8]) -> Result<(), DescriptorError> {
+        if StorageDeviceDescriptor::parse(buffer)?.serial.is_none() {
+            return Ok(());
+        }
+        // parse checked that the string starts inside the descriptor and ends in a nul before its size
+        let start = LittleEndian::read_u32(&buffer[DESCRIPTOR_SERIAL_OFFSET..DESCRIPTOR_SERIAL_OFFSET + 4]) as usize;
+        let len = buffer[start..].iter().position(|&byte| byte == 0).unwrap_or(0);
+        let field = &mut buffer[start..start + len];
+
+        let text = String::from_utf8_lossy(field).into_owned();
+        match AtaString::from_hex(&text) {
+            Some(_) => {
+                let upper_case = text.bytes().any(|digit| digit.is_ascii_uppercase());
+                let digits = text.trim();
+                let mut decoded: Vec<u8> = (0..digits.len()).step_by(2).map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap_or(0)).collect();
+                self.substitute(&mut decoded);
+                let encoded: String = decoded.iter().map(|byte| if upper_case { format!("{:02X}", byte) } else { format!("{:02x}", byte) }).collect();
+                let digits_start = text.find(digits).unwrap_or(0);
+                field[digits_start..digits_start + encoded.len()].copy_from_slice(encoded.as_bytes());
+            }
+            None => self.substitute(field),
+        }
+        Ok(())
+    }
+
+    // rewrites the serial number (bytes 4-23) of an NVMe Identify Controller structure, and the
+    // serial inside the subsystem NQN where the controller built the NQN from it
+    pub fn scrub_nvme_identify_controller(&self, data: &mut [u8; NVME_IDENTIFY_DATA_SIZE]) {
+        let serial = String::from_utf8_lossy(&data[4..24]).trim_matches(|c: char| c == ' ' || c == '\0').to_string();
+        self.substitute(&mut data[4..24]);
+        if serial.is_empty() {
+            return;
+        }
+        let pseudonym = self.pseudonym(&serial);
+        let nqn = &mut data[768..1024];
+        let mut i = 0;
+        while let Some(found) = nqn[i..].windows(serial.len()).position(|window| window == serial.as_bytes()) {
+            let at = i + found;
+            nqn[at..at + serial.len()].copy_from_slice(pseudonym.as_bytes());
+            i = at + serial.len();
+        }
+    }
+
+    // rewrites the NGUID (bytes 104-119) and EUI-64 (bytes 120-127) of an NVMe Identify Namespace structure
+    pub fn scrub_nvme_identify_namespace(&self, data: &mut [u8; NVME_IDENTIFY_DATA_SIZE]) {
+        self.scrub_identifier(NIDT_NGUID, &mut data[104..120]);
+        self.scrub_identifier(NIDT_EUI64, &mut data[120..128]);
+    }
+
+    // rewrites the EUI-64, NGUID and UUID entries of a Namespace Identification Descriptor list
+    // the same way as the Identify Namespace fields, ERROR_INVALID_DATA if the list does not parse
+    pub fn scrub_namespace_descriptors(&self, data: &mut [u8]) -> Result<(), u32> {
+        parse_namespace_descriptors(data)?;
+        let mut offset = 0;
+        while offset + 4 <= data.len() && data[offset] != 0 {
+            let (kind, len) = (data[offset], data[offset + 1] as usize);
+            let value = data.get_mut(offset + 4..offset + 4 + len).ok_or(ERROR_INVALID_DATA)?;
+            if matches!(kind, NIDT_EUI64 | NIDT_NGUID | NIDT_UUID) {
+                self.scrub_identifier(kind, value);
+            }
+            offset += 4 + len;
+        }
+        Ok(())
+    }
+
+    // rewrites the serial number of a unit serial number vpd page (0x80), ERROR_INVALID_DATA if it is not one
+    pub fn scrub_unit_serial_number(&self, page: &mut [u8]) -> Result<(), u32> {
+        if page.len() < 4 || page[1] != VPD_UNIT_SERIAL_NUMBER {
+            return Err(ERROR_INVALID_DATA);
+        }
+        let end = (4 + u16::from_be_bytes([page[2], page[3]]) as usize).min(page.len());
+        self.substitute(&mut page[4..end]);
+        Ok(())
+    }
+
+    // rewrites the EUI-64, NAA, T10 vendor id and SCSI name designators of a device identification
+    // vpd page (0x83), ERROR_INVALID_DATA if the page does not parse
+    //
+    // the IEEE OUI of EUI-64 and NAA designators and the vendor of T10 vendor ids are kept
+    pub fn scrub_device_identification(&self, page: &mut [u8]) -> Result<(), u32> {
+        parse_device_identification(page)?;
+        let end = (4 + u16::from_be_bytes([page[2], page[3]]) as usize).min(page.len());
+        let mut offset = 4;
+        while offset + 4 <= end {
+            let (designator_type, len) = (page[offset + 1] & 0x0F, page[offset + 3] as usize);
+            let value = &mut page[offset + 4..offset + 4 + len];
+            match designator_type {
+                DESIGNATOR_EUI64 => self.scrub_identifier(NIDT_EUI64, value),
+                DESIGNATOR_NAA if len >= 4 => {
+                    let naa_and_oui = value[3] & 0xF0; // the NAA nibble shifts the OUI half a byte further
+                    self.scrub_identifier(NIDT_EUI64, value);
+                    value[3] = value[3] & 0x0F | naa_and_oui;
+                }
+                DESIGNATOR_T10_VENDOR_ID if len > 8 => self.substitute(&mut value[8..]),
+                DESIGNATOR_SCSI_NAME => self.substitute(value),
+                _ => {}
+            }
+            offset += 4 + len;
+        }
+        Ok(())
+    }
+
+    // scrubs the IDENTIFY blocks, storage descriptors, vpd pages and NVMe identify structures a
+    // capture recorded, returns how many responses were rewritten
+    //
+    // structures that do not parse are left as they are, check the capture by hand if the count is short
+    pub fn scrub_capture(&self, capture: &mut Capture) -> usize {
+        let mut scrubbed = 0;
+        for event in &mut capture.events {
+            let rewritten = match event {
+                CapturedEvent::Ioctl { control_code, input, output, output_len, result: Ok(_), .. } => {
+                    rewrite(output, *output_len, |buffer| self.scrub_ioctl_output(*control_code, input, buffer))
+                }
+                CapturedEvent::Scsi { cdb, direction: DataDirection::FromDevice, data, data_len, result: Ok(ScsiResponse { status: SCSI_STATUS_GOOD, .. }), .. } => {
+                    rewrite(data, *data_len, |buffer| self.scrub_scsi_data(cdb, buffer))
+                }
+                CapturedEvent::Nvme { command, data, data_len, result: Ok(_), .. } => rewrite(data, *data_len, |buffer| self.scrub_nvme_data(command, buffer)),
+                _ => false,
+            };
+            if rewritten {
+                scrubbed += 1;
+            }
+        }
+        scrubbed
+    }
+
+    fn scrub_ioctl_output(&self, control_code: u32, input: &[u8], buffer: &mut [u8]) -> bool {
+        match control_code {
+            DFP_RECEIVE_DRIVE_DATA if input.get(IDENTIFY_COMMAND_OFFSET) == Some(&IDE_ATA_IDENTIFY) => {
+                match buffer.get_mut(IDENTIFY_DATA_OFFSET..IDENTIFY_DATA_OFFSET + IDENTIFY_BUFFER_SIZE) {
+                    Some(block) => {
+                        self.scrub_identify(block.try_into().unwrap());
+                        true
+                    }
+                    None => false,
+                }
+            }
+            IOCTL_STORAGE_QUERY_PROPERTY => self.scrub_storage_descriptor(buffer).is_ok(),
+            _ => false,
+        }
+    }
+
+    // IDENTIFY DEVICE through ATA PASS-THROUGH and the serial and identification vpd pages
+    fn scrub_scsi_data(&self, cdb: &[u8], buffer: &mut [u8]) -> bool {
+        let ata_command = match cdb.first() {
+            Some(&ATA_PASS_THROUGH_12) => cdb.get(9),
+            Some(&ATA_PASS_THROUGH_16) => cdb.get(14),
+            _ => None,
+        };
+        if ata_command == Some(&ATA_IDENTIFY_DEVICE) {
+            return match buffer.get_mut(..IDENTIFY_BUFFER_SIZE) {
+                Some(block) => {
+                    self.scrub_identify(block.try_into().unwrap());
+                    true
+                }
+                None => false,
+            };
+        }
+        let vpd_page = match cdb {
+            [INQUIRY, evpd, page, ..] if evpd & 0x01 != 0 => *page,
+            _ => return false,
+        };
+        match vpd_page {
+            VPD_UNIT_SERIAL_NUMBER => self.scrub_unit_serial_number(buffer).is_ok(),
+            VPD_DEVICE_IDENTIFICATION => self.scrub_device_identification(buffer).is_ok(),
+            _ => false,
+        }
+    }
+
+    // Identify Controller, Identify Namespace and the namespace identification descriptor list
+    fn scrub_nvme_data(&self, command: &NvmeAdminCommand, buffer: &mut [u8]) -> bool {
+        if command.opcode != NVME_ADMIN_IDENTIFY {
+            return false;
+        }
+        let structure: Option<&mut [u8; NVME_IDENTIFY_DATA_SIZE]> = buffer.get_mut(..NVME_IDENTIFY_DATA_SIZE).and_then(|data| data.try_into().ok());
+        match (command.cdw10 as u8, structure) {
+            (CNS_CONTROLLER, Some(data)) => self.scrub_nvme_identify_controller(data),
+            (CNS_NAMESPACE, Some(data)) => self.scrub_nvme_identify_namespace(data),
+            (CNS_NAMESPACE_DESCRIPTORS, _) => return self.scrub_namespace_descriptors(buffer).is_ok(),
+            _ => return false,
+        }
+        true
+    }
+
+    // replaces an EUI-64, NGUID or UUID, all zero means not reported and is kept
+    fn scrub_identifier(&self, kind: u8, value: &mut [u8]) {
+        if value.iter().all(|&byte| byte == 0) {
+            return;
+        }
+        let original = value.to_vec();
+        let mut stream = Stream::new(&self.key, &format!("identifier {}", kind), &original);
+        let kept = match kind {
+            NIDT_EUI64 => 3, // IEEE OUI
+            _ => 0,
+        };
+        for byte in &mut value[kept..] {
+            *byte = stream.next_u64() as u8;
+        }
+        if kind == NIDT_UUID && value.len() == 16 {
+            value[6] = value[6] & 0x0F | original[6] & 0xF0; // version
+            value[8] = value[8] & 0x3F | original[8] & 0xC0; // variant
+        }
+    }
+
+    // substitutes the digits and letters of `text` in place, everything else (padding, dashes, dots) stays
+    fn substitute(&self, text: &mut [u8]) {
+        let mut seed: Vec<u8> = text.iter().copied().filter(u8::is_ascii_alphanumeric).collect();
+        if seed.is_empty() {
+            return;
+        }
+        seed.sort_unstable();
+        let mut stream = Stream::new(&self.key, "serial", &seed);
+        let digits = stream.permutation(b'0', 10);
+        let upper = stream.permutation(b'A', 26);
+        let lower = stream.permutation(b'a', 26);
+        for byte in text.iter_mut() {
+            *byte = match *byte {
+                b'0'..=b'9' => digits[(*byte - b'0') as usize],
+                b'A'..=b'Z' => upper[(*byte - b'A') as usize],
+                b'a'..=b'z' => lower[(*byte - b'a') as usize],
+                other => other,
+            };
+        }
+    }
+}
+
+// deterministic pseudo random numbers from a key, a purpose and an input (FNV-1a seeded splitmix64)
+struct Stream(u64);
+
+impl Stream {
+    fn new(key: &[u8], purpose: &str, input: &[u8]) -> Self {
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        for &byte in key.iter().chain(&[0]).chain(purpose.as_bytes()).chain(&[0]).chain(input) {
+            hash = (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        Stream(hash)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // the `count` characters from `first` on, shuffled
+    fn permutation(&mut self, first: u8, count: u8) -> Vec<u8> {
+        let mut characters: Vec<u8> = (first..first + count).collect();
+        for i in (1..characters.len()).rev() {
+            let j = (self.next_u64() % (i as u64 + 1)) as usize;
+            characters.swap(i, j);
+        }
+        characters
+    }
+}
+
+// runs `scrub` on a recorded buffer padded back to its size, and stores the result without its trailing zeros
+fn rewrite(recorded: &mut Vec<u8>, len: usize, scrub: impl FnOnce(&mut [u8]) -> bool) -> bool {
+    let mut buffer = recorded.clone();
+    buffer.resize(len.max(buffer.len()), 0);
+    if !scrub(&mut buffer) {
+        return false;
+    }
+    let end = buffer.iter().rposition(|&byte| byte != 0).map_or(0, |last| last + 1);
+    buffer.truncate(end);
+    *recorded = buffer;
+    true
+}
+
+fn byte_sum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+fn swap_pairs(bytes: &[u8]) -> Vec<u8> {
+    let mut swapped = bytes.to_vec();
+    for pair in swapped.chunks_exact_mut(2) {
+        pair.swap(0, 1);
+    }
+    swapped
+}
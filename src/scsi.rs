@@ -0,0 +1,211 @@
+// SCSI INQUIRY and the vital product data (VPD) pages that identify a logical unit
+//
+// the parsers only work on bytes, so the same code decodes pages read through a
+// ScsiTransport and the raw vpd_pg80 / vpd_pg83 attributes linux exposes in sysfs
+
+use crate::ata_passthrough::{check_response, SENSE_BUFFER_SIZE};
+use crate::drive_info::DriveIdentifier;
+use crate::transport::{DataDirection, DriveHandle, ScsiTransport, ERROR_INVALID_DATA};
+use std::time::Duration;
+
+pub const INQUIRY: u8 = 0x12; // operation code
+
+pub const VPD_SUPPORTED_PAGES: u8 = 0x00; // vpd page codes
+pub const VPD_UNIT_SERIAL_NUMBER: u8 = 0x80;
+pub const VPD_DEVICE_IDENTIFICATION: u8 = 0x83;
+
+pub const STANDARD_INQUIRY_SIZE: usize = 36; // up to and including the product revision level
+pub const VPD_BUFFER_SIZE: usize = 1024;     // allocation length for the vpd pages, room for the designators of a multi-port SAS device
+
+pub const DESIGNATOR_VENDOR_SPECIFIC: u8 = 0x0; // designator types in the device identification page
+pub const DESIGNATOR_T10_VENDOR_ID: u8 = 0x1;
+pub const DESIGNATOR_EUI64: u8 = 0x2;
+pub const DESIGNATOR_NAA: u8 = 0x3;
+pub const DESIGNATOR_SCSI_NAME: u8 = 0x8;
+
+// builds a 6 byte INQUIRY CDB, for the standard data if `vpd_page` is None
+pub fn inquiry_cdb(vpd_page: Option<u8>, allocation_length: u16) -> [u8; 6] {
+    let [length_high, length_low] = allocation_length.to_be_bytes();
+    match vpd_page {
+        Some(page) => [INQUIRY, 0x01, page, length_high, length_low, 0], // EVPD set
+        None => [INQUIRY, 0x00, 0x00, length_high, length_low, 0],
+    }
+}
+
+// the standard INQUIRY data
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StandardInquiry {
+    pub peripheral_qualifier: u8,   // byte 0 bits 7-5, 0 when a device is connected
+    pub peripheral_device_type: u8, // byte 0 bits 4-0, 0 for a direct access block device
+    pub removable: bool,            // byte 1 bit 7
+    pub version: u8,                // SPC version the device claims, byte 2
+    pub vendor: String,             // T10 vendor identification, bytes 8-15
+    pub product: String,            // bytes 16-31
+    pub revision: String,           // bytes 32-35
+}
+
+impl StandardInquiry {
+    // decodes standard INQUIRY data, None if it is shorter than 36 bytes
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < STANDARD_INQUIRY_SIZE {
+            return None;
+        }
+        Some(StandardInquiry {
+            peripheral_qualifier: data[0] >> 5,
+            peripheral_device_type: data[0] & 0x1F,
+            removable: data[1] & 0x80 != 0,
+            version: data[2],
+            vendor: scsi_string(&data[8..16]),
+            product: scsi_string(&data[16..32]),
+            revision: scsi_string(&data[32..36]),
+        })
+    }
+}
+
+// the payload of a vpd page after checking its page code, cut to the page length
+fn vpd_payload(page: &[u8], page_code: u8) -> Option<&[u8]> {
+    if page.len() < 4 || page[1] != page_code {
+        return None;
+    }
+    let length = u16::from_be_bytes([page[2], page[3]]) as usize;
+    Some(page.get(4..4 + length).unwrap_or(&page[4..])) // tolerate pages cut short by the allocation length
+}
+
+// extracts the product serial number from a unit serial number page (0x80)
+pub fn parse_unit_serial_number(page: &[u8]) -> Option<String> {
+    let serial = scsi_string(vpd_payload(page, VPD_UNIT_SERIAL_NUMBER)?);
+    if serial.is_empty() {
+        None
+    } else {
+        Some(serial)
+    }
+}
+
+// what a designation descriptor is attached to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Association {
+    LogicalUnit,  // the addressed logical unit, i.e. the disk itself
+    TargetPort,   // the port the command came in through
+    TargetDevice, // the SCSI target device containing the logical unit
+    Reserved,
+}
+
+// the designators of a device identification page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Designator {
+    VendorSpecific(Vec<u8>),
+    T10VendorId { vendor: String, identifier: String }, // e.g. ATA + model and serial for SATA disks behind a SAT layer
+    Eui64(Vec<u8>),                                     // 8, 12 or 16 bytes
+    Naa(Vec<u8>),                                       // 8 or 16 bytes, the NAA type is the top nibble
+    ScsiName(String),                                   // e.g. iqn.2003-01.org.linux-iscsi...
+    Other { designator_type: u8, value: Vec<u8> },      // port numbers, groups, MD5 and UUID designators
+}
+
+// one entry of the device identification page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DesignationDescriptor {
+    pub protocol_identifier: u8, // only meaningful when PIV is set
+    pub code_set: u8,            // 1 binary, 2 ascii, 3 utf-8
+    pub association: Association,
+    pub designator: Designator,
+}
+
+impl DesignationDescriptor {
+    // the designator as a drive identifier, for the ones that name the logical unit worldwide
+    pub fn identifier(&self) -> Option<DriveIdentifier> {
+        if self.association != Association::LogicalUnit {
+            return None;
+        }
+        match &self.designator {
+            Designator::Naa(bytes) => Some(DriveIdentifier::Naa(bytes.clone())),
+            Designator::Eui64(bytes) => match bytes.len() {
+                8 => Some(DriveIdentifier::Eui64(bytes.as_slice().try_into().ok()?)),
+                16 => Some(DriveIdentifier::Nguid(bytes.as_slice().try_into().ok()?)),
+                _ => None,
+            },
+            Designator::ScsiName(name) => Some(DriveIdentifier::ScsiName(name.clone())),
+            Designator::T10VendorId { vendor, identifier } => Some(DriveIdentifier::T10VendorId(format!("{:<8}{}", vendor, identifier))),
+            _ => None,
+        }
+    }
+}
+
+// decodes a device identification page (0x83)
+//
+// every descriptor is a 4 byte header followed by the designator, a descriptor that runs
+// past the page or a page with the wrong code is reported as ERROR_INVALID_DATA
+pub fn parse_device_identification(page: &[u8]) -> Result<Vec<DesignationDescriptor>, u32> {
+    let payload = vpd_payload(page, VPD_DEVICE_IDENTIFICATION).ok_or(ERROR_INVALID_DATA)?;
+    let mut descriptors = Vec::new();
+    let mut offset = 0;
+    while offset + 4 <= payload.len() {
+        let header = &payload[offset..offset + 4];
+        let value = payload.get(offset + 4..offset + 4 + header[3] as usize).ok_or(ERROR_INVALID_DATA)?;
+        let designator_type = header[1] & 0x0F;
+        let designator = match designator_type {
+            DESIGNATOR_VENDOR_SPECIFIC => Designator::VendorSpecific(value.to_vec()),
+            DESIGNATOR_T10_VENDOR_ID if value.len() >= 8 => {
+                Designator::T10VendorId { vendor: scsi_string(&value[..8]), identifier: scsi_string(&value[8..]) }
+            }
+            DESIGNATOR_EUI64 => Designator::Eui64(value.to_vec()),
+            DESIGNATOR_NAA => Designator::Naa(value.to_vec()),
+            DESIGNATOR_SCSI_NAME => Designator::ScsiName(scsi_string(value)),
+            _ => Designator::Other { designator_type, value: value.to_vec() },
+        };
+        descriptors.push(DesignationDescriptor {
+            protocol_identifier: header[0] >> 4,
+            code_set: header[0] & 0x0F,
+            association: match (header[1] >> 4) & 0x03 {
+                0 => Association::LogicalUnit,
+                1 => Association::TargetPort,
+                2 => Association::TargetDevice,
+                _ => Association::Reserved,
+            },
+            designator,
+        });
+        offset += 4 + value.len();
+    }
+    Ok(descriptors)
+}
+
+// the worldwide identifiers of the logical unit in a device identification page, without duplicates
+pub fn logical_unit_identifiers(descriptors: &[DesignationDescriptor]) -> Vec<DriveIdentifier> {
+    let mut identifiers: Vec<DriveIdentifier> = Vec::new();
+    for identifier in descriptors.iter().filter_map(DesignationDescriptor::identifier) {
+        if !identifiers.contains(&identifier) {
+            identifiers.push(identifier);
+        }
+    }
+    identifiers
+}
+
+// ascii fields in SCSI data are space padded, sometimes nul terminated
+fn scsi_string(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).trim_matches(|c: char| c.is_whitespace() || c == '\0').to_string()
+}
+
+// sends an INQUIRY and returns the bytes the device transferred
+fn inquiry(transport: &dyn ScsiTransport, handle: DriveHandle, vpd_page: Option<u8>, allocation_length: usize, timeout: Duration) -> Result<Vec<u8>, u32> {
+    let mut data = vec![0u8; allocation_length];
+    let mut sense = [0u8; SENSE_BUFFER_SIZE];
+    let cdb = inquiry_cdb(vpd_page, allocation_length as u16);
+    let response = transport.execute(handle, &cdb, DataDirection::FromDevice, &mut data, &mut sense, timeout)?;
+    check_response(&response, &sense)?;
+    data.truncate(response.transferred);
+    Ok(data)
+}
+
+// reads the standard INQUIRY data
+pub fn standard_inquiry(transport: &dyn ScsiTransport, handle: DriveHandle, timeout: Duration) -> Result<StandardInquiry, u32> {
+    StandardInquiry::parse(&inquiry(transport, handle, None, STANDARD_INQUIRY_SIZE, timeout)?).ok_or(ERROR_INVALID_DATA)
+}
+
+// reads the unit serial number page, None if the device has no serial in it
+pub fn unit_serial_number(transport: &dyn ScsiTransport, handle: DriveHandle, timeout: Duration) -> Result<Option<String>, u32> {
+    Ok(parse_unit_serial_number(&inquiry(transport, handle, Some(VPD_UNIT_SERIAL_NUMBER), VPD_BUFFER_SIZE, timeout)?))
+}
+
+// reads and decodes the device identification page
+pub fn device_identification(transport: &dyn ScsiTransport, handle: DriveHandle, timeout: Duration) -> Result<Vec<DesignationDescriptor>, u32> {
+    parse_device_identification(&inquiry(transport, handle, Some(VPD_DEVICE_IDENTIFICATION), VPD_BUFFER_SIZE, timeout)?)
+}
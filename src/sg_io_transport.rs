@@ -0,0 +1,120 @@
+use crate::structs::*;
+use crate::errno::last_error_code;
+use crate::transport::{DataDirection, DriveHandle, ScsiResponse, ScsiTransport};
+use std::ffi::CString;
+use std::os::raw::{c_int, c_uchar, c_uint, c_ushort, c_void};
+use std::time::Duration;
+
+const SG_IO: libc::c_ulong = 0x2285; // ioctl request, from <scsi/sg.h>
+const SG_INTERFACE_ID_ORIG: c_int = b'S' as c_int;
+
+const SG_DXFER_NONE: c_int = -1; // transfer directions
+const SG_DXFER_TO_DEV: c_int = -2;
+const SG_DXFER_FROM_DEV: c_int = -3;
+
+const SG_INFO_OK_MASK: c_uint = 0x1; // info is OK unless this bit is set
+const DID_TIME_OUT: c_ushort = 0x03; // host_status
+const DRIVER_TIMEOUT: c_ushort = 0x06; // driver_status, low nibble
+const DRIVER_SENSE: c_ushort = 0x08; // sense data was written, not an error by itself
+
+// mirrors struct sg_io_hdr from <scsi/sg.h>
+#[repr(C)]
+struct SgIoHdr {
+    interface_id: c_int,
+    dxfer_direction: c_int,
+    cmd_len: c_uchar,
+    mx_sb_len: c_uchar,
+    iovec_count: c_ushort,
+    dxfer_len: c_uint,
+    dxferp: *mut c_void,
+    cmdp: *const c_uchar,
+    sbp: *mut c_uchar,
+    timeout: c_uint, // milliseconds
+    flags: c_uint,
+    pack_id: c_int,
+    usr_ptr: *mut c_void,
+    status: c_uchar,
+    masked_status: c_uchar,
+    msg_status: c_uchar,
+    sb_len_wr: c_uchar,
+    host_status: c_ushort,
+    driver_status: c_ushort,
+    resid: c_int,
+    duration: c_uint,
+    info: c_uint,
+}
+
+// ScsiTransport backed by the linux SG_IO ioctl on /dev/sdX or /dev/sgN
+//
+// ata pass-through needs CAP_SYS_RAWIO, without it the kernel answers EPERM (access denied)
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SgIoTransport;
+
+impl ScsiTransport for SgIoTransport {
+    fn open(&self, path: &str) -> Result<DriveHandle, u32> {
+        let device_name = CString::new(path).map_err(|_| ERROR_INVALID_NAME)?; // open needs a nul terminated name
+        let fd = unsafe { libc::open(device_name.as_ptr(), libc::O_RDONLY | libc::O_NONBLOCK) };
+        if fd < 0 {
+            return Err(last_error_code());
+        }
+        Ok(DriveHandle(fd as usize))
+    }
+
+    fn execute(&self, handle: DriveHandle, cdb: &[u8], direction: DataDirection, data: &mut [u8], sense: &mut [u8], timeout: Duration) -> Result<ScsiResponse, u32> {
+        let dxfer_direction = match direction {
+            DataDirection::None => SG_DXFER_NONE,
+            DataDirection::FromDevice => SG_DXFER_FROM_DEV,
+            DataDirection::ToDevice => SG_DXFER_TO_DEV,
+        };
+        let dxfer_len = if direction == DataDirection::None { 0 } else { data.len() };
+
+        let mut header = SgIoHdr {
+            interface_id: SG_INTERFACE_ID_ORIG,
+            dxfer_direction,                                          // direction of the data phase
+            cmd_len: cdb.len() as c_uchar,                            // size of the CDB
+            mx_sb_len: sense.len().min(u8::MAX as usize) as c_uchar,  // room for sense data
+            iovec_count: 0,                                           // no scatter gather
+            dxfer_len: dxfer_len as c_uint,                           // size of the data buffer
+            dxferp: data.as_mut_ptr() as *mut c_void,                 // data buffer
+            cmdp: cdb.as_ptr(),                                       // the CDB
+            sbp: sense.as_mut_ptr(),                                  // sense buffer
+            timeout: timeout.as_millis().clamp(1, c_uint::MAX as u128 - 1) as c_uint, // the kernel aborts the command after this long, 0 would mean its default
+            flags: 0,
+            pack_id: 0,
+            usr_ptr: std::ptr::null_mut(),
+            status: 0,
+            masked_status: 0,
+            msg_status: 0,
+            sb_len_wr: 0,
+            host_status: 0,
+            driver_status: 0,
+            resid: 0,
+            duration: 0,
+            info: 0,
+        };
+
+        if unsafe { libc::ioctl(handle.0 as c_int, SG_IO, &mut header) } < 0 {
+            return Err(last_error_code());
+        }
+
+        if header.info & SG_INFO_OK_MASK != 0 {
+            // the command did not complete cleanly, tell transport problems apart from device answers
+            if header.host_status == DID_TIME_OUT || header.driver_status & 0x0F == DRIVER_TIMEOUT {
+                return Err(ERROR_TIMEOUT);
+            }
+            if header.host_status != 0 || header.driver_status & !DRIVER_SENSE != 0 {
+                return Err(ERROR_GEN_FAILURE);
+            }
+        }
+
+        Ok(ScsiResponse {
+            status: header.status,
+            sense_len: header.sb_len_wr as usize,
+            transferred: dxfer_len.saturating_sub(header.resid.max(0) as usize),
+        })
+    }
+
+    fn close(&self, handle: DriveHandle) {
+        unsafe { libc::close(handle.0 as c_int); }
+    }
+}
@@ -0,0 +1,146 @@
+// decoding of the ATA SMART attribute table and its thresholds
+//
+// both SMART READ DATA (feature D0h) and SMART READ ATTRIBUTE THRESHOLDS (feature D1h) answer
+// with a 512 byte block holding 30 twelve byte entries from offset 2, the parsers only work
+// on those blocks so they can be checked against canned data
+
+use byteorder::{ByteOrder, LittleEndian};
+
+pub const SMART_BLOCK_SIZE: usize = 512;       // size of the data and threshold blocks
+pub const SMART_ATTRIBUTE_COUNT: usize = 30;   // entries in either table
+const SMART_ENTRY_SIZE: usize = 12;
+const SMART_TABLE_OFFSET: usize = 2;           // after the 2 byte revision number
+
+pub const ATTRIBUTE_FLAG_PRE_FAIL: u16 = 0x0001; // failing the threshold predicts imminent failure
+pub const ATTRIBUTE_FLAG_ONLINE: u16 = 0x0002;   // updated during normal operation, not only offline scans
+
+// what a failing attribute means for the drive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AttributeKind {
+    PreFail, // the drive is about to fail
+    OldAge,  // the drive is past its design life
+}
+
+// how the attribute values compare to the threshold, like the WHEN_FAILED column of smartctl
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ThresholdStatus {
+    Ok,            // current and worst are above the threshold, or there is none to compare with
+    FailingNow,    // the current value is at or below the threshold
+    FailedInPast,  // the current value recovered, but the worst value reached the threshold
+}
+
+// one entry of the attribute table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SmartAttribute {
+    pub id: u8,                 // e.g. 5 for reallocated sectors, 194 for temperature
+    pub flags: u16,
+    pub current: u8,            // normalized value, usually 1-253 with higher being better
+    pub worst: u8,              // lowest normalized value seen
+    pub raw: u64,               // 48 bit vendor specific raw value
+    pub threshold: Option<u8>,  // normalized failure threshold, None if the thresholds were not read
+}
+
+impl SmartAttribute {
+    pub fn kind(&self) -> AttributeKind {
+        if self.flags & ATTRIBUTE_FLAG_PRE_FAIL != 0 {
+            AttributeKind::PreFail
+        } else {
+            AttributeKind::OldAge
+        }
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.flags & ATTRIBUTE_FLAG_ONLINE != 0
+    }
+
+    // a threshold of 0 means the attribute can never fail, above FDh it is invalid
+    pub fn status(&self) -> ThresholdStatus {
+        match self.threshold {
+            Some(threshold @ 0x01..=0xFD) if self.current <= threshold => ThresholdStatus::FailingNow,
+            Some(threshold @ 0x01..=0xFD) if self.worst <= threshold => ThresholdStatus::FailedInPast,
+            _ => ThresholdStatus::Ok,
+        }
+    }
+}
+
+// the decoded attribute table of one drive
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SmartAttributes {
+    pub revision: u16,                   // data structure revision number
+    pub attributes: Vec<SmartAttribute>, // non-empty entries, in table order
+    pub checksum_valid: bool,            // whether the data block sums to zero (some drives never set it)
+}
+
+impl SmartAttributes {
+    // decodes a SMART READ DATA block, matching thresholds by attribute id if given
+    pub fn parse(data: &[u8; SMART_BLOCK_SIZE], thresholds: Option<&[u8; SMART_BLOCK_SIZE]>) -> Self {
+        let thresholds: Vec<(u8, u8)> = match thresholds {
+            Some(thresholds) => smart_entries(thresholds).map(|entry| (entry[0], entry[1])).collect(),
+            None => Vec::new(),
+        };
+        let attributes = smart_entries(data)
+            .map(|entry| SmartAttribute {
+                id: entry[0],
+                flags: LittleEndian::read_u16(&entry[1..3]),
+                current: entry[3],
+                worst: entry[4],
+                raw: LittleEndian::read_u48(&entry[5..11]),
+                threshold: thresholds.iter().find(|(id, _)| *id == entry[0]).map(|(_, threshold)| *threshold),
+            })
+            .collect();
+
+        SmartAttributes {
+            revision: LittleEndian::read_u16(&data[0..2]),
+            attributes,
+            checksum_valid: smart_checksum(data) == data[SMART_BLOCK_SIZE - 1],
+        }
+    }
+
+    // the attribute with the given id, if the drive reports it
+    pub fn get(&self, id: u8) -> Option<&SmartAttribute> {
+        self.attributes.iter().find(|attribute| attribute.id == id)
+    }
+
+    // attributes whose current value is at or below their threshold
+    pub fn failing_now(&self) -> impl Iterator<Item = &SmartAttribute> {
+        self.attributes.iter().filter(|attribute| attribute.status() == ThresholdStatus::FailingNow)
+    }
+}
+
+// the non-empty twelve byte entries of a data or threshold block
+fn smart_entries(block: &[u8; SMART_BLOCK_SIZE]) -> impl Iterator<Item = &[u8]> {
+    block[SMART_TABLE_OFFSET..SMART_TABLE_OFFSET + SMART_ATTRIBUTE_COUNT * SMART_ENTRY_SIZE]
+        .chunks_exact(SMART_ENTRY_SIZE)
+        .filter(|entry| entry[0] != 0) // id 0 marks an unused entry
+}
+
+// the byte that makes the first 511 bytes of a SMART block sum to zero
+pub fn smart_checksum(block: &[u8; SMART_BLOCK_SIZE]) -> u8 {
+    block[..SMART_BLOCK_SIZE - 1].iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)).wrapping_neg()
+}
+
+// the overall verdict of a drive's self assessment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HealthVerdict {
+    Passed,
+    Failing, // ATA: a pre-fail attribute crossed its threshold, NVMe: a critical warning is set
+}
+
+// result of a quick health check, without downloading the attribute table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Health {
+    pub verdict: HealthVerdict,
+    pub critical_warning: Option<u8>, // NVMe critical warning bits the verdict came from, None on ATA
+}
+
+// decodes the lba mid/high registers SMART RETURN STATUS answers with
+//
+// 4Fh/C2h means no threshold exceeded, F4h/2Ch means one was, anything else means
+// the registers did not make it back (common with lossy USB bridges)
+pub fn decode_return_status(lba_mid: u8, lba_high: u8) -> Option<HealthVerdict> {
+    match (lba_mid, lba_high) {
+        (0x4F, 0xC2) => Some(HealthVerdict::Passed),
+        (0xF4, 0x2C) => Some(HealthVerdict::Failing),
+        _ => None,
+    }
+}
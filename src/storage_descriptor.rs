@@ -0,0 +1,93 @@
+// bounds checked decoding of the STORAGE_DEVICE_DESCRIPTOR returned by IOCTL_STORAGE_QUERY_PROPERTY
+//
+// the descriptor is a fixed 36 byte header followed by nul terminated strings the header
+// points at with byte offsets. nothing in it is trusted: the size has to fit the buffer and
+// every string has to start and end inside the descriptor
+
+use crate::drive_info::BusType;
+use crate::structs::STORAGE_DEVICE_DESCRIPTOR;
+use byteorder::{ByteOrder, LittleEndian};
+use std::error::Error;
+use std::fmt;
+
+pub const STORAGE_DEVICE_DESCRIPTOR_HEADER_SIZE: usize = std::mem::offset_of!(STORAGE_DEVICE_DESCRIPTOR, raw_device_properties); // 36, up to and including RawPropertiesLength
+
+// why a buffer was not accepted as a STORAGE_DEVICE_DESCRIPTOR
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescriptorError {
+    TooShort { len: usize },                                    // the buffer cannot hold the header
+    BadSize { size: usize, len: usize },                        // the size field is smaller than the header or larger than the buffer
+    OffsetOutOfRange { field: &'static str, offset: usize },    // a string offset points into the header or past the descriptor
+    Unterminated { field: &'static str, offset: usize },        // a string runs to the end of the descriptor without a nul
+}
+
+impl fmt::Display for DescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DescriptorError::TooShort { len } => write!(f, "STORAGE_DEVICE_DESCRIPTOR truncated to {} bytes", len),
+            DescriptorError::BadSize { size, len } => write!(f, "STORAGE_DEVICE_DESCRIPTOR claims {} bytes in a {} byte buffer", size, len),
+            DescriptorError::OffsetOutOfRange { field, offset } => write!(f, "STORAGE_DEVICE_DESCRIPTOR {} offset {} is out of range", field, offset),
+            DescriptorError::Unterminated { field, offset } => write!(f, "STORAGE_DEVICE_DESCRIPTOR {} at offset {} is not nul terminated", field, offset),
+        }
+    }
+}
+
+impl Error for DescriptorError {}
+
+// the decoded descriptor, strings the device did not report are None
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StorageDeviceDescriptor {
+    pub version: u32,
+    pub device_type: u8,          // SCSI peripheral device type, 0 for a disk
+    pub device_type_modifier: u8,
+    pub removable: bool,          // RemovableMedia
+    pub command_queueing: bool,
+    pub vendor: Option<String>,
+    pub product: Option<String>,
+    pub revision: Option<String>,
+    pub serial: Option<String>,
+    pub bus_type: BusType,
+}
+
+impl StorageDeviceDescriptor {
+    // decodes a descriptor from the start of `buffer`, which may be longer than the descriptor
+    pub fn parse(buffer: &[u8]) -> Result<Self, DescriptorError> {
+        if buffer.len() < STORAGE_DEVICE_DESCRIPTOR_HEADER_SIZE {
+            return Err(DescriptorError::TooShort { len: buffer.len() });
+        }
+        let size = LittleEndian::read_u32(&buffer[4..8]) as usize;
+        if size < STORAGE_DEVICE_DESCRIPTOR_HEADER_SIZE || size > buffer.len() {
+            return Err(DescriptorError::BadSize { size, len: buffer.len() });
+        }
+        let descriptor = &buffer[..size];
+
+        Ok(StorageDeviceDescriptor {
+            version: LittleEndian::read_u32(&descriptor[0..4]),
+            device_type: descriptor[8],
+            device_type_modifier: descriptor[9],
+            removable: descriptor[10] != 0,
+            command_queueing: descriptor[11] != 0,
+            vendor: descriptor_string(descriptor, 12, "vendor id")?,
+            product: descriptor_string(descriptor, 16, "product id")?,
+            revision: descriptor_string(descriptor, 20, "product revision")?,
+            serial: descriptor_string(descriptor, 24, "serial number")?,
+            bus_type: BusType::from_storage_bus_type(LittleEndian::read_u32(&descriptor[28..32])),
+        })
+    }
+}
+
+// reads the string whose offset is stored at `offset_field`, None for offset 0 or a blank string
+fn descriptor_string(descriptor: &[u8], offset_field: usize, field: &'static str) -> Result<Option<String>, DescriptorError> {
+    let offset = LittleEndian::read_u32(&descriptor[offset_field..offset_field + 4]) as usize;
+    if offset == 0 {
+        return Ok(None);
+    }
+    if offset < STORAGE_DEVICE_DESCRIPTOR_HEADER_SIZE || offset >= descriptor.len() {
+        return Err(DescriptorError::OffsetOutOfRange { field, offset });
+    }
+    let bytes = &descriptor[offset..];
+    let len = bytes.iter().position(|&byte| byte == 0).ok_or(DescriptorError::Unterminated { field, offset })?;
+    let text = String::from_utf8_lossy(&bytes[..len]);
+    let text = text.trim();
+    Ok(if text.is_empty() { None } else { Some(text.to_string()) })
+}
@@ -1,14 +1,62 @@
 #![warn(dead_code)]
-use winapi::shared::minwindef::{DWORD, BYTE, USHORT};
-use winapi::shared::ntdef::ULONG;
+#![allow(clippy::upper_case_acronyms)] // names mirror the windows sdk
+
+// windows sdk type aliases, defined here so the layouts below build on every platform
+pub type BYTE = u8;
+pub type DWORD = u32;
+pub type ULONG = u32;
 
 // define constants used for interacting with the hard drive
 pub const IDENTIFY_BUFFER_SIZE: usize = 512; // size of the buffer used for IDENTIFY commands
 pub const DFP_GET_VERSION: DWORD = 0x00074080; // IOCTL code for getting the driver version
 pub const DFP_RECEIVE_DRIVE_DATA: DWORD = 0x0007c088; // IOCTL code for receiving drive data
 pub const IDE_ATA_IDENTIFY: BYTE = 0xEC; // command code for ATA IDENTIFY DEVICE
+pub const SMART_CMD: BYTE = 0xB0; // command code for ATA SMART
+pub const READ_ATTRIBUTES: BYTE = 0xD0; // SMART feature register value for READ DATA
+pub const READ_THRESHOLDS: BYTE = 0xD1; // SMART feature register value for READ ATTRIBUTE THRESHOLDS
+pub const RETURN_SMART_STATUS: BYTE = 0xDA; // SMART feature register value for RETURN STATUS
+pub const SMART_CYL_LOW: BYTE = 0x4F; // SMART commands carry this signature in the cylinder registers
+pub const SMART_CYL_HI: BYTE = 0xC2;
 pub const MAX_IDE_DRIVES: usize = 16; // maximum number of IDE drives to check
-pub const INVALID_HANDLE_VALUE: winapi::shared::ntdef::HANDLE = usize::MAX as winapi::shared::ntdef::HANDLE; // invalid handle value
+
+// pieces of the CTL_CODE macro from winioctl.h
+pub const IOCTL_DISK_BASE: DWORD = 0x00000007; // FILE_DEVICE_DISK
+pub const IOCTL_STORAGE_BASE: DWORD = 0x0000002d; // FILE_DEVICE_MASS_STORAGE
+pub const METHOD_BUFFERED: DWORD = 0;
+pub const FILE_ANY_ACCESS: DWORD = 0;
+pub const FILE_READ_ACCESS: DWORD = 0x0001;
+pub const FILE_WRITE_ACCESS: DWORD = 0x0002;
+
+// const version of the CTL_CODE macro
+pub const fn ctl_code(device_type: DWORD, function: DWORD, method: DWORD, access: DWORD) -> DWORD {
+    (device_type << 16) | (access << 14) | (function << 2) | method
+}
+
+pub const SMART_GET_VERSION: DWORD = ctl_code(IOCTL_DISK_BASE, 0x0020, METHOD_BUFFERED, FILE_READ_ACCESS); // control code for getting SMART version
+pub const SMART_SEND_DRIVE_COMMAND: DWORD = ctl_code(IOCTL_DISK_BASE, 0x0021, METHOD_BUFFERED, FILE_READ_ACCESS | FILE_WRITE_ACCESS); // control code for non-data SMART commands
+pub const SMART_RCV_DRIVE_DATA: DWORD = ctl_code(IOCTL_DISK_BASE, 0x0022, METHOD_BUFFERED, FILE_READ_ACCESS | FILE_WRITE_ACCESS); // control code for receiving SMART data
+pub const IOCTL_STORAGE_QUERY_PROPERTY: DWORD = ctl_code(IOCTL_STORAGE_BASE, 0x0500, METHOD_BUFFERED, FILE_ANY_ACCESS); // control code for querying storage properties
+pub const IOCTL_DISK_GET_DRIVE_GEOMETRY: DWORD = ctl_code(IOCTL_DISK_BASE, 0x0000, METHOD_BUFFERED, FILE_ANY_ACCESS); // control code for basic disk geometry
+pub const IOCTL_DISK_GET_DRIVE_GEOMETRY_EX: DWORD = ctl_code(IOCTL_DISK_BASE, 0x0028, METHOD_BUFFERED, FILE_ANY_ACCESS); // control code for extended disk geometry
+
+// STORAGE_PROPERTY_ID and STORAGE_QUERY_TYPE values
+pub const STORAGE_DEVICE_PROPERTY: DWORD = 0; // StorageDeviceProperty
+pub const PROPERTY_STANDARD_QUERY: DWORD = 0; // PropertyStandardQuery
+
+// win32 error codes surfaced by transports
+pub const ERROR_SUCCESS: DWORD = 0;
+pub const ERROR_FILE_NOT_FOUND: DWORD = 2;
+pub const ERROR_ACCESS_DENIED: DWORD = 5;
+pub const ERROR_INVALID_DATA: DWORD = 13;
+pub const ERROR_CRC: DWORD = 23; // data error (cyclic redundancy check), used for IDENTIFY checksum mismatches
+pub const ERROR_NOT_READY: DWORD = 21;
+pub const ERROR_GEN_FAILURE: DWORD = 31;
+pub const ERROR_NOT_SUPPORTED: DWORD = 50;
+pub const ERROR_INVALID_PARAMETER: DWORD = 87;
+pub const ERROR_INSUFFICIENT_BUFFER: DWORD = 122;
+pub const ERROR_INVALID_NAME: DWORD = 123;
+pub const ERROR_IO_PENDING: DWORD = 997;
+pub const ERROR_TIMEOUT: DWORD = 1460; // an operation did not finish before its deadline and was cancelled
 
 
 // structures representing various data structures used for IOCTL communication
@@ -25,13 +73,15 @@ pub struct GETVERSIONOUTPARAMS {
     pub dw_reserved: [DWORD; 4], // reserved DWORDs
 }
 
-// structure for sending commands to the drive
-#[repr(C)]
+// structure for sending commands to the drive, pack(1) in the sdk
+#[repr(C, packed(1))]
 pub struct SENDCMDINPARAMS {
     pub c_buffer_size: ULONG, // size of the command buffer
     pub ir_drive_regs: IDEREGS, // IDE registers
     pub b_drive_number: BYTE, // drive number
-    pub c_buffer: [BYTE; IDENTIFY_BUFFER_SIZE], // command buffer
+    pub b_reserved: [BYTE; 3], // reserved bytes
+    pub dw_reserved: [DWORD; 4], // reserved DWORDs
+    pub b_buffer: [BYTE; 1], // input data, unused by the commands sent here
 }
 
 // structure representing IDE registers
@@ -47,16 +97,22 @@ pub struct IDEREGS {
     pub b_reserved: BYTE, // reserved byte
 }
 
-// structure for receiving command output from the drive
-#[repr(C)]
+// status the driver reports alongside SENDCMDOUTPARAMS
+#[repr(C, packed(1))]
+pub struct DRIVERSTATUS {
+    pub b_driver_error: BYTE, // error code from the driver
+    pub b_ide_error: BYTE, // contents of the IDE error register, only valid if b_driver_error is SMART_IDE_ERROR
+    pub b_reserved: [BYTE; 2], // reserved bytes
+    pub dw_reserved: [DWORD; 2], // reserved DWORDs
+}
+
+// structure for receiving command output from the drive, pack(1) in the sdk
+//
+// the sdk declares bBuffer[1] and callers over-allocate, here it is sized for one sector
+#[repr(C, packed(1))]
 pub struct SENDCMDOUTPARAMS {
     pub c_buffer_size: ULONG, // size of the output buffer
-    pub ir_drive_regs: IDEREGS, // IDE registers
-    pub b_drive_number: BYTE, // drive number
-    pub b_status_reg: BYTE, // status register
-    pub b_error_reg: BYTE, // error register
-    pub b_interrupt_reason_reg: BYTE, // interrupt reason register
-    pub b_reserved: [BYTE; 3], // reserved bytes
+    pub driver_status: DRIVERSTATUS, // driver error and IDE error register
     pub b_buffer: [BYTE; IDENTIFY_BUFFER_SIZE], // output buffer
 }
 
@@ -72,68 +128,20 @@ pub struct GETVERSIONINPARAMS {
 }
 
 
-// structure containing detailed information about the drive, retrieved via IDENTIFY DEVICE command
-#[repr(C)]
-#[allow(non_snake_case)]
-pub struct IDENTIFY_DATA {
-    pub GeneralConfiguration: USHORT,
-    pub NumberOfCylinders: USHORT,
-    pub Reserved1: USHORT,
-    pub NumberOfHeads: USHORT,
-    pub UnformattedBytesPerTrack: USHORT,
-    pub UnformattedBytesPerSector: USHORT,
-    pub SectorsPerTrack: USHORT,
-    pub VendorUnique1: [USHORT; 3],
-    pub SerialNumber: [USHORT; 10],
-    pub BufferType: USHORT,
-    pub BufferSectorSize: USHORT,
-    pub NumberOfEccBytes: USHORT,
-    pub FirmwareRevision: [USHORT; 4],
-    pub ModelNumber: [USHORT; 20],
-    pub MaximumBlockTransfer: BYTE,
-    pub VendorUnique2: BYTE,
-    pub DoubleWordIo: USHORT,
-    pub Capabilities: USHORT,
-    pub Reserved2: USHORT,
-    pub VendorUnique3: BYTE,
-    pub PioCycleTimingMode: BYTE,
-    pub VendorUnique4: BYTE,
-    pub DmaCycleTimingMode: BYTE,
-    pub TranslationFieldsValid: USHORT,
-    pub NumberOfCurrentCylinders: USHORT,
-    pub NumberOfCurrentHeads: USHORT,
-    pub CurrentSectorsPerTrack: USHORT,
-    pub CurrentSectorCapacity: ULONG,
-    pub CurrentMultiSectorSetting: USHORT,
-    pub UserAddressableSectors: ULONG,
-    pub SingleWordDMASupport: USHORT,
-    pub MultiWordDMASupport: USHORT,
-    pub AdvancedPIOModes: USHORT,
-    pub MinimumMWXferCycleTime: USHORT,
-    pub RecommendedMWXferCycleTime: USHORT,
-    pub MinimumPIOCycleTime: USHORT,
-    pub MinimumPIOCycleTimeIORDY: USHORT,
-    pub Reserved5: [USHORT; 2],
-    pub ReleaseTimeOverlapped: USHORT,
-    pub ReleaseTimeServiceCommand: USHORT,
-    pub MajorRevision: USHORT,
-    pub MinorRevision: USHORT,
-    pub Reserved6: [USHORT; 50],
-    pub SpecialFunctionsEnabled: USHORT,
-    pub Reserved7: [USHORT; 128],
-}
-
 // structure representing a storage device descriptor
 #[repr(C)]
 pub struct STORAGE_DEVICE_DESCRIPTOR {
     pub version: DWORD, // descriptor version
     pub size: DWORD, // descriptor size
     pub device_type: BYTE, // device type
+    pub device_type_modifier: BYTE, // vendor specific device type modifier
+    pub removable_media: BYTE, // non-zero for removable media
+    pub command_queueing: BYTE, // non-zero if the device supports command queueing
     pub vendor_id_offset: DWORD, // offset to the vendor ID string
     pub product_id_offset: DWORD, // offset to the product ID string
     pub product_revision_offset: DWORD, // offset to the product revision string
     pub serial_number_offset: DWORD, // offset to the serial number string
-    pub bus_type: BYTE, // bus type
+    pub bus_type: DWORD, // STORAGE_BUS_TYPE, a 32 bit enum
     pub raw_properties_length: DWORD, // length of the raw device properties
     pub raw_device_properties: [BYTE; 1], // raw device properties
 }
@@ -141,11 +149,11 @@ pub struct STORAGE_DEVICE_DESCRIPTOR {
 // structure representing disk geometry
 #[repr(C)]
 pub struct DISK_GEOMETRY {
-    pub cylinders: ULONG, // number of cylinders
-    pub media_type: BYTE, // media type
-    pub tracks_per_cylinder: USHORT, // number of tracks per cylinder
-    pub sectors_per_track: USHORT, // number of sectors per track
-    pub bytes_per_sector: USHORT, // number of bytes per sector
+    pub cylinders: i64, // number of cylinders, a LARGE_INTEGER
+    pub media_type: DWORD, // MEDIA_TYPE, a 32 bit enum
+    pub tracks_per_cylinder: DWORD, // number of tracks per cylinder
+    pub sectors_per_track: DWORD, // number of sectors per track
+    pub bytes_per_sector: DWORD, // number of bytes per sector
 }
 
 // structure representing extended disk geometry
@@ -159,8 +167,53 @@ pub struct DISK_GEOMETRY_EX {
 
 // structure used for querying storage device properties
 #[repr(C)]
+#[allow(non_snake_case)]
 pub struct STORAGE_PROPERTY_QUERY {
     pub PropertyId: DWORD, // property ID to query
     pub QueryType: DWORD, // type of query
     pub AdditionalParameters: [BYTE; 1], // additional parameters
-}
\ No newline at end of file
+}
+
+
+// layouts checked against the sizes and offsets of the windows sdk definitions, so a
+// mismatch fails the build on every platform instead of corrupting data at runtime
+const _: () = {
+    use std::mem::{offset_of, size_of};
+
+    assert!(size_of::<GETVERSIONOUTPARAMS>() == 24);
+    assert!(offset_of!(GETVERSIONOUTPARAMS, b_ide_device_map) == 3);
+    assert!(offset_of!(GETVERSIONOUTPARAMS, f_capabilities) == 4);
+    assert!(size_of::<GETVERSIONINPARAMS>() == 24);
+    assert!(offset_of!(GETVERSIONINPARAMS, f_capabilities) == 4);
+
+    assert!(size_of::<IDEREGS>() == 8);
+    assert!(offset_of!(IDEREGS, b_command_reg) == 6);
+
+    assert!(size_of::<SENDCMDINPARAMS>() == 33);
+    assert!(offset_of!(SENDCMDINPARAMS, ir_drive_regs) == 4);
+    assert!(offset_of!(SENDCMDINPARAMS, b_drive_number) == 12);
+    assert!(offset_of!(SENDCMDINPARAMS, dw_reserved) == 16);
+    assert!(offset_of!(SENDCMDINPARAMS, b_buffer) == 32);
+
+    assert!(size_of::<DRIVERSTATUS>() == 12);
+    assert!(size_of::<SENDCMDOUTPARAMS>() == 16 + IDENTIFY_BUFFER_SIZE); // 17 in the sdk, with bBuffer[1]
+    assert!(offset_of!(SENDCMDOUTPARAMS, driver_status) == 4);
+    assert!(offset_of!(SENDCMDOUTPARAMS, b_buffer) == 16);
+
+    assert!(size_of::<STORAGE_PROPERTY_QUERY>() == 12);
+    assert!(offset_of!(STORAGE_PROPERTY_QUERY, AdditionalParameters) == 8);
+
+    assert!(size_of::<STORAGE_DEVICE_DESCRIPTOR>() == 40);
+    assert!(offset_of!(STORAGE_DEVICE_DESCRIPTOR, removable_media) == 10);
+    assert!(offset_of!(STORAGE_DEVICE_DESCRIPTOR, vendor_id_offset) == 12);
+    assert!(offset_of!(STORAGE_DEVICE_DESCRIPTOR, serial_number_offset) == 24);
+    assert!(offset_of!(STORAGE_DEVICE_DESCRIPTOR, bus_type) == 28);
+    assert!(offset_of!(STORAGE_DEVICE_DESCRIPTOR, raw_device_properties) == 36);
+
+    assert!(size_of::<DISK_GEOMETRY>() == 24);
+    assert!(offset_of!(DISK_GEOMETRY, media_type) == 8);
+    assert!(offset_of!(DISK_GEOMETRY, bytes_per_sector) == 20);
+    assert!(size_of::<DISK_GEOMETRY_EX>() == 40);
+    assert!(offset_of!(DISK_GEOMETRY_EX, disk_size) == 24);
+    assert!(offset_of!(DISK_GEOMETRY_EX, data) == 32);
+};
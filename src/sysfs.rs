@@ -0,0 +1,139 @@
+// linux backend that reads drive identity from sysfs (/sys/block/*)
+//
+// the root is configurable so the same code can run against a fake directory tree
+
+use crate::drive_info::{BusType, DriveIdentifier};
+use crate::scsi::{logical_unit_identifiers, parse_device_identification, parse_unit_serial_number};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_SYSFS_ROOT: &str = "/sys"; // where sysfs is mounted on a normal system
+
+// identity attributes of one block device as exposed by sysfs
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SysfsDrive {
+    pub name: String,               // block device name, e.g. sda or nvme0n1
+    pub serial: Option<String>,     // device/serial
+    pub model: Option<String>,      // device/model
+    pub firmware: Option<String>,   // device/rev (device/firmware_rev on nvme)
+    pub wwid: Option<String>,       // device/wwid
+    pub vpd_serial: Option<String>, // unit serial number decoded from device/vpd_pg80
+    pub identifiers: Vec<DriveIdentifier>, // logical unit designators decoded from device/vpd_pg83, then the wwid
+    pub bus_type: BusType,          // guessed from where the device sits in /sys/devices
+}
+
+impl SysfsDrive {
+    // device node for this drive, e.g. /dev/sda
+    pub fn device_path(&self) -> String {
+        format!("/dev/{}", self.name)
+    }
+
+    // best serial number available, preferring the plain attribute over the vpd page
+    pub fn best_serial(&self) -> Option<&str> {
+        self.serial.as_deref().or(self.vpd_serial.as_deref())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SysfsBackend {
+    root: PathBuf,
+}
+
+impl Default for SysfsBackend {
+    fn default() -> Self {
+        SysfsBackend::new(DEFAULT_SYSFS_ROOT)
+    }
+}
+
+impl SysfsBackend {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        SysfsBackend { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    // lists the block devices backed by real hardware, sorted by name
+    // (loop, ram, zram and device-mapper nodes have no `device` link and are skipped)
+    pub fn list_drives(&self) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(self.root.join("block"))? {
+            let entry = entry?;
+            if entry.path().join("device").exists() {
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    // reads the identity attributes of a single block device
+    pub fn read_drive(&self, name: &str) -> io::Result<SysfsDrive> {
+        let block_dir = self.root.join("block").join(name);
+        let device_dir = block_dir.join("device");
+        if !device_dir.exists() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} has no device directory", block_dir.display())));
+        }
+
+        let wwid = read_attribute(&device_dir.join("wwid")).or_else(|| read_attribute(&block_dir.join("wwid")));
+        let mut identifiers = fs::read(device_dir.join("vpd_pg83"))
+            .ok()
+            .and_then(|page| parse_device_identification(&page).ok())
+            .map(|descriptors| logical_unit_identifiers(&descriptors))
+            .unwrap_or_default();
+        if let Some(identifier) = wwid.as_deref().and_then(DriveIdentifier::from_wwid) {
+            if !identifiers.contains(&identifier) {
+                identifiers.push(identifier); // the only identifier of an nvme namespace, sysfs has no vpd pages for those
+            }
+        }
+
+        Ok(SysfsDrive {
+            name: name.to_string(),
+            serial: read_attribute(&device_dir.join("serial")),
+            model: read_attribute(&device_dir.join("model")),
+            firmware: read_attribute(&device_dir.join("rev")).or_else(|| read_attribute(&device_dir.join("firmware_rev"))),
+            wwid,
+            vpd_serial: fs::read(device_dir.join("vpd_pg80")).ok().and_then(|page| parse_unit_serial_number(&page)),
+            identifiers,
+            bus_type: guess_bus_type(name, &block_dir),
+        })
+    }
+}
+
+// /sys/block/* entries are symlinks into /sys/devices, whose path names the bus chain
+fn guess_bus_type(name: &str, block_dir: &Path) -> BusType {
+    if name.starts_with("nvme") {
+        return BusType::Nvme;
+    }
+    let device_path = fs::canonicalize(block_dir).unwrap_or_else(|_| block_dir.to_path_buf());
+    let device_path = device_path.to_string_lossy();
+    if device_path.contains("/usb") {
+        BusType::Usb
+    } else if device_path.contains("/ata") {
+        BusType::Sata
+    } else if device_path.contains("/session") {
+        BusType::IScsi
+    } else if device_path.contains("/virtio") {
+        BusType::Virtual
+    } else if device_path.contains("/mmc") {
+        BusType::Mmc
+    } else if device_path.contains("/end_device-") {
+        BusType::Sas
+    } else {
+        BusType::Unknown
+    }
+}
+
+// reads a text attribute, returning None if it is missing or blank
+fn read_attribute(path: &Path) -> Option<String> {
+    let value = fs::read_to_string(path).ok()?;
+    let value = value.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
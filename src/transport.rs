@@ -0,0 +1,110 @@
+// transport abstractions used by the ioctl and SCSI based retrieval methods
+//
+// everything in ioctl_operations talks to a DriveTransport instead of calling
+// DeviceIoControl directly, so the fallback chain in HardDriveSerial can run
+// against the win32 backend or against an in-memory fake on any platform
+
+// control codes a transport is expected to understand
+pub use crate::structs::{
+    DFP_GET_VERSION, DFP_RECEIVE_DRIVE_DATA, SMART_GET_VERSION, SMART_RCV_DRIVE_DATA, SMART_SEND_DRIVE_COMMAND,
+    IOCTL_STORAGE_QUERY_PROPERTY, IOCTL_DISK_GET_DRIVE_GEOMETRY, IOCTL_DISK_GET_DRIVE_GEOMETRY_EX,
+};
+
+// win32 error codes a transport reports failures with
+pub use crate::structs::{
+    ERROR_SUCCESS, ERROR_FILE_NOT_FOUND, ERROR_ACCESS_DENIED, ERROR_INVALID_DATA, ERROR_CRC, ERROR_NOT_READY,
+    ERROR_GEN_FAILURE, ERROR_NOT_SUPPORTED, ERROR_INVALID_PARAMETER, ERROR_INSUFFICIENT_BUFFER,
+    ERROR_INVALID_NAME, ERROR_IO_PENDING, ERROR_TIMEOUT,
+};
+use std::time::Duration;
+
+// opaque handle to an opened drive, only meaningful to the transport that issued it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DriveHandle(pub usize);
+
+// access rights a drive is opened with, one per retrieval method
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccessRights {
+    Admin, // read/write access, shared read/write
+    Smart, // read/write access, shared read/write/delete
+    Zero,  // no access rights, shared read/write
+}
+
+// low level access to a drive: open it, send control codes to it, close it
+//
+// errors are reported as win32 error codes so every backend speaks the same language
+pub trait DriveTransport: Send + Sync {
+    // opens the drive at `path` (e.g. \\.\PhysicalDrive0) with the given rights
+    fn open(&self, path: &str, rights: AccessRights) -> Result<DriveHandle, u32>;
+
+    // sends `control_code` with `input` to the drive and fills `output`,
+    // returning the number of bytes the drive wrote
+    //
+    // a request still pending after `timeout` must be cancelled and reported as ERROR_TIMEOUT
+    fn ioctl(&self, handle: DriveHandle, control_code: u32, input: &[u8], output: &mut [u8], timeout: Duration) -> Result<usize, u32>;
+
+    // releases a handle previously returned by `open`
+    fn close(&self, handle: DriveHandle);
+}
+
+// direction of the data phase of a SCSI command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DataDirection {
+    None,       // no data phase
+    FromDevice, // the device fills the data buffer
+    ToDevice,   // the device reads the data buffer
+}
+
+// how a SCSI command ended, the data and sense bytes are in the caller's buffers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScsiResponse {
+    pub status: u8,         // SCSI status byte, 0x00 GOOD or 0x02 CHECK CONDITION
+    pub sense_len: usize,   // number of sense bytes written
+    pub transferred: usize, // number of data bytes actually transferred
+}
+
+// low level access to a drive through SCSI command descriptor blocks, e.g. linux SG_IO
+//
+// like DriveTransport, failures to deliver a command are reported as win32 error codes,
+// while failures reported by the device come back as a CHECK CONDITION status and sense data
+pub trait ScsiTransport: Send + Sync {
+    // opens the device at `path` (e.g. /dev/sda)
+    fn open(&self, path: &str) -> Result<DriveHandle, u32>;
+
+    // sends `cdb` to the device, moving `data` in `direction` and collecting sense bytes into `sense`
+    //
+    // a command still pending after `timeout` must be aborted and reported as ERROR_TIMEOUT
+    fn execute(&self, handle: DriveHandle, cdb: &[u8], direction: DataDirection, data: &mut [u8], sense: &mut [u8], timeout: Duration) -> Result<ScsiResponse, u32>;
+
+    // releases a handle previously returned by `open`
+    fn close(&self, handle: DriveHandle);
+}
+
+// an NVMe admin command, the fields map onto the submission queue entry
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct NvmeAdminCommand {
+    pub opcode: u8,
+    pub nsid: u32,
+    pub cdw10: u32,
+    pub cdw11: u32,
+    pub cdw12: u32,
+    pub cdw13: u32,
+    pub cdw14: u32,
+    pub cdw15: u32,
+}
+
+// low level access to an NVMe controller through admin commands, e.g. linux NVME_IOCTL_ADMIN_CMD
+//
+// a command the controller completes with a non-zero status is reported as a win32 error code
+// like any other failure (ERROR_NOT_SUPPORTED for invalid opcodes or fields)
+pub trait NvmeTransport: Send + Sync {
+    // opens the controller or namespace at `path` (e.g. /dev/nvme0)
+    fn open(&self, path: &str) -> Result<DriveHandle, u32>;
+
+    // sends `command` with `data` as its data buffer (controller to host),
+    // returning dword 0 of the completion queue entry
+    fn admin_command(&self, handle: DriveHandle, command: &NvmeAdminCommand, data: &mut [u8], timeout: Duration) -> Result<u32, u32>;
+
+    // releases a handle previously returned by `open`
+    fn close(&self, handle: DriveHandle);
+}
@@ -1,158 +1,35 @@
 #![warn(dead_code)]
 use crate::structs::*;
+use crate::ata_string::AtaString;
+use crate::identify::{verify_identify_block, IdentifyIntegrityError};
+use crate::transport::{DriveHandle, DriveTransport};
 use std::io;
 use std::mem;
-use winapi::shared::minwindef::{DWORD, BYTE, ULONG};
-use winapi::um::winnt::HANDLE;
-use winapi::um::ioapiset::DeviceIoControl;
-use byteorder::{ReadBytesExt, LittleEndian};
-use std::ptr::null_mut;
+use std::slice;
+use std::time::Duration;
 
-// converts a slice of DWORDs to a string, handling potential whitespace and null characters
-pub fn convert_to_string(
-    dw_disk_data: &[DWORD],
-    i_first_index: usize,
-    i_last_index: usize,
-    pcsz_buf: &mut [BYTE],
-) {
-    let mut i_position = 0;
-
-    // iterate over the DWORD slice, extracting characters and building the string
-    for i_index in i_first_index..=i_last_index {
-        let c_temp = (dw_disk_data[i_index] / 256) as BYTE;
-        if c_temp == 0 { break; } // exit the loop if a null character is encountered
-        if c_temp != b' ' {
-            pcsz_buf[i_position] = c_temp;
-            i_position += 1;
-        }
-
-        let c_temp1 = (dw_disk_data[i_index] % 256) as BYTE;
-        if c_temp1 == 0 { break; } // exit the loop if a null character is encountered
-        if c_temp1 != b' ' {
-            pcsz_buf[i_position] = c_temp1;
-            i_position += 1;
-        }
-    }
-
-    pcsz_buf[i_position] = 0; // null-terminate the string
-
-    // remove trailing whitespace from the string
-    for i_index in (0..i_position).rev() {
-        if pcsz_buf[i_index].is_ascii_whitespace() {
-            pcsz_buf[i_index] = 0;
-        } else {
-            break;
-        }
-    }
+// views a #[repr(C)] struct as raw bytes so it can be handed to a transport
+// safety: T must be plain old data that was fully initialized (e.g. via zeroed)
+pub unsafe fn struct_as_bytes<T>(value: &T) -> &[u8] {
+    slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>())
 }
 
-
-// flips and decodes bytes in a string, handling different encoding possibilities
-pub fn flip_and_code_bytes(
-    i_pos: usize,
-    i_flip: i32,
-    pcsz_str: &[BYTE],
-    pcsz_buf: &mut [BYTE],
-) {
-    if i_pos <= 0 {
-        return;
-    }
-
-    let mut i_j = 1;
-    let mut i_k = 0;
-
-    // attempt to decode the string assuming hexadecimal encoding
-    for i_i in i_pos..pcsz_str.len() {
-        if pcsz_str[i_i] == 0 {
-            break;
-        }
-
-        let c_c = pcsz_str[i_i].to_ascii_lowercase();
-        let c_c = if c_c.is_ascii_whitespace() { b'0' } else { c_c };
-
-        pcsz_buf[i_k] <<= 4;
-
-        if c_c >= b'0' && c_c <= b'9' {
-            pcsz_buf[i_k] |= c_c - b'0';
-        } else if c_c >= b'a' && c_c <= b'f' {
-            pcsz_buf[i_k] |= c_c - b'a' + 10;
-        } else {
-            i_j = 0;
-            break;
-        }
-
-        if i_j == 2 {
-            if pcsz_buf[i_k] != 0 && !pcsz_buf[i_k].is_ascii_graphic() {
-                i_j = 0;
-                break;
-            }
-            i_k += 1;
-            i_j = 0;
-        }
-    }
-
-    // if hexadecimal decoding failed, attempt to decode as ASCII
-    if i_j == 0 {
-        i_j = 1;
-        i_k = 0;
-        for i_i in i_pos..pcsz_str.len() {
-            if pcsz_str[i_i] == 0 {
-                break;
-            }
-
-            let c_c = pcsz_str[i_i];
-
-            if !c_c.is_ascii_graphic() {
-                i_j = 0;
-                break;
-            }
-
-            pcsz_buf[i_k] = c_c;
-            i_k += 1;
-        }
-    }
-
-    // flip bytes if requested
-    if i_flip != 0 {
-        for i_j in (0..i_k).step_by(2) {
-            pcsz_buf.swap(i_j, i_j + 1);
-        }
-    }
-
-    // remove leading and trailing whitespace
-    let mut i_i = -1;
-    let mut i_j = -1;
-
-    for i_k in 0..pcsz_buf.len() {
-        if pcsz_buf[i_k] != 0 && !pcsz_buf[i_k].is_ascii_whitespace() {
-            if i_i == -1 {
-                i_i = i_k as i32;
-            }
-            i_j = i_k as i32;
-        }
-    }
-
-    if i_i >= 0 && i_j >= 0 {
-        for i_k in i_i..=i_j {
-            if pcsz_buf[i_k as usize] == 0 {
-                break;
-            }
-            pcsz_buf[(i_k - i_i) as usize] = pcsz_buf[i_k as usize];
-        }
-        pcsz_buf[(i_j - i_i + 1) as usize] = 0;
-    }
+// mutable counterpart of struct_as_bytes, used for output buffers
+// safety: T must be plain old data for which every bit pattern is valid
+pub unsafe fn struct_as_bytes_mut<T>(value: &mut T) -> &mut [u8] {
+    slice::from_raw_parts_mut(value as *mut T as *mut u8, mem::size_of::<T>())
 }
 
-
-// sends an IDENTIFY DEVICE command to the drive
+// sends an IDENTIFY DEVICE command to the drive, returns the number of bytes the drive returned
 pub fn do_identify(
-    h_physical_drive_ioctl: HANDLE,
+    transport: &dyn DriveTransport,
+    h_physical_drive_ioctl: DriveHandle,
     p_scip: &mut SENDCMDINPARAMS,
     p_scop: &mut SENDCMDOUTPARAMS,
     b_id_cmd: BYTE,
     b_drive_num: BYTE,
-    lpcb_bytes_returned: &mut DWORD,
-) -> bool {
+    timeout: Duration,
+) -> Result<usize, DWORD> {
     // set up the command parameters
     p_scip.c_buffer_size = IDENTIFY_BUFFER_SIZE as ULONG;
     p_scip.ir_drive_regs.b_features_reg = 0;
@@ -166,45 +43,38 @@ pub fn do_identify(
     p_scip.b_drive_number = b_drive_num;
     p_scip.c_buffer_size = IDENTIFY_BUFFER_SIZE as ULONG;
 
-    // send the command to the drive using DeviceIoControl
-    unsafe {
-        DeviceIoControl(
-            h_physical_drive_ioctl,
-            DFP_RECEIVE_DRIVE_DATA,
-            p_scip as *mut _ as *mut winapi::ctypes::c_void,
-            (mem::size_of::<SENDCMDINPARAMS>() - 1) as DWORD,
-            p_scop as *mut _ as *mut winapi::ctypes::c_void,
-            (mem::size_of::<SENDCMDOUTPARAMS>() + IDENTIFY_BUFFER_SIZE - 1) as DWORD,
-            lpcb_bytes_returned,
-            null_mut(),
-        ) != 0
-    }
+    // send the command to the drive through the transport
+    let input = unsafe { struct_as_bytes(p_scip) };
+    transport.ioctl(
+        h_physical_drive_ioctl,
+        DFP_RECEIVE_DRIVE_DATA,
+        &input[..mem::size_of::<SENDCMDINPARAMS>() - 1],
+        unsafe { struct_as_bytes_mut(p_scop) },
+        timeout,
+    )
 }
 
 // extracts the serial number from the IDENTIFY DEVICE data
 pub fn extract_serial_from_identify_data(identify_data: &[BYTE]) -> io::Result<String> {
-    // create a reader for the identify data
-    let mut reader = io::Cursor::new(identify_data);
-
-    // read the data into a DWORD array
-    let mut dw_disk_data: [DWORD; 256] = [0; 256];
-    for i_ijk in 0..256 {
-        dw_disk_data[i_ijk] = reader.read_u16::<LittleEndian>()?.into();
-    }
-
-    // convert the relevant portion of the DWORD array to a string
-    let mut csz_serial_number: [BYTE; 1024] = [0; 1024];
-    convert_to_string(&dw_disk_data, 10, 19, &mut csz_serial_number);
+    // refuse blank, floating bus and corrupted buffers instead of decoding a plausible wrong serial
+    verify_identify_block(identify_data).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
 
-    // create a string from the resulting byte array, removing trailing null characters
-    let serial_number = String::from_utf8_lossy(&csz_serial_number)
-        .trim_end_matches(char::from(0))
-        .to_string();
+    // the serial number is words 10-19, whatever part of them a short buffer holds
+    let serial_field = identify_data.get(20..identify_data.len().min(40)).unwrap_or_default();
+    let serial_number = AtaString::from_swapped_bytes(serial_field).into_string();
 
     // return an error if the serial number is empty
     if serial_number.is_empty() {
-        return Err(io::Error::new(io::ErrorKind::Other, "Failed to extract serial number"));
+        return Err(io::Error::other("Failed to extract serial number"));
     }
 
     Ok(serial_number) // return the extracted serial number
-}
\ No newline at end of file
+}
+
+// maps an IDENTIFY decoding failure to the error code reported by the retrieval methods
+pub fn identify_error_code(error: &io::Error) -> DWORD {
+    match error.get_ref().and_then(|inner| inner.downcast_ref::<IdentifyIntegrityError>()) {
+        Some(IdentifyIntegrityError::ChecksumMismatch { .. }) => ERROR_CRC,
+        _ => ERROR_INVALID_DATA,
+    }
+}
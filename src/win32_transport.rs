@@ -0,0 +1,140 @@
+use crate::structs::*;
+use crate::transport::{AccessRights, DriveHandle, DriveTransport};
+use std::ffi::CString;
+use std::mem::zeroed;
+use std::ptr::null_mut;
+use std::time::Duration;
+use tracing::warn;
+use winapi::{
+    ctypes::c_void,
+    shared::{minwindef::FALSE, winerror::WAIT_TIMEOUT},
+    um::{
+        winnt::{GENERIC_READ, GENERIC_WRITE, FILE_SHARE_READ, FILE_SHARE_WRITE, FILE_SHARE_DELETE, HANDLE},
+        fileapi::{CreateFileA, OPEN_EXISTING},
+        handleapi::{CloseHandle, INVALID_HANDLE_VALUE},
+        ioapiset::{CancelIoEx, DeviceIoControl, GetOverlappedResult},
+        errhandlingapi::GetLastError,
+        synchapi::{WaitForSingleObject, CreateEventA},
+        minwinbase::OVERLAPPED,
+        winbase::{INFINITE, FILE_FLAG_OVERLAPPED, WAIT_OBJECT_0},
+    },
+};
+
+// DriveTransport backed by CreateFileA and overlapped DeviceIoControl
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Win32Transport;
+
+impl DriveTransport for Win32Transport {
+    fn open(&self, path: &str, rights: AccessRights) -> Result<DriveHandle, u32> {
+        // desired access and share mode for each set of rights
+        let (desired_access, share_mode) = match rights {
+            AccessRights::Admin => (GENERIC_READ | GENERIC_WRITE, FILE_SHARE_READ | FILE_SHARE_WRITE),
+            AccessRights::Smart => (GENERIC_READ | GENERIC_WRITE, FILE_SHARE_DELETE | FILE_SHARE_READ | FILE_SHARE_WRITE),
+            AccessRights::Zero => (0, FILE_SHARE_READ | FILE_SHARE_WRITE),
+        };
+        let drive_name = CString::new(path).map_err(|_| ERROR_INVALID_NAME)?; // CreateFileA needs a nul terminated name
+
+        let handle = unsafe {
+            CreateFileA(
+                drive_name.as_ptr(),   // drive name to open
+                desired_access,        // desired access
+                share_mode,            // share mode
+                null_mut(),            // security attributes (none)
+                OPEN_EXISTING,         // open only if it exists
+                FILE_FLAG_OVERLAPPED,  // overlapped operation flag
+                null_mut(),            // template file (none)
+            )
+        };
+
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(unsafe { GetLastError() });
+        }
+        Ok(DriveHandle(handle as usize))
+    }
+
+    fn ioctl(&self, handle: DriveHandle, control_code: u32, input: &[u8], output: &mut [u8], timeout: Duration) -> Result<usize, u32> {
+        let h_physical_drive_ioctl = handle.0 as HANDLE;
+        let mut dw_bytes_returned: DWORD = 0; // initialize the number of bytes returned
+        // the driver gets buffers of its own, a request it never completes must not keep the caller's
+        let mut request = Box::new(PendingIoctl { overlapped: unsafe { zeroed() }, input: input.to_vec(), output: output.to_vec() });
+        request.overlapped.hEvent = unsafe { CreateEventA(null_mut(), 1, 0, null_mut()) }; // create an event for the overlapped operation
+        if request.overlapped.hEvent.is_null() {
+            return Err(unsafe { GetLastError() });
+        }
+
+        let in_buffer = if request.input.is_empty() { null_mut() } else { request.input.as_mut_ptr() as *mut c_void };
+        let out_buffer = if request.output.is_empty() { null_mut() } else { request.output.as_mut_ptr() as *mut c_void };
+
+        let result = unsafe {
+            DeviceIoControl(
+                h_physical_drive_ioctl,   // handle to the drive
+                control_code,             // control code
+                in_buffer,                // input buffer
+                input.len() as DWORD,     // size of input buffer
+                out_buffer,               // output buffer
+                output.len() as DWORD,    // size of output buffer
+                &mut dw_bytes_returned,   // number of bytes returned
+                &mut request.overlapped,  // overlapped structure
+            )
+        };
+
+        let outcome = if result != 0 {
+            Ok(dw_bytes_returned as usize)
+        } else {
+            let error = unsafe { GetLastError() };
+            if error != ERROR_IO_PENDING {
+                Err(error)
+            } else {
+                match unsafe { WaitForSingleObject(request.overlapped.hEvent, timeout_millis(timeout)) } { // wait for the overlapped operation to complete
+                    WAIT_OBJECT_0 => overlapped_result(h_physical_drive_ioctl, &mut request.overlapped),
+                    wait_result => {
+                        let error = if wait_result == WAIT_TIMEOUT { ERROR_TIMEOUT } else { unsafe { GetLastError() } };
+                        // cancel the request, then give the driver a bounded grace period to let go of the buffers
+                        unsafe { CancelIoEx(h_physical_drive_ioctl, &mut request.overlapped) };
+                        if unsafe { WaitForSingleObject(request.overlapped.hEvent, timeout_millis(CANCEL_GRACE_PERIOD)) } != WAIT_OBJECT_0 {
+                            // a driver that ignores the cancel may still write to the request, so it is never freed
+                            warn!(control_code, "driver ignored the cancel, abandoning the pending request");
+                            Box::leak(request);
+                            return Err(error);
+                        }
+                        Err(error)
+                    }
+                }
+            }
+        };
+
+        output.copy_from_slice(&request.output); // the request completed, whatever the driver wrote is final
+        unsafe { CloseHandle(request.overlapped.hEvent); }
+        outcome
+    }
+
+    fn close(&self, handle: DriveHandle) {
+        unsafe { CloseHandle(handle.0 as HANDLE); }
+    }
+}
+
+// how long a cancelled request may take to complete before its buffers are abandoned
+const CANCEL_GRACE_PERIOD: Duration = Duration::from_millis(500);
+
+// an overlapped request and the buffers the driver reads and writes, boxed so that a request
+// the driver never completes can be leaked instead of freed under it
+struct PendingIoctl {
+    overlapped: OVERLAPPED,
+    input: Vec<u8>,
+    output: Vec<u8>,
+}
+
+// result of an overlapped operation whose event was signalled
+fn overlapped_result(h_physical_drive_ioctl: HANDLE, overlapped: &mut OVERLAPPED) -> Result<usize, DWORD> {
+    let mut dw_bytes_returned: DWORD = 0;
+    if unsafe { GetOverlappedResult(h_physical_drive_ioctl, overlapped, &mut dw_bytes_returned, FALSE) } == 0 {
+        Err(unsafe { GetLastError() })
+    } else {
+        Ok(dw_bytes_returned as usize)
+    }
+}
+
+// WaitForSingleObject milliseconds, never INFINITE
+fn timeout_millis(timeout: Duration) -> DWORD {
+    timeout.as_millis().min((INFINITE - 1) as u128) as DWORD
+}
@@ -0,0 +1,155 @@
+mod common;
+
+use common::{add_block_device, descriptor_sense, identify_block, FakeSatDevice, FakeScsiTransport};
+use simplehddserial::ata_passthrough::*;
+use simplehddserial::transport::{DriveHandle, ScsiResponse, ScsiTransport, ERROR_NOT_SUPPORTED};
+use simplehddserial::{Agreement, HardDriveSerial, Method, ScanOptions, SerialError};
+use std::sync::Arc;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn identify_cdb_16() {
+    let cdb = build_cdb(CdbSize::Sixteen, &AtaTaskfile::identify_device(), AtaProtocol::PioDataIn, false);
+    assert_eq!(cdb, vec![0x85, 0x08, 0x0E, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0xEC, 0]);
+}
+
+#[test]
+fn smart_read_data_cdb_12() {
+    let cdb = build_cdb(CdbSize::Twelve, &AtaTaskfile::smart(SMART_READ_DATA), AtaProtocol::PioDataIn, false);
+    assert_eq!(cdb, vec![0xA1, 0x08, 0x0E, 0xD0, 1, 0, 0x4F, 0xC2, 0, 0xB0, 0, 0]);
+}
+
+#[test]
+fn check_condition_flag_and_48_bit_lba_placement() {
+    let taskfile = AtaTaskfile { features: 0x0102, count: 0x0304, lba: 0x0A0B_0C0D_0E0F, device: 0x40, command: 0x25 };
+    let cdb = build_cdb(CdbSize::Sixteen, &taskfile, AtaProtocol::NonData, true);
+    assert_eq!(cdb, vec![0x85, 0x06, 0x20, 0x01, 0x02, 0x03, 0x04, 0x0C, 0x0F, 0x0B, 0x0E, 0x0A, 0x0D, 0x40, 0x25, 0]);
+}
+
+#[test]
+fn decodes_descriptor_sense_with_ata_status_return() {
+    let sense = [
+        0x72, 0x01, 0x00, 0x1D, 0, 0, 0, 14,
+        0x09, 0x0C, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x4F, 0x00, 0xC2, 0xA0, 0x50,
+    ];
+    let decoded = decode_sense(&sense).unwrap();
+    assert_eq!(decoded.sense_key, SENSE_KEY_RECOVERED_ERROR);
+    assert_eq!((decoded.asc, decoded.ascq), (0x00, 0x1D));
+    let ata_status = decoded.ata_status.unwrap();
+    assert_eq!(ata_status.count, 1);
+    assert_eq!(ata_status.lba, 0xC2_4F00);
+    assert_eq!(ata_status.device, 0xA0);
+    assert_eq!(ata_status.status, 0x50);
+    assert!(!ata_status.is_error());
+}
+
+#[test]
+fn decodes_fixed_sense_with_ata_information() {
+    let mut sense = [0u8; 18];
+    sense[0] = 0x70;
+    sense[2] = SENSE_KEY_RECOVERED_ERROR;
+    sense[3] = 0x04; // error: ABRT
+    sense[4] = 0x51; // status: DRDY | DSC | ERR
+    sense[5] = 0xA0; // device
+    sense[6] = 0x01; // count
+    sense[7] = 10;
+    sense[9..12].copy_from_slice(&[0x00, 0x4F, 0xC2]);
+    sense[12] = 0x00;
+    sense[13] = 0x1D;
+
+    let ata_status = decode_sense(&sense).unwrap().ata_status.unwrap();
+    assert_eq!(ata_status.lba, 0xC2_4F00);
+    assert!(ata_status.is_aborted());
+    assert_eq!(check_response(&ScsiResponse { status: SCSI_STATUS_CHECK_CONDITION, sense_len: 18, transferred: 0 }, &sense), Err(ERROR_NOT_SUPPORTED));
+}
+
+#[test]
+fn unknown_sense_formats_are_ignored() {
+    assert_eq!(decode_sense(&[]), None);
+    assert_eq!(decode_sense(&[0x7F, 0, 0, 0, 0, 0, 0, 0]), None);
+    assert_eq!(decode_sense(&[0x70, 0, 0x05]), None); // truncated
+}
+
+#[test]
+fn falls_back_to_12_byte_cdbs() {
+    let device = FakeSatDevice { only_12_byte_cdbs: true, ..FakeSatDevice::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01") };
+    let fake = FakeScsiTransport::new().with_device("/dev/sda", device);
+    let handle = fake.open("/dev/sda").unwrap();
+
+    let identify = identify_device(&fake, handle, TIMEOUT).unwrap();
+    assert_eq!(identify, identify_block("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01"));
+    let opcodes: Vec<u8> = fake.cdbs().iter().map(|cdb| cdb[0]).collect();
+    assert_eq!(opcodes, vec![ATA_PASS_THROUGH_16, ATA_PASS_THROUGH_12]);
+}
+
+#[test]
+fn aborted_ata_commands_are_not_supported() {
+    let fake = FakeScsiTransport::new().with_device("/dev/sda", FakeSatDevice::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01"));
+    let handle = fake.open("/dev/sda").unwrap();
+    assert_eq!(smart_read_data(&fake, handle, TIMEOUT), Err(ERROR_NOT_SUPPORTED)); // no SMART data configured
+}
+
+#[test]
+fn short_transfers_are_rejected() {
+    struct ShortRead;
+    impl ScsiTransport for ShortRead {
+        fn open(&self, _path: &str) -> Result<DriveHandle, u32> {
+            Ok(DriveHandle(1))
+        }
+        fn execute(&self, _: DriveHandle, _: &[u8], _: simplehddserial::transport::DataDirection, _: &mut [u8], _: &mut [u8], _: Duration) -> Result<ScsiResponse, u32> {
+            Ok(ScsiResponse { status: SCSI_STATUS_GOOD, sense_len: 0, transferred: 256 })
+        }
+        fn close(&self, _: DriveHandle) {}
+    }
+    assert_eq!(identify_device(&ShortRead, DriveHandle(1), TIMEOUT), Err(simplehddserial::transport::ERROR_INVALID_DATA));
+}
+
+#[test]
+fn check_condition_without_ata_status_maps_the_sense_key() {
+    let mut sense = [0u8; 32];
+    let sense_len = descriptor_sense(&mut sense, SENSE_KEY_NOT_READY, 0x04, 0x02, None);
+    let response = ScsiResponse { status: SCSI_STATUS_CHECK_CONDITION, sense_len, transferred: 0 };
+    assert_eq!(check_response(&response, &sense), Err(simplehddserial::transport::ERROR_NOT_READY));
+}
+
+#[test]
+fn drives_behind_a_sas_hba_get_their_serial_through_pass_through() {
+    let root = tempfile::tempdir().unwrap();
+    add_block_device(root.path(), "sda", &[("model", b"WDC WD10EZEX-08W\n")]); // no serial, no vpd pages
+    let scsi = Arc::new(FakeScsiTransport::new().with_device("/dev/sda", FakeSatDevice::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01")));
+
+    let mut hd_serial = HardDriveSerial::with_sysfs_root(root.path()).with_scsi_transport(scsi.clone());
+    let drives = hd_serial.get_serial_numbers().unwrap();
+    assert_eq!(drives.len(), 1);
+    assert_eq!(drives[0].serial, "WD-WCC4E1234567");
+    assert_eq!(drives[0].method, Method::AtaPassThrough);
+    assert_eq!(drives[0].model.as_deref(), Some("WDC WD10EZEX-08WN4A0"));
+    assert_eq!(hd_serial.errors(), &[SerialError::NotSupported { drive: Some(0), method: Method::Sysfs }]);
+    assert_eq!(scsi.open_handle_count(), 0);
+}
+
+#[test]
+fn pass_through_votes_alongside_sysfs() {
+    let root = tempfile::tempdir().unwrap();
+    add_block_device(root.path(), "sdb", &[("vpd_pg80", b"\x00\x80\x00\x08Z1D2ABCD")]);
+    let scsi = FakeScsiTransport::new().with_device("/dev/sdb", FakeSatDevice::ata("Z1D2ABCD", "ST2000DM001-1CH164", "CC29"));
+
+    let drives = HardDriveSerial::with_sysfs_root(root.path()).with_scsi_transport(Arc::new(scsi)).get_serial_numbers().unwrap();
+    assert_eq!(drives[0].consensus.agreement, Agreement::Unanimous);
+    assert_eq!(drives[0].consensus.agreeing, vec![Method::Sysfs, Method::AtaPassThrough]);
+}
+
+#[test]
+fn pass_through_runs_on_the_ioctl_backend_too() {
+    let scsi = FakeScsiTransport::new().with_device("\\\\.\\PhysicalDrive0", FakeSatDevice::ata("Z1D2ABCD", "ST2000DM001-1CH164", "CC29"));
+    let options = ScanOptions::new().with_methods([Method::AtaPassThrough]).with_drive_indices([0]);
+    let drives = HardDriveSerial::with_transport(Arc::new(common::FakeTransport::new()))
+        .with_scsi_transport(Arc::new(scsi))
+        .with_options(options)
+        .get_serial_numbers()
+        .unwrap();
+    assert_eq!(drives[0].serial, "Z1D2ABCD");
+    assert_eq!(drives[0].method, Method::AtaPassThrough);
+}
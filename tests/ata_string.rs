@@ -0,0 +1,115 @@
+mod common;
+
+use common::{identify_block, put_ata_string};
+use proptest::prelude::*;
+use simplehddserial::ata_string::AtaString;
+use simplehddserial::IdentifyDevice;
+
+#[test]
+fn keeps_internal_spaces() {
+    let mut block = [0u8; 512];
+    put_ata_string(&mut block, 27, 46, "WDC WD10EZEX-08WN4A0");
+    let words: Vec<u16> = block.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+    assert_eq!(AtaString::from_words(&words[27..=46]).as_str(), "WDC WD10EZEX-08WN4A0");
+    assert_eq!(AtaString::from_swapped_bytes(&block[54..94]).as_str(), "WDC WD10EZEX-08WN4A0");
+}
+
+#[test]
+fn identify_fields_go_through_the_codec() {
+    let identify = IdentifyDevice::parse(&identify_block("  S3Z9NB0K123456X", "Samsung SSD 860 EVO 500GB", "RVT02B6Q"));
+    assert_eq!(identify.serial, "S3Z9NB0K123456X"); // leading padding trimmed
+    assert_eq!(identify.model, "Samsung SSD 860 EVO 500GB");
+    assert_eq!(identify.firmware, "RVT02B6Q");
+}
+
+#[test]
+fn stops_at_the_first_nul() {
+    assert_eq!(AtaString::from_bytes(b"WD-WCC4E123\0garbage").as_str(), "WD-WCC4E123");
+    assert_eq!(AtaString::from_bytes(b"\0WD-WCC4E123").as_str(), "");
+}
+
+#[test]
+fn odd_lengths_keep_the_last_byte() {
+    assert_eq!(AtaString::from_swapped_bytes(b"DWW-").as_str(), "WD-W");
+    assert_eq!(AtaString::from_swapped_bytes(b"DWW-C").as_str(), "WD-WC");
+    assert_eq!(AtaString::new("WD-WC").to_swapped_bytes(5), b"DWW-C");
+    assert_eq!(AtaString::new("ABC").swapped().as_str(), "BA C");
+}
+
+#[test]
+fn decodes_hex_serials() {
+    assert_eq!(AtaString::from_hex("57442d5743433445313233").unwrap().as_str(), "WD-WCC4E123");
+    assert_eq!(AtaString::from_hex("2020202057442D57434334453132332020").unwrap().as_str(), "WD-WCC4E123"); // upper case, padded
+    assert_eq!(AtaString::new("WD-WCC4E123").to_hex(), "57442d5743433445313233");
+    assert_eq!(AtaString::from_hex("WD-WCC4E123"), None); // not hex
+    assert_eq!(AtaString::from_hex("57442"), None);       // half a byte
+    assert_eq!(AtaString::from_hex("0102"), None);        // not printable
+    assert_eq!(AtaString::from_hex("4C530001230615117052"), None); // a real serial that happens to be hex
+    assert_eq!(AtaString::from_hex("57442d57000000").unwrap().as_str(), "WD-W"); // nul padded
+    assert_eq!(AtaString::from_hex("2020"), None);        // nothing but padding
+}
+
+#[test]
+fn encodes_into_fixed_width_fields() {
+    let text = AtaString::new("WDC WD10EZEX");
+    assert_eq!(text.to_padded_bytes(14), b"WDC WD10EZEX  ");
+    assert_eq!(text.to_padded_bytes(3), b"WDC");
+    assert_eq!(text.to_words(2), vec![0x5744, 0x4320]);
+    assert_eq!(text.to_words(0), Vec::<u16>::new());
+}
+
+// printable ascii without leading or trailing spaces, what a decoded field always looks like
+fn ata_text(max_len: usize) -> impl Strategy<Value = String> {
+    proptest::string::string_regex(&format!("([!-~]([ -~]{{0,{}}}[!-~])?)?", max_len.saturating_sub(2))).unwrap()
+}
+
+proptest! {
+    #[test]
+    fn words_round_trip(text in ata_text(40)) {
+        let encoded = AtaString::new(&text).to_words(20);
+        prop_assert_eq!(encoded.len(), 20);
+        prop_assert_eq!(AtaString::from_words(&encoded).into_string(), text);
+    }
+
+    #[test]
+    fn swapped_bytes_round_trip(text in ata_text(40), extra in 0usize..3) {
+        let len = text.len() + extra;
+        let encoded = AtaString::new(&text).to_swapped_bytes(len);
+        prop_assert_eq!(encoded.len(), len);
+        prop_assert_eq!(AtaString::from_swapped_bytes(&encoded).into_string(), text);
+    }
+
+    #[test]
+    fn padded_bytes_round_trip(text in ata_text(40), extra in 0usize..8) {
+        let encoded = AtaString::new(&text).to_padded_bytes(text.len() + extra);
+        prop_assert_eq!(AtaString::from_bytes(&encoded).into_string(), text);
+    }
+
+    #[test]
+    fn hex_round_trip(text in ata_text(40)) {
+        let decoded = AtaString::from_hex(&AtaString::new(&text).to_hex());
+        if text.is_empty() {
+            prop_assert_eq!(decoded, None);
+        } else {
+            prop_assert_eq!(decoded.unwrap().into_string(), text);
+        }
+    }
+
+    #[test]
+    fn swapping_twice_is_identity_for_even_lengths(text in "[!-~]{0,40}") {
+        prop_assume!(text.len().is_multiple_of(2)); // spaces and odd lengths move padding around, see odd_lengths_keep_the_last_byte
+        let text = AtaString::new(&text);
+        prop_assert_eq!(text.swapped().swapped(), text);
+    }
+
+    #[test]
+    fn decoding_never_panics_and_is_trimmed(bytes in proptest::collection::vec(any::<u8>(), 0..64), width in 0usize..64) {
+        for decoded in [AtaString::from_bytes(&bytes), AtaString::from_swapped_bytes(&bytes)] {
+            prop_assert_eq!(decoded.as_str().trim(), decoded.as_str());
+            prop_assert!(!decoded.as_str().contains('\0'));
+            // encoding whatever came out never panics either, at any width
+            let _ = (decoded.to_words(width), decoded.to_swapped_bytes(width), decoded.swapped());
+        }
+        let _ = AtaString::from_hex(&String::from_utf8_lossy(&bytes));
+    }
+}
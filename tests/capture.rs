@@ -0,0 +1,258 @@
+mod common;
+
+use common::scripted::{Fault, Injection, ScriptedTransport};
+use common::*;
+use simplehddserial::capture::*;
+use simplehddserial::transport::*;
+use simplehddserial::{DriveInfo, HardDriveSerial, Method, ScanOptions, SerialError};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+const SERIAL: &str = "WD-WCC4E1234567";
+
+fn options() -> ScanOptions {
+    ScanOptions::new()
+        .with_methods([Method::AdminRights, Method::Smart, Method::ZeroRights])
+        .with_drive_indices([0])
+        .with_operation_timeout(Duration::from_millis(50))
+}
+
+fn scan(transport: Arc<dyn DriveTransport>) -> (Vec<DriveInfo>, Vec<SerialError>) {
+    let mut hd_serial = HardDriveSerial::with_transport(transport).with_options(options());
+    let drives = hd_serial.get_serial_numbers().unwrap_or_default();
+    (drives, hd_serial.errors().to_vec())
+}
+
+// serial, model, firmware, chosen method and every method's outcome of one drive
+type Report = (String, Option<String>, Option<String>, Method, Vec<(Method, Result<String, SerialError>)>);
+
+// everything a scan reports except the timings
+fn report(drives: &[DriveInfo]) -> Vec<Report> {
+    drives
+        .iter()
+        .map(|drive| {
+            let outcomes = drive.outcomes.iter().map(|outcome| (outcome.method, outcome.result.clone())).collect();
+            (drive.serial.clone(), drive.model.clone(), drive.firmware.clone(), drive.method, outcomes)
+        })
+        .collect()
+}
+
+#[test]
+fn a_recorded_scan_replays_identically() {
+    let recorder = Arc::new(RecordingTransport::new(Arc::new(FakeTransport::new().with_drive(0, FakeDrive::wd_blue()))));
+    let (recorded, recorded_errors) = scan(recorder.clone());
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("scan.capture");
+    recorder.capture().save(&path).unwrap();
+    let replay = Arc::new(ReplayTransport::new(Capture::load(&path).unwrap()));
+    let (replayed, replayed_errors) = scan(replay.clone());
+
+    assert_eq!(report(&recorded)[0].0, SERIAL);
+    assert_eq!(report(&replayed), report(&recorded));
+    assert_eq!(replayed_errors, recorded_errors);
+    assert_eq!(replay.remaining(), 0);
+}
+
+#[test]
+fn records_every_request_of_every_method() {
+    let recorder = Arc::new(RecordingTransport::new(Arc::new(FakeTransport::new().with_drive(0, FakeDrive::wd_blue()))));
+    scan(recorder.clone());
+    let capture = recorder.capture();
+
+    let opens: Vec<AccessRights> = capture
+        .events
+        .iter()
+        .filter_map(|event| match event {
+            CapturedEvent::Open { rights, .. } => Some(*rights),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(opens, vec![AccessRights::Admin, AccessRights::Smart, AccessRights::Zero]);
+
+    let Some(CapturedEvent::Ioctl { path, control_code, output, output_len, result, .. }) = capture.events.get(1) else {
+        panic!("expected the admin method's DFP_GET_VERSION after its open");
+    };
+    assert_eq!(path, "\\\\.\\PhysicalDrive0");
+    assert_eq!(*control_code, DFP_GET_VERSION);
+    assert_eq!(*output_len, 24);
+    assert!(output.len() <= *output_len && output.last() != Some(&0)); // trailing zeros are not stored
+    assert_eq!(*result, Ok(24));
+}
+
+#[test]
+fn text_format_round_trips() {
+    let capture = Capture {
+        events: vec![
+            CapturedEvent::Open { path: "\\\\.\\PhysicalDrive1".to_string(), rights: AccessRights::Admin, result: Err(ERROR_ACCESS_DENIED), elapsed: Duration::from_micros(40) },
+            CapturedEvent::Open { path: "\\\\.\\PhysicalDrive1".to_string(), rights: AccessRights::Zero, result: Ok(()), elapsed: Duration::from_micros(31) },
+            CapturedEvent::Ioctl {
+                path: "\\\\.\\PhysicalDrive1".to_string(),
+                control_code: IOCTL_STORAGE_QUERY_PROPERTY,
+                input: vec![0; 12],
+                output: vec![],
+                output_len: 4096,
+                result: Err(ERROR_TIMEOUT),
+                elapsed: Duration::from_millis(50),
+            },
+        ],
+    };
+    let text = capture.to_string();
+    assert!(text.starts_with("simplehddserial capture 2\n"));
+    assert_eq!(Capture::parse(&text).unwrap(), capture);
+}
+
+#[test]
+fn scsi_and_nvme_events_round_trip() {
+    let inquiry = [0x12, 0x01, 0x80, 0x03, 0xFC, 0x00];
+    let capture = Capture {
+        events: vec![
+            CapturedEvent::ScsiOpen { path: "/dev/sda".to_string(), result: Ok(()), elapsed: Duration::from_micros(20) },
+            CapturedEvent::Scsi {
+                path: "/dev/sda".to_string(),
+                cdb: inquiry.to_vec(),
+                direction: DataDirection::FromDevice,
+                data: vec![0x00, 0x80, 0x00, 0x02, b'4', b'2'],
+                data_len: 1020,
+                sense: vec![],
+                result: Ok(ScsiResponse { status: 0x00, sense_len: 0, transferred: 6 }),
+                elapsed: Duration::from_micros(180),
+            },
+            CapturedEvent::Scsi {
+                path: "/dev/sda".to_string(),
+                cdb: vec![0x85, 0x08, 0x0E, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0x40, 0xEC, 0],
+                direction: DataDirection::FromDevice,
+                data: vec![],
+                data_len: 512,
+                sense: vec![0x72, 0x05, 0x20, 0x00, 0, 0, 0, 0],
+                result: Ok(ScsiResponse { status: 0x02, sense_len: 8, transferred: 0 }), // CHECK CONDITION, INVALID COMMAND OPERATION CODE
+                elapsed: Duration::from_micros(90),
+            },
+            CapturedEvent::Scsi {
+                path: "/dev/sda".to_string(),
+                cdb: vec![0x00; 6],
+                direction: DataDirection::None,
+                data: vec![],
+                data_len: 0,
+                sense: vec![],
+                result: Err(ERROR_TIMEOUT),
+                elapsed: Duration::from_millis(50),
+            },
+            CapturedEvent::NvmeOpen { path: "/dev/nvme1".to_string(), result: Err(ERROR_ACCESS_DENIED), elapsed: Duration::from_micros(15) },
+            CapturedEvent::NvmeOpen { path: "/dev/nvme0".to_string(), result: Ok(()), elapsed: Duration::from_micros(12) },
+            CapturedEvent::Nvme {
+                path: "/dev/nvme0".to_string(),
+                command: NvmeAdminCommand { opcode: 0x06, nsid: 0, cdw10: 0x01, ..NvmeAdminCommand::default() },
+                data: vec![0x4D, 0x14],
+                data_len: 4096,
+                result: Ok(0),
+                elapsed: Duration::from_micros(90),
+            },
+            CapturedEvent::Nvme {
+                path: "/dev/nvme0".to_string(),
+                command: NvmeAdminCommand { opcode: 0x02, nsid: 0xFFFF_FFFF, cdw10: 0x007F_0002, ..NvmeAdminCommand::default() },
+                data: vec![],
+                data_len: 512,
+                result: Err(ERROR_NOT_SUPPORTED),
+                elapsed: Duration::from_micros(40),
+            },
+        ],
+    };
+    let text = capture.to_string();
+    assert!(text.contains("\nscsi\tpath=/dev/sda\tcdb=12018003fc00\tdir=from_device\tdata_len=1020\tdata=008000023432\tstatus=0x00\tsense=\tresult=ok:6\t"));
+    assert!(text.contains("\nnvme\tpath=/dev/nvme0\topcode=0x06\tnsid=0\tcdw10=0x00000001\tcdw11=0x00000000\t"));
+    assert_eq!(Capture::parse(&text).unwrap(), capture);
+}
+
+#[test]
+fn scsi_and_nvme_commands_replay_identically() {
+    let options = ScanOptions::new()
+        .with_methods([Method::AtaPassThrough, Method::ScsiVpd, Method::NvmeIdentify])
+        .with_drive_paths(["/dev/sda", "/dev/sdb", "/dev/nvme0n1"]);
+    let scsi = FakeScsiTransport::new()
+        .with_device("/dev/sda", FakeSatDevice::ata(SERIAL, "WDC WD10EZEX-08WN4A0", "01.01A01"))
+        .with_device("/dev/sdb", FakeSatDevice::scsi("SEAGATE", "ST4000NM0023", "0004", "Z1Z0ABCD", &[0x50, 0x00, 0xC5, 0x00, 0x12, 0x34, 0x56, 0x78]));
+    let namespace = FakeNamespace { identify: nvme_identify_namespace([0x00, 0x25, 0x38, 0x5B, 0x71, 0xB0, 0x9E, 0x83], [0; 16]), descriptors: None };
+    let nvme = FakeNvmeTransport::new()
+        .with_controller("/dev/nvme0", nvme_identify_controller("S4EWNX0R123456", "Samsung SSD 970 EVO Plus 1TB", "2B2QEXM7"))
+        .with_namespace("/dev/nvme0", 1, namespace);
+    let scan = |transport: Arc<dyn DriveTransport>, scsi: Arc<dyn ScsiTransport>, nvme: Arc<dyn NvmeTransport>| {
+        let mut hd_serial = HardDriveSerial::with_transport(transport).with_scsi_transport(scsi).with_nvme_transport(nvme).with_options(options.clone());
+        let drives = hd_serial.get_serial_numbers().unwrap_or_default();
+        (report(&drives), hd_serial.errors().to_vec())
+    };
+
+    let recorder = Recorder::new();
+    let recorded = scan(Arc::new(FakeTransport::new()), Arc::new(recorder.scsi_transport(Arc::new(scsi))), Arc::new(recorder.nvme_transport(Arc::new(nvme))));
+    let capture = Capture::parse(&recorder.capture().to_string()).unwrap();
+    assert!(capture.events.iter().any(|event| matches!(event, CapturedEvent::Scsi { result: Ok(ScsiResponse { status: 0x02, sense_len: 1.., .. }), .. })));
+
+    let (replay_scsi, replay_nvme) = (Arc::new(ReplayScsiTransport::new(capture.clone())), Arc::new(ReplayNvmeTransport::new(capture.clone())));
+    let replayed = scan(Arc::new(ReplayTransport::new(capture)), replay_scsi.clone(), replay_nvme.clone());
+    assert_eq!(recorded.0.iter().map(|drive| drive.0.as_str()).collect::<Vec<_>>(), vec![SERIAL, "Z1Z0ABCD", "S4EWNX0R123456"]);
+    assert_eq!(replayed, recorded);
+    assert_eq!((replay_scsi.remaining(), replay_nvme.remaining()), (0, 0));
+}
+
+#[test]
+fn replays_a_timeout_without_waiting() {
+    let scripted = ScriptedTransport::new(FakeTransport::new().with_drive(0, FakeDrive::wd_blue()))
+        .with_injection(Injection::on(0, Fault::PendingForever).with_rights(AccessRights::Admin));
+    let recorder = Arc::new(RecordingTransport::new(Arc::new(scripted)));
+    let (recorded, recorded_errors) = scan(recorder.clone());
+    assert_eq!(recorded_errors, vec![SerialError::Timeout { drive: Some(0), method: Method::AdminRights }]);
+
+    let replay = Arc::new(ReplayTransport::new(recorder.capture()));
+    let (replayed, replayed_errors) = scan(replay);
+    assert_eq!(report(&replayed), report(&recorded));
+    assert_eq!(replayed_errors, recorded_errors);
+    assert!(replayed[0].outcomes[0].duration < Duration::from_millis(50));
+}
+
+#[test]
+fn unknown_drives_and_requests_are_refused() {
+    let replay = ReplayTransport::new(Capture::default());
+    assert_eq!(replay.open("\\\\.\\PhysicalDrive0", AccessRights::Admin), Err(ERROR_FILE_NOT_FOUND));
+
+    let capture = Capture::parse("simplehddserial capture 1\nopen\tpath=\\\\.\\PhysicalDrive0\trights=zero\tresult=ok\tmicros=10\n").unwrap();
+    let replay = ReplayTransport::new(capture);
+    let handle = replay.open("\\\\.\\PhysicalDrive0", AccessRights::Zero).unwrap();
+    let mut output = [0u8; 24];
+    assert_eq!(replay.ioctl(handle, IOCTL_DISK_GET_DRIVE_GEOMETRY, &[], &mut output, Duration::from_secs(1)), Err(ERROR_NOT_SUPPORTED));
+    assert_eq!(replay.open("\\\\.\\PhysicalDrive0", AccessRights::Zero), Err(ERROR_FILE_NOT_FOUND)); // each open answers once
+}
+
+#[test]
+fn rejects_unknown_versions_and_malformed_lines() {
+    assert!(matches!(Capture::parse("simplehddserial capture 3\n"), Err(CaptureError::UnsupportedVersion(_))));
+    assert!(matches!(Capture::parse("simplehddserial capture 0\n"), Err(CaptureError::UnsupportedVersion(_))));
+    assert!(matches!(Capture::parse(""), Err(CaptureError::UnsupportedVersion(_))));
+
+    let bad_hex = "# bug report from a field machine\nsimplehddserial capture 1\n\nioctl\tpath=x\tcode=0x00074080\tin=0\tout_len=24\tout=\tresult=ok:24\tmicros=1\n";
+    match Capture::parse(bad_hex) {
+        Err(CaptureError::Malformed { line, reason }) => {
+            assert_eq!(line, 4);
+            assert_eq!(reason, "odd number of hex digits");
+        }
+        other => panic!("expected a malformed line, got {:?}", other),
+    }
+    let missing_field = "simplehddserial capture 1\nopen\tpath=x\trights=admin\tmicros=1\n";
+    assert_eq!(Capture::parse(missing_field).unwrap_err().to_string(), "malformed capture line 2: missing result");
+}
+
+#[test]
+fn a_checked_in_capture_replays_on_any_platform() {
+    // synthesized by recording a FakeTransport scan of the drive in wd10ezex.hdparm, not taken
+    // from real hardware, it stands in for the kind of file a bug report would attach. it is a
+    // version 1 file, which later versions still read
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/wd10ezex.capture");
+    let (drives, errors) = scan(Arc::new(ReplayTransport::new(Capture::load(path).unwrap())));
+
+    assert_eq!(drives.len(), 1);
+    assert_eq!(drives[0].serial, SERIAL);
+    assert_eq!(drives[0].model.as_deref(), Some("WDC WD10EZEX-08WN4A0"));
+    assert_eq!(drives[0].method, Method::AdminRights);
+    assert!(drives[0].outcomes.iter().all(|outcome| outcome.result.is_ok()));
+    assert!(errors.is_empty());
+}
@@ -0,0 +1,278 @@
+mod common;
+
+// runs the hddserial binary against captures recorded from the in-memory fakes, so every
+// command and exit status can be checked without touching the machine's drives
+
+use common::scripted::{Fault, Injection, ScriptedTransport};
+use common::*;
+use simplehddserial::capture::{Capture, CapturedEvent, Recorder, RecordingTransport};
+use simplehddserial::transport::{DriveTransport, ERROR_ACCESS_DENIED};
+use simplehddserial::{DriveInfo, HardDriveSerial, ScanOptions};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+
+const SERIAL: &str = "WD-WCC4E1234567";
+
+// records what the binary will ask for: a scan of `drives`, then `query` on the first drive found
+fn record(dir: &TempDir, transport: impl DriveTransport + 'static, drives: Option<&[usize]>, query: impl Fn(&HardDriveSerial, &DriveInfo)) -> PathBuf {
+    let recorder = Arc::new(RecordingTransport::new(Arc::new(transport)));
+    let options = ScanOptions::new().with_operation_timeout(Duration::from_millis(50));
+    let options = match drives {
+        Some(drives) => options.with_drive_indices(drives.iter().copied()),
+        None => options,
+    };
+    let mut hd_serial = HardDriveSerial::with_transport(recorder.clone()).with_options(options);
+    if let Some(drive) = hd_serial.get_serial_numbers().ok().and_then(|drives| drives.into_iter().next()) {
+        query(&hd_serial, &drive);
+    }
+    let path = dir.path().join("drives.capture");
+    recorder.capture().save(&path).unwrap();
+    path
+}
+
+fn without_timings(capture: Capture) -> Vec<CapturedEvent> {
+    let mut events = capture.events;
+    for event in &mut events {
+        match event {
+            CapturedEvent::Open { elapsed, .. }
+            | CapturedEvent::Ioctl { elapsed, .. }
+            | CapturedEvent::ScsiOpen { elapsed, .. }
+            | CapturedEvent::Scsi { elapsed, .. }
+            | CapturedEvent::NvmeOpen { elapsed, .. }
+            | CapturedEvent::Nvme { elapsed, .. } => *elapsed = Duration::ZERO,
+        }
+    }
+    events
+}
+
+fn hddserial(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_hddserial")).args(args).output().unwrap()
+}
+
+fn replay(capture: &Path, args: &[&str]) -> Output {
+    let mut all = vec!["--replay", capture.to_str().unwrap(), "--timeout", "50"];
+    all.extend_from_slice(args);
+    hddserial(&all)
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn list_prints_every_drive() {
+    let dir = tempfile::tempdir().unwrap();
+    let fake = FakeTransport::new().with_drive(0, FakeDrive::wd_blue()).with_drive(3, FakeDrive::ata("S3Z9NB0K123456X", "Samsung SSD 860 EVO 500GB", "RVT02B6Q"));
+    let capture = record(&dir, fake, None, |_, _| {});
+
+    let output = replay(&capture, &["list"]);
+    assert_eq!(output.status.code(), Some(0));
+    let table = stdout(&output);
+    let lines: Vec<&str> = table.lines().collect();
+    assert!(lines[0].starts_with("INDEX  PATH"), "{}", table);
+    assert!(lines[1].contains(SERIAL) && lines[1].contains("WDC WD10EZEX-08WN4A0") && lines[1].contains("admin rights"));
+    assert!(lines[2].starts_with("3 ") && lines[2].contains("S3Z9NB0K123456X"));
+
+    let json = stdout(&replay(&capture, &["--format", "json", "list"]));
+    assert!(json.starts_with("[\n  {\"index\": 0, \"path\": \"\\\\\\\\.\\\\PhysicalDrive0\", \"serial\": \"WD-WCC4E1234567\""), "{}", json);
+    assert!(json.contains("\"agreement\": \"unanimous\""));
+
+    let csv = stdout(&replay(&capture, &["--format", "csv", "list"]));
+    assert_eq!(csv.lines().next(), Some("index,path,serial,model,firmware,bus,method,agreement,methods"));
+    assert!(csv.lines().nth(1).unwrap().starts_with("0,\\\\.\\PhysicalDrive0,WD-WCC4E1234567,WDC WD10EZEX-08WN4A0,01.01A01,"));
+}
+
+#[test]
+fn exit_status_tells_partial_from_total_failure() {
+    let dir = tempfile::tempdir().unwrap();
+    let fake = FakeTransport::new().with_drive(0, FakeDrive::wd_blue()).with_drive(1, FakeDrive::wd_blue());
+    let partial = record(&dir, ScriptedTransport::new(fake).with_injection(Injection::on(1, Fault::PendingForever)), None, |_, _| {});
+    let output = replay(&partial, &["list"]);
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(stdout(&output).lines().count(), 2); // the header and drive 0
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Drive 1"));
+
+    let dir = tempfile::tempdir().unwrap();
+    let hung = ScriptedTransport::new(FakeTransport::new().with_drive(0, FakeDrive::wd_blue())).with_injection(Injection::on(0, Fault::PendingForever));
+    let failed = record(&dir, hung, None, |_, _| {});
+    assert_eq!(replay(&failed, &["list"]).status.code(), Some(1));
+    assert_eq!(replay(&failed, &["identify", "0"]).status.code(), Some(1));
+}
+
+#[test]
+fn denied_drives_are_not_missing_drives() {
+    let dir = tempfile::tempdir().unwrap();
+    let fake = FakeTransport::new().with_drive(0, FakeDrive::wd_blue()).with_drive(1, FakeDrive::wd_blue());
+    let denied = ScriptedTransport::new(fake)
+        .with_injection(Injection::on(0, Fault::OpenFails(ERROR_ACCESS_DENIED)))
+        .with_injection(Injection::on(1, Fault::OpenFails(ERROR_ACCESS_DENIED)));
+    let capture = record(&dir, denied, None, |_, _| {});
+
+    let output = replay(&capture, &["list"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Drive 0") && stderr.contains("Drive 1") && !stderr.contains("no drives found"), "{}", stderr);
+    assert_eq!(replay(&capture, &["identify", "1"]).status.code(), Some(1));
+}
+
+#[test]
+fn no_drives_is_its_own_exit_status() {
+    let dir = tempfile::tempdir().unwrap();
+    let empty = dir.path().join("empty.capture");
+    Capture::default().save(&empty).unwrap();
+    assert_eq!(replay(&empty, &["list"]).status.code(), Some(3));
+    assert_eq!(replay(&empty, &["smart", "0"]).status.code(), Some(3));
+}
+
+#[test]
+fn identify_decodes_the_identify_block() {
+    let dir = tempfile::tempdir().unwrap();
+    let capture = record(&dir, FakeTransport::new().with_drive(0, FakeDrive::wd_blue()), Some(&[0]), |hd_serial, drive| {
+        hd_serial.identify_data(drive).unwrap();
+    });
+
+    let output = replay(&capture, &["identify", "0"]);
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    let table = stdout(&output);
+    assert!(table.contains(&format!("serial                {}\n", SERIAL)), "{}", table);
+    assert!(table.contains("capacity_bytes        1000204886016\n"));
+    assert!(table.contains("wwn                   50014ee2b5a1c3d4\n"));
+
+    let json = stdout(&replay(&capture, &["--format", "json", "identify", "\\\\.\\PhysicalDrive0"]));
+    assert!(json.starts_with("{\"serial\": \"WD-WCC4E1234567\", \"model\": \"WDC WD10EZEX-08WN4A0\""), "{}", json);
+    assert!(json.contains("\"rotation\": \"7200 rpm\""));
+}
+
+#[test]
+fn smart_prints_the_attribute_table() {
+    let dir = tempfile::tempdir().unwrap();
+    let capture = record(&dir, FakeTransport::new().with_drive(0, FakeDrive::wd_blue()), Some(&[0]), |hd_serial, drive| {
+        hd_serial.smart_attributes(drive).unwrap();
+    });
+
+    let csv = stdout(&replay(&capture, &["--format", "csv", "smart", "0"]));
+    assert_eq!(csv, "id,flags,kind,current,worst,threshold,raw,status\n5,0033,pre-fail,200,200,140,0,ok\n9,0032,old-age,71,71,0,21345,ok\n");
+}
+
+#[test]
+fn dump_writes_the_raw_block() {
+    let dir = tempfile::tempdir().unwrap();
+    let capture = record(&dir, FakeTransport::new().with_drive(0, FakeDrive::wd_blue()), Some(&[0]), |hd_serial, drive| {
+        hd_serial.identify_data(drive).unwrap();
+    });
+    let hdparm = std::fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/wd10ezex.hdparm")).unwrap();
+    assert_eq!(stdout(&replay(&capture, &["dump", "0"])), hdparm);
+
+    let binary = dir.path().join("identify.bin");
+    let output = replay(&capture, &["dump", "0", "--binary", "--output", binary.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(std::fs::read(&binary).unwrap(), load_hdparm("wd10ezex"));
+}
+
+#[test]
+fn dump_capture_records_what_it_asked() {
+    let dir = tempfile::tempdir().unwrap();
+    let capture = record(&dir, FakeTransport::new().with_drive(0, FakeDrive::wd_blue()), Some(&[0]), |hd_serial, drive| {
+        hd_serial.identify_data(drive).unwrap();
+    });
+    let output = replay(&capture, &["dump", "0", "--capture"]);
+    assert_eq!(output.status.code(), Some(0));
+
+    // a capture of a replay asks the same questions, so it replays again
+    let recaptured = dir.path().join("recaptured.capture");
+    std::fs::write(&recaptured, &output.stdout).unwrap();
+    assert_eq!(without_timings(Capture::load(&recaptured).unwrap()), without_timings(Capture::load(&capture).unwrap()));
+    assert_eq!(stdout(&replay(&recaptured, &["identify", "0"])).lines().next(), Some(format!("serial                {}", SERIAL).as_str()));
+}
+
+#[test]
+fn captures_of_the_linux_transports_replay_by_device_path() {
+    // what dump --capture records on linux: the sysfs scan and the IDENTIFY through SG_IO
+    let dir = tempfile::tempdir().unwrap();
+    let root = tempfile::tempdir().unwrap();
+    add_block_device(root.path(), "sda", &[("model", b"WDC WD10EZEX-08W\n")]);
+    let recorder = Recorder::new();
+    let scsi = FakeScsiTransport::new().with_device("/dev/sda", FakeSatDevice { identify: Some(load_hdparm("wd10ezex")), ..FakeSatDevice::default() });
+    let mut hd_serial = HardDriveSerial::with_sysfs_root(root.path()).with_scsi_transport(Arc::new(recorder.scsi_transport(Arc::new(scsi))));
+    let drive = hd_serial.get_serial_numbers().unwrap().remove(0);
+    hd_serial.identify_data(&drive).unwrap();
+    let capture = dir.path().join("sda.capture");
+    recorder.capture().save(&capture).unwrap();
+
+    let identify = replay(&capture, &["identify", "/dev/sda"]);
+    assert_eq!(identify.status.code(), Some(0), "{}", String::from_utf8_lossy(&identify.stderr));
+    assert_eq!(stdout(&identify).lines().next(), Some(format!("serial                {}", SERIAL).as_str()));
+
+    // recording a replay goes through the replayed SCSI transport, so the SCSI commands are captured again
+    let output = replay(&capture, &["dump", "/dev/sda", "--capture"]);
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    let recaptured = dir.path().join("recaptured.capture");
+    std::fs::write(&recaptured, &output.stdout).unwrap();
+    let scsi_commands = |path: &Path| Capture::load(path).unwrap().events.into_iter().filter(|event| matches!(event, CapturedEvent::Scsi { .. })).count();
+    assert_eq!(scsi_commands(&recaptured), scsi_commands(&capture));
+    assert_eq!(stdout(&replay(&recaptured, &["identify", "/dev/sda"])), stdout(&identify));
+}
+
+#[test]
+fn lists_drives_from_a_sysfs_tree() {
+    let root = tempfile::tempdir().unwrap();
+    add_block_device(root.path(), "sda", &[("model", b"WDC WD10EZEX-08W\n"), ("vpd_pg80", b"\x00\x80\x00\x0fWD-WCC4E1234567")]);
+    let output = hddserial(&["--sysfs-root", root.path().to_str().unwrap(), "--format", "csv", "list"]);
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout(&output).lines().nth(1).unwrap().starts_with("0,/dev/sda,WD-WCC4E1234567,"));
+}
+
+#[test]
+fn decode_reports_a_dump_without_any_drive() {
+    let data = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+    let hdparm = data.join("wd10ezex.hdparm");
+    let output = hddserial(&["decode", hdparm.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("ATA IDENTIFY DEVICE data (hdparm --Istdout words)"));
+
+    // the same report as identify on the live drive
+    let dir = tempfile::tempdir().unwrap();
+    let capture = record(&dir, FakeTransport::new().with_drive(0, FakeDrive::wd_blue()), Some(&[0]), |hd_serial, drive| {
+        hd_serial.identify_data(drive).unwrap();
+    });
+    assert_eq!(stdout(&output), stdout(&replay(&capture, &["identify", "0"])));
+
+    let descriptor = data.join("sandisk_cruzer_blade.storage-descriptor");
+    let json = stdout(&hddserial(&["--format", "json", "decode", descriptor.to_str().unwrap()]));
+    assert_eq!(json, "{\"serial\": \"4C530001230615117052\", \"model\": \"SanDisk Cruzer Blade\", \"firmware\": \"1.00\", \"bus\": \"Usb\", \"identifiers\": null}\n");
+
+    let garbage = dir.path().join("garbage.txt");
+    std::fs::write(&garbage, "not a dump\n").unwrap();
+    let output = hddserial(&["decode", garbage.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not binary, hdparm --Istdout, smartctl -r ioctl or plain hex data"));
+}
+
+#[test]
+fn decode_survives_nonsense_sector_sizes() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut block = [0u8; 512];
+    block[212..214].copy_from_slice(&0x5000u16.to_le_bytes()); // word 106: logical sector longer than 256 words
+    block[234..238].fill(0xFF); // words 117-118
+    let dump = dir.path().join("identify.bin");
+    std::fs::write(&dump, block).unwrap();
+
+    let output = hddserial(&["decode", dump.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout(&output).contains("logical_sector_size   512\n"), "{}", stdout(&output));
+}
+
+#[test]
+fn bad_command_lines_exit_with_usage() {
+    for args in [&[][..], &["frobnicate"], &["identify"], &["decode"], &["list", "extra"], &["--format", "xml", "list"], &["--binary", "list"], &["--timeout"]] {
+        let output = hddserial(args);
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("--help"));
+    }
+    let help = hddserial(&["--help"]);
+    assert_eq!(help.status.code(), Some(0));
+    assert!(stdout(&help).starts_with("usage: hddserial"));
+}
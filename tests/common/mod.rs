@@ -0,0 +1,585 @@
+// in-memory DriveTransport shared by the integration tests
+#![allow(dead_code)] // every test binary only uses part of the fake
+
+pub mod scripted;
+
+use simplehddserial::transport::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+// offset of the 512 byte data buffer inside SENDCMDOUTPARAMS
+pub const SENDCMDOUT_BUFFER_OFFSET: usize = 16; // after cBufferSize and DRIVERSTATUS
+
+// offset of SerialNumberOffset inside STORAGE_DEVICE_DESCRIPTOR
+pub const DESCRIPTOR_SERIAL_OFFSET: usize = 24;
+
+// how a fake drive fails to answer its ioctls
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hang {
+    UntilCancelled, // stays pending until the timeout passes, then is cancelled like overlapped i/o
+    Forever,        // never returns, like a driver that ignores the cancel
+}
+
+// canned responses for a single fake drive
+#[derive(Debug, Clone, Default)]
+pub struct FakeDrive {
+    pub ide_device_map: u8,                  // reported by DFP_GET_VERSION, 0 means admin method unsupported
+    pub identify: Option<[u8; 512]>,         // IDENTIFY DEVICE block returned by the receive drive data ioctls
+    pub descriptor: Option<Vec<u8>>,         // STORAGE_DEVICE_DESCRIPTOR returned by IOCTL_STORAGE_QUERY_PROPERTY
+    pub hang: Option<Hang>,                  // every ioctl hangs instead of answering
+    pub smart_data: Option<[u8; 512]>,       // SMART READ DATA block
+    pub smart_thresholds: Option<[u8; 512]>, // SMART READ ATTRIBUTE THRESHOLDS block
+    pub smart_return_status: Option<(u8, u8)>, // cylinder low/high returned by SMART RETURN STATUS
+}
+
+impl FakeDrive {
+    // a drive that answers every method with the same identity
+    pub fn ata(serial: &str, model: &str, firmware: &str) -> Self {
+        FakeDrive {
+            ide_device_map: 1,
+            identify: Some(identify_block(serial, model, firmware)),
+            descriptor: Some(descriptor_block(serial)),
+            ..FakeDrive::default()
+        }
+    }
+
+    // the drive in wd10ezex.hdparm (serial WD-WCC4E1234567), answering every method and the SMART reads
+    pub fn wd_blue() -> Self {
+        FakeDrive {
+            identify: Some(load_hdparm("wd10ezex")),
+            smart_data: Some(smart_data_block(&[(5, 0x0033, 200, 200, 0), (9, 0x0032, 71, 71, 21345)])),
+            smart_thresholds: Some(smart_thresholds_block(&[(5, 140), (9, 0)])),
+            ..FakeDrive::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01")
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct FakeTransport {
+    drives: HashMap<String, FakeDrive>,
+    open_handles: Mutex<HashMap<usize, String>>,
+    next_handle: AtomicUsize,
+    cancelled: AtomicUsize,
+    peak_open_handles: AtomicUsize,
+    ioctls: Mutex<Vec<IoctlCall>>,
+}
+
+// control code and buffer sizes of one ioctl the fake received
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoctlCall {
+    pub control_code: u32,
+    pub input_len: usize,
+    pub output_len: usize,
+}
+
+impl FakeTransport {
+    pub fn new() -> Self {
+        FakeTransport::default()
+    }
+
+    // registers a drive under \\.\PhysicalDrive{index}
+    pub fn with_drive(mut self, index: usize, drive: FakeDrive) -> Self {
+        self.drives.insert(format!("\\\\.\\PhysicalDrive{}", index), drive);
+        self
+    }
+
+    // number of handles that were opened but not closed yet
+    pub fn open_handle_count(&self) -> usize {
+        self.open_handles.lock().unwrap().len()
+    }
+
+    // most handles that were open at the same time, i.e. how many drives were probed concurrently
+    pub fn peak_open_handles(&self) -> usize {
+        self.peak_open_handles.load(Ordering::SeqCst)
+    }
+
+    // number of ioctls that hung until their timeout and were cancelled
+    pub fn cancelled_count(&self) -> usize {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    // every ioctl received so far, in order
+    pub fn ioctls(&self) -> Vec<IoctlCall> {
+        self.ioctls.lock().unwrap().clone()
+    }
+
+    fn drive(&self, handle: DriveHandle) -> Result<FakeDrive, u32> {
+        let path = self.open_handles.lock().unwrap().get(&handle.0).cloned().ok_or(ERROR_INVALID_PARAMETER)?;
+        Ok(self.drives[&path].clone())
+    }
+}
+
+impl DriveTransport for FakeTransport {
+    fn open(&self, path: &str, _rights: AccessRights) -> Result<DriveHandle, u32> {
+        if !self.drives.contains_key(path) {
+            return Err(ERROR_FILE_NOT_FOUND);
+        }
+        let handle = self.next_handle.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut open_handles = self.open_handles.lock().unwrap();
+        open_handles.insert(handle, path.to_string());
+        self.peak_open_handles.fetch_max(open_handles.len(), Ordering::SeqCst);
+        Ok(DriveHandle(handle))
+    }
+
+    fn ioctl(&self, handle: DriveHandle, control_code: u32, input: &[u8], output: &mut [u8], timeout: Duration) -> Result<usize, u32> {
+        let drive = self.drive(handle)?;
+        self.ioctls.lock().unwrap().push(IoctlCall { control_code, input_len: input.len(), output_len: output.len() });
+        match drive.hang {
+            Some(Hang::UntilCancelled) => {
+                thread::sleep(timeout);
+                self.cancelled.fetch_add(1, Ordering::SeqCst);
+                return Err(ERROR_TIMEOUT);
+            }
+            Some(Hang::Forever) => loop {
+                thread::park();
+            },
+            None => {}
+        }
+        match control_code {
+            DFP_GET_VERSION => {
+                output[0] = 1; // version
+                output[3] = drive.ide_device_map;
+                Ok(output.len())
+            }
+            DFP_RECEIVE_DRIVE_DATA => {
+                // SENDCMDINPARAMS: the features register is byte 4, the command register byte 10
+                let block = match (input[10], input[4]) {
+                    (0xB0, 0xD0) => drive.smart_data,
+                    (0xB0, 0xD1) => drive.smart_thresholds,
+                    (0xB0, _) => None,
+                    _ => drive.identify,
+                };
+                let block = block.ok_or(ERROR_NOT_SUPPORTED)?;
+                output[SENDCMDOUT_BUFFER_OFFSET..SENDCMDOUT_BUFFER_OFFSET + 512].copy_from_slice(&block);
+                Ok(output.len())
+            }
+            SMART_SEND_DRIVE_COMMAND if input[4] == 0xDA => {
+                let (cyl_low, cyl_high) = drive.smart_return_status.ok_or(ERROR_NOT_SUPPORTED)?;
+                // the returned IDEREGS land in the data buffer
+                output[SENDCMDOUT_BUFFER_OFFSET + 3] = cyl_low;
+                output[SENDCMDOUT_BUFFER_OFFSET + 4] = cyl_high;
+                Ok(output.len())
+            }
+            IOCTL_STORAGE_QUERY_PROPERTY => {
+                let descriptor = drive.descriptor.ok_or(ERROR_NOT_SUPPORTED)?;
+                let len = descriptor.len().min(output.len());
+                output[..len].copy_from_slice(&descriptor[..len]);
+                Ok(len)
+            }
+            IOCTL_DISK_GET_DRIVE_GEOMETRY | IOCTL_DISK_GET_DRIVE_GEOMETRY_EX => Ok(output.len()), // all zero geometry
+            _ => Err(ERROR_NOT_SUPPORTED),
+        }
+    }
+
+    fn close(&self, handle: DriveHandle) {
+        self.open_handles.lock().unwrap().remove(&handle.0);
+    }
+}
+
+// writes an ATA string (two characters per word, high byte first, space padded) into words first..=last
+pub fn put_ata_string(block: &mut [u8; 512], first: usize, last: usize, text: &str) {
+    let width = (last - first + 1) * 2;
+    let mut padded = text.as_bytes().to_vec();
+    padded.resize(width, b' ');
+    for (i, pair) in padded.chunks(2).enumerate() {
+        let word = first + i;
+        block[word * 2] = pair[1];
+        block[word * 2 + 1] = pair[0];
+    }
+}
+
+// builds an IDENTIFY DEVICE block with the given serial, model and firmware and a valid checksum
+pub fn identify_block(serial: &str, model: &str, firmware: &str) -> [u8; 512] {
+    let mut block = [0u8; 512];
+    block[0] = 0x40; // fixed, non removable ATA device
+    put_ata_string(&mut block, 10, 19, serial);
+    put_ata_string(&mut block, 23, 26, firmware);
+    put_ata_string(&mut block, 27, 46, model);
+    seal_identify_block(&mut block);
+    block
+}
+
+// builds a SMART READ DATA block from (id, flags, current, worst, raw) entries, with a valid checksum
+pub fn smart_data_block(attributes: &[(u8, u16, u8, u8, u64)]) -> [u8; 512] {
+    let mut block = [0u8; 512];
+    block[0] = 0x10; // revision
+    for (i, (id, flags, current, worst, raw)) in attributes.iter().enumerate() {
+        let entry = &mut block[2 + i * 12..2 + (i + 1) * 12];
+        entry[0] = *id;
+        entry[1..3].copy_from_slice(&flags.to_le_bytes());
+        entry[3] = *current;
+        entry[4] = *worst;
+        entry[5..11].copy_from_slice(&raw.to_le_bytes()[..6]);
+    }
+    block[511] = simplehddserial::smart::smart_checksum(&block);
+    block
+}
+
+// builds a SMART READ ATTRIBUTE THRESHOLDS block from (id, threshold) entries
+pub fn smart_thresholds_block(thresholds: &[(u8, u8)]) -> [u8; 512] {
+    let mut block = [0u8; 512];
+    block[0] = 0x10; // revision
+    for (i, (id, threshold)) in thresholds.iter().enumerate() {
+        block[2 + i * 12] = *id;
+        block[3 + i * 12] = *threshold;
+    }
+    block[511] = simplehddserial::smart::smart_checksum(&block);
+    block
+}
+
+// writes the word 255 signature and checksum, e.g. after editing a block
+pub fn seal_identify_block(block: &mut [u8; 512]) {
+    block[510] = 0xA5;
+    block[511] = simplehddserial::identify::identify_checksum(block);
+}
+
+// builds a STORAGE_DEVICE_DESCRIPTOR carrying only a serial number string
+pub fn descriptor_block(serial: &str) -> Vec<u8> {
+    let mut block = vec![0u8; 36];
+    block.extend_from_slice(serial.as_bytes());
+    block.push(0);
+    let size = block.len() as u32;
+    block[0..4].copy_from_slice(&36u32.to_le_bytes()); // version
+    block[4..8].copy_from_slice(&size.to_le_bytes()); // size
+    block[DESCRIPTOR_SERIAL_OFFSET..DESCRIPTOR_SERIAL_OFFSET + 4].copy_from_slice(&36u32.to_le_bytes());
+    block
+}
+
+// loads a binary page stored as plain hex (e.g. `xxd -p` output) from tests/data
+pub fn load_hex(name: &str) -> Vec<u8> {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data").join(name);
+    let text: String = std::fs::read_to_string(&path).unwrap().split_whitespace().collect();
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+}
+
+// loads an IDENTIFY block stored in `hdparm --Istdout` format from tests/data
+pub fn load_hdparm(name: &str) -> [u8; 512] {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data").join(format!("{}.hdparm", name));
+    let text = std::fs::read_to_string(&path).unwrap();
+    let words: Vec<u16> = text.split_whitespace().map(|word| u16::from_str_radix(word, 16).unwrap()).collect();
+    assert_eq!(words.len(), 256, "{} is not a full IDENTIFY block", path.display());
+
+    let mut block = [0u8; 512];
+    for (i, word) in words.iter().enumerate() {
+        block[i * 2..i * 2 + 2].copy_from_slice(&word.to_le_bytes());
+    }
+    block
+}
+
+// canned answers for a single fake SCSI device that translates ATA PASS-THROUGH (SAT)
+#[derive(Debug, Clone, Default)]
+pub struct FakeSatDevice {
+    pub identify: Option<[u8; 512]>,   // returned for IDENTIFY DEVICE
+    pub smart_data: Option<[u8; 512]>, // returned for SMART READ DATA
+    pub smart_thresholds: Option<[u8; 512]>, // returned for SMART READ ATTRIBUTE THRESHOLDS
+    pub smart_return_status: Option<(u8, u8)>, // lba mid/high returned for SMART RETURN STATUS
+    pub only_12_byte_cdbs: bool,       // rejects ATA PASS-THROUGH(16) with INVALID OPERATION CODE
+    pub inquiry: Option<Vec<u8>>,      // standard INQUIRY data, INQUIRY is rejected if None
+    pub vpd_pages: Vec<Vec<u8>>,       // vpd pages by their page code in byte 1
+    pub hang: Option<Hang>,            // every command hangs instead of answering
+}
+
+impl FakeSatDevice {
+    pub fn ata(serial: &str, model: &str, firmware: &str) -> Self {
+        FakeSatDevice { identify: Some(identify_block(serial, model, firmware)), ..FakeSatDevice::default() }
+    }
+
+    // a plain SCSI disk that answers INQUIRY, vpd 0x80 and vpd 0x83 with one NAA designator but no ATA commands
+    pub fn scsi(vendor: &str, product: &str, revision: &str, serial: &str, naa: &[u8]) -> Self {
+        FakeSatDevice {
+            inquiry: Some(standard_inquiry(vendor, product, revision)),
+            vpd_pages: vec![vpd_page(0x80, serial.as_bytes()), vpd_page(0x83, &designation_descriptor(0x01, 0x03, naa))],
+            ..FakeSatDevice::default()
+        }
+    }
+}
+
+// standard INQUIRY data of a direct access block device
+pub fn standard_inquiry(vendor: &str, product: &str, revision: &str) -> Vec<u8> {
+    let mut data = vec![0u8; 36];
+    data[2] = 0x06; // SPC-4
+    data[4] = 31; // additional length
+    for (range, text) in [(8..16, vendor), (16..32, product), (32..36, revision)] {
+        data[range.clone()].fill(b' ');
+        data[range.start..range.start + text.len()].copy_from_slice(text.as_bytes());
+    }
+    data
+}
+
+// wraps `payload` in a vpd page header
+pub fn vpd_page(page_code: u8, payload: &[u8]) -> Vec<u8> {
+    let mut page = vec![0x00, page_code];
+    page.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    page.extend_from_slice(payload);
+    page
+}
+
+// one device identification descriptor with the logical unit association
+pub fn designation_descriptor(code_set: u8, designator_type: u8, designator: &[u8]) -> Vec<u8> {
+    let mut descriptor = vec![code_set, designator_type, 0x00, designator.len() as u8];
+    descriptor.extend_from_slice(designator);
+    descriptor
+}
+
+// in-memory ScsiTransport emulating a SAT layer in front of ata drives
+#[derive(Default)]
+pub struct FakeScsiTransport {
+    devices: HashMap<String, FakeSatDevice>,
+    open_handles: Mutex<HashMap<usize, String>>,
+    next_handle: AtomicUsize,
+    cdbs: Mutex<Vec<Vec<u8>>>,
+    timeouts: Mutex<Vec<Duration>>,
+}
+
+impl FakeScsiTransport {
+    pub fn new() -> Self {
+        FakeScsiTransport::default()
+    }
+
+    // registers a device under `path`, e.g. /dev/sda
+    pub fn with_device(mut self, path: &str, device: FakeSatDevice) -> Self {
+        self.devices.insert(path.to_string(), device);
+        self
+    }
+
+    // every CDB received so far, in order
+    pub fn cdbs(&self) -> Vec<Vec<u8>> {
+        self.cdbs.lock().unwrap().clone()
+    }
+
+    // the timeout every command was given, in order
+    pub fn timeouts(&self) -> Vec<Duration> {
+        self.timeouts.lock().unwrap().clone()
+    }
+
+    pub fn open_handle_count(&self) -> usize {
+        self.open_handles.lock().unwrap().len()
+    }
+}
+
+// writes CHECK CONDITION descriptor sense data, optionally with an ata status return descriptor
+pub fn descriptor_sense(sense: &mut [u8], sense_key: u8, asc: u8, ascq: u8, ata_error_status: Option<(u8, u8)>) -> usize {
+    sense[..8].copy_from_slice(&[0x72, sense_key, asc, ascq, 0, 0, 0, 0]);
+    match ata_error_status {
+        Some((error, status)) => {
+            sense[7] = 14;
+            sense[8..22].copy_from_slice(&[0x09, 0x0C, 0, error, 0, 0, 0, 0, 0, 0, 0, 0, 0xA0, status]);
+            22
+        }
+        None => 8,
+    }
+}
+
+impl ScsiTransport for FakeScsiTransport {
+    fn open(&self, path: &str) -> Result<DriveHandle, u32> {
+        if !self.devices.contains_key(path) {
+            return Err(ERROR_FILE_NOT_FOUND);
+        }
+        let handle = self.next_handle.fetch_add(1, Ordering::SeqCst) + 1;
+        self.open_handles.lock().unwrap().insert(handle, path.to_string());
+        Ok(DriveHandle(handle))
+    }
+
+    fn execute(&self, handle: DriveHandle, cdb: &[u8], _direction: DataDirection, data: &mut [u8], sense: &mut [u8], timeout: Duration) -> Result<ScsiResponse, u32> {
+        self.cdbs.lock().unwrap().push(cdb.to_vec());
+        self.timeouts.lock().unwrap().push(timeout);
+        let path = self.open_handles.lock().unwrap().get(&handle.0).cloned().ok_or(ERROR_INVALID_PARAMETER)?;
+        let device = &self.devices[&path];
+        match device.hang {
+            Some(Hang::UntilCancelled) => {
+                thread::sleep(timeout);
+                return Err(ERROR_TIMEOUT);
+            }
+            Some(Hang::Forever) => loop {
+                thread::park();
+            },
+            None => {}
+        }
+        let check_condition = |sense_len| Ok(ScsiResponse { status: 0x02, sense_len, transferred: 0 });
+
+        if cdb[0] == 0x12 {
+            let page = match (cdb[1] & 0x01, &device.inquiry) {
+                (0, Some(inquiry)) => Some(inquiry.clone()),
+                (1, Some(_)) => device.vpd_pages.iter().find(|page| page[1] == cdb[2]).cloned(),
+                _ => None,
+            };
+            return match page {
+                Some(page) => {
+                    let len = page.len().min(data.len());
+                    data[..len].copy_from_slice(&page[..len]);
+                    Ok(ScsiResponse { status: 0x00, sense_len: 0, transferred: len })
+                }
+                None => check_condition(descriptor_sense(sense, 0x05, 0x24, 0x00, None)), // invalid field in cdb
+            };
+        }
+
+        // (features, command) out of either CDB size
+        let (features, command) = match cdb[0] {
+            0x85 if !device.only_12_byte_cdbs => (cdb[4], cdb[14]),
+            0xA1 => (cdb[3], cdb[9]),
+            _ => return check_condition(descriptor_sense(sense, 0x05, 0x20, 0x00, None)), // invalid operation code
+        };
+        if (command, features) == (0xB0, 0xDA) {
+            let Some((lba_mid, lba_high)) = device.smart_return_status else {
+                return check_condition(descriptor_sense(sense, 0x0B, 0x00, 0x00, Some((0x04, 0x51)))); // ata ABRT
+            };
+            // CK_COND: RECOVERED ERROR, ATA PASS THROUGH INFORMATION AVAILABLE, registers in the descriptor
+            sense[..8].copy_from_slice(&[0x72, 0x01, 0x00, 0x1D, 0, 0, 0, 14]);
+            sense[8..22].copy_from_slice(&[0x09, 0x0C, 0, 0x00, 0, 0, 0, 0, 0, lba_mid, 0, lba_high, 0xA0, 0x50]);
+            return check_condition(22);
+        }
+        let block = match (command, features) {
+            (0xEC, _) => device.identify,
+            (0xB0, 0xD0) => device.smart_data,
+            (0xB0, 0xD1) => device.smart_thresholds,
+            _ => None,
+        };
+        match block {
+            Some(block) => {
+                let len = block.len().min(data.len());
+                data[..len].copy_from_slice(&block[..len]);
+                Ok(ScsiResponse { status: 0x00, sense_len: 0, transferred: len })
+            }
+            None => check_condition(descriptor_sense(sense, 0x0B, 0x00, 0x00, Some((0x04, 0x51)))), // ata ABRT
+        }
+    }
+
+    fn close(&self, handle: DriveHandle) {
+        self.open_handles.lock().unwrap().remove(&handle.0);
+    }
+}
+
+// creates /block/{name}/device under a fake sysfs `root` and writes the given attributes into it
+pub fn add_block_device(root: &std::path::Path, name: &str, attributes: &[(&str, &[u8])]) {
+    let device_dir = root.join("block").join(name).join("device");
+    std::fs::create_dir_all(&device_dir).unwrap();
+    for (attribute, contents) in attributes {
+        std::fs::write(device_dir.join(attribute), contents).unwrap();
+    }
+}
+
+// synthesized Identify Controller data structure (CNS 01h) with the given strings, space padded
+pub fn nvme_identify_controller(serial: &str, model: &str, firmware: &str) -> Vec<u8> {
+    let mut data = vec![0u8; 4096];
+    let mut put = |range: std::ops::Range<usize>, text: &str| {
+        data[range.clone()].fill(b' ');
+        data[range.start..range.start + text.len()].copy_from_slice(text.as_bytes());
+    };
+    put(4..24, serial);
+    put(24..64, model);
+    put(64..72, firmware);
+    data
+}
+
+// synthesized Identify Namespace data structure (CNS 00h) with the given identifiers
+pub fn nvme_identify_namespace(eui64: [u8; 8], nguid: [u8; 16]) -> Vec<u8> {
+    let mut data = vec![0u8; 4096];
+    data[0..8].copy_from_slice(&1_953_525_168u64.to_le_bytes()); // NSZE
+    data[8..16].copy_from_slice(&1_953_525_168u64.to_le_bytes()); // NCAP
+    data[104..120].copy_from_slice(&nguid);
+    data[120..128].copy_from_slice(&eui64);
+    data
+}
+
+// encodes a Namespace Identification Descriptor list from (type, value) pairs
+pub fn nvme_namespace_descriptors(descriptors: &[(u8, &[u8])]) -> Vec<u8> {
+    let mut data = vec![0u8; 4096];
+    let mut offset = 0;
+    for (kind, value) in descriptors {
+        data[offset] = *kind;
+        data[offset + 1] = value.len() as u8;
+        data[offset + 4..offset + 4 + value.len()].copy_from_slice(value);
+        offset += 4 + value.len();
+    }
+    data
+}
+
+// canned Identify responses for one namespace
+#[derive(Debug, Clone, Default)]
+pub struct FakeNamespace {
+    pub identify: Vec<u8>,            // Identify Namespace data
+    pub descriptors: Option<Vec<u8>>, // descriptor list, None for a controller older than NVMe 1.3
+}
+
+// in-memory NvmeTransport answering Identify with canned data per controller path
+#[derive(Default)]
+pub struct FakeNvmeTransport {
+    controllers: HashMap<String, Vec<u8>>, // path -> Identify Controller data
+    namespaces: HashMap<(String, u32), FakeNamespace>,
+    log_pages: HashMap<(String, u8), Vec<u8>>, // (path, log id) -> page
+    open_handles: Mutex<HashMap<usize, String>>,
+    next_handle: AtomicUsize,
+    commands: Mutex<Vec<NvmeAdminCommand>>,
+}
+
+impl FakeNvmeTransport {
+    pub fn new() -> Self {
+        FakeNvmeTransport::default()
+    }
+
+    // registers a controller under `path`, e.g. /dev/nvme0
+    pub fn with_controller(mut self, path: &str, identify_controller: Vec<u8>) -> Self {
+        self.controllers.insert(path.to_string(), identify_controller);
+        self
+    }
+
+    // registers namespace `nsid` of the controller at `path`
+    pub fn with_namespace(mut self, path: &str, nsid: u32, namespace: FakeNamespace) -> Self {
+        self.namespaces.insert((path.to_string(), nsid), namespace);
+        self
+    }
+
+    // registers log page `lid` of the controller at `path`
+    pub fn with_log_page(mut self, path: &str, lid: u8, page: Vec<u8>) -> Self {
+        self.log_pages.insert((path.to_string(), lid), page);
+        self
+    }
+
+    // every admin command received so far, in order
+    pub fn commands(&self) -> Vec<NvmeAdminCommand> {
+        self.commands.lock().unwrap().clone()
+    }
+
+    pub fn open_handle_count(&self) -> usize {
+        self.open_handles.lock().unwrap().len()
+    }
+}
+
+impl NvmeTransport for FakeNvmeTransport {
+    fn open(&self, path: &str) -> Result<DriveHandle, u32> {
+        if !self.controllers.contains_key(path) {
+            return Err(ERROR_FILE_NOT_FOUND);
+        }
+        let handle = self.next_handle.fetch_add(1, Ordering::SeqCst) + 1;
+        self.open_handles.lock().unwrap().insert(handle, path.to_string());
+        Ok(DriveHandle(handle))
+    }
+
+    fn admin_command(&self, handle: DriveHandle, command: &NvmeAdminCommand, data: &mut [u8], _timeout: Duration) -> Result<u32, u32> {
+        self.commands.lock().unwrap().push(*command);
+        let path = self.open_handles.lock().unwrap().get(&handle.0).cloned().ok_or(ERROR_INVALID_PARAMETER)?;
+        let namespace = self.namespaces.get(&(path.clone(), command.nsid));
+        let page = match (command.opcode, command.cdw10 & 0xFF) {
+            (0x06, 0x01) => Some(&self.controllers[&path]),
+            (0x06, 0x00) => namespace.map(|namespace| &namespace.identify),
+            (0x06, 0x03) => namespace.and_then(|namespace| namespace.descriptors.as_ref()),
+            (0x02, lid) => self.log_pages.get(&(path.clone(), lid as u8)),
+            _ => None,
+        };
+        match page {
+            Some(page) => {
+                let len = page.len().min(data.len());
+                data[..len].copy_from_slice(&page[..len]);
+                Ok(0)
+            }
+            None => Err(ERROR_NOT_SUPPORTED), // invalid opcode, field or namespace
+        }
+    }
+
+    fn close(&self, handle: DriveHandle) {
+        self.open_handles.lock().unwrap().remove(&handle.0);
+    }
+}
@@ -0,0 +1,153 @@
+// fault injecting wrapper around FakeTransport
+//
+// every open and ioctl is checked against a script of injections, the first one that matches
+// replaces or bends the answer of the wrapped fake. an injection can be limited to one drive,
+// the access rights a method opens with (which tells the admin and smart methods apart, their
+// control codes are the same), one control code and a number of hits
+
+use super::FakeTransport;
+use simplehddserial::transport::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+// what goes wrong
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    OpenFails(u32),     // CreateFile fails with this win32 error code
+    Fails(u32),         // DeviceIoControl fails with this win32 error code
+    PendingForever,     // ERROR_IO_PENDING that never signals, the transport cancels it at the timeout
+    ShortRead(usize),   // the output is filled but only this many bytes are reported returned
+    PartialFill(usize), // only this many output bytes are written, the full length is reported
+}
+
+// one entry of the script
+#[derive(Debug, Clone)]
+pub struct Injection {
+    drive: usize,
+    rights: Option<AccessRights>,
+    control_code: Option<u32>,
+    remaining: Option<usize>,
+    fault: Fault,
+}
+
+impl Injection {
+    // injects `fault` into every matching request for drive `drive`
+    pub fn on(drive: usize, fault: Fault) -> Self {
+        Injection { drive, rights: None, control_code: None, remaining: None, fault }
+    }
+
+    // only for handles opened with `rights`, i.e. one retrieval method
+    pub fn with_rights(mut self, rights: AccessRights) -> Self {
+        self.rights = Some(rights);
+        self
+    }
+
+    // only for this control code
+    pub fn with_control_code(mut self, control_code: u32) -> Self {
+        self.control_code = Some(control_code);
+        self
+    }
+
+    // only for the first `times` matching requests, then the drive answers normally again
+    pub fn times(mut self, times: usize) -> Self {
+        self.remaining = Some(times);
+        self
+    }
+
+    fn matches(&self, drive: usize, rights: AccessRights, control_code: Option<u32>) -> bool {
+        let is_open_fault = matches!(self.fault, Fault::OpenFails(_));
+        self.drive == drive
+            && self.remaining != Some(0)
+            && self.rights.is_none_or(|wanted| wanted == rights)
+            && is_open_fault == control_code.is_none()
+            && self.control_code.is_none_or(|wanted| Some(wanted) == control_code)
+    }
+}
+
+pub struct ScriptedTransport {
+    inner: FakeTransport,
+    script: Mutex<Vec<Injection>>,
+    handles: Mutex<HashMap<DriveHandle, (usize, AccessRights)>>,
+    cancelled: AtomicUsize,
+}
+
+impl ScriptedTransport {
+    pub fn new(inner: FakeTransport) -> Self {
+        ScriptedTransport { inner, script: Mutex::new(Vec::new()), handles: Mutex::new(HashMap::new()), cancelled: AtomicUsize::new(0) }
+    }
+
+    // appends an injection, earlier ones win when several match
+    pub fn with_injection(self, injection: Injection) -> Self {
+        self.script.lock().unwrap().push(injection);
+        self
+    }
+
+    // the wrapped fake, e.g. to check its handle bookkeeping
+    pub fn inner(&self) -> &FakeTransport {
+        &self.inner
+    }
+
+    // number of requests that stayed pending until they were cancelled
+    pub fn cancelled_count(&self) -> usize {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    // takes one hit of the first matching injection
+    fn fault(&self, drive: usize, rights: AccessRights, control_code: Option<u32>) -> Option<Fault> {
+        let mut script = self.script.lock().unwrap();
+        let injection = script.iter_mut().find(|injection| injection.matches(drive, rights, control_code))?;
+        if let Some(remaining) = injection.remaining.as_mut() {
+            *remaining -= 1;
+        }
+        Some(injection.fault)
+    }
+}
+
+// drive index of a \\.\PhysicalDriveN path
+fn drive_index(path: &str) -> usize {
+    path.strip_prefix("\\\\.\\PhysicalDrive").and_then(|n| n.parse().ok()).unwrap_or(usize::MAX)
+}
+
+impl DriveTransport for ScriptedTransport {
+    fn open(&self, path: &str, rights: AccessRights) -> Result<DriveHandle, u32> {
+        let drive = drive_index(path);
+        if let Some(Fault::OpenFails(code)) = self.fault(drive, rights, None) {
+            return Err(code);
+        }
+        let handle = self.inner.open(path, rights)?;
+        self.handles.lock().unwrap().insert(handle, (drive, rights));
+        Ok(handle)
+    }
+
+    fn ioctl(&self, handle: DriveHandle, control_code: u32, input: &[u8], output: &mut [u8], timeout: Duration) -> Result<usize, u32> {
+        let (drive, rights) = self.handles.lock().unwrap().get(&handle).copied().ok_or(ERROR_INVALID_PARAMETER)?;
+        match self.fault(drive, rights, Some(control_code)) {
+            None | Some(Fault::OpenFails(_)) => self.inner.ioctl(handle, control_code, input, output, timeout),
+            Some(Fault::Fails(code)) => Err(code),
+            Some(Fault::PendingForever) => {
+                thread::sleep(timeout);
+                self.cancelled.fetch_add(1, Ordering::SeqCst);
+                Err(ERROR_TIMEOUT)
+            }
+            Some(Fault::ShortRead(len)) => {
+                self.inner.ioctl(handle, control_code, input, output, timeout)?;
+                Ok(len.min(output.len()))
+            }
+            Some(Fault::PartialFill(len)) => {
+                let mut scratch = vec![0u8; output.len()];
+                self.inner.ioctl(handle, control_code, input, &mut scratch, timeout)?;
+                let len = len.min(output.len());
+                output[..len].copy_from_slice(&scratch[..len]);
+                Ok(output.len())
+            }
+        }
+    }
+
+    fn close(&self, handle: DriveHandle) {
+        self.handles.lock().unwrap().remove(&handle);
+        self.inner.close(handle);
+    }
+}
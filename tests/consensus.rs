@@ -0,0 +1,112 @@
+mod common;
+
+use common::{descriptor_block, FakeDrive, FakeTransport};
+use simplehddserial::{reconcile, Agreement, HardDriveSerial, Method};
+use std::sync::Arc;
+
+fn candidates(serials: &[(Method, &str)]) -> Vec<(Method, String)> {
+    serials.iter().map(|(method, serial)| (*method, serial.to_string())).collect()
+}
+
+#[test]
+fn identical_serials_are_unanimous() {
+    let consensus = reconcile(&candidates(&[
+        (Method::AdminRights, "WD-WCC4E1234567"),
+        (Method::Smart, "WD-WCC4E1234567"),
+        (Method::ZeroRights, "WD-WCC4E1234567"),
+    ]))
+    .unwrap();
+    assert_eq!(consensus.serial, "WD-WCC4E1234567");
+    assert_eq!(consensus.agreement, Agreement::Unanimous);
+    assert_eq!(consensus.agreeing, vec![Method::AdminRights, Method::Smart, Method::ZeroRights]);
+    assert!(!consensus.has_conflict());
+}
+
+#[test]
+fn padding_is_ignored() {
+    let consensus = reconcile(&candidates(&[(Method::AdminRights, "     WD-WCC4E1234567"), (Method::ZeroRights, "WD-WCC4E1234567\0\0")])).unwrap();
+    assert_eq!(consensus.serial, "WD-WCC4E1234567");
+    assert_eq!(consensus.agreement, Agreement::Unanimous);
+}
+
+#[test]
+fn hex_encoded_serial_joins_the_plain_one() {
+    // "S3Z9NB0K123456X" as some storage drivers report it
+    let consensus = reconcile(&candidates(&[
+        (Method::AdminRights, "S3Z9NB0K123456X"),
+        (Method::ZeroRights, "53335a394e42304b313233343536582020202020"),
+    ]))
+    .unwrap();
+    assert_eq!(consensus.serial, "S3Z9NB0K123456X");
+    assert_eq!(consensus.agreement, Agreement::Unanimous);
+}
+
+#[test]
+fn byte_swapped_serial_joins_the_straight_one() {
+    let consensus = reconcile(&candidates(&[
+        (Method::ZeroRights, "W -DCW4C1E325476"), // swapped along with the 5 leading pad bytes of the field
+        (Method::AdminRights, "WD-WCC4E1234567"),
+        (Method::Smart, "WD-WCC4E1234567"),
+    ]))
+    .unwrap();
+    assert_eq!(consensus.serial, "WD-WCC4E1234567");
+    assert_eq!(consensus.agreement, Agreement::Unanimous);
+    assert_eq!(consensus.agreeing, vec![Method::ZeroRights, Method::AdminRights, Method::Smart]);
+}
+
+#[test]
+fn the_majority_wins_and_the_rest_is_flagged() {
+    let consensus = reconcile(&candidates(&[
+        (Method::AdminRights, "WD-WCC4E1234567"),
+        (Method::Smart, "WD-WCC4E1234567"),
+        (Method::ZeroRights, "0000000000000001"),
+    ]))
+    .unwrap();
+    assert_eq!(consensus.serial, "WD-WCC4E1234567");
+    assert_eq!(consensus.agreement, Agreement::Majority);
+    assert_eq!(consensus.conflicts, vec![(Method::ZeroRights, "0000000000000001".to_string())]);
+}
+
+#[test]
+fn a_tie_goes_to_the_earliest_method() {
+    let consensus = reconcile(&candidates(&[(Method::Smart, "S3Z9NB0K123456X"), (Method::ZeroRights, "VB0123456")])).unwrap();
+    assert_eq!(consensus.serial, "S3Z9NB0K123456X");
+    assert_eq!(consensus.agreement, Agreement::Plurality);
+    assert_eq!(consensus.agreeing, vec![Method::Smart]);
+    assert!(consensus.has_conflict());
+}
+
+#[test]
+fn a_single_candidate_is_taken_as_is() {
+    let consensus = reconcile(&candidates(&[(Method::Sysfs, "  Z1D2ABCD  ")])).unwrap();
+    assert_eq!(consensus.serial, "Z1D2ABCD");
+    assert_eq!(consensus.agreement, Agreement::Single);
+    assert!(reconcile(&[]).is_none());
+}
+
+#[test]
+fn a_scan_reports_one_drive_with_the_voted_serial() {
+    let mut drive = FakeDrive::ata("Z1D2ABCD", "ST2000DM001-1CH164", "CC29");
+    drive.descriptor = Some(descriptor_block("1Z2DBADC")); // the driver did not swap the ATA words back
+    let fake = FakeTransport::new().with_drive(0, drive);
+
+    let drives = HardDriveSerial::with_transport(Arc::new(fake)).get_serial_numbers().unwrap();
+    assert_eq!(drives.len(), 1);
+    assert_eq!(drives[0].serial, "Z1D2ABCD");
+    assert_eq!(drives[0].consensus.agreement, Agreement::Unanimous);
+    assert_eq!(drives[0].outcomes[2].result.as_deref(), Ok("1Z2DBADC"));
+}
+
+#[test]
+fn a_scan_flags_a_method_that_disagrees() {
+    let mut drive = FakeDrive::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01");
+    drive.descriptor = Some(descriptor_block("WCC4E7654321"));
+    let fake = FakeTransport::new().with_drive(0, drive);
+
+    let drives = HardDriveSerial::with_transport(Arc::new(fake)).get_serial_numbers().unwrap();
+    let consensus = &drives[0].consensus;
+    assert_eq!(drives[0].serial, "WD-WCC4E1234567");
+    assert_eq!(drives[0].method, Method::AdminRights);
+    assert_eq!(consensus.agreement, Agreement::Majority);
+    assert_eq!(consensus.conflicts, vec![(Method::ZeroRights, "WCC4E7654321".to_string())]);
+}
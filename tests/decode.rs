@@ -0,0 +1,161 @@
+mod common;
+
+use common::*;
+use simplehddserial::decode::{decode, decode_structure, read_dump, DecodeError, InputFormat, Structure};
+use simplehddserial::identify::IdentifyIntegrityError;
+use simplehddserial::nvme::NIDT_NGUID;
+use simplehddserial::scsi::{VPD_DEVICE_IDENTIFICATION, VPD_UNIT_SERIAL_NUMBER};
+use simplehddserial::{BusType, DriveIdentifier, DriveIdentity, DriveInfo, HardDriveSerial, Method, ScanOptions};
+use std::fmt::Write;
+use std::sync::Arc;
+
+const SERIAL: &str = "WD-WCC4E1234567";
+
+fn identity_of(drive: &DriveInfo) -> DriveIdentity {
+    DriveIdentity {
+        serial: drive.serial.clone(),
+        model: drive.model.clone(),
+        firmware: drive.firmware.clone(),
+        bus_type: drive.bus_type,
+        identifiers: drive.identifiers.clone(),
+    }
+}
+
+// what a live scan of `drive` with only `method` reports
+fn live_identity(drive: FakeDrive, method: Method) -> DriveIdentity {
+    let options = ScanOptions::new().with_methods([method]).with_drive_indices([0]);
+    let drives = HardDriveSerial::with_transport(Arc::new(FakeTransport::new().with_drive(0, drive))).with_options(options).get_serial_numbers().unwrap();
+    identity_of(&drives[0])
+}
+
+// `bytes` laid out like the IDENTIFY DEVICE block of `smartctl -r ioctl,2`, with the
+// surrounding report lines and a second data block that is not part of the dump
+fn smartctl_report(bytes: &[u8]) -> String {
+    let mut text = String::from("REPORT-IOCTL: DeviceFD=3 Command=IDENTIFY DEVICE\n Input:   FR=0x00, SC=0x01, LL=0x00, LM=0x00, LH=0x00, DEV=0x40, CMD=0xec\n");
+    text += "\n===== [IDENTIFY DEVICE] DATA START (BASE-16) =====\n";
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let ascii: String = chunk.iter().map(|&byte| if (b' '..=b'~').contains(&byte) { byte as char } else { '.' }).collect();
+        writeln!(text, "{:03}-{:03}: {} |{}|", line * 16, line * 16 + 15, hex.join(" "), ascii).unwrap();
+    }
+    text += "===== [IDENTIFY DEVICE] DATA END (512 Bytes) =====\n\n";
+    text += "===== [SMART DATA] DATA START (BASE-16) =====\n000-015: 10 00 01 0f 00 64 64 00 00 00 00 00 00 00 00 00 |.....dd.........|\n";
+    text
+}
+
+#[test]
+fn every_layout_of_an_identify_block_decodes_alike() {
+    let block = load_hdparm("wd10ezex");
+    let hdparm = std::fs::read(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/wd10ezex.hdparm")).unwrap();
+    let plain_hex: String = block.chunks(32).map(|line| line.iter().map(|byte| format!("{:02x}", byte)).collect::<String>() + "\n").collect();
+
+    for (input, format) in [
+        (hdparm, InputFormat::Hdparm),
+        (block.to_vec(), InputFormat::Binary),
+        (smartctl_report(&block).into_bytes(), InputFormat::Smartctl),
+        (plain_hex.into_bytes(), InputFormat::PlainHex),
+    ] {
+        let dump = decode(&input).unwrap();
+        assert_eq!((dump.format, dump.bytes.as_slice()), (format, &block[..]));
+        let Structure::AtaIdentify(identify) = &dump.structure else {
+            panic!("{:?} decoded as {}", format, dump.structure);
+        };
+        assert_eq!(identify.serial, SERIAL);
+        assert_eq!(identify.capacity_bytes(), 1_000_204_886_016);
+    }
+}
+
+#[test]
+fn decoded_identity_matches_a_live_query() {
+    let block = load_hdparm("wd10ezex");
+    let drive = FakeDrive { ide_device_map: 1, identify: Some(block), ..FakeDrive::default() };
+    assert_eq!(decode(&block).unwrap().structure.identity(), live_identity(drive, Method::AdminRights));
+
+    let descriptor = load_hex("sandisk_cruzer_blade.storage-descriptor");
+    let decoded = decode_structure(&descriptor).unwrap();
+    assert!(matches!(decoded, Structure::StorageDeviceDescriptor(_)));
+    let drive = FakeDrive { descriptor: Some(descriptor), ..FakeDrive::default() };
+    assert_eq!(decoded.identity(), live_identity(drive, Method::ZeroRights));
+    assert_eq!(decoded.identity().bus_type, BusType::Usb);
+}
+
+#[test]
+fn descriptors_without_a_usable_serial_report_the_rest() {
+    // the zero rights method rejects this serial, so the decoded identity has none either
+    let identity = decode_structure(&load_hex("samsung_970_evo_plus.storage-descriptor")).unwrap().identity();
+    assert_eq!(identity.serial, "");
+    assert_eq!(identity.model.as_deref(), Some("NVMe Samsung SSD 970 EVO Plus 1TB"));
+    assert_eq!(identity.bus_type, BusType::Nvme);
+}
+
+#[test]
+fn nvme_controller_and_namespace_structures_are_told_apart() {
+    let controller = nvme_identify_controller("S4EWNX0R123456", "Samsung SSD 970 EVO Plus 1TB", "2B2QEXM7");
+    let Structure::NvmeIdentifyController(parsed) = decode_structure(&controller).unwrap() else {
+        panic!("not decoded as a controller");
+    };
+    assert_eq!(parsed.serial, "S4EWNX0R123456");
+
+    let eui64 = [0x00, 0x25, 0x38, 0x5B, 0x71, 0xB0, 0x9E, 0x83];
+    let namespace = decode_structure(&nvme_identify_namespace(eui64, [0; 16])).unwrap();
+    assert!(matches!(namespace, Structure::NvmeIdentifyNamespace(_)));
+    let identity = namespace.identity();
+    assert_eq!(identity.serial, "0025385b71b09e83"); // what a controller with a blank serial reports
+    assert_eq!((identity.bus_type, identity.identifiers), (BusType::Nvme, vec![DriveIdentifier::Eui64(eui64)]));
+}
+
+#[test]
+fn vpd_pages_are_recognized_by_their_page_code() {
+    let unit_serial = vpd_page(VPD_UNIT_SERIAL_NUMBER, b"  WD-WCC4E1234567");
+    assert_eq!(decode(&unit_serial).unwrap().structure, Structure::UnitSerialNumber(SERIAL.to_string()));
+
+    let naa = [0x50, 0x01, 0x4E, 0xE2, 0xB5, 0xA1, 0xC3, 0xD4];
+    let mut page = vpd_page(VPD_DEVICE_IDENTIFICATION, &designation_descriptor(0x01, 0x03, &naa));
+    page.resize(255, 0); // the unused part of the allocation length
+    let identity = decode_structure(&page).unwrap().identity();
+    assert_eq!((identity.serial.as_str(), identity.identifiers), ("", vec![DriveIdentifier::Naa(naa.to_vec())]));
+
+    let mut truncated = vpd_page(VPD_DEVICE_IDENTIFICATION, &designation_descriptor(0x01, 0x03, &naa));
+    truncated[7] = 0x40; // designator length past the page
+    assert!(matches!(decode_structure(&truncated), Err(DecodeError::InvalidVpdPage)));
+}
+
+#[test]
+fn corrupted_identify_data_is_refused_like_a_live_query() {
+    let mut block = load_hdparm("wd10ezex");
+    block[100] ^= 0x01;
+    assert!(matches!(decode(&block), Err(DecodeError::Identify(IdentifyIntegrityError::ChecksumMismatch { .. }))));
+    assert!(matches!(decode(&[0u8; 512]), Err(DecodeError::Identify(IdentifyIntegrityError::AllZero))));
+}
+
+#[test]
+fn unrecognized_dumps_say_why() {
+    assert!(matches!(decode(&[0x12u8; 100]), Err(DecodeError::UnrecognizedStructure { len: 100 })));
+    assert!(matches!(read_dump(b"Model Number: WDC WD10EZEX\n"), Err(DecodeError::UnrecognizedInput)));
+    assert!(matches!(read_dump(b"# only a comment\n\n"), Err(DecodeError::UnrecognizedInput)));
+
+    let Err(DecodeError::Malformed { line, reason }) = read_dump(b"# plain hex\n0a0b0c\n0d0e0\n") else {
+        panic!("odd hex accepted");
+    };
+    assert_eq!((line, reason.as_str()), (3, "odd number of hex digits"));
+
+    let report = smartctl_report(&load_hdparm("wd10ezex")).replace("016-031:", "032-047:");
+    let Err(error @ DecodeError::Malformed { line: 6, .. }) = read_dump(report.as_bytes()) else {
+        panic!("a missing smartctl line went unnoticed");
+    };
+    assert_eq!(error.to_string(), "malformed dump line 6: offset 32 where 16 was expected");
+}
+
+#[test]
+fn smartctl_reports_of_other_pages_are_read_too() {
+    let page = vpd_page(VPD_UNIT_SERIAL_NUMBER, SERIAL.as_bytes());
+    let text: String = page.chunks(16).enumerate().map(|(line, chunk)| format!("{:03}-{:03}: {}\n", line * 16, line * 16 + 15, chunk.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<_>>().join(" "))).collect();
+    let dump = decode(text.as_bytes()).unwrap();
+    assert_eq!(dump.format, InputFormat::Smartctl);
+    assert_eq!(dump.structure.identity().serial, SERIAL);
+    assert_eq!(format!("{} ({})", dump.structure, dump.format), "unit serial number vpd page (0x80) (smartctl -r ioctl hex)");
+
+    // a namespace descriptor list is not one of the decoded structures
+    let descriptors = nvme_namespace_descriptors(&[(NIDT_NGUID, &[0x11; 16])]);
+    assert!(matches!(decode_structure(&descriptors[..64]), Err(DecodeError::UnrecognizedStructure { len: 64 })));
+}
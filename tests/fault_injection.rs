@@ -0,0 +1,238 @@
+mod common;
+
+// drives HardDriveSerial through the ways real drivers misbehave and pins down what every
+// method reports for each, so changes to the fallback chain show up as a changed outcome
+
+use common::scripted::{Fault, Injection, ScriptedTransport};
+use common::{FakeDrive, FakeTransport, SENDCMDOUT_BUFFER_OFFSET};
+use simplehddserial::transport::*;
+use simplehddserial::{Agreement, DriveInfo, HardDriveSerial, Method, ScanOptions, SerialError};
+use std::sync::Arc;
+use std::time::Duration;
+
+const SERIAL: &str = "WD-WCC4E1234567";
+const OPERATION_TIMEOUT: Duration = Duration::from_millis(50);
+
+// what one scan reported
+struct Scan {
+    drives: Vec<DriveInfo>,
+    errors: Vec<SerialError>,
+}
+
+// scans the given drives with the admin, smart and zero rights methods
+fn scan(transport: &Arc<ScriptedTransport>, drives: impl IntoIterator<Item = usize>) -> Scan {
+    let options = ScanOptions::new()
+        .with_methods([Method::AdminRights, Method::Smart, Method::ZeroRights])
+        .with_drive_indices(drives)
+        .with_operation_timeout(OPERATION_TIMEOUT);
+    let mut hd_serial = HardDriveSerial::with_transport(transport.clone()).with_options(options);
+    let drives = hd_serial.get_serial_numbers().unwrap_or_default();
+    assert_eq!(transport.inner().open_handle_count(), 0, "a method leaked its handle");
+    Scan { drives, errors: hd_serial.errors().to_vec() }
+}
+
+fn scripted(drive: FakeDrive, injections: impl IntoIterator<Item = Injection>) -> Arc<ScriptedTransport> {
+    let transport = injections.into_iter().fold(ScriptedTransport::new(FakeTransport::new().with_drive(0, drive)), ScriptedTransport::with_injection);
+    Arc::new(transport)
+}
+
+// (method, serial or error) of every method tried on a drive
+fn outcomes(drive: &DriveInfo) -> Vec<(Method, Result<String, SerialError>)> {
+    drive.outcomes.iter().map(|outcome| (outcome.method, outcome.result.clone())).collect()
+}
+
+fn ok(method: Method) -> (Method, Result<String, SerialError>) {
+    (method, Ok(SERIAL.to_string()))
+}
+
+fn failed(method: Method, error: fn(Option<usize>, Method) -> SerialError) -> (Method, Result<String, SerialError>) {
+    (method, Err(error(Some(0), method)))
+}
+
+fn timeout(drive: Option<usize>, method: Method) -> SerialError {
+    SerialError::Timeout { drive, method }
+}
+
+fn not_supported(drive: Option<usize>, method: Method) -> SerialError {
+    SerialError::NotSupported { drive, method }
+}
+
+fn access_denied(drive: Option<usize>, method: Method) -> SerialError {
+    SerialError::AccessDenied { drive, method }
+}
+
+fn checksum_mismatch(drive: Option<usize>, method: Method) -> SerialError {
+    SerialError::ChecksumMismatch { drive, method }
+}
+
+fn malformed(drive: Option<usize>, method: Method) -> SerialError {
+    SerialError::InvalidResponse { drive, method, reason: "malformed response".to_string() }
+}
+
+#[test]
+fn a_healthy_drive_answers_every_method() {
+    let scan = scan(&scripted(FakeDrive::wd_blue(), []), [0]);
+    assert_eq!(outcomes(&scan.drives[0]), vec![ok(Method::AdminRights), ok(Method::Smart), ok(Method::ZeroRights)]);
+    assert_eq!(scan.drives[0].consensus.agreement, Agreement::Unanimous);
+    assert!(scan.errors.is_empty());
+}
+
+#[test]
+fn io_pending_that_never_signals_times_out_one_method() {
+    let transport = scripted(FakeDrive::wd_blue(), [Injection::on(0, Fault::PendingForever).with_rights(AccessRights::Admin)]);
+    let scan = scan(&transport, [0]);
+
+    assert_eq!(outcomes(&scan.drives[0]), vec![failed(Method::AdminRights, timeout), ok(Method::Smart), ok(Method::ZeroRights)]);
+    assert_eq!(scan.drives[0].method, Method::Smart);
+    assert_eq!(scan.errors, vec![timeout(Some(0), Method::AdminRights)]);
+    assert_eq!(transport.cancelled_count(), 1); // DFP_GET_VERSION, nothing after it was sent
+}
+
+#[test]
+fn a_drive_that_never_answers_times_out_every_method() {
+    let transport = scripted(FakeDrive::wd_blue(), [Injection::on(0, Fault::PendingForever)]);
+    let scan = scan(&transport, [0]);
+
+    assert!(scan.drives.is_empty());
+    assert_eq!(
+        scan.errors,
+        vec![timeout(Some(0), Method::AdminRights), timeout(Some(0), Method::Smart), timeout(Some(0), Method::ZeroRights)]
+    );
+    assert_eq!(transport.cancelled_count(), 3); // zero rights does not try the geometry queries after a hang
+}
+
+#[test]
+fn short_bytes_returned_is_refused() {
+    let short = Fault::ShortRead(SENDCMDOUT_BUFFER_OFFSET + 256); // half of the IDENTIFY block
+    let transport = scripted(
+        FakeDrive::wd_blue(),
+        [
+            Injection::on(0, short).with_rights(AccessRights::Admin).with_control_code(DFP_RECEIVE_DRIVE_DATA),
+            Injection::on(0, short).with_rights(AccessRights::Smart).with_control_code(SMART_RCV_DRIVE_DATA),
+        ],
+    );
+    let scan = scan(&transport, [0]);
+
+    assert_eq!(outcomes(&scan.drives[0]), vec![failed(Method::AdminRights, malformed), failed(Method::Smart, malformed), ok(Method::ZeroRights)]);
+    assert_eq!(scan.errors, vec![malformed(Some(0), Method::AdminRights)]); // the smart method only logs hangs and checksum failures
+}
+
+#[test]
+fn unsupported_ioctls_fall_through_to_zero_rights_quietly() {
+    let transport = scripted(
+        FakeDrive::wd_blue(),
+        [
+            Injection::on(0, Fault::Fails(ERROR_NOT_SUPPORTED)).with_rights(AccessRights::Admin),
+            Injection::on(0, Fault::Fails(ERROR_NOT_SUPPORTED)).with_rights(AccessRights::Smart),
+        ],
+    );
+    let scan = scan(&transport, [0]);
+
+    assert_eq!(outcomes(&scan.drives[0]), vec![failed(Method::AdminRights, not_supported), failed(Method::Smart, not_supported), ok(Method::ZeroRights)]);
+    assert_eq!(scan.drives[0].method, Method::ZeroRights);
+    assert!(scan.errors.is_empty());
+}
+
+#[test]
+fn access_denied_on_one_method_only() {
+    let transport = scripted(FakeDrive::wd_blue(), [Injection::on(0, Fault::OpenFails(ERROR_ACCESS_DENIED)).with_rights(AccessRights::Admin)]);
+    let scan = scan(&transport, [0]);
+
+    assert_eq!(outcomes(&scan.drives[0]), vec![failed(Method::AdminRights, access_denied), ok(Method::Smart), ok(Method::ZeroRights)]);
+    assert_eq!(scan.drives[0].method, Method::Smart);
+    assert_eq!(scan.errors, vec![access_denied(Some(0), Method::AdminRights)]);
+}
+
+#[test]
+fn partially_filled_identify_fails_its_checksum() {
+    let partial = Fault::PartialFill(SENDCMDOUT_BUFFER_OFFSET + 511); // the checksum byte never arrived
+    let transport = scripted(FakeDrive::wd_blue(), [Injection::on(0, partial).with_control_code(DFP_RECEIVE_DRIVE_DATA)]);
+    let scan = scan(&transport, [0]);
+
+    assert_eq!(
+        outcomes(&scan.drives[0]),
+        vec![failed(Method::AdminRights, checksum_mismatch), failed(Method::Smart, checksum_mismatch), ok(Method::ZeroRights)]
+    );
+    assert_eq!(scan.errors, vec![checksum_mismatch(Some(0), Method::AdminRights), checksum_mismatch(Some(0), Method::Smart)]);
+}
+
+#[test]
+fn partially_filled_identify_without_checksum_is_outvoted() {
+    // the transfer stopped inside the (right aligned) serial field, the word 255 signature never arrived either
+    // so the block looks like one from an older drive without a checksum
+    let partial = Fault::PartialFill(SENDCMDOUT_BUFFER_OFFSET + 30);
+    let transport = scripted(FakeDrive::wd_blue(), [Injection::on(0, partial).with_rights(AccessRights::Admin).with_control_code(DFP_RECEIVE_DRIVE_DATA)]);
+    let scan = scan(&transport, [0]);
+
+    let drive = &scan.drives[0];
+    assert_eq!(outcomes(drive), vec![(Method::AdminRights, Ok("WD-WC".to_string())), ok(Method::Smart), ok(Method::ZeroRights)]);
+    assert_eq!(drive.serial, SERIAL);
+    assert_eq!(drive.consensus.agreement, Agreement::Majority);
+    assert_eq!(drive.consensus.conflicts, vec![(Method::AdminRights, "WD-WC".to_string())]);
+}
+
+#[test]
+fn descriptor_with_bad_offsets_is_not_trusted() {
+    let mut drive = FakeDrive::wd_blue();
+    let descriptor = drive.descriptor.as_mut().unwrap();
+    descriptor[24..28].copy_from_slice(&4096u32.to_le_bytes()); // SerialNumberOffset past the end
+    let scan = scan(&scripted(drive, []), [0]);
+
+    assert_eq!(outcomes(&scan.drives[0]), vec![ok(Method::AdminRights), ok(Method::Smart), failed(Method::ZeroRights, not_supported)]);
+    assert!(scan.errors.is_empty());
+}
+
+#[test]
+fn a_transient_failure_only_affects_one_scan() {
+    let transport = scripted(
+        FakeDrive::wd_blue(),
+        [Injection::on(0, Fault::Fails(ERROR_GEN_FAILURE)).with_rights(AccessRights::Admin).with_control_code(DFP_RECEIVE_DRIVE_DATA).times(1)],
+    );
+
+    let first = scan(&transport, [0]);
+    let gen_failure = SerialError::Os { drive: Some(0), method: Method::AdminRights, code: ERROR_GEN_FAILURE };
+    assert_eq!(outcomes(&first.drives[0])[0], (Method::AdminRights, Err(gen_failure.clone())));
+    assert_eq!(first.drives[0].method, Method::Smart);
+    assert_eq!(first.errors, vec![gen_failure]);
+
+    let second = scan(&transport, [0]);
+    assert_eq!(outcomes(&second.drives[0]), vec![ok(Method::AdminRights), ok(Method::Smart), ok(Method::ZeroRights)]);
+    assert!(second.errors.is_empty());
+}
+
+#[test]
+fn faults_stay_with_their_drive() {
+    let fake = FakeTransport::new().with_drive(0, FakeDrive::wd_blue()).with_drive(1, FakeDrive::wd_blue()).with_drive(2, FakeDrive::wd_blue());
+    let transport = Arc::new(
+        ScriptedTransport::new(fake)
+            .with_injection(Injection::on(1, Fault::OpenFails(ERROR_ACCESS_DENIED)))
+            .with_injection(Injection::on(2, Fault::PendingForever)),
+    );
+    let scan = scan(&transport, [0, 1, 2]);
+
+    // drive 1 could not be opened at all, every method reports why
+    let found: Vec<usize> = scan.drives.iter().map(|drive| drive.index).collect();
+    assert_eq!(found, vec![0]);
+    assert_eq!(outcomes(&scan.drives[0]), vec![ok(Method::AdminRights), ok(Method::Smart), ok(Method::ZeroRights)]);
+    assert_eq!(
+        scan.errors,
+        vec![
+            access_denied(Some(1), Method::AdminRights),
+            access_denied(Some(1), Method::Smart),
+            access_denied(Some(1), Method::ZeroRights),
+            timeout(Some(2), Method::AdminRights),
+            timeout(Some(2), Method::Smart),
+            timeout(Some(2), Method::ZeroRights),
+        ]
+    );
+}
+
+#[test]
+fn a_drive_that_cannot_be_opened_reports_why() {
+    let transport = scripted(FakeDrive::wd_blue(), [Injection::on(0, Fault::OpenFails(ERROR_ACCESS_DENIED))]);
+    let options = ScanOptions::new().with_methods([Method::AdminRights, Method::Smart, Method::ZeroRights]).with_drive_indices([0]);
+    let mut hd_serial = HardDriveSerial::with_transport(transport).with_options(options);
+
+    let denied = vec![access_denied(Some(0), Method::AdminRights), access_denied(Some(0), Method::Smart), access_denied(Some(0), Method::ZeroRights)];
+    assert_eq!(hd_serial.get_serial_numbers().unwrap_err(), denied);
+}
@@ -0,0 +1,152 @@
+mod common;
+
+use common::{add_block_device, identify_block, load_hdparm, seal_identify_block, FakeDrive, FakeSatDevice, FakeScsiTransport, FakeTransport};
+use simplehddserial::identify::*;
+use simplehddserial::{HardDriveSerial, Method, SerialError};
+use std::sync::Arc;
+
+#[test]
+fn golden_wd10ezex() {
+    let identify = IdentifyDevice::parse(&load_hdparm("wd10ezex"));
+
+    assert_eq!(identify.serial, "WD-WCC4E1234567");
+    assert_eq!(identify.firmware, "01.01A01");
+    assert_eq!(identify.model, "WDC WD10EZEX-08WN4A0");
+    assert_eq!(identify.lba28_sectors, 0x0FFF_FFFF);
+    assert_eq!(identify.lba48_sectors, Some(1_953_525_168));
+    assert_eq!(identify.capacity_bytes(), 1_000_204_886_016);
+    assert_eq!(identify.logical_sector_size, 512);
+    assert_eq!(identify.physical_sector_size, 4096);
+    assert_eq!(identify.wwn, Some(0x5001_4EE2_B5A1_C3D4));
+    assert_eq!(identify.rotation_rate, RotationRate::Rpm(7200));
+    assert_eq!(identify.form_factor, FormFactor::Inch3_5);
+    assert!(!identify.trim_supported);
+    assert_eq!(identify.ata_major_version, Some(AtaMajorVersion::Acs2));
+
+    let sata = identify.sata.unwrap();
+    assert_eq!(sata.max_speed, Some(SataSpeed::Gen3));
+    assert_eq!(sata.current_speed, Some(SataSpeed::Gen3));
+    assert!(sata.ncq_supported);
+}
+
+#[test]
+fn golden_samsung_860_evo() {
+    let identify = IdentifyDevice::parse(&load_hdparm("samsung_860_evo"));
+
+    assert_eq!(identify.serial, "S3Z9NB0K123456X");
+    assert_eq!(identify.firmware, "RVT02B6Q");
+    assert_eq!(identify.model, "Samsung SSD 860 EVO 500GB");
+    assert_eq!(identify.sectors(), 976_773_168);
+    assert_eq!(identify.capacity_bytes(), 500_107_862_016);
+    assert_eq!(identify.logical_sector_size, 512);
+    assert_eq!(identify.physical_sector_size, 512);
+    assert_eq!(identify.wwn, Some(0x5002_538E_40A1_B2C3));
+    assert_eq!(identify.rotation_rate, RotationRate::NonRotating);
+    assert_eq!(identify.form_factor, FormFactor::Inch2_5);
+    assert!(identify.trim_supported);
+    assert_eq!(identify.ata_major_version, Some(AtaMajorVersion::Acs4));
+    assert_eq!(identify.sata.unwrap().max_speed, Some(SataSpeed::Gen3));
+}
+
+#[test]
+fn golden_maxtor_pata() {
+    let identify = IdentifyDevice::parse(&load_hdparm("maxtor_6y040l0"));
+
+    assert_eq!(identify.serial, "L2A7HJ8G");
+    assert_eq!(identify.model, "MAXTOR 6Y040L0");
+    assert_eq!(identify.firmware, "A93.0500");
+    assert_eq!(identify.lba28_sectors, 80_293_248);
+    assert_eq!(identify.lba48_sectors, None);
+    assert_eq!(identify.capacity_bytes(), 41_110_142_976);
+    assert_eq!(identify.wwn, None);
+    assert_eq!(identify.rotation_rate, RotationRate::NotReported);
+    assert_eq!(identify.form_factor, FormFactor::NotReported);
+    assert_eq!(identify.sata, None);
+    assert_eq!(identify.ata_major_version, Some(AtaMajorVersion::AtaAtapi6));
+    assert_eq!(identify.ata_major_version.unwrap().to_string(), "ATA/ATAPI-6");
+}
+
+#[test]
+fn long_logical_sectors() {
+    let mut block = identify_block("SN1", "MODEL", "FW");
+    // word 106: valid, multiple logical per physical (2^1), logical sector longer than 256 words
+    block[212..214].copy_from_slice(&0x7001u16.to_le_bytes());
+    // words 117-118: 2048 words = 4096 bytes
+    block[234..236].copy_from_slice(&2048u16.to_le_bytes());
+    seal_identify_block(&mut block);
+
+    let identify = IdentifyDevice::parse(&block);
+    assert_eq!(identify.logical_sector_size, 4096);
+    assert_eq!(identify.physical_sector_size, 8192);
+}
+
+#[test]
+fn implausible_sector_sizes_are_not_reported() {
+    let mut block = [0u8; 512];
+    block[212..214].copy_from_slice(&0x5000u16.to_le_bytes()); // word 106: valid, logical sector longer than 256 words
+    block[234..238].fill(0xFF); // words 117-118: a size that does not fit in 32 bits once doubled
+    let identify = IdentifyDevice::parse(&block);
+    assert_eq!(identify.logical_sector_size, 512);
+    assert_eq!(identify.physical_sector_size, 512);
+
+    // word 83 bit 10: 48-bit addressing, words 100-103: every sector there is
+    block[166..168].copy_from_slice(&0x4400u16.to_le_bytes());
+    block[200..208].fill(0xFF);
+    block[234..238].copy_from_slice(&[0x00, 0x08, 0x00, 0x00]); // 2048 words
+    let identify = IdentifyDevice::parse(&block);
+    assert_eq!(identify.logical_sector_size, 4096);
+    assert_eq!(identify.capacity_bytes(), u64::MAX);
+}
+
+#[test]
+fn model_keeps_internal_spaces() {
+    let identify = IdentifyDevice::parse(&identify_block("SN1", "ST1000DM010-2EP102", "CC43"));
+    assert_eq!(identify.model, "ST1000DM010-2EP102");
+    let identify = IdentifyDevice::parse(&identify_block("SN1", "TOSHIBA MQ01ABD100", "AX001U"));
+    assert_eq!(identify.model, "TOSHIBA MQ01ABD100");
+}
+
+#[test]
+fn golden_dumps_pass_integrity_checks() {
+    for name in ["wd10ezex", "samsung_860_evo", "maxtor_6y040l0"] {
+        assert_eq!(verify_identify_block(&load_hdparm(name)), Ok(()), "{}", name);
+    }
+}
+
+#[test]
+fn flipped_byte_is_a_checksum_mismatch() {
+    let mut block = load_hdparm("wd10ezex");
+    block[20] ^= 0x01; // one bit of the serial number
+    assert!(matches!(verify_identify_block(&block), Err(IdentifyIntegrityError::ChecksumMismatch { .. })));
+    assert!(IdentifyDevice::parse_checked(&block).is_err());
+}
+
+#[test]
+fn blank_buffers_are_rejected() {
+    assert_eq!(verify_identify_block(&[0x00; 512]), Err(IdentifyIntegrityError::AllZero));
+    assert_eq!(verify_identify_block(&[0xFF; 512]), Err(IdentifyIntegrityError::AllOnes));
+}
+
+#[test]
+fn blank_blocks_are_not_returned_as_identify_data() {
+    // the drive is found through its descriptor, but answers IDENTIFY with nothing
+    let drive = FakeDrive { identify: Some([0x00; 512]), ..FakeDrive::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01") };
+    let mut hd_serial = HardDriveSerial::with_transport(Arc::new(FakeTransport::new().with_drive(0, drive)));
+    let drives = hd_serial.get_serial_numbers().unwrap();
+    assert!(matches!(hd_serial.identify_data(&drives[0]), Err(SerialError::InvalidResponse { method: Method::Smart, .. })));
+
+    let root = tempfile::tempdir().unwrap();
+    add_block_device(root.path(), "sda", &[("serial", b"WD-WCC4E1234567\n")]);
+    let device = FakeSatDevice { identify: Some([0xFF; 512]), ..FakeSatDevice::default() }; // an empty bridge response
+    let mut hd_serial = HardDriveSerial::with_sysfs_root(root.path()).with_scsi_transport(Arc::new(FakeScsiTransport::new().with_device("/dev/sda", device)));
+    let drives = hd_serial.get_serial_numbers().unwrap();
+    assert!(matches!(hd_serial.identify(&drives[0]), Err(SerialError::InvalidResponse { method: Method::AtaPassThrough, .. })));
+}
+
+#[test]
+fn blocks_without_signature_are_not_checksummed() {
+    // the maxtor predates the word 255 signature, any edit still passes
+    let mut block = load_hdparm("maxtor_6y040l0");
+    block[20] ^= 0x01;
+    assert_eq!(verify_identify_block(&block), Ok(()));
+}
@@ -0,0 +1,63 @@
+mod common;
+
+// the ioctl structs are private, so their layouts are checked through the buffers that reach the
+// transport: a field added or resized in structs.rs changes these sizes or moves the data the fake
+// answers with (SENDCMDOUT_BUFFER_OFFSET), and the const asserts next to the structs stop the build first
+
+use common::{smart_data_block, FakeDrive, FakeTransport, IoctlCall, SENDCMDOUT_BUFFER_OFFSET};
+use simplehddserial::transport::*;
+use simplehddserial::{HardDriveSerial, Method, ScanOptions};
+use std::sync::Arc;
+
+const SENDCMDINPARAMS_SIZE: usize = 33;                     // 32 + bBuffer[1]
+const SENDCMDOUTPARAMS_SIZE: usize = SENDCMDOUT_BUFFER_OFFSET + 512;
+const GETVERSIONINPARAMS_SIZE: usize = 24;
+const STORAGE_PROPERTY_QUERY_SIZE: usize = 12;
+const DISK_GEOMETRY_SIZE: usize = 24;
+const DISK_GEOMETRY_EX_SIZE: usize = 40;
+
+fn calls(fake: &FakeTransport, control_code: u32) -> Vec<IoctlCall> {
+    fake.ioctls().into_iter().filter(|call| call.control_code == control_code).collect()
+}
+
+#[test]
+fn sendcmd_buffers_match_the_sdk() {
+    let mut drive = FakeDrive::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01");
+    drive.smart_data = Some(smart_data_block(&[(5, 0x0033, 100, 100, 0)]));
+    drive.smart_return_status = Some((0x4F, 0xC2));
+    let fake = Arc::new(FakeTransport::new().with_drive(0, drive));
+    let mut hd_serial = HardDriveSerial::with_transport(fake.clone())
+        .with_options(ScanOptions::new().with_methods([Method::AdminRights, Method::Smart]).with_drive_indices([0]));
+    let drives = hd_serial.get_serial_numbers().unwrap();
+    assert_eq!(drives[0].serial, "WD-WCC4E1234567"); // IDENTIFY data read from the right offset
+    hd_serial.smart_attributes(&drives[0]).unwrap();
+    hd_serial.health(&drives[0]).unwrap();
+
+    // DFP_GET_VERSION and SMART_GET_VERSION share a control code
+    for call in calls(&fake, SMART_GET_VERSION) {
+        assert_eq!((call.input_len, call.output_len), (0, GETVERSIONINPARAMS_SIZE));
+    }
+    // as do DFP_RECEIVE_DRIVE_DATA and SMART_RCV_DRIVE_DATA, IDENTIFY leaves bBuffer[1] off the input
+    let receive = calls(&fake, SMART_RCV_DRIVE_DATA);
+    assert!(receive.len() >= 3);
+    for call in receive {
+        assert!(call.input_len == SENDCMDINPARAMS_SIZE || call.input_len == SENDCMDINPARAMS_SIZE - 1, "{:?}", call);
+        assert_eq!(call.output_len, SENDCMDOUTPARAMS_SIZE);
+    }
+    let send = calls(&fake, SMART_SEND_DRIVE_COMMAND);
+    assert_eq!(send, vec![IoctlCall { control_code: SMART_SEND_DRIVE_COMMAND, input_len: SENDCMDINPARAMS_SIZE, output_len: SENDCMDOUTPARAMS_SIZE }]);
+}
+
+#[test]
+fn zero_rights_buffers_match_the_sdk() {
+    let mut drive = FakeDrive::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01");
+    drive.descriptor = Some(vec![0; 8]); // too short to parse, so the geometry queries run as well
+    let fake = Arc::new(FakeTransport::new().with_drive(0, drive));
+    let _ = HardDriveSerial::with_transport(fake.clone())
+        .with_options(ScanOptions::new().with_methods([Method::ZeroRights]).with_drive_indices([0]))
+        .get_serial_numbers();
+
+    assert_eq!(calls(&fake, IOCTL_STORAGE_QUERY_PROPERTY)[0].input_len, STORAGE_PROPERTY_QUERY_SIZE);
+    assert_eq!(calls(&fake, IOCTL_DISK_GET_DRIVE_GEOMETRY_EX)[0].output_len, DISK_GEOMETRY_EX_SIZE);
+    assert_eq!(calls(&fake, IOCTL_DISK_GET_DRIVE_GEOMETRY)[0].output_len, DISK_GEOMETRY_SIZE);
+}
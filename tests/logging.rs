@@ -0,0 +1,78 @@
+mod common;
+
+use common::{FakeDrive, FakeTransport};
+use simplehddserial::{HardDriveSerial, ScanOptions};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+// remembers every span as "name field=value ..."
+#[derive(Default)]
+struct SpanRecorder {
+    spans: Mutex<Vec<String>>,
+    events: AtomicU64,
+    next_id: AtomicU64,
+}
+
+struct FieldWriter<'a>(&'a mut String);
+
+impl Visit for FieldWriter<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.push_str(&format!(" {}={:?}", field.name(), value));
+    }
+}
+
+impl Subscriber for SpanRecorder {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut line = span.metadata().name().to_string();
+        span.record(&mut FieldWriter(&mut line));
+        self.spans.lock().unwrap().push(line);
+        Id::from_u64(self.next_id.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {
+        self.events.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+// a single test per binary, as the scan threads only see the global subscriber
+#[test]
+fn diagnostics_go_through_tracing_with_drive_and_method_spans() {
+    let recorder = Arc::new(SpanRecorder::default());
+    tracing::subscriber::set_global_default(recorder.clone()).unwrap();
+
+    let mut drive = FakeDrive::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01");
+    drive.ide_device_map = 0; // admin method reports not supported
+    let fake = FakeTransport::new().with_drive(2, drive);
+    let options = ScanOptions::new().with_drive_indices([2]);
+    let mut hd_serial = HardDriveSerial::with_transport(Arc::new(fake)).with_options(options);
+    hd_serial.get_serial_numbers().unwrap();
+
+    let spans = recorder.spans.lock().unwrap().clone();
+    assert_eq!(spans, vec![
+        "drive index=2 path=\\\\.\\PhysicalDrive2",
+        "method method=admin rights",
+        "method method=SMART",
+        "method method=zero rights",
+    ]);
+    assert!(recorder.events.load(Ordering::SeqCst) >= 3);
+
+    // the timings that used to be printed are still there
+    let timings: Vec<_> = hd_serial.timings().iter().map(|(drive, timing_type, _)| (*drive, timing_type.as_str())).collect();
+    assert_eq!(timings, vec![(2, "hd2Trd#A"), (2, "hd2Trd#SM"), (2, "hd2Trd#Z")]);
+}
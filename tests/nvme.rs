@@ -0,0 +1,249 @@
+mod common;
+
+use common::{add_block_device, load_hex, nvme_identify_controller, nvme_identify_namespace, nvme_namespace_descriptors, FakeNamespace, FakeNvmeTransport};
+use simplehddserial::nvme::*;
+use simplehddserial::smart::HealthVerdict;
+use simplehddserial::transport::ERROR_INVALID_DATA;
+use simplehddserial::{Agreement, BusType, DriveIdentifier, HardDriveSerial, Method, ScanOptions, SerialError};
+use std::sync::Arc;
+
+// synthesized Identify Controller page modelled on a Samsung 970 EVO Plus (not a capture of a real drive)
+fn samsung_970_evo_plus() -> Vec<u8> {
+    let mut data = nvme_identify_controller("S4EWNX0R123456A", "Samsung SSD 970 EVO Plus 1TB", "2B2QEXM7");
+    data[0..2].copy_from_slice(&0x144Du16.to_le_bytes()); // VID
+    data[2..4].copy_from_slice(&0x144Du16.to_le_bytes()); // SSVID
+    data[73..76].copy_from_slice(&[0x38, 0x25, 0x00]); // IEEE OUI 00-25-38, least significant byte first
+    data[78..80].copy_from_slice(&4u16.to_le_bytes()); // CNTLID
+    data[80..84].copy_from_slice(&0x0001_0300u32.to_le_bytes()); // VER 1.3.0
+    data[280..296].copy_from_slice(&1_000_204_886_016u128.to_le_bytes()); // TNVMCAP
+    data[516..520].copy_from_slice(&1u32.to_le_bytes()); // NN
+    let nqn = b"nqn.2014.08.org.nvmexpress:144d144dS4EWNX0R123456A";
+    data[768..768 + nqn.len()].copy_from_slice(nqn);
+    data
+}
+
+const EUI64: [u8; 8] = [0x00, 0x25, 0x38, 0x5B, 0x71, 0xB0, 0x9E, 0x83];
+const NGUID: [u8; 16] = [0x00, 0x25, 0x38, 0x5B, 0x71, 0xB0, 0x9E, 0x83, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01];
+
+fn parse(data: &[u8]) -> NvmeIdentifyController {
+    NvmeIdentifyController::parse(data.try_into().unwrap())
+}
+
+#[test]
+fn identify_controller_command_encoding() {
+    let command = identify_command(CNS_CONTROLLER, 0, 0);
+    assert_eq!(command.opcode, 0x06);
+    assert_eq!((command.nsid, command.cdw10), (0, 0x01));
+    assert_eq!(identify_command(CNS_NAMESPACE, 1, 0x0004).cdw10, 0x0004_0000);
+}
+
+#[test]
+fn parses_identify_controller() {
+    let controller = parse(&samsung_970_evo_plus());
+    assert_eq!((controller.vendor_id, controller.subsystem_vendor_id), (0x144D, 0x144D));
+    assert_eq!(controller.serial, "S4EWNX0R123456A");
+    assert_eq!(controller.model, "Samsung SSD 970 EVO Plus 1TB");
+    assert_eq!(controller.firmware, "2B2QEXM7");
+    assert_eq!(controller.ieee_oui_string(), "00-25-38");
+    assert_eq!(controller.controller_id, 4);
+    assert_eq!(controller.version_triple(), (1, 3, 0));
+    assert_eq!(controller.namespace_count, 1);
+    assert_eq!(controller.total_capacity, 1_000_204_886_016);
+    assert_eq!(controller.unallocated_capacity, 0);
+    assert_eq!(controller.subsystem_nqn, "nqn.2014.08.org.nvmexpress:144d144dS4EWNX0R123456A");
+}
+
+#[test]
+fn strings_are_not_byte_swapped_and_keep_inner_spaces() {
+    let controller = parse(&nvme_identify_controller("  AB CD\0\0", "Model", ""));
+    assert_eq!(controller.serial, "AB CD");
+    assert_eq!(controller.firmware, "");
+}
+
+#[test]
+fn controller_path_strips_the_namespace() {
+    assert_eq!(controller_path("/dev/nvme0n1"), "/dev/nvme0");
+    assert_eq!(controller_path("/dev/nvme12n3"), "/dev/nvme12");
+    assert_eq!(controller_path("/dev/nvme0"), "/dev/nvme0");
+    assert_eq!(controller_path("/dev/nvme0n1p2"), "/dev/nvme0n1p2");
+    assert_eq!(controller_path("/dev/sda"), "/dev/sda");
+}
+
+#[test]
+fn nvme_namespaces_get_their_serial_from_the_controller() {
+    let root = tempfile::tempdir().unwrap();
+    add_block_device(root.path(), "nvme0n1", &[("serial", b"S4EWNX0R123456A     \n")]);
+    add_block_device(root.path(), "sda", &[("serial", b"WD-WCC4E1234567\n")]);
+    let nvme = Arc::new(
+        FakeNvmeTransport::new()
+            .with_controller("/dev/nvme0", samsung_970_evo_plus())
+            .with_namespace("/dev/nvme0", 1, FakeNamespace { identify: nvme_identify_namespace(EUI64, [0; 16]), descriptors: None }),
+    );
+
+    let mut hd_serial = HardDriveSerial::with_sysfs_root(root.path()).with_nvme_transport(nvme.clone());
+    let drives = hd_serial.get_serial_numbers().unwrap();
+    let nvme_drive = drives.iter().find(|drive| drive.device_path == "/dev/nvme0n1").unwrap();
+    assert_eq!(nvme_drive.serial, "S4EWNX0R123456A");
+    assert_eq!(nvme_drive.consensus.agreement, Agreement::Unanimous);
+    assert_eq!(nvme_drive.consensus.agreeing, vec![Method::Sysfs, Method::NvmeIdentify]);
+    assert_eq!(nvme_drive.model.as_deref(), Some("Samsung SSD 970 EVO Plus 1TB"));
+    assert_eq!(nvme_drive.bus_type, BusType::Nvme);
+    assert_eq!(nvme_drive.identifiers, vec![DriveIdentifier::Eui64(EUI64)]);
+
+    // the sata drive is not sent any admin command
+    let sata_drive = drives.iter().find(|drive| drive.device_path == "/dev/sda").unwrap();
+    assert!(sata_drive.outcomes.iter().all(|outcome| outcome.method == Method::Sysfs));
+    let cns: Vec<u32> = nvme.commands().iter().map(|command| command.cdw10).collect();
+    assert_eq!(cns, vec![0x01, 0x03, 0x00]); // controller, descriptor list (rejected), namespace
+    assert_eq!(nvme.open_handle_count(), 0);
+}
+
+#[test]
+fn nvme_identify_runs_on_the_ioctl_backend_too() {
+    let nvme = FakeNvmeTransport::new().with_controller("\\\\.\\PhysicalDrive0", samsung_970_evo_plus());
+    let options = ScanOptions::new().with_methods([Method::NvmeIdentify]).with_drive_indices([0]);
+    let drives = HardDriveSerial::with_transport(Arc::new(common::FakeTransport::new()))
+        .with_nvme_transport(Arc::new(nvme))
+        .with_options(options)
+        .get_serial_numbers()
+        .unwrap();
+    assert_eq!(drives[0].serial, "S4EWNX0R123456A");
+    assert_eq!(drives[0].method, Method::NvmeIdentify);
+}
+
+#[test]
+fn parses_identify_namespace() {
+    let namespace = NvmeIdentifyNamespace::parse(nvme_identify_namespace(EUI64, NGUID).as_slice().try_into().unwrap());
+    assert_eq!(namespace.size, 1_953_525_168);
+    assert_eq!(namespace.eui64, EUI64);
+    assert_eq!(namespace.identifiers(), vec![DriveIdentifier::Eui64(EUI64), DriveIdentifier::Nguid(NGUID)]);
+    assert!(NvmeIdentifyNamespace::parse(&[0u8; 4096]).identifiers().is_empty());
+}
+
+#[test]
+fn parses_namespace_descriptor_list() {
+    let uuid = [0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC, 0xDE, 0xF0, 0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF];
+    let data = nvme_namespace_descriptors(&[(NIDT_NGUID, &NGUID), (NIDT_UUID, &uuid), (NIDT_CSI, &[0x00]), (0x07, &[1, 2])]);
+    assert_eq!(
+        parse_namespace_descriptors(&data).unwrap(),
+        vec![
+            NamespaceDescriptor::Identifier(DriveIdentifier::Nguid(NGUID)),
+            NamespaceDescriptor::Identifier(DriveIdentifier::Uuid(uuid)),
+            NamespaceDescriptor::CommandSet(0x00),
+            NamespaceDescriptor::Unknown { kind: 0x07, value: vec![1, 2] },
+        ]
+    );
+    assert_eq!(DriveIdentifier::Uuid(uuid).to_string(), "uuid.12345678-9abc-def0-0123-456789abcdef");
+    assert_eq!(DriveIdentifier::Eui64(EUI64).to_string(), "eui.0025385b71b09e83");
+}
+
+#[test]
+fn malformed_descriptor_lists_are_rejected_without_panicking() {
+    let wrong_length = nvme_namespace_descriptors(&[(NIDT_EUI64, &[1, 2, 3, 4])]);
+    assert_eq!(parse_namespace_descriptors(&wrong_length), Err(ERROR_INVALID_DATA));
+    let overrun = [NIDT_NGUID, 16, 0, 0, 0x00, 0x25, 0x38, 0x5B]; // claims 16 bytes, has 4
+    assert_eq!(parse_namespace_descriptors(&overrun), Err(ERROR_INVALID_DATA));
+    assert_eq!(parse_namespace_descriptors(&[]), Ok(vec![]));
+}
+
+#[test]
+fn controllers_with_a_blank_serial_are_identified_by_their_namespace() {
+    let nvme = FakeNvmeTransport::new()
+        .with_controller("/dev/nvme1", nvme_identify_controller("", "QEMU NVMe Ctrl", "1.0"))
+        .with_namespace(
+            "/dev/nvme1",
+            1,
+            FakeNamespace { identify: nvme_identify_namespace([0; 8], [0; 16]), descriptors: Some(nvme_namespace_descriptors(&[(NIDT_NGUID, &NGUID)])) },
+        );
+    let options = ScanOptions::new().with_methods([Method::NvmeIdentify]).with_drive_paths(["/dev/nvme1n1"]);
+    let drives = HardDriveSerial::with_transport(Arc::new(common::FakeTransport::new()))
+        .with_nvme_transport(Arc::new(nvme))
+        .with_options(options)
+        .get_serial_numbers()
+        .unwrap();
+    assert_eq!(drives[0].serial, "0025385b71b09e830000000000000001");
+    assert_eq!(drives[0].identifiers, vec![DriveIdentifier::Nguid(NGUID)]);
+}
+
+#[test]
+fn get_log_page_command_encoding() {
+    let command = get_log_page_command(LOG_SMART_HEALTH, NVME_NSID_ALL, LOG_SMART_HEALTH_SIZE);
+    assert_eq!(command.opcode, 0x02);
+    assert_eq!(command.nsid, 0xFFFF_FFFF);
+    assert_eq!((command.cdw10, command.cdw11), (0x007F_0002, 0)); // 128 dwords, zero based
+}
+
+#[test]
+fn nvme_health_comes_from_the_critical_warning_byte() {
+    let root = tempfile::tempdir().unwrap();
+    add_block_device(root.path(), "nvme0n1", &[("serial", b"S4EWNX0R123456A\n")]);
+    add_block_device(root.path(), "nvme1n1", &[("serial", b"S4EWNX0R654321B\n")]);
+    let mut worn_out = vec![0u8; 512];
+    worn_out[0] = CRITICAL_WARNING_SPARE | CRITICAL_WARNING_READ_ONLY;
+    let nvme = FakeNvmeTransport::new()
+        .with_controller("/dev/nvme0", samsung_970_evo_plus())
+        .with_log_page("/dev/nvme0", LOG_SMART_HEALTH, vec![0u8; 512])
+        .with_controller("/dev/nvme1", nvme_identify_controller("S4EWNX0R654321B", "Samsung SSD 970 EVO Plus 1TB", "2B2QEXM7"))
+        .with_log_page("/dev/nvme1", LOG_SMART_HEALTH, worn_out);
+    let mut hd_serial = HardDriveSerial::with_sysfs_root(root.path()).with_nvme_transport(Arc::new(nvme));
+    let drives = hd_serial.get_serial_numbers().unwrap();
+
+    let healthy = hd_serial.health(&drives[0]).unwrap();
+    assert_eq!((healthy.verdict, healthy.critical_warning), (HealthVerdict::Passed, Some(0)));
+    let failing = hd_serial.health(&drives[1]).unwrap();
+    assert_eq!((failing.verdict, failing.critical_warning), (HealthVerdict::Failing, Some(0x09)));
+}
+
+// synthesized SMART / Health log in `xxd -p` form, with values typical of a lightly used drive (not a capture)
+fn samsung_970_evo_plus_smart_log() -> [u8; 512] {
+    load_hex("samsung_970_evo_plus.smart-log").try_into().unwrap()
+}
+
+#[test]
+fn parses_the_smart_health_log() {
+    let log = NvmeSmartHealthLog::parse(&samsung_970_evo_plus_smart_log());
+    assert_eq!(log.critical_warning, 0);
+    assert_eq!((log.composite_temperature, log.temperature_celsius()), (309, 36));
+    assert_eq!((log.available_spare, log.available_spare_threshold, log.percentage_used), (100, 10, 2));
+    assert_eq!((log.data_units_read, log.data_units_written), (23_456_789, 34_567_890));
+    assert_eq!(log.bytes_written(), 17_698_759_680_000);
+    assert_eq!((log.host_read_commands, log.host_write_commands), (456_789_012, 567_890_123));
+    assert_eq!((log.controller_busy_time, log.power_cycles, log.power_on_hours), (1_234, 1_024, 5_678));
+    assert_eq!((log.unsafe_shutdowns, log.media_errors, log.error_log_entries), (87, 0, 1_535));
+    assert_eq!((log.warning_temperature_time, log.critical_temperature_time), (0, 0));
+    assert_eq!(log.temperature_sensors, vec![309, 316]);
+}
+
+#[test]
+fn counters_use_all_128_bits() {
+    let mut page = [0u8; 512];
+    page[48..64].copy_from_slice(&(u64::MAX as u128 + 2).to_le_bytes());
+    page[160..176].copy_from_slice(&u128::MAX.to_le_bytes());
+    let log = NvmeSmartHealthLog::parse(&page);
+    assert_eq!(log.data_units_written, 0x1_0000_0000_0000_0001);
+    assert_eq!(log.media_errors, u128::MAX);
+    assert_eq!(log.bytes_written(), (u64::MAX as u128 + 2) * 512_000);
+    assert!(log.temperature_sensors.is_empty());
+}
+
+#[test]
+fn reads_the_smart_health_log_through_the_admin_command() {
+    let root = tempfile::tempdir().unwrap();
+    add_block_device(root.path(), "nvme0n1", &[("serial", b"S4EWNX0R123456A\n")]);
+    let nvme = Arc::new(
+        FakeNvmeTransport::new()
+            .with_controller("/dev/nvme0", samsung_970_evo_plus())
+            .with_log_page("/dev/nvme0", LOG_SMART_HEALTH, samsung_970_evo_plus_smart_log().to_vec()),
+    );
+    let mut hd_serial = HardDriveSerial::with_sysfs_root(root.path()).with_nvme_transport(nvme.clone());
+    let drives = hd_serial.get_serial_numbers().unwrap();
+
+    let log = hd_serial.nvme_smart_health(&drives[0]).unwrap();
+    assert_eq!(log.power_on_hours, 5_678);
+    assert_eq!(nvme.commands().last().unwrap(), &get_log_page_command(LOG_SMART_HEALTH, NVME_NSID_ALL, LOG_SMART_HEALTH_SIZE));
+    assert_eq!(nvme.open_handle_count(), 0);
+
+    let mut without_nvme = HardDriveSerial::with_sysfs_root(root.path());
+    let drives = without_nvme.get_serial_numbers().unwrap();
+    assert_eq!(without_nvme.nvme_smart_health(&drives[0]), Err(SerialError::NotSupported { drive: Some(0), method: Method::NvmeIdentify }));
+}
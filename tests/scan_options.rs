@@ -0,0 +1,83 @@
+mod common;
+
+use common::{FakeDrive, FakeTransport};
+use simplehddserial::{Agreement, HardDriveSerial, Method, ScanOptions, SerialError};
+use std::sync::Arc;
+
+fn three_drives() -> FakeTransport {
+    FakeTransport::new()
+        .with_drive(0, FakeDrive::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01"))
+        .with_drive(1, FakeDrive::ata("S3Z9NB0K123456X", "Samsung SSD 860 EVO 500GB", "RVT02B6Q"))
+        .with_drive(3, FakeDrive::ata("Z1D2ABCD", "ST2000DM001-1CH164", "CC29"))
+}
+
+#[test]
+fn methods_run_in_the_chosen_order() {
+    let options = ScanOptions::new().with_methods([Method::ZeroRights, Method::Smart]);
+    let drives = HardDriveSerial::with_transport(Arc::new(three_drives())).with_options(options).get_serial_numbers().unwrap();
+
+    let methods: Vec<_> = drives[0].outcomes.iter().map(|outcome| outcome.method).collect();
+    assert_eq!(methods, vec![Method::ZeroRights, Method::Smart]);
+    assert_eq!(drives[0].method, Method::ZeroRights);
+}
+
+#[test]
+fn only_the_selected_indices_are_probed() {
+    let options = ScanOptions::new().with_drive_indices([3, 1, 2]);
+    let mut hd_serial = HardDriveSerial::with_transport(Arc::new(three_drives())).with_options(options);
+    let drives = hd_serial.get_serial_numbers().unwrap();
+
+    let found: Vec<_> = drives.iter().map(|drive| (drive.index, drive.serial.as_str())).collect();
+    assert_eq!(found, vec![(3, "Z1D2ABCD"), (1, "S3Z9NB0K123456X")]);
+}
+
+#[test]
+fn physical_drive_paths_keep_their_index() {
+    let options = ScanOptions::new().with_drive_paths(["\\\\.\\PhysicalDrive1"]);
+    let drives = HardDriveSerial::with_transport(Arc::new(three_drives())).with_options(options).get_serial_numbers().unwrap();
+
+    assert_eq!(drives.len(), 1);
+    assert_eq!(drives[0].index, 1);
+    assert_eq!(drives[0].device_path, "\\\\.\\PhysicalDrive1");
+}
+
+#[test]
+fn missing_paths_are_reported() {
+    let options = ScanOptions::new().with_drive_paths(["\\\\.\\PhysicalDrive7"]).with_methods([Method::Smart]);
+    let mut hd_serial = HardDriveSerial::with_transport(Arc::new(three_drives())).with_options(options);
+    assert!(hd_serial.get_serial_numbers().is_err());
+}
+
+#[test]
+fn stopping_at_the_first_serial_skips_the_other_methods() {
+    let options = ScanOptions::new().with_stop_at_first_serial(true);
+    let drives = HardDriveSerial::with_transport(Arc::new(three_drives())).with_options(options).get_serial_numbers().unwrap();
+
+    for drive in &drives {
+        assert_eq!(drive.outcomes.len(), 1);
+        assert_eq!(drive.method, Method::AdminRights);
+        assert_eq!(drive.consensus.agreement, Agreement::Single);
+    }
+}
+
+#[test]
+fn a_failed_method_does_not_count_as_the_first_serial() {
+    let mut drive = FakeDrive::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01");
+    drive.ide_device_map = 0;
+    let options = ScanOptions::new().with_stop_at_first_serial(true).with_drive_indices([0]);
+    let drives = HardDriveSerial::with_transport(Arc::new(FakeTransport::new().with_drive(0, drive))).with_options(options).get_serial_numbers().unwrap();
+
+    assert_eq!(drives[0].outcomes.len(), 2);
+    assert_eq!(drives[0].outcomes[0].result, Err(SerialError::NotSupported { drive: Some(0), method: Method::AdminRights }));
+    assert_eq!(drives[0].method, Method::Smart);
+}
+
+#[test]
+fn concurrency_is_bounded() {
+    let fake = Arc::new(three_drives());
+    let options = ScanOptions::new().with_max_concurrency(1);
+    let drives = HardDriveSerial::with_transport(fake.clone()).with_options(options).get_serial_numbers().unwrap();
+
+    assert_eq!(drives.len(), 3);
+    assert_eq!(fake.peak_open_handles(), 1);
+}
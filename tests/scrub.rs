@@ -0,0 +1,226 @@
+mod common;
+
+use common::*;
+use simplehddserial::ata_string::AtaString;
+use simplehddserial::capture::{Recorder, RecordingTransport, ReplayNvmeTransport, ReplayScsiTransport, ReplayTransport};
+use simplehddserial::identify::{verify_identify_block, IdentifyIntegrityError};
+use simplehddserial::nvme::*;
+use simplehddserial::scrub::Scrubber;
+use simplehddserial::storage_descriptor::{DescriptorError, StorageDeviceDescriptor};
+use simplehddserial::{Agreement, DriveIdentifier, HardDriveSerial, IdentifyDevice, Method, ScanOptions};
+use std::sync::Arc;
+
+const SERIAL: &str = "WD-WCC4E1234567";
+const KEY: &[u8] = b"site secret";
+
+// the character class of every byte, what a pseudonym has to keep
+fn shape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '0'..='9' => '9',
+            'A'..='Z' => 'A',
+            'a'..='z' => 'a',
+            other => other,
+        })
+        .collect()
+}
+
+#[test]
+fn pseudonyms_keep_the_shape_and_are_deterministic() {
+    let scrubber = Scrubber::new(KEY);
+    let pseudonym = scrubber.pseudonym(SERIAL);
+    assert_ne!(pseudonym, SERIAL);
+    assert_eq!(shape(&pseudonym), shape(SERIAL));
+    assert_eq!(Scrubber::new(KEY).pseudonym(SERIAL), pseudonym);
+    assert_ne!(Scrubber::new(b"another key").pseudonym(SERIAL), pseudonym);
+    assert_ne!(scrubber.pseudonym("WD-WCC4E7654321"), pseudonym); // a different drive gets a different pseudonym
+    assert_eq!(scrubber.pseudonym(" -. "), " -. ");
+}
+
+#[test]
+fn identify_serial_and_wwn_are_replaced_with_a_valid_checksum() {
+    let original = load_hdparm("wd10ezex");
+    let mut block = original;
+    Scrubber::new(KEY).scrub_identify(&mut block);
+
+    let before = IdentifyDevice::parse(&original);
+    let after = IdentifyDevice::parse(&block);
+    assert_eq!(after.serial, Scrubber::new(KEY).pseudonym(SERIAL));
+    assert_eq!((after.model.as_str(), after.firmware.as_str()), (before.model.as_str(), before.firmware.as_str()));
+    assert_eq!(verify_identify_block(&block), Ok(()));
+
+    let (wwn_before, wwn_after) = (before.wwn.unwrap(), after.wwn.unwrap());
+    assert_ne!(wwn_after, wwn_before);
+    assert_eq!(wwn_after >> 36, wwn_before >> 36); // NAA and OUI kept
+
+    // only the serial and wwn words (and the checksum) moved
+    let changed: Vec<usize> = (0..256).filter(|&word| block[word * 2..word * 2 + 2] != original[word * 2..word * 2 + 2]).collect();
+    assert!(changed.iter().all(|word| (10..=19).contains(word) || (108..=111).contains(word) || *word == 255), "{:?}", changed);
+}
+
+#[test]
+fn a_bad_checksum_stays_exactly_as_bad() {
+    let mut block = load_hdparm("wd10ezex");
+    block[511] = block[511].wrapping_add(3);
+    let before = verify_identify_block(&block);
+    Scrubber::new(KEY).scrub_identify(&mut block);
+
+    let (Err(IdentifyIntegrityError::ChecksumMismatch { stored: stored_before, expected: expected_before }), Err(IdentifyIntegrityError::ChecksumMismatch { stored, expected })) =
+        (before, verify_identify_block(&block))
+    else {
+        panic!("expected a checksum mismatch before and after");
+    };
+    assert_eq!(stored.wrapping_sub(expected), stored_before.wrapping_sub(expected_before));
+}
+
+#[test]
+fn padding_and_odd_lengths_stay_in_place() {
+    let mut block = identify_block("  S3Z9NB0K12345", "Samsung SSD 860 EVO 500GB", "RVT02B6Q");
+    let original = block;
+    Scrubber::new(KEY).scrub_identify(&mut block);
+
+    assert_eq!(block[20..22], original[20..22]); // leading spaces
+    assert_eq!(block[36..40], original[36..40]); // trailing spaces
+    assert_eq!(block[34], original[34]); // the padding that shares a word with the odd last character
+    assert_eq!(IdentifyDevice::parse(&block).serial, Scrubber::new(KEY).pseudonym("S3Z9NB0K12345"));
+}
+
+#[test]
+fn descriptor_serials_keep_their_encoding() {
+    let scrubber = Scrubber::new(KEY);
+    let pseudonym = scrubber.pseudonym(SERIAL);
+
+    let mut raw = descriptor_block(SERIAL);
+    scrubber.scrub_storage_descriptor(&mut raw).unwrap();
+    assert_eq!(StorageDeviceDescriptor::parse(&raw).unwrap().serial.as_deref(), Some(pseudonym.as_str()));
+
+    // hex of the byte swapped, space padded serial, as some storage drivers report it
+    let swapped_hex = AtaString::new(SERIAL).to_swapped_bytes(20).iter().map(|byte| format!("{:02X}", byte)).collect::<String>();
+    let mut hex = descriptor_block(&swapped_hex);
+    scrubber.scrub_storage_descriptor(&mut hex).unwrap();
+    let scrubbed = StorageDeviceDescriptor::parse(&hex).unwrap().serial.unwrap();
+    assert_eq!(scrubbed.len(), swapped_hex.len());
+    assert!(scrubbed.bytes().all(|digit| digit.is_ascii_digit() || (b'A'..=b'F').contains(&digit))); // still upper case hex
+    assert_eq!(AtaString::from_hex(&scrubbed).unwrap(), AtaString::new(&pseudonym).swapped());
+}
+
+#[test]
+fn malformed_descriptors_are_refused() {
+    let mut descriptor = descriptor_block(SERIAL);
+    descriptor[DESCRIPTOR_SERIAL_OFFSET..DESCRIPTOR_SERIAL_OFFSET + 4].copy_from_slice(&4096u32.to_le_bytes());
+    let original = descriptor.clone();
+    assert_eq!(
+        Scrubber::new(KEY).scrub_storage_descriptor(&mut descriptor),
+        Err(DescriptorError::OffsetOutOfRange { field: "serial number", offset: 4096 })
+    );
+    assert_eq!(descriptor, original);
+}
+
+#[test]
+fn nvme_serial_nqn_and_namespace_identifiers() {
+    let scrubber = Scrubber::new(KEY);
+    let mut controller: [u8; NVME_IDENTIFY_DATA_SIZE] = nvme_identify_controller("S4EWNX0R123456", "Samsung SSD 970 EVO Plus 1TB", "2B2QEXM7").try_into().unwrap();
+    let nqn = "nqn.2014.08.org.nvmexpress:144d144dS4EWNX0R123456     Samsung SSD 970 EVO Plus 1TB";
+    controller[768..768 + nqn.len()].copy_from_slice(nqn.as_bytes());
+    scrubber.scrub_nvme_identify_controller(&mut controller);
+
+    let parsed = NvmeIdentifyController::parse(&controller);
+    let pseudonym = scrubber.pseudonym("S4EWNX0R123456");
+    assert_eq!(parsed.serial, pseudonym);
+    assert_eq!(parsed.model, "Samsung SSD 970 EVO Plus 1TB");
+    assert_eq!(parsed.subsystem_nqn, nqn.replace("S4EWNX0R123456", &pseudonym));
+
+    let eui64 = [0x00, 0x25, 0x38, 0xB5, 0x81, 0x50, 0x12, 0x34];
+    let nguid = [0x00, 0x25, 0x38, 0x5B, 0x81, 0x50, 0x12, 0x34, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01];
+    let mut namespace: [u8; NVME_IDENTIFY_DATA_SIZE] = nvme_identify_namespace(eui64, [0; 16]).try_into().unwrap();
+    scrubber.scrub_nvme_identify_namespace(&mut namespace);
+    let scrubbed = NvmeIdentifyNamespace::parse(&namespace);
+    assert_ne!(scrubbed.eui64, eui64);
+    assert_eq!(scrubbed.eui64[..3], eui64[..3]); // IEEE OUI kept
+    assert_eq!(scrubbed.nguid, [0; 16]); // not reported stays not reported
+
+    // the descriptor list names the same namespace, so it scrubs to the same identifiers
+    let mut descriptors = nvme_namespace_descriptors(&[(NIDT_EUI64, &eui64), (NIDT_NGUID, &nguid), (NIDT_CSI, &[0])]);
+    scrubber.scrub_namespace_descriptors(&mut descriptors).unwrap();
+    let mut namespace: [u8; NVME_IDENTIFY_DATA_SIZE] = nvme_identify_namespace(eui64, nguid).try_into().unwrap();
+    scrubber.scrub_nvme_identify_namespace(&mut namespace);
+    let scrubbed = NvmeIdentifyNamespace::parse(&namespace);
+    assert_eq!(
+        parse_namespace_descriptors(&descriptors).unwrap(),
+        vec![
+            NamespaceDescriptor::Identifier(DriveIdentifier::Eui64(scrubbed.eui64)),
+            NamespaceDescriptor::Identifier(DriveIdentifier::Nguid(scrubbed.nguid)),
+            NamespaceDescriptor::CommandSet(0),
+        ]
+    );
+}
+
+#[test]
+fn a_scrubbed_capture_replays_with_the_pseudonym() {
+    let drive = FakeDrive { ide_device_map: 1, identify: Some(load_hdparm("wd10ezex")), descriptor: Some(descriptor_block(SERIAL)), ..FakeDrive::default() };
+    let recorder = Arc::new(RecordingTransport::new(Arc::new(FakeTransport::new().with_drive(0, drive))));
+    let options = ScanOptions::new().with_methods([Method::AdminRights, Method::Smart, Method::ZeroRights]).with_drive_indices([0]);
+    HardDriveSerial::with_transport(recorder.clone()).with_options(options.clone()).get_serial_numbers().unwrap();
+
+    let mut capture = recorder.capture();
+    assert_eq!(Scrubber::new(KEY).scrub_capture(&mut capture), 3); // two IDENTIFY blocks and the descriptor
+    let text = capture.to_string();
+    for leak in [AtaString::new(SERIAL).to_hex(), AtaString::new(SERIAL).swapped().to_hex()] {
+        assert!(!text.contains(&leak), "the serial survived scrubbing");
+    }
+
+    let mut hd_serial = HardDriveSerial::with_transport(Arc::new(ReplayTransport::new(capture))).with_options(options);
+    let drives = hd_serial.get_serial_numbers().unwrap();
+    assert_eq!(drives[0].serial, Scrubber::new(KEY).pseudonym(SERIAL));
+    assert_eq!(drives[0].method, Method::AdminRights); // the checksum still verifies
+    assert_eq!(drives[0].consensus.agreement, Agreement::Unanimous);
+    assert!(hd_serial.errors().is_empty());
+}
+
+#[test]
+fn scsi_and_nvme_responses_in_a_capture_are_scrubbed() {
+    let naa = [0x50, 0x00, 0xC5, 0x00, 0x12, 0x34, 0x56, 0x78];
+    let eui64 = [0x00, 0x25, 0x38, 0x5B, 0x71, 0xB0, 0x9E, 0x83];
+    let options = ScanOptions::new()
+        .with_methods([Method::AtaPassThrough, Method::ScsiVpd, Method::NvmeIdentify])
+        .with_drive_paths(["/dev/sda", "/dev/sdb", "/dev/nvme0n1"]);
+    let scsi = FakeScsiTransport::new()
+        .with_device("/dev/sda", FakeSatDevice { identify: Some(load_hdparm("wd10ezex")), ..FakeSatDevice::default() })
+        .with_device("/dev/sdb", FakeSatDevice::scsi("SEAGATE", "ST4000NM0023", "0004", "Z1Z0ABCD", &naa));
+    let namespace = FakeNamespace { identify: nvme_identify_namespace(eui64, [0; 16]), descriptors: Some(nvme_namespace_descriptors(&[(NIDT_EUI64, &eui64)])) };
+    let nvme = FakeNvmeTransport::new()
+        .with_controller("/dev/nvme0", nvme_identify_controller("S4EWNX0R123456", "Samsung SSD 970 EVO Plus 1TB", "2B2QEXM7"))
+        .with_namespace("/dev/nvme0", 1, namespace);
+    let recorder = Recorder::new();
+    HardDriveSerial::with_transport(Arc::new(FakeTransport::new()))
+        .with_scsi_transport(Arc::new(recorder.scsi_transport(Arc::new(scsi))))
+        .with_nvme_transport(Arc::new(recorder.nvme_transport(Arc::new(nvme))))
+        .with_options(options.clone())
+        .get_serial_numbers()
+        .unwrap();
+
+    let scrubber = Scrubber::new(KEY);
+    let mut capture = recorder.capture();
+    // the IDENTIFY block, both vpd pages, Identify Controller, Identify Namespace and the descriptor list
+    assert_eq!(scrubber.scrub_capture(&mut capture), 6);
+    let text = capture.to_string();
+    for leak in [AtaString::new(SERIAL).swapped().to_hex(), AtaString::new("Z1Z0ABCD").to_hex(), AtaString::new("S4EWNX0R123456").to_hex(), "12345678".to_string(), "71b09e83".to_string()] {
+        assert!(!text.contains(&leak), "{} survived scrubbing", leak);
+    }
+
+    let mut hd_serial = HardDriveSerial::with_transport(Arc::new(ReplayTransport::new(capture.clone())))
+        .with_scsi_transport(Arc::new(ReplayScsiTransport::new(capture.clone())))
+        .with_nvme_transport(Arc::new(ReplayNvmeTransport::new(capture)))
+        .with_options(options);
+    let drives = hd_serial.get_serial_numbers().unwrap();
+    let serials: Vec<&str> = drives.iter().map(|drive| drive.serial.as_str()).collect();
+    assert_eq!(serials, vec![scrubber.pseudonym(SERIAL), scrubber.pseudonym("Z1Z0ABCD"), scrubber.pseudonym("S4EWNX0R123456")]);
+
+    // the vendor part of the identifiers is kept
+    let [DriveIdentifier::Naa(scrubbed_naa)] = drives[1].identifiers.as_slice() else { panic!("{:?}", drives[1].identifiers) };
+    assert_eq!((&scrubbed_naa[..3], scrubbed_naa[3] & 0xF0), (&naa[..3], naa[3] & 0xF0));
+    let [DriveIdentifier::Eui64(scrubbed_eui64)] = drives[2].identifiers.as_slice() else { panic!("{:?}", drives[2].identifiers) };
+    assert_eq!(&scrubbed_eui64[..3], &eui64[..3]);
+    assert_ne!(*scrubbed_eui64, eui64);
+    assert!(hd_serial.errors().is_empty());
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use common::{add_block_device, designation_descriptor, standard_inquiry, vpd_page, FakeSatDevice, FakeScsiTransport};
+use simplehddserial::scsi::*;
+use simplehddserial::transport::ERROR_INVALID_DATA;
+use simplehddserial::{BusType, DriveIdentifier, HardDriveSerial, Method, ScanOptions, SerialError};
+use std::sync::Arc;
+
+const NAA: [u8; 8] = [0x50, 0x00, 0xC5, 0x00, 0xA1, 0xB2, 0xC3, 0xD4];
+
+// synthesized device identification page of a dual ported SAS disk (not a capture of a real drive)
+fn sas_device_identification() -> Vec<u8> {
+    let mut payload = designation_descriptor(0x01, 0x03, &NAA); // logical unit NAA
+    payload.extend(designation_descriptor(0x61, 0x93, &[0x50, 0x00, 0xC5, 0x00, 0xA1, 0xB2, 0xC3, 0xD5])); // target port NAA, SAS, PIV
+    payload.extend(designation_descriptor(0x61, 0x94, &[0x00, 0x00, 0x00, 0x01])); // relative target port 1
+    payload.extend(designation_descriptor(0x02, 0x01, b"SEAGATE ST4000NM0023    Z1Z2ABCD"));
+    vpd_page(0x83, &payload)
+}
+
+#[test]
+fn inquiry_cdbs() {
+    assert_eq!(inquiry_cdb(None, 36), [0x12, 0x00, 0x00, 0x00, 36, 0x00]);
+    assert_eq!(inquiry_cdb(Some(VPD_DEVICE_IDENTIFICATION), 0x0400), [0x12, 0x01, 0x83, 0x04, 0x00, 0x00]);
+}
+
+#[test]
+fn parses_standard_inquiry() {
+    let mut data = standard_inquiry("SEAGATE", "ST4000NM0023", "0003");
+    data[1] = 0x80;
+    let inquiry = StandardInquiry::parse(&data).unwrap();
+    assert_eq!((inquiry.vendor.as_str(), inquiry.product.as_str(), inquiry.revision.as_str()), ("SEAGATE", "ST4000NM0023", "0003"));
+    assert_eq!((inquiry.peripheral_qualifier, inquiry.peripheral_device_type), (0, 0));
+    assert!(inquiry.removable);
+    assert_eq!(inquiry.version, 0x06);
+    assert_eq!(StandardInquiry::parse(&data[..35]), None);
+}
+
+#[test]
+fn parses_unit_serial_number() {
+    assert_eq!(parse_unit_serial_number(&vpd_page(0x80, b"  Z1Z2ABCD0000C123\0")).as_deref(), Some("Z1Z2ABCD0000C123"));
+    assert_eq!(parse_unit_serial_number(&vpd_page(0x80, b"        ")), None);
+    assert_eq!(parse_unit_serial_number(&vpd_page(0x83, b"Z1Z2ABCD")), None); // wrong page code
+    assert_eq!(parse_unit_serial_number(&[0x00, 0x80]), None);
+}
+
+#[test]
+fn parses_device_identification() {
+    let descriptors = parse_device_identification(&sas_device_identification()).unwrap();
+    assert_eq!(descriptors.len(), 4);
+    assert_eq!(descriptors[0].association, Association::LogicalUnit);
+    assert_eq!(descriptors[0].designator, Designator::Naa(NAA.to_vec()));
+    assert_eq!((descriptors[1].association, descriptors[1].protocol_identifier), (Association::TargetPort, 6));
+    assert_eq!(descriptors[2].designator, Designator::Other { designator_type: 0x04, value: vec![0, 0, 0, 1] });
+    assert_eq!(descriptors[3].designator, Designator::T10VendorId { vendor: "SEAGATE".to_string(), identifier: "ST4000NM0023    Z1Z2ABCD".to_string() });
+
+    // target port designators do not name the disk
+    let identifiers = logical_unit_identifiers(&descriptors);
+    assert_eq!(identifiers, vec![DriveIdentifier::Naa(NAA.to_vec()), DriveIdentifier::T10VendorId("SEAGATE ST4000NM0023    Z1Z2ABCD".to_string())]);
+    assert_eq!(identifiers[0].to_string(), "naa.5000c500a1b2c3d4");
+    assert_eq!(identifiers[1].to_string(), "t10.SEAGATE ST4000NM0023    Z1Z2ABCD");
+}
+
+#[test]
+fn parses_eui64_and_scsi_name_designators() {
+    let eui64 = [0x00, 0x25, 0x38, 0x5B, 0x71, 0xB0, 0x9E, 0x83];
+    let mut payload = designation_descriptor(0x01, 0x02, &eui64);
+    payload.extend(designation_descriptor(0x03, 0x08, b"iqn.2003-01.org.linux-iscsi.target:sn.1234\0\0"));
+    let descriptors = parse_device_identification(&vpd_page(0x83, &payload)).unwrap();
+    assert_eq!(
+        logical_unit_identifiers(&descriptors),
+        vec![DriveIdentifier::Eui64(eui64), DriveIdentifier::ScsiName("iqn.2003-01.org.linux-iscsi.target:sn.1234".to_string())]
+    );
+}
+
+#[test]
+fn malformed_device_identification_is_rejected_without_panicking() {
+    let mut truncated = vpd_page(0x83, &designation_descriptor(0x01, 0x03, &NAA));
+    truncated[7] = 16; // the designator claims more bytes than the page holds
+    assert_eq!(parse_device_identification(&truncated), Err(ERROR_INVALID_DATA));
+    assert_eq!(parse_device_identification(&vpd_page(0x80, b"Z1Z2ABCD")), Err(ERROR_INVALID_DATA));
+    assert_eq!(parse_device_identification(&[]), Err(ERROR_INVALID_DATA));
+    assert_eq!(parse_device_identification(&vpd_page(0x83, &[])), Ok(vec![]));
+}
+
+#[test]
+fn sysfs_exposes_the_vpd_pg83_identifiers() {
+    let root = tempfile::tempdir().unwrap();
+    add_block_device(root.path(), "sdc", &[("vpd_pg80", &vpd_page(0x80, b"Z1Z2ABCD")), ("vpd_pg83", &sas_device_identification())]);
+
+    let drives = HardDriveSerial::with_sysfs_root(root.path()).get_serial_numbers().unwrap();
+    assert_eq!(drives[0].serial, "Z1Z2ABCD");
+    assert_eq!(drives[0].identifiers[0], DriveIdentifier::Naa(NAA.to_vec()));
+}
+
+#[test]
+fn sas_disks_get_their_serial_through_the_vpd_pages() {
+    let scsi = Arc::new(FakeScsiTransport::new().with_device("/dev/sdd", FakeSatDevice::scsi("SEAGATE", "ST4000NM0023", "0003", "Z1Z2ABCD", &NAA)));
+    let options = ScanOptions::new().with_methods([Method::AtaPassThrough, Method::ScsiVpd]).with_drive_paths(["/dev/sdd"]);
+    let mut hd_serial = HardDriveSerial::with_transport(Arc::new(common::FakeTransport::new())).with_scsi_transport(scsi.clone()).with_options(options);
+
+    let drives = hd_serial.get_serial_numbers().unwrap();
+    assert_eq!(drives[0].serial, "Z1Z2ABCD");
+    assert_eq!(drives[0].method, Method::ScsiVpd);
+    assert_eq!(drives[0].model.as_deref(), Some("SEAGATE ST4000NM0023"));
+    assert_eq!(drives[0].firmware.as_deref(), Some("0003"));
+    assert_eq!(drives[0].bus_type, BusType::Unknown);
+    assert_eq!(drives[0].identifiers, vec![DriveIdentifier::Naa(NAA.to_vec())]);
+    // the disk rejects ATA PASS-THROUGH, which is not worth an error
+    assert_eq!(drives[0].outcomes[0].result, Err(SerialError::NotSupported { drive: Some(0), method: Method::AtaPassThrough }));
+    assert!(hd_serial.errors().is_empty());
+    assert_eq!(scsi.open_handle_count(), 0);
+}
@@ -0,0 +1,174 @@
+mod common;
+
+use common::{add_block_device, smart_data_block, smart_thresholds_block, FakeDrive, FakeSatDevice, FakeScsiTransport, FakeTransport};
+use simplehddserial::smart::*;
+use simplehddserial::transport::ERROR_INVALID_DATA;
+use simplehddserial::{HardDriveSerial, Method, SerialError};
+use std::sync::Arc;
+
+// synthesized attribute table modelled on a desktop hard drive (not a capture of a real drive)
+fn wd10ezex_smart_data() -> [u8; 512] {
+    smart_data_block(&[
+        (1, 0x002F, 200, 200, 0),               // raw read error rate
+        (3, 0x0027, 173, 171, 2325),            // spin up time
+        (5, 0x0033, 200, 200, 0),               // reallocated sector count
+        (9, 0x0032, 71, 71, 21345),             // power on hours
+        (194, 0x0022, 110, 100, 0x0028_0012_0021), // temperature, min and max packed into the raw value
+        (197, 0x0032, 200, 200, 0),             // current pending sectors
+    ])
+}
+
+// the thresholds are listed in a different order to check they are matched by id
+fn wd10ezex_thresholds() -> [u8; 512] {
+    smart_thresholds_block(&[(5, 140), (3, 21), (1, 51), (9, 0), (194, 0), (197, 0)])
+}
+
+#[test]
+fn parses_the_attribute_table() {
+    let smart = SmartAttributes::parse(&wd10ezex_smart_data(), Some(&wd10ezex_thresholds()));
+    assert_eq!(smart.revision, 0x10);
+    assert!(smart.checksum_valid);
+    assert_eq!(smart.attributes.iter().map(|attribute| attribute.id).collect::<Vec<_>>(), vec![1, 3, 5, 9, 194, 197]);
+
+    let spin_up = smart.get(3).unwrap();
+    assert_eq!((spin_up.flags, spin_up.current, spin_up.worst, spin_up.raw, spin_up.threshold), (0x0027, 173, 171, 2325, Some(21)));
+    assert_eq!(smart.get(194).unwrap().raw, 0x0028_0012_0021); // all 48 bits
+    assert_eq!(smart.get(5).unwrap().threshold, Some(140));
+    assert_eq!(smart.get(10), None);
+}
+
+#[test]
+fn classifies_pre_fail_and_old_age_attributes() {
+    let smart = SmartAttributes::parse(&wd10ezex_smart_data(), None);
+    assert_eq!(smart.get(5).unwrap().kind(), AttributeKind::PreFail);
+    assert_eq!(smart.get(9).unwrap().kind(), AttributeKind::OldAge);
+    assert!(smart.get(9).unwrap().is_online());
+    assert!(smart.attributes.iter().all(|attribute| attribute.threshold.is_none()));
+}
+
+#[test]
+fn reports_thresholds_exceeded_now_and_in_the_past() {
+    let data = smart_data_block(&[
+        (5, 0x0033, 130, 130, 1024), // below its threshold
+        (3, 0x0027, 173, 20, 2325),  // recovered, but the worst value went below
+        (1, 0x002F, 51, 51, 0),      // exactly at the threshold counts as failing
+        (9, 0x0032, 1, 1, 90000),    // threshold 0 never fails
+        (7, 0x002E, 100, 1, 0),      // no threshold entry
+    ]);
+    let thresholds = smart_thresholds_block(&[(5, 140), (3, 21), (1, 51), (9, 0)]);
+    let smart = SmartAttributes::parse(&data, Some(&thresholds));
+
+    assert_eq!(smart.get(5).unwrap().status(), ThresholdStatus::FailingNow);
+    assert_eq!(smart.get(3).unwrap().status(), ThresholdStatus::FailedInPast);
+    assert_eq!(smart.get(1).unwrap().status(), ThresholdStatus::FailingNow);
+    assert_eq!(smart.get(9).unwrap().status(), ThresholdStatus::Ok);
+    assert_eq!(smart.get(7).unwrap().status(), ThresholdStatus::Ok);
+    assert_eq!(smart.failing_now().map(|attribute| attribute.id).collect::<Vec<_>>(), vec![5, 1]);
+}
+
+#[test]
+fn flags_a_bad_checksum_but_still_decodes() {
+    let mut data = wd10ezex_smart_data();
+    data[511] ^= 0xFF;
+    let smart = SmartAttributes::parse(&data, None);
+    assert!(!smart.checksum_valid);
+    assert_eq!(smart.attributes.len(), 6);
+}
+
+#[test]
+fn reads_smart_through_smart_rcv_drive_data() {
+    let drive = FakeDrive {
+        smart_data: Some(wd10ezex_smart_data()),
+        smart_thresholds: Some(wd10ezex_thresholds()),
+        ..FakeDrive::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01")
+    };
+    let transport = Arc::new(FakeTransport::new().with_drive(0, drive));
+    let mut hd_serial = HardDriveSerial::with_transport(transport.clone());
+    let drives = hd_serial.get_serial_numbers().unwrap();
+
+    let smart = hd_serial.smart_attributes(&drives[0]).unwrap();
+    assert_eq!(smart.get(5).unwrap().threshold, Some(140));
+    assert_eq!(smart.get(9).unwrap().raw, 21345);
+    assert_eq!(transport.open_handle_count(), 0);
+}
+
+#[test]
+fn drives_without_thresholds_still_report_attributes() {
+    let drive = FakeDrive { smart_data: Some(wd10ezex_smart_data()), ..FakeDrive::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01") };
+    let mut hd_serial = HardDriveSerial::with_transport(Arc::new(FakeTransport::new().with_drive(0, drive)));
+    let drives = hd_serial.get_serial_numbers().unwrap();
+
+    let smart = hd_serial.smart_attributes(&drives[0]).unwrap();
+    assert_eq!(smart.attributes.len(), 6);
+    assert!(smart.attributes.iter().all(|attribute| attribute.threshold.is_none()));
+}
+
+#[test]
+fn drives_without_smart_report_not_supported() {
+    let mut hd_serial = HardDriveSerial::with_transport(Arc::new(FakeTransport::new().with_drive(1, FakeDrive::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01"))));
+    let drives = hd_serial.get_serial_numbers().unwrap();
+    assert_eq!(hd_serial.smart_attributes(&drives[0]), Err(SerialError::NotSupported { drive: Some(1), method: Method::Smart }));
+}
+
+#[test]
+fn reads_smart_through_ata_pass_through_on_linux() {
+    let root = tempfile::tempdir().unwrap();
+    add_block_device(root.path(), "sda", &[("serial", b"WD-WCC4E1234567\n")]);
+    let device = FakeSatDevice {
+        smart_data: Some(wd10ezex_smart_data()),
+        smart_thresholds: Some(wd10ezex_thresholds()),
+        ..FakeSatDevice::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01")
+    };
+    let mut hd_serial = HardDriveSerial::with_sysfs_root(root.path()).with_scsi_transport(Arc::new(FakeScsiTransport::new().with_device("/dev/sda", device)));
+    let drives = hd_serial.get_serial_numbers().unwrap();
+
+    let smart = hd_serial.smart_attributes(&drives[0]).unwrap();
+    assert_eq!(smart.get(3).unwrap().threshold, Some(21));
+
+    // without a SCSI transport sysfs has no way to send SMART commands
+    let mut sysfs_only = HardDriveSerial::with_sysfs_root(root.path());
+    let drives = sysfs_only.get_serial_numbers().unwrap();
+    assert_eq!(sysfs_only.smart_attributes(&drives[0]), Err(SerialError::NotSupported { drive: Some(0), method: Method::AtaPassThrough }));
+}
+
+#[test]
+fn decodes_the_return_status_registers() {
+    assert_eq!(decode_return_status(0x4F, 0xC2), Some(HealthVerdict::Passed));
+    assert_eq!(decode_return_status(0xF4, 0x2C), Some(HealthVerdict::Failing));
+    assert_eq!(decode_return_status(0x00, 0x00), None);
+}
+
+#[test]
+fn health_through_smart_send_drive_command() {
+    let transport = FakeTransport::new()
+        .with_drive(0, FakeDrive { smart_return_status: Some((0x4F, 0xC2)), ..FakeDrive::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01") })
+        .with_drive(1, FakeDrive { smart_return_status: Some((0xF4, 0x2C)), ..FakeDrive::ata("Z1D2ABCD", "ST2000DM001-1CH164", "CC29") })
+        .with_drive(2, FakeDrive { smart_return_status: Some((0x00, 0x00)), ..FakeDrive::ata("S3Z9NB0K123456X", "Samsung SSD 860 EVO 500GB", "RVT02B6Q") });
+    let mut hd_serial = HardDriveSerial::with_transport(Arc::new(transport));
+    let drives = hd_serial.get_serial_numbers().unwrap();
+
+    let verdicts: Vec<_> = drives.iter().map(|drive| hd_serial.health(drive).map(|health| health.verdict)).collect();
+    assert_eq!(
+        verdicts,
+        vec![
+            Ok(HealthVerdict::Passed),
+            Ok(HealthVerdict::Failing),
+            Err(SerialError::from_os_code(Some(2), Method::Smart, ERROR_INVALID_DATA)), // registers lost on the way back
+        ]
+    );
+    assert_eq!(hd_serial.health(&drives[0]).unwrap().critical_warning, None);
+}
+
+#[test]
+fn health_through_ata_pass_through() {
+    let root = tempfile::tempdir().unwrap();
+    add_block_device(root.path(), "sda", &[("serial", b"Z1D2ABCD\n")]);
+    let device = FakeSatDevice { smart_return_status: Some((0xF4, 0x2C)), ..FakeSatDevice::ata("Z1D2ABCD", "ST2000DM001-1CH164", "CC29") };
+    let scsi = Arc::new(FakeScsiTransport::new().with_device("/dev/sda", device));
+    let mut hd_serial = HardDriveSerial::with_sysfs_root(root.path()).with_scsi_transport(scsi.clone());
+    let drives = hd_serial.get_serial_numbers().unwrap();
+
+    assert_eq!(hd_serial.health(&drives[0]).unwrap().verdict, HealthVerdict::Failing);
+    // non-data, CK_COND set, features DAh, lba mid/high carrying the SMART signature
+    assert_eq!(scsi.cdbs().last().unwrap(), &vec![0x85, 0x06, 0x20, 0, 0xDA, 0, 1, 0, 0, 0, 0x4F, 0, 0xC2, 0, 0xB0, 0]);
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use common::{descriptor_block, load_hex, FakeDrive, FakeTransport};
+use simplehddserial::storage_descriptor::*;
+use simplehddserial::{BusType, DriveInfo, HardDriveSerial, Method, ScanOptions, SerialError};
+use std::sync::Arc;
+
+// synthesized descriptors laid out the way stornvme and usbstor fill them in (not captures of real drives)
+fn samsung_970_evo_plus() -> Vec<u8> {
+    load_hex("samsung_970_evo_plus.storage-descriptor")
+}
+
+fn sandisk_cruzer_blade() -> Vec<u8> {
+    load_hex("sandisk_cruzer_blade.storage-descriptor")
+}
+
+// scans drive 0 with only the zero rights method
+fn zero_rights(descriptor: Vec<u8>) -> Result<DriveInfo, Vec<SerialError>> {
+    let drive = FakeDrive { descriptor: Some(descriptor), ..FakeDrive::default() };
+    let options = ScanOptions::new().with_methods([Method::ZeroRights]).with_drive_indices([0]);
+    let mut drives = HardDriveSerial::with_transport(Arc::new(FakeTransport::new().with_drive(0, drive)))
+        .with_options(options)
+        .get_serial_numbers()?;
+    Ok(drives.remove(0))
+}
+
+#[test]
+fn parses_an_nvme_descriptor() {
+    let descriptor = StorageDeviceDescriptor::parse(&samsung_970_evo_plus()).unwrap();
+    assert_eq!(descriptor.device_type, 0);
+    assert!(!descriptor.removable);
+    assert!(descriptor.command_queueing);
+    assert_eq!(descriptor.vendor.as_deref(), Some("NVMe"));
+    assert_eq!(descriptor.product.as_deref(), Some("Samsung SSD 970 EVO Plus 1TB"));
+    assert_eq!(descriptor.revision.as_deref(), Some("2B2QEXM7"));
+    assert_eq!(descriptor.serial.as_deref(), Some("0025_38B5_8150_1234."));
+    assert_eq!(descriptor.bus_type, BusType::Nvme);
+}
+
+#[test]
+fn parses_a_removable_usb_descriptor() {
+    let descriptor = StorageDeviceDescriptor::parse(&sandisk_cruzer_blade()).unwrap();
+    assert!(descriptor.removable);
+    assert_eq!(descriptor.bus_type, BusType::Usb);
+    assert_eq!(descriptor.vendor.as_deref(), Some("SanDisk")); // space padding is trimmed
+    assert_eq!(descriptor.product.as_deref(), Some("Cruzer Blade"));
+    assert_eq!(descriptor.revision.as_deref(), Some("1.00"));
+    assert_eq!(descriptor.serial.as_deref(), Some("4C530001230615117052"));
+}
+
+#[test]
+fn ignores_bytes_past_the_descriptor_size() {
+    let mut buffer = sandisk_cruzer_blade();
+    buffer.resize(10000, 0xFF); // the rest of the ioctl output buffer
+    assert_eq!(StorageDeviceDescriptor::parse(&buffer).unwrap(), StorageDeviceDescriptor::parse(&sandisk_cruzer_blade()).unwrap());
+}
+
+#[test]
+fn missing_strings_are_none() {
+    let descriptor = StorageDeviceDescriptor::parse(&descriptor_block("WD-WCC4E1234567")).unwrap();
+    assert_eq!((descriptor.vendor, descriptor.product, descriptor.revision), (None, None, None));
+    assert_eq!(descriptor.serial.as_deref(), Some("WD-WCC4E1234567"));
+}
+
+#[test]
+fn rejects_truncated_and_oversized_descriptors() {
+    let descriptor = samsung_970_evo_plus();
+    assert_eq!(StorageDeviceDescriptor::parse(&descriptor[..8]), Err(DescriptorError::TooShort { len: 8 }));
+    let len = descriptor.len();
+    assert_eq!(StorageDeviceDescriptor::parse(&descriptor[..len - 1]), Err(DescriptorError::BadSize { size: len, len: len - 1 }));
+
+    let mut undersized = descriptor.clone();
+    undersized[4..8].copy_from_slice(&8u32.to_le_bytes()); // a bare STORAGE_DESCRIPTOR_HEADER
+    assert_eq!(StorageDeviceDescriptor::parse(&undersized), Err(DescriptorError::BadSize { size: 8, len }));
+}
+
+#[test]
+fn rejects_offsets_outside_the_descriptor() {
+    let mut descriptor = samsung_970_evo_plus();
+    let len = descriptor.len();
+    descriptor[24..28].copy_from_slice(&(len as u32).to_le_bytes()); // serial just past the end
+    assert_eq!(StorageDeviceDescriptor::parse(&descriptor), Err(DescriptorError::OffsetOutOfRange { field: "serial number", offset: len }));
+
+    descriptor[24..28].copy_from_slice(&0xFFFF_FFF0u32.to_le_bytes());
+    assert!(matches!(StorageDeviceDescriptor::parse(&descriptor), Err(DescriptorError::OffsetOutOfRange { .. })));
+
+    descriptor[24..28].copy_from_slice(&12u32.to_le_bytes()); // into the header
+    assert_eq!(StorageDeviceDescriptor::parse(&descriptor), Err(DescriptorError::OffsetOutOfRange { field: "serial number", offset: 12 }));
+}
+
+#[test]
+fn rejects_unterminated_strings() {
+    let mut descriptor = samsung_970_evo_plus();
+    let last = descriptor.len() - 1;
+    descriptor[last] = b'.'; // the serial runs into the end of the descriptor
+    assert!(matches!(StorageDeviceDescriptor::parse(&descriptor), Err(DescriptorError::Unterminated { field: "serial number", .. })));
+}
+
+#[test]
+fn zero_rights_reports_the_descriptor_identity() {
+    let drive = zero_rights(sandisk_cruzer_blade()).unwrap();
+    assert_eq!(drive.serial, "4C530001230615117052");
+    assert_eq!(drive.model.as_deref(), Some("SanDisk Cruzer Blade"));
+    assert_eq!(drive.firmware.as_deref(), Some("1.00"));
+    assert_eq!(drive.bus_type, BusType::Usb);
+}
+
+#[test]
+fn zero_rights_falls_back_on_a_malformed_descriptor() {
+    let mut descriptor = sandisk_cruzer_blade();
+    descriptor[24..28].copy_from_slice(&9999u32.to_le_bytes());
+    // no serial is read out of the bad offset, the geometry fallback has none either and that is not logged
+    assert_eq!(zero_rights(descriptor).unwrap_err(), Vec::<SerialError>::new());
+}
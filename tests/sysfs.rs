@@ -0,0 +1,136 @@
+mod common;
+
+use common::add_block_device;
+use simplehddserial::sysfs::SysfsBackend;
+use simplehddserial::{BusType, DriveIdentifier, HardDriveSerial, Method, ScanOptions, SerialError};
+use std::fs;
+use tempfile::TempDir;
+
+// a tree with one sata disk, one nvme disk and a loop device
+fn fake_sysfs() -> TempDir {
+    let root = tempfile::tempdir().unwrap();
+    add_block_device(root.path(), "sda", &[
+        ("model", b"WDC WD10EZEX-08W\n"),
+        ("rev", b"1A01\n"),
+        ("wwid", b"t10.ATA     WDC WD10EZEX-08WN4A0                        WD-WCC4E1234567\n"),
+        ("vpd_pg80", b"\x00\x80\x00\x0fWD-WCC4E1234567"),
+    ]);
+    add_block_device(root.path(), "nvme0n1", &[
+        ("serial", b"S4EWNX0R123456A     \n"),
+        ("model", b"Samsung SSD 970 EVO Plus 1TB            \n"),
+        ("firmware_rev", b"2B2QEXM7\n"),
+        ("wwid", b"eui.0025385b71b09e83\n"),
+    ]);
+    fs::create_dir_all(root.path().join("block").join("loop0")).unwrap();
+    root
+}
+
+#[test]
+fn lists_only_hardware_backed_block_devices() {
+    let root = fake_sysfs();
+    let drives = SysfsBackend::new(root.path()).list_drives().unwrap();
+    assert_eq!(drives, vec!["nvme0n1", "sda"]);
+}
+
+#[test]
+fn reads_identity_attributes() {
+    let root = fake_sysfs();
+    let backend = SysfsBackend::new(root.path());
+
+    let sda = backend.read_drive("sda").unwrap();
+    assert_eq!(sda.serial, None);
+    assert_eq!(sda.vpd_serial.as_deref(), Some("WD-WCC4E1234567"));
+    assert_eq!(sda.best_serial(), Some("WD-WCC4E1234567"));
+    assert_eq!(sda.model.as_deref(), Some("WDC WD10EZEX-08W"));
+    assert_eq!(sda.firmware.as_deref(), Some("1A01"));
+    assert!(sda.wwid.as_deref().unwrap().starts_with("t10.ATA"));
+    assert_eq!(sda.device_path(), "/dev/sda");
+
+    let nvme = backend.read_drive("nvme0n1").unwrap();
+    assert_eq!(nvme.serial.as_deref(), Some("S4EWNX0R123456A"));
+    assert_eq!(nvme.model.as_deref(), Some("Samsung SSD 970 EVO Plus 1TB"));
+    assert_eq!(nvme.firmware.as_deref(), Some("2B2QEXM7"));
+}
+
+#[test]
+fn wwid_becomes_an_identifier() {
+    let root = fake_sysfs();
+    let mut hd_serial = HardDriveSerial::with_sysfs_root(root.path());
+    let drives = hd_serial.get_serial_numbers().unwrap();
+    let (nvme, sda) = (&drives[0], &drives[1]);
+    assert_eq!(nvme.identifiers, vec![DriveIdentifier::Eui64([0x00, 0x25, 0x38, 0x5B, 0x71, 0xB0, 0x9E, 0x83])]);
+    assert_eq!(sda.identifiers, vec![DriveIdentifier::T10VendorId("ATA     WDC WD10EZEX-08WN4A0                        WD-WCC4E1234567".to_string())]);
+}
+
+#[test]
+fn wwid_spellings_round_trip() {
+    for wwid in [
+        "eui.0025385b71b09e83",
+        "eui.0025385b71b09e830000000000000001",
+        "naa.50014ee2b5a1c3d4",
+        "uuid.5f1e2b3c-4d5e-4f60-8172-8394a5b6c7d8",
+        "t10.ATA     WDC WD10EZEX-08WN4A0                        WD-WCC4E1234567",
+    ] {
+        assert_eq!(DriveIdentifier::from_wwid(wwid).unwrap().to_string(), wwid);
+    }
+    for wwid in ["nvme.144d-5334455758-53616d73756e67-00000001", "eui.0025385b", "naa.zz", "serial"] {
+        assert_eq!(DriveIdentifier::from_wwid(wwid), None, "{}", wwid);
+    }
+}
+
+#[test]
+fn missing_device_is_an_error() {
+    let root = fake_sysfs();
+    assert!(SysfsBackend::new(root.path()).read_drive("sdz").is_err());
+}
+
+#[test]
+fn hard_drive_serial_scans_sysfs() {
+    let root = fake_sysfs();
+    let drives = HardDriveSerial::with_sysfs_root(root.path()).get_serial_numbers().unwrap();
+
+    let found: Vec<_> = drives.iter().map(|drive| (drive.device_path.as_str(), drive.serial.as_str())).collect();
+    assert_eq!(found, vec![("/dev/nvme0n1", "S4EWNX0R123456A"), ("/dev/sda", "WD-WCC4E1234567")]);
+    assert_eq!(drives[0].bus_type, BusType::Nvme);
+    assert_eq!(drives[0].model.as_deref(), Some("Samsung SSD 970 EVO Plus 1TB"));
+    assert_eq!(drives[1].firmware.as_deref(), Some("1A01"));
+    assert!(drives.iter().all(|drive| drive.method == Method::Sysfs && drive.outcomes.len() == 1));
+}
+
+#[test]
+fn drives_without_a_serial_are_reported() {
+    let root = tempfile::tempdir().unwrap();
+    add_block_device(root.path(), "vda", &[("features", b"0\n")]);
+    let errors = HardDriveSerial::with_sysfs_root(root.path()).get_serial_numbers().unwrap_err();
+    assert_eq!(errors, vec![SerialError::NotSupported { drive: Some(0), method: Method::Sysfs }]);
+}
+
+#[test]
+fn missing_sysfs_root_is_reported() {
+    let root = tempfile::tempdir().unwrap();
+    let errors = HardDriveSerial::with_sysfs_root(root.path().join("nope")).get_serial_numbers().unwrap_err();
+    assert_eq!(errors, vec![SerialError::DeviceNotPresent { drive: None, method: Method::Sysfs }]);
+}
+
+#[test]
+fn scans_only_the_selected_devices() {
+    let root = fake_sysfs();
+    let options = ScanOptions::new().with_drive_paths(["/dev/sda"]);
+    let drives = HardDriveSerial::with_sysfs_root(root.path()).with_options(options).get_serial_numbers().unwrap();
+    assert_eq!(drives.len(), 1);
+    assert_eq!(drives[0].index, 1); // keeps its place in the listing
+    assert_eq!(drives[0].device_path, "/dev/sda");
+
+    let options = ScanOptions::new().with_drive_indices([0]);
+    let drives = HardDriveSerial::with_sysfs_root(root.path()).with_options(options).get_serial_numbers().unwrap();
+    assert_eq!(drives.len(), 1);
+    assert_eq!(drives[0].device_path, "/dev/nvme0n1");
+
+    // an index past the end of the listing is not a device called ""
+    let options = ScanOptions::new().with_drive_indices([1, 5]);
+    let mut hd_serial = HardDriveSerial::with_sysfs_root(root.path()).with_options(options);
+    let drives = hd_serial.get_serial_numbers().unwrap();
+    assert_eq!(drives.len(), 1);
+    assert_eq!(drives[0].device_path, "/dev/sda");
+    assert_eq!(hd_serial.errors(), &[SerialError::DeviceNotPresent { drive: Some(5), method: Method::Sysfs }]);
+}
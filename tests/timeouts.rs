@@ -0,0 +1,122 @@
+mod common;
+
+use common::{add_block_device, FakeDrive, FakeSatDevice, FakeScsiTransport, FakeTransport, Hang};
+use simplehddserial::{HardDriveSerial, Method, ScanOptions, SerialError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn hung_drive(hang: Hang) -> FakeDrive {
+    FakeDrive { hang: Some(hang), ..FakeDrive::ata("WD-WCC4E7654321", "WDC WD10EZEX-08WN4A0", "01.01A01") }
+}
+
+#[test]
+fn pending_ioctls_are_cancelled_after_the_operation_timeout() {
+    let fake = Arc::new(
+        FakeTransport::new()
+            .with_drive(0, FakeDrive::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01"))
+            .with_drive(1, hung_drive(Hang::UntilCancelled)),
+    );
+    let options = ScanOptions::new().with_operation_timeout(Duration::from_millis(50));
+    let mut hd_serial = HardDriveSerial::with_transport(fake.clone()).with_options(options);
+
+    let start = Instant::now();
+    let drives = hd_serial.get_serial_numbers().unwrap();
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    // the healthy drive is unaffected, every method on the hung one timed out
+    assert_eq!(drives.len(), 1);
+    assert_eq!(drives[0].serial, "WD-WCC4E1234567");
+    assert_eq!(hd_serial.errors(), &[
+        SerialError::Timeout { drive: Some(1), method: Method::AdminRights },
+        SerialError::Timeout { drive: Some(1), method: Method::Smart },
+        SerialError::Timeout { drive: Some(1), method: Method::ZeroRights },
+    ]);
+    assert_eq!(fake.cancelled_count(), 3);
+    assert_eq!(fake.open_handle_count(), 0);
+}
+
+#[test]
+fn the_scan_deadline_caps_the_operation_timeout() {
+    let fake = FakeTransport::new().with_drive(0, hung_drive(Hang::UntilCancelled));
+    let options = ScanOptions::new().with_operation_timeout(Duration::from_secs(600)).with_scan_timeout(Duration::from_millis(100));
+
+    let start = Instant::now();
+    let errors = HardDriveSerial::with_transport(Arc::new(fake)).with_options(options).get_serial_numbers().unwrap_err();
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(errors.iter().all(|error| matches!(error, SerialError::Timeout { drive: Some(0), .. })));
+    assert!(!errors.is_empty());
+}
+
+#[test]
+fn a_transport_that_never_returns_is_abandoned_at_the_scan_deadline() {
+    let fake = FakeTransport::new()
+        .with_drive(0, FakeDrive::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01"))
+        .with_drive(2, hung_drive(Hang::Forever));
+    let options = ScanOptions::new().with_scan_timeout(Duration::from_millis(200));
+    let mut hd_serial = HardDriveSerial::with_transport(Arc::new(fake)).with_options(options);
+
+    let start = Instant::now();
+    let drives = hd_serial.get_serial_numbers().unwrap();
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    assert_eq!(drives.len(), 1);
+    assert_eq!(drives[0].index, 0);
+    // the drive is stuck in its first method
+    assert_eq!(hd_serial.errors(), &[SerialError::Timeout { drive: Some(2), method: Method::AdminRights }]);
+}
+
+#[test]
+fn the_scan_deadline_applies_to_the_sysfs_backend() {
+    let root = tempfile::tempdir().unwrap();
+    let mut scsi = FakeScsiTransport::new();
+    for name in ["sda", "sdb", "sdc"] {
+        add_block_device(root.path(), name, &[("model", b"WDC WD10EZEX-08W\n")]);
+        scsi = scsi.with_device(&format!("/dev/{}", name), FakeSatDevice { hang: Some(Hang::UntilCancelled), ..FakeSatDevice::default() });
+    }
+    let scsi = Arc::new(scsi);
+    let options = ScanOptions::new()
+        .with_methods([Method::AtaPassThrough])
+        .with_operation_timeout(Duration::from_millis(150))
+        .with_scan_timeout(Duration::from_millis(250))
+        .with_max_concurrency(1); // one drive after the other
+    let mut hd_serial = HardDriveSerial::with_sysfs_root(root.path()).with_scsi_transport(scsi.clone()).with_options(options);
+
+    let start = Instant::now();
+    let errors = hd_serial.get_serial_numbers().unwrap_err();
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    // sda uses up its operation timeout, sdb the rest of the scan and sdc is never sent anything
+    assert_eq!(errors, vec![
+        SerialError::Timeout { drive: Some(0), method: Method::AtaPassThrough },
+        SerialError::Timeout { drive: Some(1), method: Method::AtaPassThrough },
+        SerialError::Timeout { drive: Some(2), method: Method::AtaPassThrough },
+    ]);
+    let timeouts = scsi.timeouts();
+    assert_eq!(timeouts.len(), 2);
+    assert_eq!(timeouts[0], Duration::from_millis(150));
+    assert!(timeouts[1] < Duration::from_millis(150) && timeouts[1] >= Duration::from_millis(1), "{:?}", timeouts);
+    std::thread::sleep(Duration::from_millis(100)); // sdb times out with the scan, its worker may still be closing the handle
+    assert_eq!(scsi.open_handle_count(), 0);
+}
+
+#[test]
+fn sysfs_drives_are_probed_concurrently() {
+    let root = tempfile::tempdir().unwrap();
+    let mut scsi = FakeScsiTransport::new();
+    for name in ["sda", "sdb", "sdc"] {
+        add_block_device(root.path(), name, &[("model", b"WDC WD10EZEX-08W\n")]);
+        scsi = scsi.with_device(&format!("/dev/{}", name), FakeSatDevice { hang: Some(Hang::UntilCancelled), ..FakeSatDevice::default() });
+    }
+    let scsi = Arc::new(scsi);
+    let options = ScanOptions::new()
+        .with_methods([Method::AtaPassThrough])
+        .with_operation_timeout(Duration::from_millis(150))
+        .with_scan_timeout(Duration::from_millis(250))
+        .with_max_concurrency(3);
+    let errors = HardDriveSerial::with_sysfs_root(root.path()).with_scsi_transport(scsi.clone()).with_options(options).get_serial_numbers().unwrap_err();
+
+    // every drive started right away, so none of them was cut short by the scan deadline
+    assert_eq!(errors.len(), 3);
+    assert_eq!(scsi.timeouts(), vec![Duration::from_millis(150); 3]);
+    assert_eq!(scsi.open_handle_count(), 0);
+}
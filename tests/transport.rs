@@ -0,0 +1,107 @@
+mod common;
+
+use common::{FakeDrive, FakeTransport};
+use simplehddserial::{BusType, HardDriveSerial, Method, SerialError};
+use std::sync::Arc;
+
+#[test]
+fn every_method_runs_through_the_transport() {
+    let fake = FakeTransport::new().with_drive(0, FakeDrive::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01"));
+    let drives = HardDriveSerial::with_transport(Arc::new(fake)).get_serial_numbers().unwrap();
+
+    // admin, smart and zero rights all read the same disk, which is reported once
+    assert_eq!(drives.len(), 1);
+    let drive = &drives[0];
+    assert_eq!(drive.index, 0);
+    assert_eq!(drive.device_path, "\\\\.\\PhysicalDrive0");
+    assert_eq!(drive.serial, "WD-WCC4E1234567");
+    assert_eq!(drive.model.as_deref(), Some("WDC WD10EZEX-08WN4A0"));
+    assert_eq!(drive.firmware.as_deref(), Some("01.01A01"));
+    assert_eq!(drive.bus_type, BusType::Ata);
+    assert_eq!(drive.method, Method::AdminRights);
+
+    let methods: Vec<_> = drive.outcomes.iter().map(|outcome| outcome.method).collect();
+    assert_eq!(methods, vec![Method::AdminRights, Method::Smart, Method::ZeroRights]);
+    assert!(drive.outcomes.iter().all(|outcome| outcome.result.as_deref() == Ok("WD-WCC4E1234567")));
+}
+
+#[test]
+fn drives_keep_their_index() {
+    let fake = FakeTransport::new()
+        .with_drive(1, FakeDrive::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01"))
+        .with_drive(3, FakeDrive::ata("S3Z9NB0K123456X", "Samsung SSD 860 EVO 500GB", "RVT02B6Q"));
+    let drives = HardDriveSerial::with_transport(Arc::new(fake)).get_serial_numbers().unwrap();
+
+    let found: Vec<_> = drives.iter().map(|drive| (drive.index, drive.serial.as_str())).collect();
+    assert_eq!(found, vec![(1, "WD-WCC4E1234567"), (3, "S3Z9NB0K123456X")]);
+}
+
+#[test]
+fn smart_still_answers_when_admin_method_is_unsupported() {
+    let mut drive = FakeDrive::ata("S3Z9NB0K123456X", "Samsung SSD 860 EVO 500GB", "RVT02B6Q");
+    drive.ide_device_map = 0;
+    drive.descriptor = None;
+    let fake = FakeTransport::new().with_drive(2, drive);
+
+    let drives = HardDriveSerial::with_transport(Arc::new(fake)).get_serial_numbers().unwrap();
+    assert_eq!(drives.len(), 1);
+    assert_eq!(drives[0].serial, "S3Z9NB0K123456X");
+    assert_eq!(drives[0].method, Method::Smart);
+    assert_eq!(drives[0].outcomes[0].result, Err(SerialError::NotSupported { drive: Some(2), method: Method::AdminRights }));
+}
+
+#[test]
+fn no_drives_reports_an_error() {
+    let result = HardDriveSerial::with_transport(Arc::new(FakeTransport::new())).get_serial_numbers();
+    assert!(result.is_err());
+}
+
+#[test]
+fn handles_are_closed_after_a_scan() {
+    let fake = Arc::new(
+        FakeTransport::new()
+            .with_drive(0, FakeDrive::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01"))
+            .with_drive(1, FakeDrive::default()),
+    );
+    let _ = HardDriveSerial::with_transport(fake.clone()).get_serial_numbers();
+    assert_eq!(fake.open_handle_count(), 0);
+}
+
+#[test]
+fn corrupted_identify_is_reported_not_decoded() {
+    let mut drive = FakeDrive::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01");
+    drive.identify.as_mut().unwrap()[21] ^= 0x04; // flip one bit of the serial, leaving word 255 stale
+    drive.descriptor = None;
+    let fake = FakeTransport::new().with_drive(0, drive);
+
+    let errors = HardDriveSerial::with_transport(Arc::new(fake)).get_serial_numbers().unwrap_err();
+    // one for the admin method and one for SMART
+    assert_eq!(errors, vec![
+        SerialError::ChecksumMismatch { drive: Some(0), method: Method::AdminRights },
+        SerialError::ChecksumMismatch { drive: Some(0), method: Method::Smart },
+    ]);
+}
+
+#[test]
+fn all_zero_identify_is_rejected() {
+    let mut drive = FakeDrive::ata("WD-WCC4E1234567", "WDC WD10EZEX-08WN4A0", "01.01A01");
+    drive.identify = Some([0u8; 512]);
+    drive.descriptor = None;
+    let fake = FakeTransport::new().with_drive(0, drive);
+
+    assert!(HardDriveSerial::with_transport(Arc::new(fake)).get_serial_numbers().is_err());
+}
+
+#[test]
+fn errors_can_be_branched_on() {
+    let error = SerialError::from_os_code(Some(4), Method::AdminRights, 5);
+    assert_eq!(error, SerialError::AccessDenied { drive: Some(4), method: Method::AdminRights });
+    assert_eq!(error.to_string(), "Drive 4: access denied using admin rights");
+
+    assert!(matches!(SerialError::from_os_code(Some(0), Method::Smart, 2), SerialError::DeviceNotPresent { .. }));
+    assert!(matches!(SerialError::from_os_code(Some(0), Method::Smart, 1460), SerialError::Timeout { .. }));
+    assert_eq!(SerialError::from_os_code(None, Method::ZeroRights, 1117), SerialError::Os { drive: None, method: Method::ZeroRights, code: 1117 });
+
+    let boxed: Box<dyn std::error::Error> = Box::new(error);
+    assert!(boxed.to_string().contains("access denied"));
+}